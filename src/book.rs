@@ -1,13 +1,33 @@
 use crate::source;
-use crate::updater::{Unsupported, UpdateResult, WebNovel};
+use crate::updater::{
+    compile_time_selector, Audit, Rating, Unsupported, UpdateResult, WebNovel, BACKEND_META,
+    COVER_URL_META, VERSION_META,
+};
 
 use epub::doc::EpubDoc;
 use eyre::Result;
+use lazy_static::lazy_static;
+use scraper::{Html, Selector};
 use std::fmt::{Debug, Formatter};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+/// Spine items of native and `FanFicFare` e-books which are not chapters.
+pub const NON_CHAPTER_IDS: [&str; 5] = ["title", "warnings", "title_page", "toc_page", "nav"];
+/// Start of the identifiers of the chapters added from the original text of a translated book.
+pub const ORIGINAL_CHAPTER_PREFIX: &str = "original-";
+
+/// Whether the spine item `id` is a chapter of the book itself.
+fn is_own_chapter(id: &str) -> bool {
+    !NON_CHAPTER_IDS.contains(&id) && !id.starts_with(ORIGINAL_CHAPTER_PREFIX)
+}
+
+lazy_static! {
+    static ref PUBLISHED_SELECTOR: Selector = compile_time_selector("meta[name=published]");
+}
 
 pub struct Book {
     pub title: String,
+    pub path: PathBuf,
     url: String,
     updater: Option<Box<dyn WebNovel>>,
 }
@@ -30,21 +50,114 @@ impl Book {
         let title = Self::get_book_title(path).unwrap_or_else(|| String::from("Unknown Title"));
         Self {
             title,
+            path: path.to_path_buf(),
             url,
             updater: source.get_updater(),
         }
     }
 
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// URL of the cover, only recorded by the native backend.
+    pub fn cover_url(&self) -> Option<String> {
+        EpubDoc::new(&self.path)
+            .ok()?
+            .mdata(COVER_URL_META)
+            .filter(|u| !u.is_empty())
+    }
+
+    /// Backend (and version of autebooks) which last wrote the book, if it was recorded.
+    pub fn provenance(&self) -> Option<String> {
+        let epub_doc = EpubDoc::new(&self.path).ok()?;
+        let backend = epub_doc.mdata(BACKEND_META)?;
+        Some(
+            epub_doc
+                .mdata(VERSION_META)
+                .map_or_else(|| backend.clone(), |v| format!("{backend} (autebooks {v})")),
+        )
+    }
+
+    pub fn nb_chapters(&self) -> Option<usize> {
+        let epub_doc = EpubDoc::new(&self.path).ok()?;
+        Some(
+            epub_doc
+                .spine
+                .iter()
+                .filter(|id| is_own_chapter(id))
+                .count(),
+        )
+    }
+
+    /// Publication date of the last chapter as recorded by the native backend, else the date of
+    /// the book.
+    pub fn last_chapter_date(&self) -> Option<String> {
+        let mut epub_doc = EpubDoc::new(&self.path).ok()?;
+        let last_chapter = epub_doc
+            .spine
+            .iter()
+            .rev()
+            .find(|id| is_own_chapter(id))
+            .cloned();
+        last_chapter
+            .and_then(|id| epub_doc.get_resource_str(&id))
+            .and_then(|(xhtml, _mime)| {
+                Html::parse_document(&xhtml)
+                    .select(&PUBLISHED_SELECTOR)
+                    .next()
+                    .and_then(|e| e.attr("content"))
+                    .map(String::from)
+            })
+            .or_else(|| epub_doc.mdata("date"))
+    }
+
     pub fn update(&self, file_path: &Path) -> UpdateResult {
         self.updater
             .as_ref()
             .map_or(UpdateResult::Unsupported, |s| s.update(file_path))
     }
 
+    pub fn check(&self, file_path: &Path) -> Result<usize> {
+        self.updater
+            .as_ref()
+            .map_or(Err(Unsupported.into()), |s| s.check(file_path))
+    }
+
     pub fn create(dir: &Path, url: &str) -> Result<Self> {
         Self::get_source(url).map_or(Err(Unsupported.into()), |s| s.create(dir, None, url))
     }
 
+    /// Book of `url` without its chapters, see `WebNovel::create_stub`.
+    pub fn create_stub(dir: &Path, url: &str) -> Result<Self> {
+        Self::get_source(url).map_or(Err(Unsupported.into()), |s| s.create_stub(dir, url))
+    }
+
+    /// Content rating of the work at `url`, `None` if its source does not tell.
+    pub fn rating(url: &str) -> Result<Option<Rating>> {
+        Self::get_source(url).map_or(Err(Unsupported.into()), |s| s.rating(url))
+    }
+
+    pub fn rerender(&self) -> Result<()> {
+        self.updater
+            .as_ref()
+            .map_or(Err(Unsupported.into()), |s| s.rerender(&self.path))
+    }
+
+    /// Compare the chapters of the book with the ones of its source, repairing them if `fix`.
+    pub fn audit(&self, fix: bool) -> Result<Audit> {
+        self.updater
+            .as_ref()
+            .map_or(Err(Unsupported.into()), |s| s.audit(&self.path, fix))
+    }
+
+    /// Record `url` as the source of a book written by another tool, so that it can be updated.
+    pub fn adopt(path: &Path, url: &str) -> Result<Self> {
+        let updater = Self::get_source(url).ok_or(Unsupported)?;
+        updater.adopt(path, url)?;
+        Ok(Self::new(path))
+    }
+
     pub fn stash_and_recreate(&self, file_path: &Path, stash_dir: &Path) -> Result<Self> {
         self.updater.as_ref().map_or(Err(Unsupported.into()), |s| {
             s.stash_and_recreate(file_path, stash_dir, &self.url)
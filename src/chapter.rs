@@ -0,0 +1,327 @@
+use crate::book::NON_CHAPTER_IDS;
+use crate::updater::compile_time_selector;
+
+use epub::doc::EpubDoc;
+use eyre::{eyre, Result};
+use lazy_regex::regex;
+use lazy_static::lazy_static;
+use scraper::{ElementRef, Html, Node, Selector};
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+lazy_static! {
+    static ref TITLE_SELECTOR: Selector = compile_time_selector("title");
+    static ref BODY_SELECTOR: Selector = compile_time_selector("body");
+    static ref HEADING_TITLE_SELECTOR: Selector =
+        compile_time_selector("h1.chapter-title, h3.fff_chapter_title");
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    #[default]
+    Html,
+    Md,
+    Txt,
+}
+
+impl Format {
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Html => "html",
+            Self::Md => "md",
+            Self::Txt => "txt",
+        }
+    }
+}
+
+/// A chapter as stored in an e-book.
+pub struct Chapter {
+    /// Position of the chapter in the book, starting at 1.
+    pub number: usize,
+    pub id: String,
+    pub title: String,
+    /// Inner HTML of the chapter's body, without its title.
+    pub content: String,
+}
+
+/// Chapters to extract, either by number (`5`, `3-7`, `3-`) or by identifier.
+#[derive(Debug, Clone)]
+pub enum Selection {
+    Numbers(RangeInclusive<usize>),
+    Id(String),
+}
+
+impl Chapter {
+    pub fn extract_from_epub(path: &Path) -> Result<Vec<Self>> {
+        let mut epub_doc = EpubDoc::new(path)?;
+        let ids: Vec<String> = epub_doc
+            .spine
+            .iter()
+            .filter(|id| !NON_CHAPTER_IDS.contains(&id.as_str()))
+            .cloned()
+            .collect();
+
+        let mut chapters = Vec::new();
+        for (id, number) in ids.into_iter().zip(1..) {
+            let (xhtml, _mime) = epub_doc
+                .get_resource_str(&id)
+                .ok_or_else(|| eyre!("Chapter '{id}' is missing from the e-book"))?;
+            let parsed = Html::parse_document(&xhtml);
+            let title = parsed
+                .select(&TITLE_SELECTOR)
+                .next()
+                .map(|e| e.text().collect::<String>())
+                .unwrap_or_default();
+
+            // The title is rendered on its own.
+            let mut content = parsed
+                .select(&BODY_SELECTOR)
+                .next()
+                .map(|e| e.inner_html())
+                .unwrap_or_default();
+            if let Some(heading) = parsed.select(&HEADING_TITLE_SELECTOR).next() {
+                content = content.replacen(&heading.html(), "", 1);
+            }
+
+            chapters.push(Self {
+                number,
+                id,
+                title,
+                content,
+            });
+        }
+        Ok(chapters)
+    }
+
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Html => format!("<h1>{}</h1>\n{}", self.title, self.content),
+            Format::Md | Format::Txt => {
+                let fragment = Html::parse_fragment(&self.content);
+                let mut text = String::new();
+                render_text(fragment.root_element(), format, &mut text);
+                let text = regex!(r"[ \t]*\n[ \t]*").replace_all(text.trim(), "\n");
+                let text = regex!(r"\n{3,}").replace_all(&text, "\n\n");
+                if format == Format::Md {
+                    format!("# {}\n\n{text}\n", self.title)
+                } else {
+                    format!("{}\n\n{text}\n", self.title)
+                }
+            }
+        }
+    }
+
+    pub fn is_selected(&self, selection: &Selection) -> bool {
+        match selection {
+            Selection::Numbers(range) => range.contains(&self.number),
+            Selection::Id(id) => *id == self.id,
+        }
+    }
+
+    /// Name of the file the chapter is extracted to, sorting in reading order.
+    pub fn filename(&self, format: Format) -> String {
+        let title: String = self
+            .title
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '_' })
+            .collect();
+        format!("{:04}_{title}.{}", self.number, format.extension())
+    }
+}
+
+impl std::str::FromStr for Selection {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |n: &str| n.trim().parse::<usize>().ok();
+        let selection = match s.split_once('-') {
+            Some((start, "")) => parse(start).map(|start| Self::Numbers(start..=usize::MAX)),
+            Some((start, end)) => parse(start)
+                .zip(parse(end))
+                .map(|(start, end)| Self::Numbers(start..=end)),
+            None => parse(s).map(|n| Self::Numbers(n..=n)),
+        };
+        Ok(selection.unwrap_or_else(|| Self::Id(s.to_string())))
+    }
+}
+
+/// Text of the element, with the inline formatting of Markdown if requested.
+fn render_text(element: ElementRef, format: Format, out: &mut String) {
+    let md = format == Format::Md;
+    for child in element.children() {
+        if let Node::Text(text) = child.value() {
+            out.push_str(&regex!(r"\s+").replace_all(text, " "));
+            continue;
+        }
+        let Some(child) = ElementRef::wrap(child) else {
+            continue;
+        };
+        match child.value().name() {
+            "br" => out.push('\n'),
+            "hr" => out.push_str(if md { "\n\n---\n\n" } else { "\n\n* * *\n\n" }),
+            "img" => {
+                if md {
+                    let alt = child.attr("alt").unwrap_or_default();
+                    let src = child.attr("src").unwrap_or_default();
+                    let _ = write!(out, "![{alt}]({src})");
+                }
+            }
+            heading @ ("h1" | "h2" | "h3" | "h4" | "h5" | "h6") => {
+                out.push_str("\n\n");
+                if md {
+                    let level = heading[1..].parse().unwrap_or(1);
+                    let _ = write!(out, "{} ", "#".repeat(level));
+                }
+                render_text(child, format, out);
+                out.push_str("\n\n");
+            }
+            "li" => {
+                out.push_str("\n- ");
+                render_text(child, format, out);
+                out.push('\n');
+            }
+            "em" | "i" if md => wrap(child, "*", out),
+            "strong" | "b" if md => wrap(child, "**", out),
+            "p" | "div" | "blockquote" | "ul" | "ol" | "table" | "tr" => {
+                out.push_str("\n\n");
+                if md && child.value().name() == "blockquote" {
+                    out.push_str("> ");
+                }
+                render_text(child, format, out);
+                out.push_str("\n\n");
+            }
+            _ => render_text(child, format, out),
+        }
+    }
+}
+
+fn wrap(element: ElementRef, marker: &str, out: &mut String) {
+    let mut inner = String::new();
+    render_text(element, Format::Md, &mut inner);
+    if inner.trim().is_empty() {
+        out.push_str(&inner);
+    } else {
+        let _ = write!(out, "{marker}{}{marker}", inner.trim());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_markdown() {
+        let chapter = Chapter {
+            number: 1,
+            id: String::from("1"),
+            title: String::from("Prologue"),
+            content: String::from(
+                "<p>It <em>was</em> a\n  <strong>dark</strong> night.</p><hr><p>The end</p>",
+            ),
+        };
+        assert_eq!(
+            chapter.render(Format::Md),
+            "# Prologue\n\nIt *was* a **dark** night.\n\n---\n\nThe end\n"
+        );
+        assert_eq!(
+            chapter.render(Format::Txt),
+            "Prologue\n\nIt was a dark night.\n\n* * *\n\nThe end\n"
+        );
+    }
+
+    #[test]
+    fn test_selection() {
+        let chapter = |number| Chapter {
+            number,
+            id: number.to_string(),
+            title: String::new(),
+            content: String::new(),
+        };
+        let range: Selection = "3-5".parse().unwrap_or(Selection::Id(String::new()));
+        assert!(!chapter(2).is_selected(&range));
+        assert!(chapter(5).is_selected(&range));
+        let from: Selection = "3-".parse().unwrap_or(Selection::Id(String::new()));
+        assert!(chapter(300).is_selected(&from));
+    }
+
+    /// Minimal e-book made of a title page and of `chapters` (identifier, title, body).
+    fn write_epub(path: &Path, chapters: &[(&str, &str, &str)]) -> Result<()> {
+        use std::io::Write as _;
+        use zip::write::SimpleFileOptions;
+
+        let mut items = String::from(
+            r#"<item id="title_page" href="title.xhtml" media-type="application/xhtml+xml"/>"#,
+        );
+        let mut spine = String::from(r#"<itemref idref="title_page"/>"#);
+        for (id, _, _) in chapters {
+            let _ = write!(
+                items,
+                r#"<item id="{id}" href="{id}.xhtml" media-type="application/xhtml+xml"/>"#
+            );
+            let _ = write!(spine, r#"<itemref idref="{id}"/>"#);
+        }
+
+        let mut epub = zip::ZipWriter::new(std::fs::File::create(path)?);
+        let options = SimpleFileOptions::default();
+        let mut add = |name: &str, contents: &str| -> Result<()> {
+            epub.start_file(name, options)?;
+            epub.write_all(contents.as_bytes())?;
+            Ok(())
+        };
+        add("mimetype", "application/epub+zip")?;
+        add(
+            "META-INF/container.xml",
+            r#"<?xml version="1.0"?><container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container"><rootfiles><rootfile full-path="content.opf" media-type="application/oebps-package+xml"/></rootfiles></container>"#,
+        )?;
+        add(
+            "content.opf",
+            &format!(
+                r#"<?xml version="1.0"?><package xmlns="http://www.idpf.org/2007/opf" version="3.0"><metadata xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:title>Book</dc:title></metadata><manifest>{items}</manifest><spine>{spine}</spine></package>"#
+            ),
+        )?;
+        add(
+            "title.xhtml",
+            "<html><head><title>Book</title></head><body><h1>Book</h1></body></html>",
+        )?;
+        for (id, title, body) in chapters {
+            add(
+                &format!("{id}.xhtml"),
+                &format!(
+                    r#"<html><head><title>{title}</title></head><body><h1 class="chapter-title">{title}</h1>{body}</body></html>"#
+                ),
+            )?;
+        }
+        epub.finish()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_from_epub() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("book.epub");
+        write_epub(
+            &path,
+            &[
+                ("11", "Prologue", "<p>It begins.</p>"),
+                ("12", "The End", "<p>It <em>ends</em>.</p>"),
+            ],
+        )?;
+
+        let chapters = Chapter::extract_from_epub(&path)?;
+
+        // The title page is not a chapter.
+        let chapters: Vec<_> = chapters
+            .iter()
+            .map(|c| (c.number, c.id.as_str(), c.render(Format::Md)))
+            .collect();
+        assert_eq!(
+            chapters,
+            [
+                (1, "11", String::from("# Prologue\n\nIt begins.\n")),
+                (2, "12", String::from("# The End\n\nIt *ends*.\n")),
+            ]
+        );
+        Ok(())
+    }
+}
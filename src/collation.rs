@@ -0,0 +1,67 @@
+//! Ordering of titles by the rules of the locale of the environment, so that libraries with
+//! non-ASCII titles (accented, Cyrillic, Japanese…) are listed in a sensible order.
+
+use icu_collator::{Collator, CollatorOptions};
+use icu_locid::Locale;
+use std::cmp::Ordering;
+use std::env;
+
+thread_local! {
+    static COLLATOR: Option<Collator> = collator();
+}
+
+/// Language tag (`fr-FR`) of a POSIX locale (`fr_FR.UTF-8@euro`), `None` for the C locale.
+fn language_tag(posix_locale: &str) -> Option<String> {
+    let name = posix_locale.split(['.', '@']).next()?;
+    if name.is_empty() || name == "C" || name == "POSIX" {
+        return None;
+    }
+    Some(name.replace('_', "-"))
+}
+
+fn collator() -> Option<Collator> {
+    let posix_locale = ["LC_ALL", "LC_COLLATE", "LANG"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|value| !value.is_empty())?;
+    locale_collator(&posix_locale)
+}
+
+fn locale_collator(posix_locale: &str) -> Option<Collator> {
+    let locale: Locale = language_tag(posix_locale)?.parse().ok()?;
+    Collator::try_new(&(&locale).into(), CollatorOptions::new()).ok()
+}
+
+fn compare_with(collator: Option<&Collator>, a: &str, b: &str) -> Ordering {
+    collator.map_or_else(|| a.cmp(b), |collator| collator.compare(a, b))
+}
+
+/// Compare two titles with the collation of the locale, or by code points without one.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    COLLATOR.with(|collator| compare_with(collator.as_ref(), a, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_language_tag() {
+        assert_eq!(language_tag("fr_FR.UTF-8@euro").as_deref(), Some("fr-FR"));
+        assert_eq!(language_tag("ja_JP").as_deref(), Some("ja-JP"));
+        assert_eq!(language_tag("C.UTF-8"), None);
+    }
+
+    #[test]
+    fn test_compare() {
+        let mut titles = ["Fable", "Éclair", "Eau", "abîme"];
+        let collator = locale_collator("fr_FR.UTF-8");
+        titles.sort_by(|a, b| compare_with(collator.as_ref(), a, b));
+        assert_eq!(titles, ["abîme", "Eau", "Éclair", "Fable"]);
+
+        // Without a locale, the titles are sorted by code points.
+        let collator = locale_collator("C");
+        titles.sort_by(|a, b| compare_with(collator.as_ref(), a, b));
+        assert_eq!(titles, ["Eau", "Fable", "abîme", "Éclair"]);
+    }
+}
@@ -0,0 +1,147 @@
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::integrations::{CalibreWeb, Healthcheck, Kavita};
+use crate::notifier::{Discord, Email};
+use crate::summary;
+use std::sync::OnceLock;
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Prefix of the temporary directories in which e-books are assembled.
+pub const TEMP_PREFIX: &str = "autebooks-";
+
+/// File created in each of those directories, so that only they are cleaned up after a crash.
+/// It stays locked while its directory is in use.
+pub const TEMP_MARKER: &str = ".autebooks-temp";
+
+/// Run-wide settings, filled once from the command line in `main`.
+#[derive(Debug, Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Config {
+    /// Directory in which e-books are assembled before being copied to their destination.
+    pub temp_dir: Option<PathBuf>,
+    /// Keep cached images which are no longer used by their book.
+    pub keep_orphans: bool,
+    /// Check whether cached images changed at their source and download them again if so.
+    pub refresh_images: bool,
+    /// Leave out the new chapters which repeat the chapter before them.
+    pub dedupe_chapters: bool,
+    /// Stash only what differs from the recreated book instead of whole books.
+    pub delta_stash: bool,
+    /// Fetch the cover with the native backend when `FanFicFare` could not update it.
+    #[cfg_attr(not(feature = "fanficfare"), allow(dead_code))]
+    pub retry_cover_natively: bool,
+    /// Update the books modified by something else since autebooks last wrote them.
+    pub overwrite_external_changes: bool,
+    pub image_budget: ImageBudget,
+    /// Also export the image-only chapters of each book as a CBZ archive.
+    pub export_cbz: bool,
+    /// Add a page listing the content warnings of the source after the title page.
+    pub warnings_page: bool,
+    /// Books larger than this many bytes are also written as volumes which each fit in it.
+    pub split_size: Option<u64>,
+    pub image_hosts: ImageHostRules,
+    /// Royal Road chapters left out, by regular expressions matched against their title.
+    pub skip_chapters: Vec<String>,
+    /// Where the chapters of the original text of the translated books go, if anywhere.
+    pub parallel_text: Option<ParallelText>,
+    /// Space the requests to each host according to the `Crawl-delay` of its robots.txt.
+    pub respect_crawl_delay: bool,
+    /// Maintain the `KOReader` sidecars of the books.
+    pub koreader: bool,
+    /// Hosts (and their sub-domains) whose books are skipped, as they are down for maintenance.
+    pub maintenance_hosts: Vec<String>,
+    /// Stop attempting the books of a host after this many failed in a row, 0 to never stop.
+    pub max_host_failures: u32,
+    /// `FanFicFare` options (`key=value`) given for every book.
+    #[cfg_attr(not(feature = "fanficfare"), allow(dead_code))]
+    pub site_options: Vec<String>,
+    /// `FanFicFare` is stopped after running this long for a book, `None` to let it run.
+    #[cfg_attr(not(feature = "fanficfare"), allow(dead_code))]
+    pub fanficfare_timeout: Option<Duration>,
+    /// Number of chapters of a book downloaded at the same time by the native backend.
+    pub chapter_concurrency: usize,
+    /// Kavita server to notify once books changed.
+    pub kavita: Option<Kavita>,
+    /// Calibre-Web server to upload the changed books to.
+    pub calibre_web: Option<CalibreWeb>,
+    pub healthcheck: Option<Healthcheck>,
+    pub summary: summary::Options,
+    /// SMTP account through which a digest of each update is emailed.
+    pub email: Option<Email>,
+    /// Discord webhook to which each updated book is posted.
+    pub discord: Option<Discord>,
+    /// Responses are recorded to, or replayed from, a directory rather than only the network.
+    pub fixtures: Option<Fixtures>,
+}
+
+/// Directory of recorded HTTP responses, for development and tests.
+#[derive(Debug, Clone)]
+pub enum Fixtures {
+    /// Store every response in the directory.
+    Record(PathBuf),
+    /// Answer every request from the directory, without reaching the network.
+    Replay(PathBuf),
+}
+
+/// Hosts from which images are downloaded, the others are stripped from the books.
+#[derive(Debug, Default, Clone)]
+pub struct ImageHostRules {
+    /// If not empty, only these hosts (and their sub-domains) are allowed.
+    pub allow: Vec<String>,
+    /// These hosts (and their sub-domains) are never allowed.
+    pub deny: Vec<String>,
+}
+
+/// Limits on the images embedded in each book.
+#[derive(Debug, Default, Clone)]
+pub struct ImageBudget {
+    pub max_count: Option<usize>,
+    /// In bytes.
+    pub max_size: Option<u64>,
+    pub priority: ImagePriority,
+}
+
+/// Which images are kept first when a book exceeds its image budget.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImagePriority {
+    /// Images of the first chapters first.
+    #[default]
+    ChapterOrder,
+    /// Smallest images first, to keep as many of them as possible.
+    SmallestFirst,
+}
+
+/// Where the chapters of the original text of a translated book go, for language learners.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ParallelText {
+    /// Each chapter after its translation.
+    Interleave,
+    /// Every chapter after the translated ones.
+    Append,
+}
+
+impl Config {
+    pub fn init(self) {
+        if CONFIG.set(self).is_err() {
+            eprintln!("The configuration was already initialised, the new one was ignored");
+        }
+    }
+
+    pub fn get() -> &'static Self {
+        CONFIG.get_or_init(Self::default)
+    }
+
+    /// Directory dedicated to autebooks' temporary files,
+    /// `autebooks` sub-directory of the configured (or system) temp directory.
+    pub fn temp_dir(&self) -> PathBuf {
+        self.temp_dir
+            .clone()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("autebooks")
+    }
+}
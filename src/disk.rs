@@ -0,0 +1,89 @@
+use std::path::Path;
+use thiserror::Error;
+
+/// Free space kept on top of every estimate, so that a write never fills the disk completely.
+const SAFETY_MARGIN: u64 = 10 * 1024 * 1024;
+
+#[derive(Error, Debug)]
+#[error("Not enough disk space in '{dir}' : {needed} bytes needed, {available} bytes available")]
+pub struct NotEnoughSpace {
+    dir: String,
+    needed: u64,
+    available: u64,
+}
+
+/// Fail early if `dir` cannot hold `needed` more bytes,
+/// if the available space cannot be determined the check is skipped.
+pub fn ensure_available_space(dir: &Path, needed: u64) -> Result<(), NotEnoughSpace> {
+    let Some(available) = available_space(dir) else {
+        return Ok(());
+    };
+    check_space(dir, needed, available)
+}
+
+/// Fail if `available` bytes in `dir` cannot hold `needed` more bytes and the safety margin.
+fn check_space(dir: &Path, needed: u64, available: u64) -> Result<(), NotEnoughSpace> {
+    let needed = needed.saturating_add(SAFETY_MARGIN);
+    if available < needed {
+        return Err(NotEnoughSpace {
+            dir: dir.display().to_string(),
+            needed,
+            available,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+#[allow(unsafe_code, clippy::useless_conversion)]
+fn available_space(dir: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::mem::MaybeUninit;
+    use std::os::unix::ffi::OsStrExt;
+
+    // The directory may not exist yet, use its closest existing ancestor.
+    let dir = dir.ancestors().find(|d| d.exists())?;
+    let c_path = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+
+    // SAFETY: `c_path` is a valid NUL terminated string and `stat` is only read if the call succeeded.
+    let stat = unsafe {
+        if libc::statvfs(c_path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return None;
+        }
+        stat.assume_init()
+    };
+    u64::from(stat.f_bavail).checked_mul(u64::from(stat.f_frsize))
+}
+
+#[cfg(not(unix))]
+const fn available_space(_dir: &Path) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_space() {
+        let dir = Path::new("/books");
+        let available = 100 * 1024 * 1024;
+        assert!(check_space(dir, available - SAFETY_MARGIN, available).is_ok());
+        assert!(check_space(dir, available - SAFETY_MARGIN + 1, available).is_err());
+        assert!(check_space(dir, 0, SAFETY_MARGIN - 1).is_err());
+        assert!(check_space(dir, u64::MAX, available).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_ensure_available_space() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        // Not created yet, checked on its closest existing ancestor.
+        let books = dir.path().join("books");
+        assert!(ensure_available_space(&books, 0).is_ok());
+        let error = ensure_available_space(&books, u64::MAX).map_err(|e| e.to_string());
+        assert!(error.is_err_and(|e| e.starts_with("Not enough disk space")));
+        Ok(())
+    }
+}
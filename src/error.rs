@@ -0,0 +1,124 @@
+use reqwest::StatusCode;
+use thiserror::Error;
+
+use crate::disk::NotEnoughSpace;
+use crate::updater::Unsupported;
+
+/// Broad cause of a failure, to act on it without matching error messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Network,
+    Parse,
+    RateLimited,
+    NotFound,
+    Io,
+    Unsupported,
+    /// The e-book itself cannot be read (DRM, broken archive), retrying will not help.
+    Unreadable,
+}
+
+/// Error raised with an explicit category, to be wrapped in an eyre report.
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("{0}")]
+    Network(String),
+    /// The page or file does not have the expected structure.
+    #[error("{0}")]
+    Parse(String),
+    #[error("Rate limited by {0}")]
+    RateLimited(String),
+    #[error("Not found : {0}")]
+    NotFound(String),
+    /// The e-book is encrypted or not a valid EPUB archive.
+    #[error("{0}")]
+    Unreadable(String),
+}
+
+impl Category {
+    /// Category of the first error of the report's chain which has one.
+    pub fn of(report: &eyre::Report) -> Option<Self> {
+        report.chain().find_map(|cause| {
+            if let Some(e) = cause.downcast_ref::<Error>() {
+                return Some(match e {
+                    Error::Network(_) => Self::Network,
+                    Error::Parse(_) => Self::Parse,
+                    Error::RateLimited(_) => Self::RateLimited,
+                    Error::NotFound(_) => Self::NotFound,
+                    Error::Unreadable(_) => Self::Unreadable,
+                });
+            }
+            if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+                let status = e.status();
+                return Some(
+                    if status == Some(StatusCode::NOT_FOUND) || status == Some(StatusCode::GONE) {
+                        Self::NotFound
+                    } else if status == Some(StatusCode::TOO_MANY_REQUESTS) {
+                        Self::RateLimited
+                    } else if e.is_decode() {
+                        Self::Parse
+                    } else {
+                        Self::Network
+                    },
+                );
+            }
+            if cause.is::<std::io::Error>() || cause.is::<NotEnoughSpace>() {
+                return Some(Self::Io);
+            }
+            if cause.is::<serde_json::Error>() || cause.is::<epub::doc::DocError>() {
+                return Some(Self::Parse);
+            }
+            cause.is::<Unsupported>().then_some(Self::Unsupported)
+        })
+    }
+
+    /// Short name used in the summaries.
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Network => "Net",
+            Self::Parse => "Pars",
+            Self::RateLimited => "Rate",
+            Self::NotFound => "404",
+            Self::Io => "IO",
+            Self::Unsupported => "Uns",
+            Self::Unreadable => "Unrd",
+        }
+    }
+
+    /// Whether the failure says something about the host rather than about the book.
+    pub const fn is_host_failure(self) -> bool {
+        match self {
+            Self::Network | Self::RateLimited => true,
+            Self::Parse | Self::NotFound | Self::Io | Self::Unsupported | Self::Unreadable => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_category_of_wrapped_error() {
+        let report = eyre::Report::new(Error::NotFound(String::from("chapter")))
+            .wrap_err("Could not update the book");
+        assert_eq!(Category::of(&report), Some(Category::NotFound));
+        assert_eq!(Category::of(&eyre::eyre!("Something else")), None);
+    }
+
+    #[test]
+    fn test_category_of_library_errors() {
+        let io =
+            eyre::Report::new(std::io::Error::other("disk failure")).wrap_err("Could not write");
+        assert_eq!(Category::of(&io), Some(Category::Io));
+        let json = serde_json::from_str::<u32>("{").map_err(eyre::Report::new);
+        assert_eq!(
+            json.err().as_ref().and_then(Category::of),
+            Some(Category::Parse)
+        );
+
+        // Only the failures of the host count against it.
+        let rate_limited = eyre::Report::new(Error::RateLimited(String::from("royalroad.com")));
+        assert!(Category::of(&rate_limited).is_some_and(Category::is_host_failure));
+        assert!(!Category::of(&io).is_some_and(Category::is_host_failure));
+    }
+}
@@ -0,0 +1,365 @@
+use eyre::{eyre, Result};
+use lazy_static::lazy_static;
+use reqwest::blocking::{multipart, Client, Response};
+use scraper::{Html, Selector};
+use serde::Deserialize;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::updater::compile_time_selector;
+use crate::{ErrorPrint, MULTI_PROGRESS};
+
+/// A monitoring outage must not hold the run.
+const HEALTHCHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    static ref CSRF_TOKEN_SELECTOR: Selector = compile_time_selector("input[name=csrf_token]");
+}
+
+/// Kavita server asked to rescan a library once books changed.
+#[derive(Debug)]
+pub struct Kavita {
+    pub url: String,
+    pub api_key: String,
+    pub library_id: u32,
+}
+
+/// Calibre-Web server to which the changed books are uploaded.
+#[derive(Debug)]
+pub struct CalibreWeb {
+    pub url: String,
+    pub username: String,
+    pub password: String,
+}
+
+/// healthchecks.io-style monitor, alerting when runs fail or stop happening.
+#[derive(Debug)]
+pub struct Healthcheck {
+    pub url: String,
+    pub events: Vec<HealthcheckEvent>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HealthcheckEvent {
+    Start,
+    Success,
+    Failure,
+}
+
+#[derive(Deserialize)]
+struct KavitaUser {
+    token: String,
+}
+
+/// Let the configured reading servers know about the books written during this run.
+pub fn push_changes(books: &[PathBuf]) {
+    if books.is_empty() {
+        return;
+    }
+    let config = Config::get();
+    if let Some(kavita) = &config.kavita {
+        if let Err(e) = kavita.scan() {
+            MULTI_PROGRESS.eprintln(&format!("Could not ask Kavita to scan its library : {e}"));
+        }
+    }
+    if let Some(calibre_web) = &config.calibre_web {
+        if let Err(e) = calibre_web.upload(books) {
+            MULTI_PROGRESS.eprintln(&format!("Could not upload to Calibre-Web : {e}"));
+        }
+    }
+}
+
+/// Copy `files` (relative to `root`) to the rclone `remote`, leaving the rest of the library alone.
+pub fn rclone_sync(
+    root: &Path,
+    files: &[PathBuf],
+    remote: &str,
+    dry_run: bool,
+    bwlimit: Option<&str>,
+) -> Result<()> {
+    let mut files_from = tempfile::NamedTempFile::new()?;
+    files_from.write_all(files_from_list(files).as_bytes())?;
+    files_from.flush()?;
+
+    let status = rclone_command(root, files_from.path(), remote, dry_run, bwlimit)
+        .status()
+        .map_err(|e| eyre!("Could not run rclone : {e}"))?;
+    if !status.success() {
+        return Err(eyre!("rclone ended with {status}"));
+    }
+    Ok(())
+}
+
+/// Content of the `--files-from` list of rclone, in which only the paths inside the library
+/// (the relative ones) can be given.
+fn files_from_list(files: &[PathBuf]) -> String {
+    files
+        .iter()
+        .filter(|f| f.is_relative())
+        .fold(String::new(), |mut list, f| {
+            let _ = writeln!(list, "{}", f.display());
+            list
+        })
+}
+
+fn rclone_command(
+    root: &Path,
+    files_from: &Path,
+    remote: &str,
+    dry_run: bool,
+    bwlimit: Option<&str>,
+) -> Command {
+    let mut cmd = Command::new("rclone");
+    cmd.arg("copy")
+        .arg(root)
+        .arg(remote)
+        .arg("--files-from")
+        .arg(files_from);
+    if dry_run {
+        cmd.arg("--dry-run");
+    }
+    if let Some(bwlimit) = bwlimit {
+        cmd.arg("--bwlimit").arg(bwlimit);
+    }
+    cmd
+}
+
+/// Ping the healthcheck URL if it is configured for this event, never fails the run.
+pub fn ping(event: HealthcheckEvent) {
+    let Some(healthcheck) = &Config::get().healthcheck else {
+        return;
+    };
+    if !healthcheck.events.contains(&event) {
+        return;
+    }
+    let url = event.url(&healthcheck.url);
+    let res = Client::builder()
+        .timeout(HEALTHCHECK_TIMEOUT)
+        .build()
+        .and_then(|client| client.get(&url).send())
+        .and_then(Response::error_for_status);
+    if let Err(e) = res {
+        MULTI_PROGRESS.eprintln(&format!("Could not ping the healthcheck : {e}"));
+    }
+}
+
+impl HealthcheckEvent {
+    pub fn all() -> Vec<Self> {
+        vec![Self::Start, Self::Success, Self::Failure]
+    }
+
+    /// URL of the check at `base` to ping for the event, as healthchecks.io expects them.
+    fn url(self, base: &str) -> String {
+        let base = base.trim_end_matches('/');
+        match self {
+            Self::Start => format!("{base}/start"),
+            Self::Success => base.to_string(),
+            Self::Failure => format!("{base}/fail"),
+        }
+    }
+}
+
+impl std::fmt::Display for HealthcheckEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use clap::ValueEnum;
+        self.to_possible_value()
+            .map_or(Ok(()), |v| write!(f, "{}", v.get_name()))
+    }
+}
+
+impl Kavita {
+    fn scan(&self) -> Result<()> {
+        let client = Client::new();
+        let url = self.url.trim_end_matches('/');
+
+        let user: KavitaUser = serde_json::from_str(
+            &client
+                .post(format!("{url}/api/Plugin/authenticate"))
+                .query(&[
+                    ("apiKey", self.api_key.as_str()),
+                    ("pluginName", "autebooks"),
+                ])
+                .send()?
+                .error_for_status()?
+                .text()?,
+        )?;
+
+        client
+            .post(format!("{url}/api/Library/scan"))
+            .query(&[("libraryId", self.library_id)])
+            .bearer_auth(user.token)
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+impl CalibreWeb {
+    fn upload(&self, books: &[PathBuf]) -> Result<()> {
+        let client = Client::builder().cookie_store(true).build()?;
+        let url = self.url.trim_end_matches('/');
+
+        let login_page = client.get(format!("{url}/login")).send()?.text()?;
+        client
+            .post(format!("{url}/login"))
+            .form(&[
+                ("username", self.username.as_str()),
+                ("password", self.password.as_str()),
+                ("csrf_token", &csrf_token(&login_page)?),
+            ])
+            .send()?
+            .error_for_status()?;
+
+        // The upload form is only shown to logged in users allowed to upload.
+        let index_page = client.get(format!("{url}/")).send()?.text()?;
+        let csrf_token = csrf_token(&index_page)
+            .map_err(|_| eyre!("Login failed or the user is not allowed to upload"))?;
+
+        for book in books {
+            if let Err(e) = Self::upload_book(&client, url, &csrf_token, book) {
+                MULTI_PROGRESS.eprintln(&format!(
+                    "Could not upload {} to Calibre-Web : {e}",
+                    book.display()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn upload_book(client: &Client, url: &str, csrf_token: &str, book: &Path) -> Result<()> {
+        let form = multipart::Form::new()
+            .text("csrf_token", csrf_token.to_string())
+            .file("btn-upload", book)?;
+        client
+            .post(format!("{url}/upload"))
+            .multipart(form)
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+fn csrf_token(page: &str) -> Result<String> {
+    Html::parse_document(page)
+        .select(&CSRF_TOKEN_SELECTOR)
+        .find_map(|input| input.attr("value"))
+        .map(ToString::to_string)
+        .ok_or_else(|| eyre!("No CSRF token found"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csrf_token() {
+        let page = r#"<form><input type="hidden" name="csrf_token" value="abc123"></form>"#;
+        assert_eq!(csrf_token(page).ok().as_deref(), Some("abc123"));
+        assert!(csrf_token("<form></form>").is_err());
+    }
+
+    /// Answer the next requests made to the returned URL with `bodies`,
+    /// the handle gives back the head of each request.
+    fn serve(
+        bodies: Vec<&'static str>,
+    ) -> Result<(
+        String,
+        std::thread::JoinHandle<std::io::Result<Vec<String>>>,
+    )> {
+        use std::io::{BufRead, BufReader, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let handle = std::thread::spawn(move || {
+            let mut heads = Vec::new();
+            for body in bodies {
+                let (mut stream, _) = listener.accept()?;
+                let mut head = String::new();
+                let mut reader = BufReader::new(&stream);
+                while reader.read_line(&mut head)? > 2 {}
+                heads.push(head);
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )?;
+            }
+            Ok(heads)
+        });
+        Ok((url, handle))
+    }
+
+    #[test]
+    fn test_kavita_scan() -> Result<()> {
+        let (url, server) = serve(vec![r#"{"token":"jwt"}"#, ""])?;
+        let kavita = Kavita {
+            url: format!("{url}/"),
+            api_key: String::from("key"),
+            library_id: 3,
+        };
+
+        kavita.scan()?;
+
+        let heads = server.join().map_err(|_| eyre!("The server panicked"))??;
+        assert!(heads[0]
+            .starts_with("POST /api/Plugin/authenticate?apiKey=key&pluginName=autebooks HTTP/1.1"));
+        assert!(heads[1].starts_with("POST /api/Library/scan?libraryId=3 HTTP/1.1"));
+        assert!(heads[1]
+            .to_lowercase()
+            .contains("authorization: bearer jwt"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_healthcheck_urls() {
+        let base = "https://hc-ping.com/1234/";
+        assert_eq!(
+            HealthcheckEvent::Start.url(base),
+            "https://hc-ping.com/1234/start"
+        );
+        assert_eq!(
+            HealthcheckEvent::Success.url(base),
+            "https://hc-ping.com/1234"
+        );
+        assert_eq!(
+            HealthcheckEvent::Failure.url(base),
+            "https://hc-ping.com/1234/fail"
+        );
+    }
+
+    #[test]
+    fn test_rclone_sync() {
+        let files = [
+            PathBuf::from("fantasy/The Wandering Inn.epub"),
+            PathBuf::from("/elsewhere/book.epub"),
+        ];
+        assert_eq!(files_from_list(&files), "fantasy/The Wandering Inn.epub\n");
+
+        let cmd = rclone_command(
+            Path::new("/books"),
+            Path::new("/tmp/files"),
+            "remote:books",
+            true,
+            Some("1M"),
+        );
+        let args: Vec<_> = cmd.get_args().map(|a| a.to_string_lossy()).collect();
+        assert_eq!(
+            args,
+            [
+                "copy",
+                "/books",
+                "remote:books",
+                "--files-from",
+                "/tmp/files",
+                "--dry-run",
+                "--bwlimit",
+                "1M"
+            ]
+        );
+    }
+}
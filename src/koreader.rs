@@ -0,0 +1,449 @@
+use epub::doc::EpubDoc;
+use eyre::{bail, Result};
+use lazy_regex::regex;
+use lazy_static::lazy_static;
+use scraper::{Html, Selector};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::updater::compile_time_selector;
+
+lazy_static! {
+    static ref CHAPTER_URL_SELECTOR: Selector = compile_time_selector("meta[name=chapterurl]");
+}
+
+/// File of the sidecar mapping the documents renamed by the last update to their new path.
+const REMAP_FILE: &str = "autebooks_remap.lua";
+
+/// `KOReader` keeps the state of `book.epub` in `book.sdr/metadata.epub.lua`.
+pub fn sidecar_dir(book: &Path) -> PathBuf {
+    book.with_extension("sdr")
+}
+
+fn metadata_file(book: &Path) -> PathBuf {
+    let extension = book
+        .extension()
+        .map_or_else(|| String::from("epub"), |e| e.to_string_lossy().to_string());
+    sidecar_dir(book).join(format!("metadata.{extension}.lua"))
+}
+
+/// Create a minimal sidecar so that the book shows up as new (0%) on the device,
+/// an existing sidecar is left untouched.
+pub fn create_sidecar(book: &Path) -> Result<()> {
+    let metadata_file = metadata_file(book);
+    if metadata_file.exists() {
+        return Ok(());
+    }
+    let doc_path = lua_doc_path(book);
+
+    fs::create_dir_all(sidecar_dir(book))?;
+    fs::write(
+        metadata_file,
+        format!(
+            "-- we can read Lua syntax here!
+return {{
+    [\"doc_path\"] = \"{doc_path}\",
+    [\"percent_finished\"] = 0,
+    [\"summary\"] = {{
+        [\"status\"] = \"new\",
+    }},
+}}
+"
+        ),
+    )?;
+    Ok(())
+}
+
+/// Move the sidecar of a book moved from `from` to `to`, so that its reading progress follows it.
+pub fn move_sidecar(from: &Path, to: &Path) -> Result<()> {
+    let from_sidecar = sidecar_dir(from);
+    if !from_sidecar.is_dir() {
+        return Ok(());
+    }
+    let to_sidecar = sidecar_dir(to);
+    if to_sidecar.exists() {
+        bail!(
+            "A KOReader sidecar already exists at {}",
+            to_sidecar.display()
+        );
+    }
+    fs::rename(from_sidecar, to_sidecar)?;
+    set_doc_path(to)
+}
+
+/// Copy the sidecar of the book at `from` to its copy at `to`, so that it keeps the progress.
+pub fn copy_sidecar(from: &Path, to: &Path) -> Result<()> {
+    let from_sidecar = sidecar_dir(from);
+    if !from_sidecar.is_dir() {
+        return Ok(());
+    }
+    let to_sidecar = sidecar_dir(to);
+    fs::create_dir_all(&to_sidecar)?;
+    for entry in fs::read_dir(from_sidecar)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            fs::copy(entry.path(), to_sidecar.join(entry.file_name()))?;
+        }
+    }
+    set_doc_path(to)
+}
+
+/// The sidecar also stores the path of its book.
+fn set_doc_path(book: &Path) -> Result<()> {
+    let metadata_file = metadata_file(book);
+    if metadata_file.exists() {
+        let metadata = fs::read_to_string(&metadata_file)?;
+        let doc_path = format!("[\"doc_path\"] = \"{}\"", lua_doc_path(book));
+        let metadata = regex!(r#"\["doc_path"\] = "(?:[^"\\]|\\.)*""#)
+            .replace(&metadata, lazy_regex::regex::NoExpand(&doc_path));
+        fs::write(metadata_file, metadata.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Flip a finished book back to "reading" when it received new chapters,
+/// since `KOReader` hides finished books from some of its views.
+pub fn mark_as_reading(book: &Path) -> Result<()> {
+    let metadata_file = metadata_file(book);
+    if !metadata_file.exists() {
+        return Ok(());
+    }
+    let metadata = fs::read_to_string(&metadata_file)?;
+    let status_regex = regex!(r#"(\["status"\]\s*=\s*)"complete""#);
+    if status_regex.is_match(&metadata) {
+        let metadata = status_regex.replace(&metadata, r#"${1}"reading""#);
+        fs::write(metadata_file, metadata.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Documents of a book in reading order, as `KOReader` numbers them in its positions
+/// (`DocFragment[1]` being the first).
+pub struct Spine(Vec<Document>);
+
+struct Document {
+    path: String,
+    /// URL of the chapter, which survives its renaming and renumbering.
+    chapter_url: Option<String>,
+}
+
+impl Spine {
+    /// Spine of a book read by `KOReader`, `None` if it has no sidecar or cannot be read.
+    pub fn of_tracked(book: &Path) -> Option<Self> {
+        if !metadata_file(book).exists() {
+            return None;
+        }
+        Self::read(book).ok()
+    }
+
+    fn read(book: &Path) -> Result<Self> {
+        let mut epub_doc = EpubDoc::new(book)?;
+        let ids = epub_doc.spine.clone();
+        let mut documents = Vec::new();
+        for id in ids {
+            let path = epub_doc.resources.get(&id).map_or_else(
+                || id.clone(),
+                |(path, _mime)| path.to_string_lossy().to_string(),
+            );
+            let chapter_url = epub_doc.get_resource_str(&id).and_then(|(xhtml, _mime)| {
+                Html::parse_document(&xhtml)
+                    .select(&CHAPTER_URL_SELECTOR)
+                    .next()
+                    .and_then(|meta| meta.attr("content"))
+                    .map(String::from)
+            });
+            documents.push(Document { path, chapter_url });
+        }
+        Ok(Self(documents))
+    }
+
+    /// Index in `new` of each document of the spine, by chapter URL, else by path.
+    fn moves_to(&self, new: &Self) -> Vec<Option<usize>> {
+        self.0
+            .iter()
+            .map(|old| {
+                new.0
+                    .iter()
+                    .position(|d| old.chapter_url.is_some() && d.chapter_url == old.chapter_url)
+                    .or_else(|| new.0.iter().position(|d| d.path == old.path))
+            })
+            .collect()
+    }
+}
+
+/// Make the positions of the sidecar (last position, bookmarks, highlights) follow the
+/// documents of a book rewritten since `old` was read, when its chapters were renumbered
+/// or renamed, and record the renamed documents in the sidecar.
+/// The positions in a document which no longer exists are left as they were.
+pub fn follow_restructure(book: &Path, old: &Spine) -> Result<()> {
+    let metadata_file = metadata_file(book);
+    if !metadata_file.exists() {
+        return Ok(());
+    }
+    let new = Spine::read(book)?;
+    let moves = old.moves_to(&new);
+
+    let metadata = fs::read_to_string(&metadata_file)?;
+    let remapped = remap_fragments(&metadata, &moves);
+    if remapped != metadata {
+        fs::write(&metadata_file, remapped.as_bytes())?;
+    }
+
+    let renamed: Vec<(&str, &str)> = old
+        .0
+        .iter()
+        .zip(&moves)
+        .filter_map(|(document, index)| {
+            let new_path = &new.0.get((*index)?)?.path;
+            (*new_path != document.path).then_some((document.path.as_str(), new_path.as_str()))
+        })
+        .collect();
+    if !renamed.is_empty() {
+        let mut remap = String::from(
+            "-- Documents renamed by the last update : old path = new path.\nreturn {\n",
+        );
+        for (old_path, new_path) in renamed {
+            writeln!(
+                remap,
+                "    [\"{}\"] = \"{}\",",
+                lua_escape(old_path),
+                lua_escape(new_path)
+            )?;
+        }
+        remap.push_str("}\n");
+        fs::write(sidecar_dir(book).join(REMAP_FILE), remap)?;
+    } else if sidecar_dir(book).join(REMAP_FILE).exists() {
+        fs::remove_file(sidecar_dir(book).join(REMAP_FILE))?;
+    }
+    Ok(())
+}
+
+/// `metadata` with the `DocFragment[n]` of its positions moved as the documents did.
+fn remap_fragments(metadata: &str, moves: &[Option<usize>]) -> String {
+    regex!(r"DocFragment\[(\d+)\]")
+        .replace_all(metadata, |captures: &lazy_regex::regex::Captures| {
+            let new_index = captures[1]
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| n.checked_sub(1))
+                .and_then(|index| moves.get(index).copied().flatten());
+            new_index.map_or_else(
+                || captures[0].to_string(),
+                |index| format!("DocFragment[{}]", index + 1),
+            )
+        })
+        .into_owned()
+}
+
+/// `s` escaped to fit in a Lua string.
+fn lua_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Absolute path of the book, escaped to fit in a Lua string.
+fn lua_doc_path(book: &Path) -> String {
+    lua_escape(
+        &book
+            .canonicalize()
+            .unwrap_or_else(|_| book.to_path_buf())
+            .to_string_lossy(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sidecar_paths() {
+        let book = Path::new("/books/The Primal Hunter.epub");
+        assert_eq!(sidecar_dir(book), Path::new("/books/The Primal Hunter.sdr"));
+        assert_eq!(
+            metadata_file(book),
+            Path::new("/books/The Primal Hunter.sdr/metadata.epub.lua")
+        );
+    }
+
+    #[test]
+    fn test_create_sidecar() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let book = dir.path().join("book.epub");
+        create_sidecar(&book)?;
+        let metadata = fs::read_to_string(metadata_file(&book))?;
+        assert!(metadata.contains("[\"percent_finished\"] = 0"));
+        assert!(metadata.contains("[\"status\"] = \"new\""));
+
+        // The progress of a book already opened on the device is kept.
+        fs::write(
+            metadata_file(&book),
+            "return { [\"percent_finished\"] = 0.5 }",
+        )?;
+        create_sidecar(&book)?;
+        assert!(fs::read_to_string(metadata_file(&book))?.contains("0.5"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sidecar_follows_its_book() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let book = dir.path().join("book.epub");
+        let stashed = dir.path().join("stash").join("book_2024.epub");
+        fs::create_dir_all(dir.path().join("stash"))?;
+        create_sidecar(&book)?;
+
+        move_sidecar(&book, &stashed)?;
+        assert!(!sidecar_dir(&book).exists());
+        let metadata = fs::read_to_string(metadata_file(&stashed))?;
+        assert!(metadata.contains(&format!("\"{}\"", lua_doc_path(&stashed))));
+
+        copy_sidecar(&stashed, &book)?;
+        let metadata = fs::read_to_string(metadata_file(&book))?;
+        assert!(metadata.contains(&format!("\"{}\"", lua_doc_path(&book))));
+        assert!(metadata_file(&stashed).is_file());
+
+        // The sidecar of another book is not overwritten.
+        assert!(move_sidecar(&stashed, &book).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_finished_books_are_read_again() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let book = dir.path().join("book.epub");
+        create_sidecar(&book)?;
+        let finished = fs::read_to_string(metadata_file(&book))?.replace("\"new\"", "\"complete\"");
+        fs::write(metadata_file(&book), finished)?;
+
+        mark_as_reading(&book)?;
+        let metadata = fs::read_to_string(metadata_file(&book))?;
+        assert!(metadata.contains("[\"status\"] = \"reading\""));
+
+        // Books without a sidecar are left alone.
+        mark_as_reading(&dir.path().join("other.epub"))?;
+        assert!(!sidecar_dir(&dir.path().join("other.epub")).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_follow_restructure() {
+        let document = |path: &str, url: Option<&str>| Document {
+            path: path.to_string(),
+            chapter_url: url.map(String::from),
+        };
+        let old = Spine(vec![
+            document("OEBPS/text/title.xhtml", None),
+            document("OEBPS/text/1.xhtml", Some("https://example.com/1")),
+            document("OEBPS/text/2.xhtml", Some("https://example.com/2")),
+            document("OEBPS/text/3.xhtml", Some("https://example.com/3")),
+        ]);
+        // A chapter was inserted before the second one, the third was removed.
+        let new = Spine(vec![
+            document("OEBPS/text/title.xhtml", None),
+            document("OEBPS/text/1.xhtml", Some("https://example.com/1")),
+            document("OEBPS/text/4.xhtml", Some("https://example.com/4")),
+            document("OEBPS/text/2b.xhtml", Some("https://example.com/2")),
+        ]);
+        let moves = old.moves_to(&new);
+        assert_eq!(moves, [Some(0), Some(1), Some(3), None]);
+        assert_eq!(
+            remap_fragments(
+                "[\"last_xpointer\"] = \"/body/DocFragment[3]/body/p[2]/text().0\",\n\
+                [\"pos0\"] = \"/body/DocFragment[4]/body/p[1]/text().0\",",
+                &moves
+            ),
+            "[\"last_xpointer\"] = \"/body/DocFragment[4]/body/p[2]/text().0\",\n\
+            [\"pos0\"] = \"/body/DocFragment[4]/body/p[1]/text().0\","
+        );
+    }
+
+    /// Minimal e-book made of a title page and of `chapters` (file name, chapter URL).
+    fn write_epub(path: &Path, chapters: &[(&str, &str)]) -> Result<()> {
+        use std::io::Write as _;
+        use zip::write::SimpleFileOptions;
+
+        let mut items = String::from(
+            r#"<item id="title" href="title.xhtml" media-type="application/xhtml+xml"/>"#,
+        );
+        let mut spine = String::from(r#"<itemref idref="title"/>"#);
+        for (id, (name, _)) in chapters.iter().enumerate() {
+            write!(
+                items,
+                r#"<item id="c{id}" href="{name}" media-type="application/xhtml+xml"/>"#
+            )?;
+            write!(spine, r#"<itemref idref="c{id}"/>"#)?;
+        }
+
+        let mut epub = zip::ZipWriter::new(fs::File::create(path)?);
+        let options = SimpleFileOptions::default();
+        let mut add = |name: &str, contents: &str| -> Result<()> {
+            epub.start_file(name, options)?;
+            epub.write_all(contents.as_bytes())?;
+            Ok(())
+        };
+        add("mimetype", "application/epub+zip")?;
+        add(
+            "META-INF/container.xml",
+            r#"<?xml version="1.0"?><container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container"><rootfiles><rootfile full-path="content.opf" media-type="application/oebps-package+xml"/></rootfiles></container>"#,
+        )?;
+        add(
+            "content.opf",
+            &format!(
+                r#"<?xml version="1.0"?><package xmlns="http://www.idpf.org/2007/opf" version="3.0"><metadata xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:title>Book</dc:title></metadata><manifest>{items}</manifest><spine>{spine}</spine></package>"#
+            ),
+        )?;
+        add(
+            "title.xhtml",
+            "<html><head><title>Book</title></head><body><h1>Book</h1></body></html>",
+        )?;
+        for (name, url) in chapters {
+            add(
+                name,
+                &format!(
+                    "<html><head><meta name=\"chapterurl\" content=\"{url}\"/></head>\
+                    <body><p>Text</p></body></html>"
+                ),
+            )?;
+        }
+        epub.finish()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_positions_follow_the_rewritten_book() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let book = dir.path().join("book.epub");
+        write_epub(
+            &book,
+            &[
+                ("1.xhtml", "https://example.com/1"),
+                ("2.xhtml", "https://example.com/2"),
+            ],
+        )?;
+        // Books without a sidecar are not followed.
+        assert!(Spine::of_tracked(&book).is_none());
+        create_sidecar(&book)?;
+        fs::write(
+            metadata_file(&book),
+            "return { [\"last_xpointer\"] = \"/body/DocFragment[3]/body/p[1]/text().0\" }",
+        )?;
+        let old = Spine::of_tracked(&book).ok_or_else(|| eyre::eyre!("The spine is missing"))?;
+
+        // A chapter was inserted before the second one, which was renamed.
+        write_epub(
+            &book,
+            &[
+                ("1.xhtml", "https://example.com/1"),
+                ("1b.xhtml", "https://example.com/1b"),
+                ("2b.xhtml", "https://example.com/2"),
+            ],
+        )?;
+        follow_restructure(&book, &old)?;
+
+        let metadata = fs::read_to_string(metadata_file(&book))?;
+        assert!(metadata.contains("/body/DocFragment[4]/body/p[1]/text().0"));
+        let remap = fs::read_to_string(sidecar_dir(&book).join(REMAP_FILE))?;
+        assert!(remap.contains("[\"2.xhtml\"] = \"2b.xhtml\","));
+        Ok(())
+    }
+}
@@ -9,20 +9,54 @@
     clippy::wildcard_enum_match_arm,
     clippy::use_debug
 )]
-#![allow(clippy::multiple_crate_versions)]
+#![allow(clippy::multiple_crate_versions, clippy::non_std_lazy_statics)]
 mod book;
+mod chapter;
+mod collation;
+mod config;
+mod disk;
+mod error;
+mod integrations;
+mod koreader;
+mod maintenance;
+mod manifest;
+mod notifier;
+mod profile;
+mod settings;
 mod source;
+mod stash;
+mod state;
+mod summary;
 mod updater;
 
 use crate::book::Book;
-use crate::updater::UpdateResult;
-use clap::{CommandFactory, Parser, Subcommand};
+use crate::chapter::{Chapter, Format, Selection};
+use crate::config::{
+    Config, Fixtures, ImageBudget, ImageHostRules, ImagePriority, ParallelText, TEMP_MARKER,
+    TEMP_PREFIX,
+};
+use crate::error::Category;
+use crate::integrations::{CalibreWeb, Healthcheck, HealthcheckEvent, Kavita};
+use crate::maintenance::Unavailable;
+use crate::manifest::{Entry, Manifest};
+use crate::notifier::{Discord, Email, RunReport, UpdatedBook};
+use crate::profile::Profile;
+use crate::summary::{Status, Summary};
+use crate::updater::{Rating, SearchSource, UpdateResult};
+use chrono::NaiveDateTime;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
 use colorful::Colorful;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressStyle};
 use lazy_static::lazy_static;
 use rayon::prelude::*;
+use serde::Serialize;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
 use walkdir::WalkDir;
 
 const EPUB: &str = "epub";
@@ -39,143 +73,2228 @@ struct Args {
     #[clap(subcommand)]
     subcommand: Commands,
 
+    /// Profile of the configuration file whose settings are used when not given on the command line
+    /// (flags such as `--cbz=false` turn off the ones it enables).
+    #[clap(short, long)]
+    profile: Option<String>,
+
     /// Path to the work directory.
     #[clap(short, long, default_value = "./", value_hint = clap::ValueHint::DirPath)]
     dir: PathBuf,
 
-    /// Number of threads to use.
-    #[clap(short, long, default_value_t = 8)]
-    nb_threads: usize,
+    /// Number of threads to use.
+    #[clap(short, long, default_value_t = 8)]
+    nb_threads: usize,
+
+    /// Directory where e-books are assembled before being copied to their destination
+    /// (defaults to the system temp directory).
+    #[clap(long, value_hint = clap::ValueHint::DirPath)]
+    temp_dir: Option<PathBuf>,
+
+    /// Maximum number of images embedded in each book.
+    #[clap(long)]
+    max_images: Option<usize>,
+
+    /// Maximum size of the images embedded in each book, in MB.
+    #[clap(long)]
+    max_images_size: Option<u64>,
+
+    /// Which images are kept first when a book exceeds its image budget.
+    #[clap(long, value_enum, default_value_t)]
+    images_priority: ImagePriority,
+
+    /// Also export the image-only chapters of each book as a CBZ archive next to it.
+    #[clap(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    cbz: Option<bool>,
+
+    /// Add a page listing the content warnings of the source (e.g. Royal Road, AO3) after
+    /// the title page.
+    #[clap(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    warnings_page: Option<bool>,
+
+    /// Also write the books larger than this, in MB, as `Title (1 of 2).epub` volumes.
+    #[clap(long, value_name = "MB")]
+    split_size: Option<u64>,
+
+    /// Only download images from this host (and its sub-domains), can be repeated.
+    #[clap(long, value_name = "HOST")]
+    allow_image_host: Vec<String>,
+
+    /// Never download images from this host (and its sub-domains), can be repeated.
+    #[clap(long, value_name = "HOST")]
+    deny_image_host: Vec<String>,
+
+    /// Leave out the Royal Road chapters whose title matches this regular expression (case
+    /// insensitive), e.g. `announcement|poll|hiatus`, can be repeated.
+    #[clap(long, value_name = "PATTERN")]
+    skip_chapter: Vec<String>,
+
+    /// Add the chapters of `<book>.original.epub`, the original text of a translated book, to
+    /// it : each after its translation (`interleave`) or all at the end (`append`).
+    #[clap(long, value_enum)]
+    parallel_text: Option<ParallelText>,
+
+    /// Be extra polite by following the `Crawl-delay` of each site's robots.txt.
+    #[clap(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    respect_crawl_delay: Option<bool>,
+
+    /// Check the parsing against bundled known-good pages first, and leave the books untouched
+    /// (no update, rerender nor migration) if it is broken.
+    #[clap(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    self_test: Option<bool>,
+
+    /// Maintain the `KOReader` sidecars (`.sdr` directories) of the books.
+    #[clap(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    koreader: Option<bool>,
+
+    /// Skip the books of this host (and its sub-domains) as it is down for maintenance, can be repeated.
+    #[clap(long, value_name = "HOST")]
+    maintenance_host: Vec<String>,
+
+    /// Stop attempting the books of a host after this many failed in a row (0 to never stop).
+    #[clap(long, default_value_t = 3, value_name = "N")]
+    max_host_failures: u32,
+
+    /// Give this option (`key=value`, e.g. `is_adult=true`) to `FanFicFare`, can be repeated.
+    #[clap(long, value_name = "KEY=VALUE")]
+    site_option: Vec<String>,
+
+    /// Stop `FanFicFare` once it ran for this many seconds on a book (0 to never stop).
+    #[clap(long, default_value_t = 600, value_name = "SECONDS")]
+    fanficfare_timeout: u64,
+
+    /// Download this many chapters of a book at the same time (native sources).
+    #[clap(long, default_value_t = 8, value_name = "N")]
+    chapter_concurrency: usize,
+
+    /// URL of a Kavita server to ask for a library scan once books changed.
+    #[clap(long, requires_all = ["kavita_api_key", "kavita_library_id"])]
+    kavita_url: Option<String>,
+
+    /// API key of the Kavita user.
+    #[clap(long, env = "AUTEBOOKS_KAVITA_API_KEY", hide_env_values = true)]
+    kavita_api_key: Option<String>,
+
+    /// Id of the Kavita library containing the work directory.
+    #[clap(long)]
+    kavita_library_id: Option<u32>,
+
+    /// URL of a Calibre-Web server to upload the changed books to.
+    #[clap(long, requires_all = ["calibre_web_user", "calibre_web_password"])]
+    calibre_web_url: Option<String>,
+
+    /// Calibre-Web user, who must be allowed to upload.
+    #[clap(long)]
+    calibre_web_user: Option<String>,
+
+    /// Password of the Calibre-Web user.
+    #[clap(long, env = "AUTEBOOKS_CALIBRE_WEB_PASSWORD", hide_env_values = true)]
+    calibre_web_password: Option<String>,
+
+    /// Which books are listed in the text summary of an update, the JSON outputs list them all.
+    #[clap(long, value_enum, default_value_t)]
+    summary: summary::Verbosity,
+
+    /// Layout of the summary of an update.
+    #[clap(long, value_enum, default_value_t)]
+    summary_format: summary::Format,
+
+    /// Colors and characters of the lines printed for each book.
+    #[clap(long, value_enum, default_value_t)]
+    summary_theme: summary::Theme,
+
+    /// Print the outcome of each book of `update`, `add` and `check`, and the books of `list`, as
+    /// JSON, for scripts.
+    #[clap(long, value_enum, default_value_t)]
+    output: summary::Output,
+
+    /// SMTP server (using STARTTLS) through which a digest of each update is emailed.
+    #[clap(long, requires_all = ["smtp_user", "smtp_password", "email_from", "email_to"])]
+    smtp_server: Option<String>,
+
+    #[clap(long, default_value_t = 587)]
+    smtp_port: u16,
+
+    #[clap(long)]
+    smtp_user: Option<String>,
+
+    #[clap(long, env = "AUTEBOOKS_SMTP_PASSWORD", hide_env_values = true)]
+    smtp_password: Option<String>,
+
+    /// Sender of the digest email.
+    #[clap(long)]
+    email_from: Option<String>,
+
+    /// Recipient of the digest email.
+    #[clap(long)]
+    email_to: Option<String>,
+
+    /// Discord webhook to which each updated book is posted.
+    #[clap(long, env = "AUTEBOOKS_DISCORD_WEBHOOK", hide_env_values = true)]
+    discord_webhook: Option<String>,
+
+    /// healthchecks.io-style URL pinged when an update starts, succeeds or fails.
+    #[clap(long, value_name = "URL")]
+    healthcheck_url: Option<String>,
+
+    /// Events for which the healthcheck URL is pinged.
+    #[clap(long, value_enum, value_delimiter = ',', default_values_t = HealthcheckEvent::all())]
+    healthcheck_events: Vec<HealthcheckEvent>,
+
+    /// Store every HTTP response in this directory (for development).
+    #[clap(long, value_name = "DIR", conflicts_with = "replay_fixtures")]
+    record_fixtures: Option<PathBuf>,
+
+    /// Answer every HTTP request from the responses stored in this directory, without network access.
+    #[clap(long, value_name = "DIR")]
+    replay_fixtures: Option<PathBuf>,
+}
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Adds books to the work directory, based on the URL(s) given.
+    Add {
+        /// URLs of the books, of Royal Road author profiles and favorites to add all their fictions,
+        /// or directories of numbered Markdown or HTML chapters
+        urls: Vec<String>,
+
+        /// Skip the works with this content rating, can be repeated
+        #[clap(long, value_enum, value_name = "RATING")]
+        exclude_rating: Vec<Rating>,
+
+        /// Only write the metadata and title page of the books (native sources only), their
+        /// chapters are downloaded by the next update
+        #[clap(long)]
+        stub: bool,
+    },
+
+    /// Update specific books, based on path(s) given,
+    /// if no path is given it will update the work directory.
+    Update {
+        /// List of directories containing books (or books, or book aliases) to update
+        paths: Vec<PathBuf>,
+
+        /// Stash books which contains more chapters than source in the folder defined in `stash_dir`
+        /// and recreate them from source
+        #[clap(short, long)]
+        stash: bool,
+
+        /// The directory where stashed books are stored (books in this folder are excuded from updates).
+        /// It is relative to the update path.
+        #[clap(short = 'd', long, default_value = "./stashed", value_hint = clap::ValueHint::DirPath)]
+        stash_dir: PathBuf,
+
+        /// Only stash the chapters and metadata which differ from the recreated book, rather than
+        /// the whole book (restored by `restore` until the book is next updated)
+        #[clap(long, requires = "stash")]
+        delta_stash: bool,
+
+        /// Keep cached images which are no longer used by their book
+        #[clap(long)]
+        keep_orphans: bool,
+
+        /// Download cached images again if they changed at their source
+        #[clap(long)]
+        refresh_images: bool,
+
+        /// Leave out the new chapters which repeat the chapter before them (reposts under a new id),
+        /// they are only reported otherwise
+        #[clap(long)]
+        dedupe_chapters: bool,
+
+        /// Fetch the cover with the native backend when `FanFicFare` could not update it
+        #[clap(long)]
+        retry_cover_natively: bool,
+
+        /// Update the books modified by something else (e.g. edited in Calibre) since they were
+        /// last written, losing those modifications
+        #[clap(long)]
+        overwrite_external_changes: bool,
+
+        /// Instead of updating, cross-check the chapters of each book against the complete list of
+        /// the source, reporting missing, duplicated and out-of-order chapters
+        #[clap(long)]
+        audit: bool,
+
+        /// Repair the inconsistencies found by the audit
+        #[clap(long, requires = "audit")]
+        fix: bool,
+    },
+
+    /// Show which books have new chapters and how many, without downloading nor rewriting them.
+    Check {
+        /// List of directories containing books (or books, or book aliases) to check
+        paths: Vec<PathBuf>,
+
+        /// The directory where stashed books are stored (books in this folder are excuded).
+        /// It is relative to the given path.
+        #[clap(short = 'd', long, default_value = "./stashed", value_hint = clap::ValueHint::DirPath)]
+        stash_dir: PathBuf,
+    },
+
+    /// Rewrite books with the current cleaning rules and stylesheet, without downloading anything.
+    Rerender {
+        /// List of directories containing books (or books, or book aliases) to rewrite
+        paths: Vec<PathBuf>,
+
+        /// The directory where stashed books are stored (books in this folder are excuded).
+        /// It is relative to the given path.
+        #[clap(short = 'd', long, default_value = "./stashed", value_hint = clap::ValueHint::DirPath)]
+        stash_dir: PathBuf,
+    },
+
+    /// Convert the books written by rr-to-epub so that they can be updated.
+    Migrate {
+        /// List of directories containing books (or books) to convert
+        paths: Vec<PathBuf>,
+
+        /// The directory where stashed books are stored (books in this folder are excuded).
+        /// It is relative to the given path.
+        #[clap(short = 'd', long, default_value = "./stashed", value_hint = clap::ValueHint::DirPath)]
+        stash_dir: PathBuf,
+    },
+
+    /// List the books of the work directory with their source, chapters and last chapter date.
+    List {
+        /// Directories containing books to list, the work directory if none
+        paths: Vec<PathBuf>,
+
+        #[clap(short, long, value_enum, default_value_t)]
+        sort: ListOrder,
+
+        /// The directory where stashed books are stored (books in this folder are excuded).
+        /// It is relative to the given path.
+        #[clap(short = 'd', long, default_value = "./stashed", value_hint = clap::ValueHint::DirPath)]
+        stash_dir: PathBuf,
+    },
+
+    /// Search the fictions of a source by their title, and print them with their URL.
+    Search {
+        query: String,
+
+        #[clap(long, value_enum, default_value_t)]
+        source: SearchSource,
+
+        /// Add the N-th result to the work directory, can be repeated
+        #[clap(long, value_name = "N")]
+        add: Vec<usize>,
+    },
+
+    /// Make a book written by another tool updatable, by telling where it comes from.
+    Adopt {
+        #[clap(value_hint = clap::ValueHint::FilePath)]
+        file: PathBuf,
+
+        /// URL of the web novel
+        #[clap(long)]
+        url: String,
+    },
+
+    /// Check the books of the work directory against the checksums recorded when they were written,
+    /// to detect corruption or external modifications.
+    VerifyLibrary {
+        /// Replace mismatching books with their latest stashed version
+        #[clap(short, long)]
+        restore: bool,
+
+        /// Record the current checksum of mismatching books and forget missing ones
+        #[clap(short, long, conflicts_with = "restore")]
+        accept: bool,
+
+        /// The directory where stashed books are stored.
+        /// It is relative to the work directory.
+        #[clap(short = 'd', long, default_value = "./stashed", value_hint = clap::ValueHint::DirPath)]
+        stash_dir: PathBuf,
+    },
+
+    /// Put back the latest stashed version of a book (given by its path or alias) in its place.
+    /// A differential stash is rebuilt from the book it was stashed for, so it must be restored
+    /// before that book is updated.
+    Restore {
+        book: PathBuf,
+
+        /// The directory where stashed books are stored.
+        /// It is relative to the work directory.
+        #[clap(short = 'd', long, default_value = "./stashed", value_hint = clap::ValueHint::DirPath)]
+        stash_dir: PathBuf,
+    },
+
+    /// Manage the short names which can be given instead of a book's path.
+    Alias {
+        #[clap(subcommand)]
+        action: AliasAction,
+    },
+
+    /// Show the details of a book (given by its path or alias).
+    Info { book: PathBuf },
+
+    /// Delete a book (given by its path, alias or source URL) with its cached images, volumes and
+    /// CBZ export, and what the library index records about it (checksum, aliases and note).
+    /// Its reading order and original text are moved to the stash.
+    Remove {
+        book: PathBuf,
+
+        /// The directory where stashed books are stored (books in this folder are excuded).
+        /// It is relative to the work directory.
+        #[clap(short = 'd', long, default_value = "./stashed", value_hint = clap::ValueHint::DirPath)]
+        stash_dir: PathBuf,
+    },
+
+    /// Show, set or clear the note of a book (given by its path or alias), which is kept across
+    /// updates, e.g. "waiting for the rewrite to finish".
+    Note {
+        book: PathBuf,
+
+        /// New note of the book
+        text: Option<String>,
+
+        /// Remove the note of the book
+        #[clap(long, conflicts_with = "text")]
+        clear: bool,
+    },
+
+    /// Open a book (given by its path or alias) in the default EPUB reader.
+    Open {
+        book: PathBuf,
+
+        /// Command used to open the book instead of the system default
+        #[clap(long, env = "AUTEBOOKS_READER")]
+        with: Option<String>,
+    },
+
+    /// Extract chapters of a book (given by its path or alias).
+    Chapter {
+        book: PathBuf,
+
+        /// Chapter number (`5`), range of numbers (`3-7`, `3-`) or chapter identifier
+        chapters: Selection,
+
+        #[clap(short, long, value_enum, default_value_t)]
+        format: Format,
+
+        /// Write each chapter to a file of this directory instead of stdout
+        #[clap(short, long, value_hint = clap::ValueHint::DirPath)]
+        output_dir: Option<PathBuf>,
+    },
+
+    /// Download a single chapter with the native scraper and print it, e.g. to check selectors.
+    FetchChapter {
+        url: String,
+
+        #[clap(short, long, value_enum, default_value_t)]
+        format: Format,
+    },
+
+    /// Write the index, configuration, settings files and cache metadata (not the books)
+    /// to an archive, to move the setup to another machine.
+    ExportState {
+        #[clap(value_hint = clap::ValueHint::FilePath)]
+        archive: PathBuf,
+    },
+
+    /// Restore an archive written by `export-state`.
+    ImportState {
+        #[clap(value_hint = clap::ValueHint::FilePath)]
+        archive: PathBuf,
+
+        /// Replace the existing files
+        #[clap(short, long)]
+        force: bool,
+    },
+
+    /// Sign in to Royal Road, for the advance chapters and the follow list, the session is kept
+    /// for the next runs.
+    Login {
+        /// Email of the account, else the one of the configuration file
+        #[clap(long)]
+        username: Option<String>,
+
+        /// Password of the account, else the one of the configuration file
+        #[clap(long, env = "AUTEBOOKS_ROYALROAD_PASSWORD", hide_env_values = true)]
+        password: Option<String>,
+    },
+
+    /// Copy the books written by the last run to an rclone remote.
+    Sync {
+        /// rclone remote (and path) to copy the books to, e.g. `drive:Books`
+        #[clap(long, env = "AUTEBOOKS_RCLONE_REMOTE")]
+        remote: String,
+
+        /// Only show what would be transferred
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Bandwidth limit passed to rclone, e.g. `1M`
+        #[clap(long, value_name = "RATE")]
+        bwlimit: Option<String>,
+    },
+
+    /// List the supported sources, what they support and the URLs they handle.
+    Sources {
+        /// Only show the source which handles this URL
+        url: Option<String>,
+    },
+
+    /// Show the requests made and the data downloaded by the previous runs, per month and host.
+    Stats {
+        /// Only show this month, e.g. `2026-10`
+        #[clap(long, value_name = "YYYY-MM")]
+        month: Option<String>,
+    },
+
+    /// Recursively remove any 0 bytes epub in provided path(s)
+    Clean { paths: Vec<PathBuf> },
+
+    /// Generate a SHELL completion script and print to stdout
+    Completions { shell: clap_complete::Shell },
+}
+
+#[derive(Debug, Default, Clone, Copy, clap::ValueEnum)]
+enum ListOrder {
+    #[default]
+    Title,
+    /// Most chapters first
+    Chapters,
+    /// Most recently updated first
+    Updated,
+    Source,
+}
+
+#[derive(Subcommand, Debug)]
+enum AliasAction {
+    /// Give a short name to a book of the work directory.
+    Set {
+        alias: String,
+        #[clap(value_hint = clap::ValueHint::FilePath)]
+        path: PathBuf,
+    },
+    /// Remove an alias, the book itself is left untouched.
+    Remove { alias: String },
+    /// List the aliases and the books they refer to.
+    List,
+}
+
+struct FileToUpdate {
+    file_path: walkdir::DirEntry,
+    stash_path: PathBuf,
+}
+
+macro_rules! summary {
+    ($s:expr, $book_name:expr, $color:ident) => {{
+        let line = summary::line(
+            &$s.to_string(),
+            colorful::Color::$color,
+            &$book_name.to_string(),
+            0,
+            "",
+        );
+        format!("{line}\n")
+    }};
+}
+
+#[allow(clippy::too_many_lines)]
+fn main() {
+    let args = Args::parse_with_profile();
+    setup_nb_threads(args.nb_threads);
+    Config {
+        temp_dir: args.temp_dir,
+        keep_orphans: matches!(
+            args.subcommand,
+            Commands::Update {
+                keep_orphans: true,
+                ..
+            }
+        ),
+        refresh_images: matches!(
+            args.subcommand,
+            Commands::Update {
+                refresh_images: true,
+                ..
+            }
+        ),
+        delta_stash: matches!(
+            args.subcommand,
+            Commands::Update {
+                delta_stash: true,
+                ..
+            }
+        ),
+        dedupe_chapters: matches!(
+            args.subcommand,
+            Commands::Update {
+                dedupe_chapters: true,
+                ..
+            }
+        ),
+        retry_cover_natively: matches!(
+            args.subcommand,
+            Commands::Update {
+                retry_cover_natively: true,
+                ..
+            }
+        ),
+        overwrite_external_changes: matches!(
+            args.subcommand,
+            Commands::Update {
+                overwrite_external_changes: true,
+                ..
+            }
+        ),
+        image_budget: ImageBudget {
+            max_count: args.max_images,
+            max_size: args
+                .max_images_size
+                .map(|mb| mb.saturating_mul(1024 * 1024)),
+            priority: args.images_priority,
+        },
+        export_cbz: args.cbz.unwrap_or_default(),
+        warnings_page: args.warnings_page.unwrap_or_default(),
+        split_size: args.split_size.map(|mb| mb.saturating_mul(1024 * 1024)),
+        image_hosts: ImageHostRules {
+            allow: args.allow_image_host,
+            deny: args.deny_image_host,
+        },
+        skip_chapters: args.skip_chapter,
+        parallel_text: args.parallel_text,
+        respect_crawl_delay: args.respect_crawl_delay.unwrap_or_default(),
+        koreader: args.koreader.unwrap_or_default(),
+        maintenance_hosts: args.maintenance_host,
+        max_host_failures: args.max_host_failures,
+        site_options: args.site_option,
+        fanficfare_timeout: (args.fanficfare_timeout > 0)
+            .then(|| Duration::from_secs(args.fanficfare_timeout)),
+        chapter_concurrency: args.chapter_concurrency,
+        kavita: args
+            .kavita_url
+            .zip(args.kavita_api_key)
+            .zip(args.kavita_library_id)
+            .map(|((url, api_key), library_id)| Kavita {
+                url,
+                api_key,
+                library_id,
+            }),
+        calibre_web: args
+            .calibre_web_url
+            .zip(args.calibre_web_user)
+            .zip(args.calibre_web_password)
+            .map(|((url, username), password)| CalibreWeb {
+                url,
+                username,
+                password,
+            }),
+        email: args
+            .smtp_server
+            .zip(args.smtp_user)
+            .zip(args.smtp_password)
+            .zip(args.email_from.zip(args.email_to))
+            .map(|(((server, username), password), (from, to))| Email {
+                server,
+                port: args.smtp_port,
+                username,
+                password,
+                from,
+                to,
+            }),
+        discord: args
+            .discord_webhook
+            .map(|webhook_url| Discord { webhook_url }),
+        summary: summary::Options {
+            verbosity: args.summary,
+            format: args.summary_format,
+            theme: args.summary_theme,
+            output: args.output,
+        },
+        // Only the updates are scheduled, the other commands would hide how they went.
+        healthcheck: args
+            .healthcheck_url
+            .filter(|_| matches!(args.subcommand, Commands::Update { .. }))
+            .map(|url| Healthcheck {
+                url,
+                events: args.healthcheck_events,
+            }),
+        fixtures: args
+            .record_fixtures
+            .map(Fixtures::Record)
+            .or_else(|| args.replay_fixtures.map(Fixtures::Replay)),
+    }
+    .init();
+    integrations::ping(HealthcheckEvent::Start);
+    remove_orphaned_temp_files();
+    let work_dir = args.dir;
+
+    let rewrites_books = matches!(
+        args.subcommand,
+        Commands::Update { .. } | Commands::Rerender { .. } | Commands::Migrate { .. }
+    );
+    if args.self_test.unwrap_or_default() && rewrites_books {
+        if let Err(e) = updater::self_test() {
+            eprintln!("Self-test failed, the books are left untouched : {e}");
+            integrations::ping(HealthcheckEvent::Failure);
+            return;
+        }
+    }
+
+    let success = match args.subcommand {
+        Commands::Add {
+            urls,
+            exclude_rating,
+            stub,
+        } => create_books(work_dir.as_path(), &urls, &exclude_rating, stub),
+        Commands::Update {
+            mut paths,
+            stash,
+            stash_dir,
+            audit,
+            fix,
+            ..
+        } => {
+            if paths.is_empty() {
+                paths.push(work_dir.clone());
+            }
+            let paths = resolve_aliases(&work_dir, paths);
+
+            let book_files: Vec<FileToUpdate> = paths
+                .into_iter()
+                .flat_map(|p| get_book_files(&p, &p.join(&stash_dir)))
+                .collect();
+
+            if audit {
+                audit_books(&book_files, fix, &work_dir)
+            } else {
+                update_books(&book_files, stash, &work_dir)
+            }
+        }
+        Commands::Check {
+            mut paths,
+            stash_dir,
+        } => {
+            if paths.is_empty() {
+                paths.push(work_dir.clone());
+            }
+            let book_files: Vec<FileToUpdate> = resolve_aliases(&work_dir, paths)
+                .into_iter()
+                .flat_map(|p| get_book_files(&p, &p.join(&stash_dir)))
+                .collect();
+            check_books(&book_files)
+        }
+        Commands::Rerender {
+            mut paths,
+            stash_dir,
+        } => {
+            if paths.is_empty() {
+                paths.push(work_dir.clone());
+            }
+            let book_files: Vec<FileToUpdate> = resolve_aliases(&work_dir, paths)
+                .into_iter()
+                .flat_map(|p| get_book_files(&p, &p.join(&stash_dir)))
+                .collect();
+            rerender_books(&book_files, &work_dir)
+        }
+        Commands::Migrate {
+            mut paths,
+            stash_dir,
+        } => {
+            if paths.is_empty() {
+                paths.push(work_dir.clone());
+            }
+            let book_files: Vec<FileToUpdate> = resolve_aliases(&work_dir, paths)
+                .into_iter()
+                .flat_map(|p| get_book_files(&p, &p.join(&stash_dir)))
+                .collect();
+            migrate_books(&book_files, &work_dir)
+        }
+        Commands::List {
+            mut paths,
+            sort,
+            stash_dir,
+        } => {
+            if paths.is_empty() {
+                paths.push(work_dir.clone());
+            }
+            let book_files: Vec<FileToUpdate> = resolve_aliases(&work_dir, paths)
+                .into_iter()
+                .flat_map(|p| get_book_files(&p, &p.join(&stash_dir)))
+                .collect();
+            list_books(&book_files, sort)
+        }
+        Commands::Search { query, source, add } => search_fictions(&work_dir, &query, source, &add),
+        Commands::Adopt { file, url } => {
+            let file = resolve_aliases(&work_dir, vec![file]).remove(0);
+            adopt_book(&file, &url, &work_dir)
+        }
+        Commands::VerifyLibrary {
+            restore,
+            accept,
+            stash_dir,
+        } => verify_library(&work_dir, &work_dir.join(stash_dir), restore, accept),
+        Commands::Restore { book, stash_dir } => {
+            let book = resolve_aliases(&work_dir, vec![book]).remove(0);
+            restore_book(&work_dir, &work_dir.join(stash_dir), &book)
+        }
+        Commands::Alias { action } => manage_aliases(&work_dir, action),
+        Commands::Info { book } => {
+            let book = resolve_aliases(&work_dir, vec![book]).remove(0);
+            print_info(&work_dir, &book)
+        }
+        Commands::Remove { book, stash_dir } => {
+            remove_book(&work_dir, &work_dir.join(stash_dir), book)
+        }
+        Commands::Note { book, text, clear } => {
+            let book = resolve_aliases(&work_dir, vec![book]).remove(0);
+            manage_note(&work_dir, &book, text.as_deref(), clear)
+        }
+        Commands::Open { book, with } => {
+            let book = resolve_aliases(&work_dir, vec![book]).remove(0);
+            open_book(&book, with.as_deref())
+        }
+        Commands::Chapter {
+            book,
+            chapters,
+            format,
+            output_dir,
+        } => {
+            let book = resolve_aliases(&work_dir, vec![book]).remove(0);
+            extract_chapters(&book, &chapters, format, output_dir.as_deref())
+        }
+        Commands::FetchChapter { url, format } => fetch_chapter(&url, format),
+        Commands::ExportState { archive } => match state::export(&work_dir, &archive) {
+            Ok(()) => {
+                println!("Exported the state to {}", archive.display());
+                true
+            }
+            Err(e) => {
+                eprintln!("Could not export the state : {e}");
+                false
+            }
+        },
+        Commands::ImportState { archive, force } => {
+            match state::import(&archive, &work_dir, force) {
+                Ok(()) => true,
+                Err(e) => {
+                    eprintln!("Could not import the state : {e}");
+                    false
+                }
+            }
+        }
+        Commands::Sync {
+            remote,
+            dry_run,
+            bwlimit,
+        } => sync_books(&work_dir, &remote, dry_run, bwlimit.as_deref()),
+        Commands::Sources { url } => print_sources(url.as_deref()),
+        Commands::Login { username, password } => login(username, password),
+        Commands::Stats { month } => print_stats(month.as_deref()),
+        Commands::Clean { paths } => {
+            for path in &resolve_aliases(&work_dir, paths) {
+                remove_empty_epub(path);
+            }
+            true
+        }
+        Commands::Completions { shell } => {
+            clap_complete::generate(
+                shell,
+                &mut Args::command(),
+                "autebooks",
+                &mut std::io::stdout(),
+            );
+            true
+        }
+    };
+    if let Err(e) = updater::save_usage() {
+        eprintln!("Could not save the network usage : {e}");
+    }
+    integrations::ping(if success {
+        HealthcheckEvent::Success
+    } else {
+        HealthcheckEvent::Failure
+    });
+}
+
+impl Args {
+    fn parse_with_profile() -> Self {
+        let matches = Self::command().get_matches();
+        let mut args = Self::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        if let Err(e) = profile::check_config_file() {
+            Self::command().error(clap::error::ErrorKind::Io, e).exit();
+        }
+        if let Some(name) = &args.profile {
+            match profile::load(name) {
+                Ok(profile) => args.apply_profile(profile, &matches),
+                Err(e) => Self::command()
+                    .error(clap::error::ErrorKind::InvalidValue, e)
+                    .exit(),
+            }
+        }
+        args
+    }
+
+    /// Fill the settings which were not given on the command line from the profile.
+    fn apply_profile(&mut self, profile: Profile, matches: &ArgMatches) {
+        let from_cli = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+        override_default(&mut self.dir, profile.dir, from_cli("dir"));
+        override_default(
+            &mut self.nb_threads,
+            profile.nb_threads,
+            from_cli("nb_threads"),
+        );
+        self.temp_dir = self.temp_dir.take().or(profile.temp_dir);
+        self.max_images = self.max_images.or(profile.max_images);
+        self.max_images_size = self.max_images_size.or(profile.max_images_size);
+        override_default(
+            &mut self.images_priority,
+            profile.images_priority,
+            from_cli("images_priority"),
+        );
+        self.cbz = self.cbz.or(profile.cbz);
+        self.warnings_page = self.warnings_page.or(profile.warnings_page);
+        self.split_size = self.split_size.or(profile.split_size);
+        override_default(
+            &mut self.allow_image_host,
+            profile.allow_image_host,
+            from_cli("allow_image_host"),
+        );
+        override_default(
+            &mut self.deny_image_host,
+            profile.deny_image_host,
+            from_cli("deny_image_host"),
+        );
+        override_default(
+            &mut self.skip_chapter,
+            profile.skip_chapters,
+            from_cli("skip_chapter"),
+        );
+        self.parallel_text = self.parallel_text.or(profile.parallel_text);
+        self.respect_crawl_delay = self.respect_crawl_delay.or(profile.respect_crawl_delay);
+        self.koreader = self.koreader.or(profile.koreader);
+        self.self_test = self.self_test.or(profile.self_test);
+        override_default(
+            &mut self.maintenance_host,
+            profile.maintenance_host,
+            from_cli("maintenance_host"),
+        );
+        override_default(
+            &mut self.max_host_failures,
+            profile.max_host_failures,
+            from_cli("max_host_failures"),
+        );
+        override_default(
+            &mut self.site_option,
+            profile.site_options,
+            from_cli("site_option"),
+        );
+        override_default(
+            &mut self.fanficfare_timeout,
+            profile.fanficfare_timeout,
+            from_cli("fanficfare_timeout"),
+        );
+        override_default(
+            &mut self.chapter_concurrency,
+            profile.chapter_concurrency,
+            from_cli("chapter_concurrency"),
+        );
+        self.kavita_url = self.kavita_url.take().or(profile.kavita_url);
+        self.kavita_api_key = self.kavita_api_key.take().or(profile.kavita_api_key);
+        self.kavita_library_id = self.kavita_library_id.or(profile.kavita_library_id);
+        self.calibre_web_url = self.calibre_web_url.take().or(profile.calibre_web_url);
+        self.calibre_web_user = self.calibre_web_user.take().or(profile.calibre_web_user);
+        self.calibre_web_password = self
+            .calibre_web_password
+            .take()
+            .or(profile.calibre_web_password);
+        override_default(&mut self.summary, profile.summary, from_cli("summary"));
+        override_default(
+            &mut self.summary_format,
+            profile.summary_format,
+            from_cli("summary_format"),
+        );
+        override_default(
+            &mut self.summary_theme,
+            profile.summary_theme,
+            from_cli("summary_theme"),
+        );
+        override_default(&mut self.output, profile.output, from_cli("output"));
+        self.smtp_server = self.smtp_server.take().or(profile.smtp_server);
+        override_default(
+            &mut self.smtp_port,
+            profile.smtp_port,
+            from_cli("smtp_port"),
+        );
+        self.smtp_user = self.smtp_user.take().or(profile.smtp_user);
+        self.smtp_password = self.smtp_password.take().or(profile.smtp_password);
+        self.email_from = self.email_from.take().or(profile.email_from);
+        self.email_to = self.email_to.take().or(profile.email_to);
+        self.discord_webhook = self.discord_webhook.take().or(profile.discord_webhook);
+        self.healthcheck_url = self.healthcheck_url.take().or(profile.healthcheck_url);
+        override_default(
+            &mut self.healthcheck_events,
+            profile.healthcheck_events,
+            from_cli("healthcheck_events"),
+        );
+    }
+}
+
+/// Replace the default of a setting by the value of the profile, unless it was given on the
+/// command line.
+fn override_default<T>(value: &mut T, profile_value: Option<T>, from_cli: bool) {
+    if let Some(profile_value) = profile_value.filter(|_| !from_cli) {
+        *value = profile_value;
+    }
+}
+
+fn setup_nb_threads(nb_threads: usize) {
+    let custom_rayon_conf = rayon::ThreadPoolBuilder::new()
+        .num_threads(nb_threads)
+        .build_global();
+    if custom_rayon_conf.is_err() {
+        eprintln!(
+            "Could not use custom number of threads ({}), default number ({}) was used",
+            nb_threads,
+            rayon::current_num_threads()
+        );
+    }
+}
+
+/// Report of a newly created book.
+struct CreatedBook {
+    title: String,
+    url: String,
+    path: PathBuf,
+    nb_chapters: Option<usize>,
+    size: Option<u64>,
+}
+
+impl CreatedBook {
+    fn new(book: &Book, url: &str) -> Self {
+        Self {
+            title: book.title.clone(),
+            url: url.to_string(),
+            path: book
+                .path
+                .canonicalize()
+                .unwrap_or_else(|_| book.path.clone()),
+            nb_chapters: book.nb_chapters(),
+            size: fs::metadata(&book.path).ok().map(|m| m.len()),
+        }
+    }
+}
+
+impl std::fmt::Display for CreatedBook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let nb_chapters = self
+            .nb_chapters
+            .map_or_else(|| String::from("?"), |n| n.to_string());
+        let size = self
+            .size
+            .map_or_else(|| String::from("?"), |s| HumanBytes(s).to_string());
+        write!(
+            f,
+            "{:.50}\n{} ({nb_chapters} chapters, {size})",
+            self.title,
+            self.path.display()
+        )
+    }
+}
+
+/// Line of `autebooks list`.
+#[derive(Serialize)]
+struct ListedBook {
+    title: String,
+    url: String,
+    path: PathBuf,
+    nb_chapters: Option<usize>,
+    /// RFC 3339 date, as recorded in the book.
+    last_chapter: Option<String>,
+}
+
+/// Most chaptered and most recently updated books come first.
+fn sort_listed(books: &mut [ListedBook], order: ListOrder) {
+    books.sort_by(|a, b| match order {
+        ListOrder::Title => collation::compare(&a.title, &b.title),
+        ListOrder::Chapters => b.nb_chapters.cmp(&a.nb_chapters),
+        ListOrder::Updated => b.last_chapter.cmp(&a.last_chapter),
+        ListOrder::Source => a.url.cmp(&b.url),
+    });
+}
+
+fn list_books(book_files: &[FileToUpdate], order: ListOrder) -> bool {
+    let mut books: Vec<ListedBook> = book_files
+        .iter()
+        .map(|f| {
+            let book = Book::new(f.file_path.path());
+            ListedBook {
+                nb_chapters: book.nb_chapters(),
+                last_chapter: book.last_chapter_date(),
+                url: book.url().to_string(),
+                path: book.path,
+                title: book.title,
+            }
+        })
+        .collect();
+    sort_listed(&mut books, order);
+
+    match Config::get().summary.output {
+        summary::Output::Text => (),
+        summary::Output::Json => {
+            return match serde_json::to_string_pretty(&books) {
+                Ok(json) => {
+                    println!("{json}");
+                    true
+                }
+                Err(e) => {
+                    eprintln!("{e}");
+                    false
+                }
+            };
+        }
+        summary::Output::Ndjson => {
+            for book in &books {
+                match serde_json::to_string(book) {
+                    Ok(line) => println!("{line}"),
+                    Err(e) => {
+                        eprintln!("{e}");
+                        return false;
+                    }
+                }
+            }
+            return true;
+        }
+    }
+    let title_width = books
+        .iter()
+        .map(|b| b.title.chars().count())
+        .max()
+        .unwrap_or(0)
+        .min(40);
+    for book in &books {
+        let title: String = book.title.chars().take(title_width).collect();
+        println!(
+            "{title:title_width$}  {:>5}  {:10}  {}",
+            book.nb_chapters
+                .map_or_else(|| String::from("?"), |n| n.to_string()),
+            book.last_chapter
+                .as_deref()
+                .map_or("?", |d| d.get(..10).unwrap_or(d)),
+            book.url
+        );
+    }
+    true
+}
+
+/// Print the fictions matching `query`, then add the results numbered in `to_add`.
+fn search_fictions(dir: &Path, query: &str, source: SearchSource, to_add: &[usize]) -> bool {
+    let results = match updater::search(query, source) {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("Could not search '{query}' : {e}");
+            return false;
+        }
+    };
+    if results.is_empty() {
+        eprintln!("No fiction matches '{query}'");
+    }
+    for (n, result) in results.iter().enumerate() {
+        println!("{:>3}. {}  {}", n + 1, result.title, result.url);
+    }
+
+    let mut urls = Vec::new();
+    for &n in to_add {
+        let Some(result) = n.checked_sub(1).and_then(|i| results.get(i)) else {
+            eprintln!("There is no result {n} to add");
+            return false;
+        };
+        urls.push(result.url.clone());
+    }
+    urls.is_empty() || create_books(dir, &urls, &[], false)
+}
+
+/// The URLs with the author profiles and reading lists replaced by the fictions they list,
+/// and the local directories by their URL.
+fn expand_collections(urls: &[String]) -> Vec<String> {
+    let mut expanded: Vec<String> = Vec::new();
+    for url in urls {
+        let fiction_urls = if updater::listing_url(url).is_some() {
+            match updater::fiction_urls(url) {
+                Ok(fiction_urls) => fiction_urls,
+                Err(e) => {
+                    eprintln!("Could not list the fictions of '{url}' : {e}");
+                    continue;
+                }
+            }
+        } else if let Some(folder_url) = updater::folder_url(url) {
+            vec![folder_url]
+        } else {
+            vec![url.clone()]
+        };
+        for fiction_url in fiction_urls {
+            if !expanded.contains(&fiction_url) {
+                expanded.push(fiction_url);
+            }
+        }
+    }
+    expanded
+}
+
+/// Returns whether every book could be created.
+fn create_books(dir: &Path, urls: &[String], excluded_ratings: &[Rating], stub: bool) -> bool {
+    let urls = expand_collections(urls);
+    let bar = MULTI_PROGRESS.add(get_progress_bar(urls.len() as u64, 1));
+    let manifest = load_manifest(dir);
+    let changed_books = Mutex::new(Vec::new());
+    let failed = AtomicBool::new(false);
+    let summary = Summary::default();
+    // Outcomes are only gathered in the summary when printed as JSON.
+    let show = |path: &Path, title: &str, status: Status, line: String| {
+        if summary::is_machine_readable() {
+            summary.add(&bar, path, title, status);
+        } else {
+            bar.println(line);
+        }
+    };
+
+    urls.par_iter().for_each(|url| {
+        bar.set_prefix(url.clone());
+        if !excluded_ratings.is_empty() {
+            match Book::rating(url) {
+                Ok(Some(rating)) if excluded_ratings.contains(&rating) => {
+                    bar.inc(1);
+                    show(
+                        dir,
+                        url,
+                        Status::Skipped,
+                        summary!(rating.label(), url, Yellow),
+                    );
+                    return;
+                }
+                Ok(_) => (),
+                Err(e) => bar.eprintln(&format!("Could not check the rating of '{url}' : {e}")),
+            }
+        }
+        let book_dir = profile::route_for(url).map_or_else(|| dir.to_path_buf(), |d| dir.join(d));
+        if let Err(e) = fs::create_dir_all(&book_dir) {
+            bar.inc(1);
+            failed.store(true, Ordering::Relaxed);
+            let status = Status::Error(Some(Category::Io), e.to_string());
+            show(&book_dir, url, status, summary!(e, url, Red));
+            return;
+        }
+        let creation_res = if stub {
+            Book::create_stub(&book_dir, url)
+        } else {
+            Book::create(&book_dir, url)
+        };
+        bar.inc(1);
+
+        match creation_res {
+            Ok(book) => {
+                record_in_manifest(&manifest, &book.path);
+                record_change(&changed_books, &book.path);
+                if Config::get().koreader {
+                    if let Err(e) = koreader::create_sidecar(&book.path) {
+                        bar.eprintln(&format!("Could not create the KOReader sidecar : {e}"));
+                    }
+                }
+                let created_book = CreatedBook::new(&book, url);
+                let status = Status::Created {
+                    url: created_book.url.clone(),
+                    nb_chapters: created_book.nb_chapters,
+                    size: created_book.size,
+                };
+                let line = format!("{created_book}\n");
+                show(&created_book.path, &created_book.title, status, line);
+            }
+            Err(e) => {
+                failed.store(true, Ordering::Relaxed);
+                let status = Status::Error(Category::of(&e), e.to_string());
+                show(&book_dir, url, status, summary!(e, url, Red));
+            }
+        }
+    });
+    bar.finish_and_clear();
+    summary.print();
+    save_manifest(manifest);
+    push_changes(changed_books, dir);
+    !failed.into_inner()
+}
+
+/// Returns whether every book could be updated.
+#[allow(clippy::too_many_lines)]
+fn update_books(book_files: &[FileToUpdate], stash: bool, work_dir: &Path) -> bool {
+    let bar = MULTI_PROGRESS.add(get_progress_bar(book_files.len() as u64, 1));
+    let manifest = load_manifest(work_dir);
+    let changed_books = Mutex::new(Vec::new());
+    let failed = AtomicBool::new(false);
+    let summary = Summary::default();
+    let report = Mutex::new(RunReport::default());
+
+    book_files.par_iter().for_each(|file_to_update| {
+        let path = file_to_update.file_path.path();
+        let book = Book::new(path);
+        bar.set_prefix(book.title.clone());
+        let show = |status| summary.add(&bar, path, &book.title, status);
+        let report_error = |e: &eyre::Report| {
+            failed.store(true, Ordering::Relaxed);
+            if let Ok(mut report) = report.lock() {
+                report.errors.push((book.title.clone(), e.to_string()));
+            }
+            show(Status::Error(Category::of(e), e.to_string()));
+        };
+
+        // No new chapter, but the book was rewritten anyway.
+        let refreshed = |status| {
+            record_in_manifest(&manifest, path);
+            record_change(&changed_books, path);
+            show(status);
+        };
+
+        if let Some(unavailable) = maintenance::check(book.url()) {
+            show(match unavailable {
+                Unavailable::Maintenance => Status::SourceDown,
+                Unavailable::Failing => Status::HostFailing,
+                Unavailable::OutsideWindow => Status::Deferred,
+            });
+            bar.inc(1);
+            return;
+        }
+        if !Config::get().overwrite_external_changes && changed_externally(&manifest, path) {
+            show(Status::Conflict);
+            bar.inc(1);
+            return;
+        }
+        // Where each document was, for the positions of KOReader to follow the chapters.
+        let spine = Config::get()
+            .koreader
+            .then(|| koreader::Spine::of_tracked(path))
+            .flatten();
+        let result = book.update(path);
+        if let Some(spine) = &spine {
+            let rewritten = matches!(
+                result,
+                UpdateResult::Updated(_)
+                    | UpdateResult::MetadataUpdated
+                    | UpdateResult::CoverUpdated
+                    | UpdateResult::FormatUpgraded
+            );
+            if rewritten {
+                if let Err(e) = koreader::follow_restructure(path, spine) {
+                    bar.eprintln(&format!("Could not update the KOReader positions : {e}"));
+                }
+            }
+        }
+        for warning in updater::take_warnings(book.url()) {
+            show(Status::Warning(warning));
+        }
+        if let UpdateResult::Error(e) = &result {
+            // A missing or malformed book says nothing about the health of its host.
+            if Category::of(e).is_none_or(Category::is_host_failure) {
+                maintenance::record_failure(book.url());
+            }
+        } else {
+            maintenance::record_success(book.url());
+        }
+
+        match result {
+            UpdateResult::Updated(n) => {
+                record_in_manifest(&manifest, path);
+                record_change(&changed_books, path);
+                if Config::get().koreader {
+                    if let Err(e) = koreader::mark_as_reading(path) {
+                        bar.eprintln(&format!("Could not update the KOReader sidecar : {e}"));
+                    }
+                }
+                if notifier::is_enabled() {
+                    if let Ok(mut report) = report.lock() {
+                        report.updated.push(UpdatedBook {
+                            title: book.title.clone(),
+                            url: book.url().to_string(),
+                            cover_url: book.cover_url(),
+                            nb_new_chapters: n,
+                            new_chapters: last_chapter_titles(path, n),
+                        });
+                    }
+                }
+                show(Status::Updated(n));
+            }
+            UpdateResult::MetadataUpdated => refreshed(Status::MetadataUpdated),
+            UpdateResult::CoverUpdated => refreshed(Status::CoverUpdated),
+            UpdateResult::FormatUpgraded => refreshed(Status::FormatUpgraded),
+            UpdateResult::Skipped => show(Status::Skipped),
+            UpdateResult::MoreChapterThanSource(n) => {
+                show(Status::MoreChapterThanSource(n));
+                if stash {
+                    match book.stash_and_recreate(path, &file_to_update.stash_path) {
+                        Ok(book) => {
+                            record_in_manifest(&manifest, &book.path);
+                            record_change(&changed_books, &book.path);
+                            summary.add(&bar, &book.path, &book.title, Status::Recreated);
+                        }
+                        Err(e) => report_error(&e),
+                    }
+                }
+            }
+            UpdateResult::UpToDate => show(Status::UpToDate),
+            UpdateResult::Unsupported => (),
+            UpdateResult::Error(e) => report_error(&e),
+        }
+        bar.inc(1);
+    });
+    bar.finish_and_clear();
+    summary.print();
+    save_manifest(manifest);
+    push_changes(changed_books, work_dir);
+    if let Ok(report) = report.into_inner() {
+        notifier::notify(&report);
+    }
+    !failed.into_inner()
+}
+
+/// Titles of the last `nb` chapters of the book.
+fn last_chapter_titles(path: &Path, nb: u16) -> Vec<String> {
+    let Ok(chapters) = Chapter::extract_from_epub(path) else {
+        return Vec::new();
+    };
+    let skipped = chapters.len().saturating_sub(nb.into());
+    chapters
+        .into_iter()
+        .skip(skipped)
+        .map(|c| c.title)
+        .collect()
+}
+
+fn record_change(changed_books: &Mutex<Vec<PathBuf>>, file: &Path) {
+    if let Ok(mut changed_books) = changed_books.lock() {
+        changed_books.push(file.to_path_buf());
+    }
+}
+
+fn push_changes(changed_books: Mutex<Vec<PathBuf>>, work_dir: &Path) {
+    if let Ok(changed_books) = changed_books.into_inner() {
+        if let Err(e) = manifest::write_changes(work_dir, &changed_books) {
+            eprintln!("Could not write the list of changed files : {e}");
+        }
+        integrations::push_changes(&changed_books);
+    }
+}
+
+/// Returns whether every supported book could be checked.
+fn check_books(book_files: &[FileToUpdate]) -> bool {
+    let bar = MULTI_PROGRESS.add(get_progress_bar(book_files.len() as u64, 1));
+    let failed = AtomicBool::new(false);
+    let pending = AtomicUsize::new(0);
+    let summary = Summary::default();
+
+    book_files.par_iter().for_each(|file_to_update| {
+        let book = Book::new(file_to_update.file_path.path());
+        bar.set_prefix(book.title.clone());
+        // Outcomes are only gathered in the summary when printed as JSON.
+        let show = |status: Status, line: String| {
+            if summary::is_machine_readable() {
+                summary.add(&bar, &book.path, &book.title, status);
+            } else {
+                bar.println(line);
+            }
+        };
+
+        match book.check(&book.path) {
+            Ok(0) if summary::is_machine_readable() => {
+                summary.add(&bar, &book.path, &book.title, Status::UpToDate);
+            }
+            Ok(0) => (),
+            Ok(n) => {
+                pending.fetch_add(n, Ordering::Relaxed);
+                show(
+                    Status::Pending(n),
+                    summary!(format!("{n:+}"), book.title, Green),
+                );
+            }
+            Err(e) if e.is::<updater::Unsupported>() => (),
+            Err(e) => {
+                failed.store(true, Ordering::Relaxed);
+                let status = Status::Error(Category::of(&e), e.to_string());
+                show(status, summary!(e, book.title, Red));
+            }
+        }
+        bar.inc(1);
+    });
+    bar.finish_and_clear();
+    if summary::is_machine_readable() {
+        summary.print();
+    } else {
+        println!("{} chapter(s) to download", pending.into_inner());
+    }
+    !failed.into_inner()
+}
+
+/// Returns whether every supported book could be rewritten.
+fn rerender_books(book_files: &[FileToUpdate], work_dir: &Path) -> bool {
+    let bar = MULTI_PROGRESS.add(get_progress_bar(book_files.len() as u64, 1));
+    let manifest = load_manifest(work_dir);
+    let changed_books = Mutex::new(Vec::new());
+    let failed = AtomicBool::new(false);
+
+    book_files.par_iter().for_each(|file_to_update| {
+        let book = Book::new(file_to_update.file_path.path());
+        bar.set_prefix(book.title.clone());
+
+        match book.rerender() {
+            Ok(()) => {
+                record_in_manifest(&manifest, &book.path);
+                record_change(&changed_books, &book.path);
+                bar.println(summary!("Rend", book.title, Green));
+            }
+            Err(e) if e.is::<updater::Unsupported>() => (),
+            Err(e) => {
+                failed.store(true, Ordering::Relaxed);
+                bar.println(summary!(e, book.title, Red));
+            }
+        }
+        bar.inc(1);
+    });
+    bar.finish_and_clear();
+    save_manifest(manifest);
+    push_changes(changed_books, work_dir);
+    !failed.into_inner()
+}
+
+/// Returns whether every book is consistent with its source (or could be fixed).
+fn audit_books(book_files: &[FileToUpdate], fix: bool, work_dir: &Path) -> bool {
+    let bar = MULTI_PROGRESS.add(get_progress_bar(book_files.len() as u64, 1));
+    let manifest = load_manifest(work_dir);
+    let changed_books = Mutex::new(Vec::new());
+    let failed = AtomicBool::new(false);
+
+    book_files.par_iter().for_each(|file_to_update| {
+        let book = Book::new(file_to_update.file_path.path());
+        bar.set_prefix(book.title.clone());
+
+        match book.audit(fix) {
+            Ok(audit) if audit.is_consistent() => (),
+            Ok(audit) => {
+                if audit.fixed {
+                    record_in_manifest(&manifest, &book.path);
+                    record_change(&changed_books, &book.path);
+                    bar.println(summary!("Fix", book.title, Yellow));
+                } else {
+                    failed.store(true, Ordering::Relaxed);
+                    bar.println(summary!("Bad", book.title, Red));
+                }
+                bar.println(audit.to_string());
+            }
+            Err(e) if e.is::<updater::Unsupported>() => (),
+            Err(e) => {
+                failed.store(true, Ordering::Relaxed);
+                bar.println(summary!(e, book.title, Red));
+            }
+        }
+        bar.inc(1);
+    });
+    bar.finish_and_clear();
+    save_manifest(manifest);
+    push_changes(changed_books, work_dir);
+    !failed.into_inner()
+}
+
+/// Returns whether the book could be adopted.
+fn adopt_book(file: &Path, url: &str, work_dir: &Path) -> bool {
+    match Book::adopt(file, url) {
+        Ok(book) => {
+            let manifest = load_manifest(work_dir);
+            record_in_manifest(&manifest, &book.path);
+            save_manifest(manifest);
+            println!("{}", summary!("Adop", book.title, Green));
+            true
+        }
+        Err(e) => {
+            eprintln!("{}", summary!(e, file.display(), Red));
+            false
+        }
+    }
+}
+
+/// Returns whether every book written by rr-to-epub could be converted.
+fn migrate_books(book_files: &[FileToUpdate], work_dir: &Path) -> bool {
+    let bar = MULTI_PROGRESS.add(get_progress_bar(book_files.len() as u64, 1));
+    let manifest = load_manifest(work_dir);
+    let changed_books = Mutex::new(Vec::new());
+    let failed = AtomicBool::new(false);
+
+    book_files.par_iter().for_each(|file_to_update| {
+        let path = file_to_update.file_path.path();
+        let name = path.display();
+        bar.set_prefix(name.to_string());
+
+        match updater::migrate_rr_to_epub(path) {
+            Ok(true) => {
+                record_in_manifest(&manifest, path);
+                record_change(&changed_books, path);
+                bar.println(summary!("Migr", name, Green));
+            }
+            Ok(false) => (),
+            Err(e) => {
+                failed.store(true, Ordering::Relaxed);
+                bar.println(summary!(e, name, Red));
+            }
+        }
+        bar.inc(1);
+    });
+    bar.finish_and_clear();
+    save_manifest(manifest);
+    push_changes(changed_books, work_dir);
+    !failed.into_inner()
+}
+
+fn load_manifest(work_dir: &Path) -> Mutex<Option<Manifest>> {
+    let manifest = Manifest::load(work_dir)
+        .map_err(|e| MULTI_PROGRESS.eprintln(&format!("Could not load the library manifest : {e}")))
+        .ok();
+    Mutex::new(manifest)
+}
+
+fn record_in_manifest(manifest: &Mutex<Option<Manifest>>, file: &Path) {
+    // Hash the file before locking the manifest, for the books to be updated in parallel.
+    let recorded = Entry::compute(file).and_then(|entry| {
+        let Ok(mut guard) = manifest.lock() else {
+            return Ok(());
+        };
+        guard.as_mut().map_or(Ok(()), |m| m.insert(file, entry))
+    });
+    if let Err(e) = recorded {
+        MULTI_PROGRESS.eprintln(&format!(
+            "Could not record the checksum of {} : {e}",
+            file.display()
+        ));
+    }
+}
+
+fn changed_externally(manifest: &Mutex<Option<Manifest>>, file: &Path) -> bool {
+    let entry = manifest
+        .lock()
+        .ok()
+        .and_then(|guard| guard.as_ref()?.entry(file));
+    match entry.map(|entry| entry.changed_externally(file)) {
+        Some(Ok(changed)) => changed,
+        Some(Err(e)) => {
+            MULTI_PROGRESS.eprintln(&format!(
+                "Could not check the checksum of {} : {e}",
+                file.display()
+            ));
+            false
+        }
+        None => false,
+    }
+}
+
+fn save_manifest(manifest: Mutex<Option<Manifest>>) {
+    if let Ok(Some(manifest)) = manifest.into_inner() {
+        if let Err(e) = manifest.save() {
+            eprintln!("Could not save the library manifest : {e}");
+        }
+    }
+}
+
+/// Returns whether the books of the last run could be synced.
+fn sync_books(work_dir: &Path, remote: &str, dry_run: bool, bwlimit: Option<&str>) -> bool {
+    let files = match manifest::read_changes(work_dir) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("{e}");
+            return false;
+        }
+    };
+    if files.is_empty() {
+        println!("No book changed during the last run");
+        return true;
+    }
+    match integrations::rclone_sync(work_dir, &files, remote, dry_run, bwlimit) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("{e}");
+            false
+        }
+    }
 }
-#[derive(Subcommand, Debug)]
-enum Commands {
-    /// Adds books to the work directory, based on the URL(s) given.
-    Add { urls: Vec<String> },
 
-    /// Update specific books, based on path(s) given,
-    /// if no path is given it will update the work directory.
-    Update {
-        /// List of directories containing books to update
-        paths: Vec<PathBuf>,
+/// Returns whether every book matches its recorded checksum, once restored or accepted if asked.
+fn verify_library(work_dir: &Path, stash_dir: &Path, restore: bool, accept: bool) -> bool {
+    let mut manifest = match Manifest::load(work_dir) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            eprintln!("Could not load the library manifest : {e}");
+            return false;
+        }
+    };
 
-        /// Stash books which contains more chapters than source in the folder defined in `stash_dir`
-        /// and recreate them from source
-        #[clap(short, long)]
-        stash: bool,
+    let mut is_consistent = true;
+    for file in get_book_files(&work_dir.to_path_buf(), &stash_dir.to_path_buf()) {
+        let path = file.file_path.path();
+        let name = path.display();
+        let resolved = match manifest.check(path) {
+            Ok(manifest::Status::Valid) => true,
+            Ok(manifest::Status::Unknown) => match manifest.record(path) {
+                Ok(()) => {
+                    println!("{}", summary!("New", name, Blue));
+                    true
+                }
+                Err(e) => {
+                    eprintln!("{}", summary!(e, name, Red));
+                    false
+                }
+            },
+            Ok(manifest::Status::Mismatch) if restore => {
+                // Books stashed before the stash mirrored the sub-directories are at its root.
+                match restore_from_stash(path, &file.stash_path)
+                    .or_else(|_| restore_from_stash(path, stash_dir))
+                {
+                    Ok(stashed) => {
+                        println!("{}", summary!("Rest", stashed.display(), LightGreen));
+                        manifest
+                            .record(path)
+                            .map_err(|e| eprintln!("{}", summary!(e, name, Red)))
+                            .is_ok()
+                    }
+                    Err(e) => {
+                        eprintln!("{}", summary!(e, name, Red));
+                        false
+                    }
+                }
+            }
+            Ok(manifest::Status::Mismatch) if accept => match manifest.record(path) {
+                Ok(()) => {
+                    println!("{}", summary!("Acc", name, Yellow));
+                    true
+                }
+                Err(e) => {
+                    eprintln!("{}", summary!(e, name, Red));
+                    false
+                }
+            },
+            Ok(manifest::Status::Mismatch) => {
+                println!("{}", summary!("Bad", name, Red));
+                false
+            }
+            Err(e) => {
+                eprintln!("{}", summary!(e, name, Red));
+                false
+            }
+        };
+        is_consistent &= resolved;
+    }
 
-        /// The directory where stashed books are stored (books in this folder are excuded from updates).
-        /// It is relative to the update path.
-        #[clap(short = 'd', long, default_value = "./stashed", value_hint = clap::ValueHint::DirPath)]
-        stash_dir: PathBuf,
-    },
+    for missing in manifest.missing_files() {
+        println!("{}", summary!("Miss", missing.display(), Yellow));
+        if accept {
+            manifest.forget(&missing);
+        } else {
+            is_consistent = false;
+        }
+    }
 
-    /// Recursively remove any 0 bytes epub in provided path(s)
-    Clean { paths: Vec<PathBuf> },
+    if let Err(e) = manifest.save() {
+        eprintln!("Could not save the library manifest : {e}");
+        return false;
+    }
+    is_consistent
+}
 
-    /// Generate a SHELL completion script and print to stdout
-    Completions { shell: clap_complete::Shell },
+/// Returns whether the session could be opened.
+fn login(username: Option<String>, password: Option<String>) -> bool {
+    let account = profile::royal_road_account();
+    let username = username.or_else(|| account.and_then(|a| a.username.clone()));
+    let password = password.or_else(|| account.and_then(|a| a.password.clone()));
+    let (Some(username), Some(password)) = (username, password) else {
+        eprintln!("No Royal Road username and password given or configured");
+        return false;
+    };
+    match updater::login(&username, &password) {
+        Ok(()) => {
+            println!("Signed in to Royal Road as {username}");
+            true
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            false
+        }
+    }
 }
 
-struct FileToUpdate {
-    file_path: walkdir::DirEntry,
-    stash_path: PathBuf,
+/// Print the requests and bytes downloaded from each host, by month (only `month` if given).
+/// Returns whether the network usage could be read.
+fn print_stats(month: Option<&str>) -> bool {
+    let stats = match updater::stats() {
+        Ok(stats) => stats,
+        Err(e) => {
+            eprintln!("Could not read the network usage : {e}");
+            return false;
+        }
+    };
+    let months: Vec<_> = stats
+        .iter()
+        .filter(|(m, _)| month.is_none_or(|month| month == m.as_str()))
+        .collect();
+    if months.is_empty() {
+        println!("No request recorded");
+        return true;
+    }
+    for (month, hosts) in months {
+        println!("{}", month.as_str().bold());
+        let mut hosts: Vec<_> = hosts.iter().collect();
+        hosts.sort_by_key(|(_, usage)| std::cmp::Reverse(usage.bytes));
+        let width = hosts.iter().map(|(host, _)| host.len()).max().unwrap_or(0);
+        let mut total = updater::Usage::default();
+        for (host, usage) in hosts {
+            println!(
+                "  {host:width$} : {:>6} requests, {}",
+                usage.requests,
+                HumanBytes(usage.bytes)
+            );
+            total.requests += usage.requests;
+            total.bytes += usage.bytes;
+        }
+        println!(
+            "  {:width$} : {:>6} requests, {}",
+            "total",
+            total.requests,
+            HumanBytes(total.bytes)
+        );
+    }
+    true
 }
 
-macro_rules! summary {
-    ($s:expr, $book_name:expr, $color:ident) => {{
-        let prefix = format!("[{:>+4}]", $s).bold().$color();
-        format!("{} {:.50}\n", prefix, $book_name)
-    }};
+/// Returns whether the URL (if any) is handled by a source.
+fn print_sources(url: Option<&str>) -> bool {
+    let sources = match url {
+        Some(url) => {
+            let Some(capabilities) = source::route(url) else {
+                eprintln!("No source handles {url}");
+                return false;
+            };
+            vec![capabilities]
+        }
+        None => source::providers()
+            .iter()
+            .map(|provider| (provider.capabilities)())
+            .collect(),
+    };
+    for capabilities in sources {
+        let features: Vec<&str> = [
+            (capabilities.auth, "authentication"),
+            (capabilities.early_chapters, "early chapters"),
+            (capabilities.cover_refresh, "cover refresh"),
+            (capabilities.volumes, "volumes"),
+            (capabilities.feed, "feed"),
+            (capabilities.ratings, "content ratings"),
+        ]
+        .into_iter()
+        .filter_map(|(supported, feature)| supported.then_some(feature))
+        .collect();
+        println!(
+            "{} ({}) : {}",
+            capabilities.name.bold(),
+            if profile::is_source_disabled(capabilities.name) {
+                "disabled"
+            } else if capabilities.native {
+                "native"
+            } else {
+                "external"
+            },
+            if features.is_empty() {
+                String::from("-")
+            } else {
+                features.join(", ")
+            }
+        );
+        for pattern in capabilities.url_patterns {
+            println!("    {pattern}");
+        }
+    }
+    true
 }
 
-fn main() {
-    let args = Args::parse();
-    setup_nb_threads(args.nb_threads);
-    let work_dir = args.dir;
+/// Returns whether the aliases could be saved.
+fn manage_aliases(work_dir: &Path, action: AliasAction) -> bool {
+    let mut manifest = match Manifest::load(work_dir) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            eprintln!("Could not load the library manifest : {e}");
+            return false;
+        }
+    };
 
-    match args.subcommand {
-        Commands::Add { urls } => create_books(work_dir.as_path(), &urls),
-        Commands::Update {
-            mut paths,
-            stash,
-            stash_dir,
-        } => {
-            if paths.is_empty() {
-                paths.push(work_dir);
+    match action {
+        AliasAction::Set { alias, path } => {
+            if let Err(e) = manifest.set_alias(&alias, &path) {
+                eprintln!("{e}");
+                return false;
             }
+        }
+        AliasAction::Remove { alias } => {
+            if !manifest.remove_alias(&alias) {
+                eprintln!("No alias named '{alias}'");
+                return false;
+            }
+        }
+        AliasAction::List => {
+            let mut aliases: Vec<_> = manifest.aliases().collect();
+            aliases.sort_by(|(a, _), (b, _)| collation::compare(a, b));
+            for (alias, path) in aliases {
+                println!("{alias} -> {}", path.display());
+            }
+            return true;
+        }
+    }
 
-            let book_files: Vec<FileToUpdate> = paths
-                .into_iter()
-                .flat_map(|p| get_book_files(&p, &p.join(&stash_dir)))
-                .collect();
+    if let Err(e) = manifest.save() {
+        eprintln!("Could not save the library manifest : {e}");
+        return false;
+    }
+    true
+}
+
+/// Replace the paths which do not exist but are book aliases by the path of their book.
+fn resolve_aliases(work_dir: &Path, paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    if paths.iter().all(|p| p.exists()) {
+        return paths;
+    }
+    let manifest = Manifest::load(work_dir).ok();
+    paths
+        .into_iter()
+        .map(|p| {
+            if p.exists() {
+                return p;
+            }
+            p.to_str()
+                .and_then(|alias| manifest.as_ref()?.resolve_alias(alias))
+                .unwrap_or(p)
+        })
+        .collect()
+}
+
+/// Returns whether the book could be read.
+fn print_info(work_dir: &Path, path: &Path) -> bool {
+    if !path.is_file() {
+        eprintln!("No book at {}", path.display());
+        return false;
+    }
+    let book = Book::new(path);
+    let unknown = || String::from("?");
+    println!("Title       : {}", book.title);
+    println!("Source      : {}", book.url());
+    println!("Path        : {}", path.display());
+    println!(
+        "Chapters    : {}",
+        book.nb_chapters().map_or_else(unknown, |n| n.to_string())
+    );
+    println!(
+        "Size        : {}",
+        fs::metadata(path).map_or_else(|_| unknown(), |m| HumanBytes(m.len()).to_string())
+    );
+    println!(
+        "Written by  : {}",
+        book.provenance().unwrap_or_else(unknown)
+    );
+    if let Some(note) = Manifest::load(work_dir)
+        .ok()
+        .and_then(|m| m.note(path).map(String::from))
+    {
+        println!("Note        : {note}");
+    }
+    true
+}
+
+/// Path of `book`, given by its path or alias, or by its source URL which is looked for among
+/// the books of the work directory.
+fn locate_book(work_dir: &Path, stash_dir: &Path, book: PathBuf) -> Option<PathBuf> {
+    let Some(url) = book.to_str().filter(|b| b.contains("://")) else {
+        return Some(resolve_aliases(work_dir, vec![book]).remove(0));
+    };
+    let found = get_book_files(&work_dir.to_path_buf(), &stash_dir.to_path_buf())
+        .into_iter()
+        .map(|f| f.file_path.into_path())
+        .find(|p| Book::new(p).url() == url);
+    if found.is_none() {
+        eprintln!("No book of {url} in {}", work_dir.display());
+    }
+    found
+}
+
+/// Directory of the stash mirroring the one of `book` in the work directory.
+fn mirrored_stash_dir(work_dir: &Path, stash_dir: &Path, book: &Path) -> PathBuf {
+    book.parent()
+        .and_then(|p| p.strip_prefix(work_dir).ok())
+        .map_or_else(
+            || stash_dir.to_path_buf(),
+            |sub_dir| stash_dir.join(sub_dir),
+        )
+}
+
+/// Move `file`, kept next to the book at `book`, to `stash_dir` with the time it is stashed at,
+/// like the stashed books (e.g. `Title_2026-01-01_10h00.order`).
+fn stash_companion(book: &Path, file: &Path, stash_dir: &Path) -> eyre::Result<PathBuf> {
+    let stem = book
+        .file_stem()
+        .ok_or_else(|| eyre::eyre!("Could not retrieve the book's filename."))?
+        .to_string_lossy();
+    let filename = file
+        .file_name()
+        .ok_or_else(|| eyre::eyre!("Could not retrieve the filename."))?
+        .to_string_lossy();
+    let rest = filename.strip_prefix(stem.as_ref()).unwrap_or(&filename);
+    let timestamp = chrono::Utc::now().format(updater::STASH_TIMESTAMP_FORMAT);
+    fs::create_dir_all(stash_dir)?;
+    let stashed = stash_dir.join(format!("{stem}{timestamp}{rest}"));
+    fs::rename(file, &stashed)?;
+    Ok(stashed)
+}
 
-            update_books(&book_files, stash);
+/// Returns whether the book could be removed.
+fn remove_book(work_dir: &Path, stash_dir: &Path, book: PathBuf) -> bool {
+    let Some(path) = locate_book(work_dir, stash_dir, book) else {
+        return false;
+    };
+    if !path.is_file() {
+        eprintln!("No book at {}", path.display());
+        return false;
+    }
+
+    let book = Book::new(&path);
+    // Only the books written natively have cached images.
+    if source::route(book.url()).is_some_and(|capabilities| capabilities.native) {
+        if let Err(e) = updater::remove_cache(book.url()) {
+            eprintln!("Could not remove the cached images : {e}");
+        }
+    }
+    if let Err(e) = fs::remove_file(&path) {
+        eprintln!("Could not delete {} : {e}", path.display());
+        return false;
+    }
+    // The files written along with the book go with it, the ones of the user are stashed.
+    if let Err(e) = updater::remove_exports(&path) {
+        eprintln!("Could not delete the exports of {} : {e}", path.display());
+    }
+    let sidecar = koreader::sidecar_dir(&path);
+    if sidecar.is_dir() {
+        if let Err(e) = fs::remove_dir_all(&sidecar) {
+            eprintln!("Could not delete {} : {e}", sidecar.display());
         }
-        Commands::Clean { paths } => paths.iter().for_each(|p| remove_empty_epub(p.as_path())),
-        Commands::Completions { shell } => clap_complete::generate(
-            shell,
-            &mut Args::command(),
-            "autebooks",
-            &mut std::io::stdout(),
-        ),
     }
+    let reading_order = Some(settings::reading_order_file(&path)).filter(|f| f.is_file());
+    let stash_path = mirrored_stash_dir(work_dir, stash_dir, &path);
+    for file in reading_order
+        .into_iter()
+        .chain(settings::original_text(&path))
+    {
+        match stash_companion(&path, &file, &stash_path) {
+            Ok(stashed) => println!("{}", summary!("Stsh", stashed.display(), Blue)),
+            Err(e) => eprintln!("Could not stash {} : {e}", file.display()),
+        }
+    }
+    match Manifest::load(work_dir) {
+        Ok(mut manifest) => {
+            manifest.remove_book(&path);
+            if let Err(e) = manifest.save() {
+                eprintln!("Could not save the library manifest : {e}");
+            }
+        }
+        Err(e) => eprintln!("Could not load the library manifest : {e}"),
+    }
+    println!("{}", summary!("Del", book.title, Yellow));
+    true
 }
 
-fn setup_nb_threads(nb_threads: usize) {
-    let custom_rayon_conf = rayon::ThreadPoolBuilder::new()
-        .num_threads(nb_threads)
-        .build_global();
-    if custom_rayon_conf.is_err() {
-        eprintln!(
-            "Could not use custom number of threads ({}), default number ({}) was used",
-            nb_threads,
-            rayon::current_num_threads()
-        );
+/// Returns whether the note could be shown or saved.
+fn manage_note(work_dir: &Path, book: &Path, text: Option<&str>, clear: bool) -> bool {
+    let mut manifest = match Manifest::load(work_dir) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            eprintln!("Could not load the library manifest : {e}");
+            return false;
+        }
+    };
+
+    if clear {
+        if !manifest.remove_note(book) {
+            eprintln!("No note for {}", book.display());
+            return false;
+        }
+    } else if let Some(text) = text {
+        if let Err(e) = manifest.set_note(book, text) {
+            eprintln!("{e}");
+            return false;
+        }
+    } else {
+        match manifest.note(book) {
+            Some(note) => println!("{note}"),
+            None => eprintln!("No note for {}", book.display()),
+        }
+        return true;
+    }
+
+    if let Err(e) = manifest.save() {
+        eprintln!("Could not save the library manifest : {e}");
+        return false;
     }
+    true
 }
 
-fn create_books(dir: &Path, urls: &[String]) {
-    let bar = MULTI_PROGRESS.add(get_progress_bar(urls.len() as u64, 1));
+/// Returns whether the reader could be launched.
+fn open_book(book: &Path, with: Option<&str>) -> bool {
+    if !book.is_file() {
+        eprintln!("No book at {}", book.display());
+        return false;
+    }
+    match reader_command(book, with).spawn() {
+        Ok(_) => true,
+        Err(e) => {
+            eprintln!("Could not open {} : {e}", book.display());
+            false
+        }
+    }
+}
 
-    urls.par_iter().for_each(|url| {
-        bar.set_prefix(url.clone());
-        let creation_res = Book::create(dir, url);
-        bar.inc(1);
+/// Command opening `book` with `with`, or else with the system default.
+fn reader_command(book: &Path, with: Option<&str>) -> Command {
+    let mut command = match with {
+        Some(with) => Command::new(with),
+        None if cfg!(target_os = "windows") => {
+            let mut command = Command::new("cmd");
+            command.args(["/C", "start", ""]);
+            command
+        }
+        None if cfg!(target_os = "macos") => Command::new("open"),
+        None => Command::new("xdg-open"),
+    };
+    command.arg(book);
+    command
+}
 
-        match creation_res {
-            Ok(book) => bar.println(format!("{:.50}\n", book.title)),
-            Err(e) => bar.println(summary!(e, url, red)),
+/// Returns whether the selected chapters could be extracted.
+fn extract_chapters(
+    book: &Path,
+    selection: &Selection,
+    format: Format,
+    output_dir: Option<&Path>,
+) -> bool {
+    let chapters: Vec<Chapter> = match Chapter::extract_from_epub(book) {
+        Ok(chapters) => chapters
+            .into_iter()
+            .filter(|c| c.is_selected(selection))
+            .collect(),
+        Err(e) => {
+            eprintln!("Could not read {} : {e}", book.display());
+            return false;
+        }
+    };
+    if chapters.is_empty() {
+        eprintln!("No such chapter in {}", book.display());
+        return false;
+    }
+
+    let Some(output_dir) = output_dir else {
+        for chapter in &chapters {
+            println!("{}", chapter.render(format));
         }
+        return true;
+    };
+    let res = fs::create_dir_all(output_dir).and_then(|()| {
+        chapters.iter().try_for_each(|chapter| {
+            fs::write(
+                output_dir.join(chapter.filename(format)),
+                chapter.render(format),
+            )
+        })
     });
-    bar.finish_and_clear();
+    if let Err(e) = res {
+        eprintln!(
+            "Could not write the chapters to {} : {e}",
+            output_dir.display()
+        );
+        return false;
+    }
+    true
 }
 
-fn update_books(book_files: &[FileToUpdate], stash: bool) {
-    let bar = MULTI_PROGRESS.add(get_progress_bar(book_files.len() as u64, 1));
-
-    book_files.par_iter().for_each(|file_to_update| {
-        let path = file_to_update.file_path.path();
-        let book = Book::new(path);
-        bar.set_prefix(book.title.clone());
+fn fetch_chapter(url: &str, format: Format) -> bool {
+    match updater::fetch_chapter(url) {
+        Ok((title, content)) => {
+            let chapter = Chapter {
+                number: 1,
+                id: url.to_string(),
+                title,
+                content,
+            };
+            println!("{}", chapter.render(format));
+            true
+        }
+        Err(e) => {
+            eprintln!("Could not fetch the chapter : {e}");
+            false
+        }
+    }
+}
 
-        match book.update(path) {
-            UpdateResult::Updated(n) => bar.println(summary!(n, book.title, green)),
-            UpdateResult::Skipped => bar.println(summary!("Skip", book.title, blue)),
-            UpdateResult::MoreChapterThanSource(n) => {
-                bar.println(summary!(-i32::from(n), book.title, red));
-                if stash {
-                    match book.stash_and_recreate(path, &file_to_update.stash_path) {
-                        Ok(book) => bar.println(summary!("New", book.title, light_green)),
-                        Err(e) => eprintln!("{e}"),
-                    }
-                }
+/// Replace `book` by its most recent stashed version, returns the path of the stashed version used.
+/// Returns whether the book could be restored.
+fn restore_book(work_dir: &Path, stash_dir: &Path, book: &Path) -> bool {
+    // Books stashed before the stash mirrored the sub-directories are at its root.
+    let restored = restore_from_stash(book, &mirrored_stash_dir(work_dir, stash_dir, book))
+        .or_else(|_| restore_from_stash(book, stash_dir));
+    match restored {
+        Ok(stashed) => {
+            println!("{}", summary!("Rest", stashed.display(), LightGreen));
+            // The restored book is not an external modification.
+            if let Err(e) = Manifest::load(work_dir).and_then(|mut manifest| {
+                manifest.record(book)?;
+                manifest.save()
+            }) {
+                eprintln!("Could not update the library manifest : {e}");
             }
-            UpdateResult::Unsupported | UpdateResult::UpToDate => (),
-            UpdateResult::Error(e) => bar.eprintln(&e.to_string()),
+            true
         }
-        bar.inc(1);
-    });
-    bar.finish_and_clear();
+        Err(e) => {
+            eprintln!("{}", summary!(e, book.display(), Red));
+            false
+        }
+    }
+}
+
+fn restore_from_stash(book: &Path, stash_dir: &Path) -> eyre::Result<PathBuf> {
+    let stem = book
+        .file_stem()
+        .ok_or_else(|| eyre::eyre!("Could not retrieve the book's filename."))?
+        .to_string_lossy()
+        .to_string();
+
+    // Stashed books are suffixed with the time they were stashed at, the stem of other books
+    // may start with this one (e.g. `Re_Zero` and `Re_Zero_Ex`).
+    let stashed_at = |p: &Path| {
+        let stashed_stem = p.file_stem()?.to_string_lossy();
+        let suffix = stashed_stem.strip_prefix(stem.as_str())?;
+        NaiveDateTime::parse_from_str(suffix, updater::STASH_TIMESTAMP_FORMAT).ok()
+    };
+    let latest = fs::read_dir(stash_dir)?
+        .filter_map(std::result::Result::ok)
+        .map(|e| e.path())
+        .filter(|p| {
+            p.extension()
+                .is_some_and(|v| v == EPUB || v == stash::DELTA_EXTENSION)
+        })
+        .filter_map(|p| Some((stashed_at(&p)?, p)))
+        .max_by_key(|(stashed_at, _)| *stashed_at)
+        .map(|(_, p)| p)
+        .ok_or_else(|| eyre::eyre!("No stashed version of this book was found."))?;
+
+    if latest
+        .extension()
+        .is_some_and(|v| v == stash::DELTA_EXTENSION)
+    {
+        let parent_dir = book
+            .parent()
+            .ok_or_else(|| eyre::eyre!("Could not retrieve the book's parent directory."))?;
+        let rebuilt = tempfile::NamedTempFile::new_in(parent_dir)?;
+        stash::reconstruct(&latest, book, rebuilt.path())?;
+        rebuilt.persist(book)?;
+    } else {
+        fs::copy(&latest, book)?;
+    }
+    Ok(latest)
 }
 
 #[must_use]
@@ -187,8 +2306,9 @@ pub fn get_progress_bar(len: u64, show_if_more_than: u64) -> ProgressBar {
     } else {
         ProgressBar::hidden()
     };
+    #[allow(clippy::literal_string_with_formatting_args)]
     let template_progress = ProgressStyle::with_template(if show {
-        "\n{prefix}\n[{elapsed}/{duration}] {wide_bar} {pos:>3}/{len:3} ({percent}%)\n{msg}"
+        "\n{prefix}\n[{elapsed}/{duration}] {wide_bar} {pos:>3}/{len:3} ({percent}%, {eta} left)\n{msg}"
     } else {
         ""
     })
@@ -218,12 +2338,25 @@ fn get_book_files(path: &PathBuf, stash_dir: &PathBuf) -> Vec<FileToUpdate> {
     WalkDir::new(path)
         .into_iter()
         .filter_map(std::result::Result::ok)
-        .filter(|e| e.path().parent().is_some_and(|p| *p != *stash_dir))
+        .filter(|e| !e.path().starts_with(stash_dir))
         .filter(|e| e.file_type().is_file())
-        .filter(|e| e.path().extension().map_or(false, |v| v == EPUB))
-        .map(|e| FileToUpdate {
-            file_path: e,
-            stash_path: stash_dir.clone(),
+        .filter(|e| e.path().extension().is_some_and(|v| v == EPUB))
+        // The volumes of the split books are written along with their book.
+        .filter(|e| !updater::is_volume(e.path()))
+        // Like the original texts of the translated books.
+        .filter(|e| !settings::is_original_text(e.path()))
+        .map(|e| {
+            // The stash mirrors the sub-directories, so that stashed books can be put back.
+            let sub_dir = e
+                .path()
+                .parent()
+                .and_then(|p| p.strip_prefix(path).ok())
+                .map(Path::to_path_buf)
+                .unwrap_or_default();
+            FileToUpdate {
+                stash_path: stash_dir.join(sub_dir),
+                file_path: e,
+            }
         })
         .collect()
 }
@@ -233,11 +2366,355 @@ fn remove_empty_epub(path: &Path) {
         .into_iter()
         .filter_map(std::result::Result::ok)
         .filter(|e| e.file_type().is_file())
-        .filter(|e| e.path().extension().map_or(false, |v| v == EPUB))
-        .filter(|e| e.metadata().map(|m| m.len() == 0).unwrap_or(false)) // File is empty
+        .filter(|e| e.path().extension().is_some_and(|v| v == EPUB))
+        .filter(|e| e.metadata().is_ok_and(|m| m.len() == 0)) // File is empty
         .for_each(|f| {
             fs::remove_file(f.path()).unwrap_or_else(|_| {
                 eprintln!("{} is empty but could not be deleted", f.path().display());
             });
         });
 }
+
+fn remove_orphaned_temp_files() {
+    remove_orphaned_temp_dirs(&Config::get().temp_dir());
+}
+
+/// Remove the directories of `temp_dir` left by the runs which did not clean up after them,
+/// i.e. those whose marker is no longer locked by a running writer.
+fn remove_orphaned_temp_dirs(temp_dir: &Path) {
+    let Ok(entries) = fs::read_dir(temp_dir) else {
+        return;
+    };
+    entries
+        .filter_map(std::result::Result::ok)
+        .filter(|e| e.file_name().to_string_lossy().starts_with(TEMP_PREFIX))
+        .for_each(|e| {
+            // Keep the lock while removing, so that no writer can take the directory back.
+            let Ok(marker) = fs::File::open(e.path().join(TEMP_MARKER)) else {
+                return;
+            };
+            if marker.try_lock().is_err() {
+                return;
+            }
+            fs::remove_dir_all(e.path()).unwrap_or_else(|_| {
+                eprintln!(
+                    "{} was left by a previous run but could not be deleted",
+                    e.path().display()
+                );
+            });
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_orphaned_temp_dirs() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let created = |name: &str, marked: bool| -> eyre::Result<PathBuf> {
+            let dir = temp_dir.path().join(name);
+            fs::create_dir(&dir)?;
+            if marked {
+                fs::write(dir.join(TEMP_MARKER), b"")?;
+            }
+            Ok(dir)
+        };
+        let orphaned = created(&format!("{TEMP_PREFIX}orphaned"), true)?;
+        let in_use = created(&format!("{TEMP_PREFIX}in-use"), true)?;
+        let unmarked = created(&format!("{TEMP_PREFIX}unmarked"), false)?;
+        let other = created("other", true)?;
+
+        // Held as a running writer would.
+        let marker = fs::File::open(in_use.join(TEMP_MARKER))?;
+        marker.lock()?;
+        remove_orphaned_temp_dirs(temp_dir.path());
+
+        assert!(!orphaned.exists());
+        assert!(in_use.exists() && unmarked.exists() && other.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_library() -> eyre::Result<()> {
+        let work_dir = tempfile::tempdir()?;
+        let stash_dir = work_dir.path().join(".stash");
+        let book = work_dir.path().join("book.epub");
+        fs::write(&book, b"epub")?;
+        let verify = |accept: bool| verify_library(work_dir.path(), &stash_dir, false, accept);
+        assert!(verify(false));
+
+        fs::write(&book, b"changed")?;
+        assert!(!verify(false));
+        assert!(verify(true));
+        assert!(verify(false));
+
+        fs::remove_file(&book)?;
+        assert!(!verify(false));
+        assert!(verify(true));
+        assert!(verify(false));
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_from_stash() -> eyre::Result<()> {
+        let work_dir = tempfile::tempdir()?;
+        let stash_dir = work_dir.path().join(".stash");
+        fs::create_dir_all(&stash_dir)?;
+        for (stashed, contents) in [
+            ("Re_Zero_2026-01-01_10h00.epub", "older"),
+            ("Re_Zero_2026-03-01_09h30.epub", "latest"),
+            ("Re_Zero_Ex_2026-05-01_12h00.epub", "another book"),
+            ("Re_Zero_notes.epub", "not stashed"),
+        ] {
+            fs::write(stash_dir.join(stashed), contents)?;
+        }
+        let book = work_dir.path().join("Re_Zero.epub");
+        fs::write(&book, b"changed")?;
+
+        let restored = restore_from_stash(&book, &stash_dir)?;
+        assert_eq!(restored, stash_dir.join("Re_Zero_2026-03-01_09h30.epub"));
+        assert_eq!(fs::read_to_string(&book)?, "latest");
+
+        let other = work_dir.path().join("Re.epub");
+        assert!(restore_from_stash(&other, &stash_dir).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_delta_stash() -> eyre::Result<()> {
+        let write_epub = |path: &Path, chapters: &[&str]| -> eyre::Result<()> {
+            let mut zip = zip::ZipWriter::new(fs::File::create(path)?);
+            zip.start_file(
+                "mimetype",
+                zip::write::SimpleFileOptions::default()
+                    .compression_method(zip::CompressionMethod::Stored),
+            )?;
+            std::io::Write::write_all(&mut zip, b"application/epub+zip")?;
+            for (i, chapter) in chapters.iter().enumerate() {
+                zip.start_file(
+                    format!("chapter_{i}.xhtml"),
+                    zip::write::SimpleFileOptions::default(),
+                )?;
+                std::io::Write::write_all(&mut zip, chapter.as_bytes())?;
+            }
+            zip.finish()?;
+            Ok(())
+        };
+        let work_dir = tempfile::tempdir()?;
+        let stash_dir = work_dir.path().join("stashed");
+        fs::create_dir_all(work_dir.path().join("fantasy"))?;
+        fs::create_dir_all(stash_dir.join("fantasy"))?;
+        let book = work_dir.path().join("fantasy/book.epub");
+        write_epub(&book, &["One", "Two"])?;
+        let original = fs::read(&book)?;
+
+        // Stashed as `update --stash --delta-stash` does, then recreated with other chapters.
+        let stashed = stash_dir.join("fantasy/book_2026-01-01_10h00.epub");
+        fs::rename(&book, &stashed)?;
+        write_epub(&book, &["One", "Two, rewritten", "Three"])?;
+        stash::write_delta(
+            &stashed,
+            &book,
+            &stashed.with_extension(stash::DELTA_EXTENSION),
+        )?;
+        fs::remove_file(&stashed)?;
+
+        assert!(restore_book(work_dir.path(), &stash_dir, &book));
+        assert_eq!(fs::read(&book)?, original);
+        let manifest = Manifest::load(work_dir.path())?;
+        assert!(matches!(manifest.check(&book)?, manifest::Status::Valid));
+
+        // Once the book is updated, the chapters it shared with the stash may have changed.
+        write_epub(&book, &["One, rewritten", "Two"])?;
+        let updated = fs::read(&book)?;
+        assert!(!restore_book(work_dir.path(), &stash_dir, &book));
+        assert_eq!(fs::read(&book)?, updated);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stash_mirrors_sub_directories() -> eyre::Result<()> {
+        let work_dir = tempfile::tempdir()?;
+        let stash_dir = work_dir.path().join(".stash");
+        fs::create_dir_all(work_dir.path().join("fantasy"))?;
+        fs::create_dir_all(&stash_dir)?;
+        for book in ["top.epub", "fantasy/nested.epub", ".stash/stashed.epub"] {
+            fs::write(work_dir.path().join(book), b"epub")?;
+        }
+
+        let mut files: Vec<_> = get_book_files(&work_dir.path().to_path_buf(), &stash_dir)
+            .into_iter()
+            .map(|f| (f.file_path.path().to_path_buf(), f.stash_path))
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            [
+                (
+                    work_dir.path().join("fantasy/nested.epub"),
+                    stash_dir.join("fantasy")
+                ),
+                (work_dir.path().join("top.epub"), stash_dir.join("")),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_aliases() -> eyre::Result<()> {
+        let work_dir = tempfile::tempdir()?;
+        let book = work_dir.path().join("book.epub");
+        fs::write(&book, b"epub")?;
+        let mut manifest = Manifest::load(work_dir.path())?;
+        manifest.set_alias("b", &book)?;
+        manifest.save()?;
+
+        let resolved = resolve_aliases(
+            work_dir.path(),
+            ["b", "unknown", &book.to_string_lossy()]
+                .map(PathBuf::from)
+                .to_vec(),
+        );
+        assert_eq!(
+            resolved,
+            [book.canonicalize()?, PathBuf::from("unknown"), book]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_reader_command() {
+        let book = Path::new("/books/A Book.epub");
+        let command = reader_command(book, Some("koreader"));
+        assert_eq!(command.get_program(), "koreader");
+        assert_eq!(command.get_args().collect::<Vec<_>>(), [book]);
+
+        let default = reader_command(book, None);
+        assert_eq!(default.get_args().last(), Some(book.as_os_str()));
+        if cfg!(target_os = "linux") {
+            assert_eq!(default.get_program(), "xdg-open");
+        }
+    }
+
+    #[test]
+    fn test_created_book_report() {
+        let created_book = CreatedBook {
+            title: String::from("A Book"),
+            url: String::from("https://www.royalroad.com/fiction/1"),
+            path: PathBuf::from("/books/A Book.epub"),
+            nb_chapters: Some(12),
+            size: Some(2048),
+        };
+        assert_eq!(
+            created_book.to_string(),
+            "A Book\n/books/A Book.epub (12 chapters, 2.00 KiB)"
+        );
+
+        let unknown = CreatedBook {
+            nb_chapters: None,
+            size: None,
+            ..created_book
+        };
+        assert!(unknown.to_string().ends_with("(? chapters, ?)"));
+    }
+
+    #[test]
+    fn test_list_order() {
+        let listed = |title: &str, url: &str, nb_chapters, last_chapter: Option<&str>| ListedBook {
+            title: title.to_string(),
+            url: url.to_string(),
+            path: PathBuf::from(format!("{title}.epub")),
+            nb_chapters,
+            last_chapter: last_chapter.map(String::from),
+        };
+        let mut books = vec![
+            listed(
+                "Beta",
+                "https://b.example/",
+                Some(3),
+                Some("2024-01-08T00:00:00Z"),
+            ),
+            listed("Alpha", "https://c.example/", None, None),
+            listed(
+                "Gamma",
+                "https://a.example/",
+                Some(10),
+                Some("2023-12-01T00:00:00Z"),
+            ),
+        ];
+        let titles =
+            |books: &[ListedBook]| books.iter().map(|b| b.title.clone()).collect::<Vec<_>>();
+
+        sort_listed(&mut books, ListOrder::Title);
+        assert_eq!(titles(&books), ["Alpha", "Beta", "Gamma"]);
+        sort_listed(&mut books, ListOrder::Chapters);
+        assert_eq!(titles(&books), ["Gamma", "Beta", "Alpha"]);
+        sort_listed(&mut books, ListOrder::Updated);
+        assert_eq!(titles(&books), ["Beta", "Gamma", "Alpha"]);
+        sort_listed(&mut books, ListOrder::Source);
+        assert_eq!(titles(&books), ["Gamma", "Beta", "Alpha"]);
+    }
+
+    #[test]
+    fn test_remove_book() -> eyre::Result<()> {
+        let work_dir = tempfile::tempdir()?;
+        let book = work_dir.path().join("book.epub");
+        let other = work_dir.path().join("other.epub");
+        for file in [&book, &other] {
+            fs::write(file, b"Not really an e-book")?;
+        }
+        let sidecar = koreader::sidecar_dir(&book);
+        fs::create_dir(&sidecar)?;
+        fs::write(sidecar.join("metadata.epub.lua"), b"return {}")?;
+        let reading_order = settings::reading_order_file(&book);
+        let original_text = book.with_extension("original.epub");
+        let volume = work_dir.path().join("book (1 of 2).epub");
+        let cbz = book.with_extension("cbz");
+        for file in [&reading_order, &original_text, &volume, &cbz] {
+            fs::write(file, b"")?;
+        }
+        let mut manifest = Manifest::load(work_dir.path())?;
+        manifest.set_alias("book", &book)?;
+        manifest.set_alias("other", &other)?;
+        manifest.save()?;
+        let stash_dir = work_dir.path().join("stashed");
+
+        assert!(remove_book(
+            work_dir.path(),
+            &stash_dir,
+            PathBuf::from("book")
+        ));
+        for removed in [
+            &book,
+            &sidecar,
+            &reading_order,
+            &original_text,
+            &volume,
+            &cbz,
+        ] {
+            assert!(!removed.exists(), "{} was kept", removed.display());
+        }
+        // The files written by the user are stashed rather than deleted.
+        let mut stashed: Vec<_> = fs::read_dir(&stash_dir)?
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().to_string()))
+            .collect::<eyre::Result<_>>()?;
+        stashed.sort();
+        let kinds: Vec<_> = stashed
+            .iter()
+            .filter_map(|name| Some(name.strip_prefix("book_")?.split_once('.')?.1))
+            .collect();
+        assert_eq!(kinds, ["order", "original.epub"]);
+        assert!(other.is_file());
+        let manifest = Manifest::load(work_dir.path())?;
+        assert_eq!(manifest.resolve_alias("book"), None);
+        assert!(manifest.resolve_alias("other").is_some());
+
+        assert!(!remove_book(
+            work_dir.path(),
+            &stash_dir,
+            PathBuf::from("book")
+        ));
+        Ok(())
+    }
+}
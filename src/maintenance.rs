@@ -0,0 +1,101 @@
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use url::Url;
+
+use crate::config::Config;
+use crate::profile;
+
+lazy_static! {
+    /// Number of books of each host which failed to update in a row.
+    static ref FAILURES: Mutex<HashMap<String, u32>> = Mutex::new(HashMap::new());
+}
+
+fn host(url: &str) -> Option<String> {
+    Url::parse(url).ok()?.host_str().map(str::to_lowercase)
+}
+
+/// Whether `host` is the host `rule` or one of its sub-domains.
+fn is_under(host: &str, rule: &str) -> bool {
+    let rule = rule.to_lowercase();
+    host == rule || host.ends_with(&format!(".{rule}"))
+}
+
+/// Why the books of a host are not attempted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unavailable {
+    /// The host is configured as down for maintenance.
+    Maintenance,
+    /// The previous books of the host kept failing during this run.
+    Failing,
+    /// The host may not be used at this time, see `profile::Window`.
+    OutsideWindow,
+}
+
+/// Whether the books of this source should be skipped.
+pub fn check(url: &str) -> Option<Unavailable> {
+    let host = host(url)?;
+    let config = Config::get();
+    let in_maintenance = config
+        .maintenance_hosts
+        .iter()
+        .any(|rule| is_under(&host, rule));
+    if in_maintenance {
+        return Some(Unavailable::Maintenance);
+    }
+    if !profile::in_window(url) {
+        return Some(Unavailable::OutsideWindow);
+    }
+    is_failing(&host, config.max_host_failures).then_some(Unavailable::Failing)
+}
+
+/// Whether the last `max_failures` books of `host` failed, 0 never giving up on a host.
+fn is_failing(host: &str, max_failures: u32) -> bool {
+    max_failures > 0
+        && FAILURES
+            .lock()
+            .is_ok_and(|f| f.get(host).is_some_and(|n| *n >= max_failures))
+}
+
+pub fn record_failure(url: &str) {
+    if let (Some(host), Ok(mut failures)) = (host(url), FAILURES.lock()) {
+        *failures.entry(host).or_default() += 1;
+    }
+}
+
+pub fn record_success(url: &str) {
+    if let (Some(host), Ok(mut failures)) = (host(url), FAILURES.lock()) {
+        failures.remove(&host);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_maintenance_rules() {
+        assert!(is_under("www.royalroad.com", "RoyalRoad.com"));
+        assert!(is_under("royalroad.com", "royalroad.com"));
+        assert!(!is_under("notroyalroad.com", "royalroad.com"));
+        assert_eq!(
+            host("https://WWW.RoyalRoad.com/fiction/1"),
+            Some(String::from("www.royalroad.com"))
+        );
+    }
+
+    #[test]
+    fn test_failing_hosts() {
+        let url = "https://failing.example.com/story/1";
+        for _ in 0..3 {
+            record_failure(url);
+        }
+        assert!(is_failing("failing.example.com", 3));
+        assert!(!is_failing("failing.example.com", 4));
+        assert!(!is_failing("failing.example.com", 0));
+
+        // A single success closes the circuit again.
+        record_success(url);
+        assert!(!is_failing("failing.example.com", 3));
+    }
+}
@@ -0,0 +1,434 @@
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const MANIFEST_FILENAME: &str = ".autebooks-manifest.json";
+/// File locked while the manifest is saved, so that concurrent runs save it one at a time.
+const MANIFEST_LOCK_FILENAME: &str = ".autebooks-manifest.lock";
+/// Files written by the last run, for sync tools to transfer only these.
+const CHANGES_FILENAME: &str = ".autebooks-changes.json";
+
+/// Checksums of the e-books managed by autebooks, stored at the root of the library.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(skip)]
+    root: PathBuf,
+    entries: BTreeMap<String, Entry>,
+    /// Short names given to books, to be used instead of their path.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    aliases: BTreeMap<String, String>,
+    /// Notes of the user about books, kept across updates.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    notes: BTreeMap<String, String>,
+    /// The aliases and notes as they were loaded, so that saving only applies the changes made
+    /// since (e.g. `note` may save the manifest while an update runs).
+    #[serde(skip)]
+    loaded_aliases: BTreeMap<String, String>,
+    #[serde(skip)]
+    loaded_notes: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Entry {
+    sha256: String,
+    size: u64,
+    /// Modification time when recorded, in seconds since the epoch, to skip hashing unchanged files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    modified: Option<u64>,
+}
+
+pub enum Status {
+    /// The file is not in the manifest yet.
+    Unknown,
+    Valid,
+    /// The file changed since autebooks last wrote it.
+    Mismatch,
+}
+
+impl Manifest {
+    pub fn load(root: &Path) -> Result<Self> {
+        let root = root.canonicalize()?;
+        let mut manifest = Self::read(&root)?;
+        manifest.loaded_aliases = manifest.aliases.clone();
+        manifest.loaded_notes = manifest.notes.clone();
+        manifest.root = root;
+        Ok(manifest)
+    }
+
+    fn read(root: &Path) -> Result<Self> {
+        let manifest_path = root.join(MANIFEST_FILENAME);
+        Ok(if manifest_path.exists() {
+            serde_json::from_str(&fs::read_to_string(&manifest_path)?)?
+        } else {
+            Self::default()
+        })
+    }
+
+    /// Write the manifest, along with the aliases and notes saved by other commands since it was
+    /// loaded. It is replaced at once, so that a crash does not leave it half written.
+    pub fn save(&self) -> Result<()> {
+        let lock = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.root.join(MANIFEST_LOCK_FILENAME))?;
+        lock.lock()?;
+
+        let saved = Self::read(&self.root)?;
+        let manifest = Self {
+            entries: self.entries.clone(),
+            aliases: merged(saved.aliases, &self.loaded_aliases, &self.aliases),
+            notes: merged(saved.notes, &self.loaded_notes, &self.notes),
+            ..Self::default()
+        };
+        let mut temp_file = tempfile::NamedTempFile::new_in(&self.root)?;
+        serde_json::to_writer_pretty(&mut temp_file, &manifest)?;
+        temp_file.persist(self.root.join(MANIFEST_FILENAME))?;
+        Ok(())
+    }
+
+    /// Store the current checksum of `file`, to be called each time autebooks writes it.
+    pub fn record(&mut self, file: &Path) -> Result<()> {
+        self.insert(file, Entry::compute(file)?)
+    }
+
+    /// Store `entry`, computed with `Entry::compute` beforehand, as the checksum of `file`.
+    pub fn insert(&mut self, file: &Path, entry: Entry) -> Result<()> {
+        let key = self.key(file)?;
+        self.entries.insert(key, entry);
+        Ok(())
+    }
+
+    /// Checksum of `file` when autebooks last wrote it.
+    pub fn entry(&self, file: &Path) -> Option<Entry> {
+        self.entries.get(&self.key(file).ok()?).cloned()
+    }
+
+    pub fn check(&self, file: &Path) -> Result<Status> {
+        let Some(expected) = self.entries.get(&self.key(file)?) else {
+            return Ok(Status::Unknown);
+        };
+        if Entry::compute(file)?.same_contents(expected) {
+            Ok(Status::Valid)
+        } else {
+            Ok(Status::Mismatch)
+        }
+    }
+
+    /// Files present in the manifest which no longer exist on disk.
+    pub fn missing_files(&self) -> Vec<PathBuf> {
+        self.entries
+            .keys()
+            .map(|k| self.root.join(k))
+            .filter(|p| !p.exists())
+            .collect()
+    }
+
+    pub fn forget(&mut self, file: &Path) {
+        if let Ok(key) = self.key(file) {
+            self.entries.remove(&key);
+        }
+    }
+
+    /// Drop everything recorded about a book: its checksum, its aliases and its note.
+    pub fn remove_book(&mut self, file: &Path) {
+        if let Ok(key) = self.key(file) {
+            self.entries.remove(&key);
+            self.notes.remove(&key);
+            self.aliases.retain(|_, k| *k != key);
+        }
+    }
+
+    pub fn set_alias(&mut self, alias: &str, file: &Path) -> Result<()> {
+        if !file.is_file() {
+            return Err(eyre!("No book at {}", file.display()));
+        }
+        let key = self.key(file)?;
+        self.aliases.insert(alias.to_string(), key);
+        Ok(())
+    }
+
+    /// Returns whether the alias existed.
+    pub fn remove_alias(&mut self, alias: &str) -> bool {
+        self.aliases.remove(alias).is_some()
+    }
+
+    pub fn resolve_alias(&self, alias: &str) -> Option<PathBuf> {
+        self.aliases.get(alias).map(|k| self.root.join(k))
+    }
+
+    pub fn aliases(&self) -> impl Iterator<Item = (&String, PathBuf)> {
+        self.aliases.iter().map(|(a, k)| (a, self.root.join(k)))
+    }
+
+    pub fn set_note(&mut self, file: &Path, note: &str) -> Result<()> {
+        if !file.is_file() {
+            return Err(eyre!("No book at {}", file.display()));
+        }
+        let key = self.key(file)?;
+        self.notes.insert(key, note.to_string());
+        Ok(())
+    }
+
+    /// Returns whether the book had a note.
+    pub fn remove_note(&mut self, file: &Path) -> bool {
+        self.key(file)
+            .is_ok_and(|key| self.notes.remove(&key).is_some())
+    }
+
+    pub fn note(&self, file: &Path) -> Option<&str> {
+        self.notes.get(&self.key(file).ok()?).map(String::as_str)
+    }
+
+    fn key(&self, file: &Path) -> Result<String> {
+        let file = file
+            .parent()
+            .and_then(|p| p.canonicalize().ok())
+            .zip(file.file_name())
+            .map(|(parent, name)| parent.join(name))
+            .ok_or_else(|| eyre!("Invalid path : {}", file.display()))?;
+        Ok(file
+            .strip_prefix(&self.root)
+            .unwrap_or(&file)
+            .to_string_lossy()
+            .to_string())
+    }
+}
+
+/// `saved` with the changes from `loaded` to `current`.
+fn merged(
+    mut saved: BTreeMap<String, String>,
+    loaded: &BTreeMap<String, String>,
+    current: &BTreeMap<String, String>,
+) -> BTreeMap<String, String> {
+    saved.retain(|key, _| current.contains_key(key) || !loaded.contains_key(key));
+    for (key, value) in current {
+        if loaded.get(key) != Some(value) {
+            saved.insert(key.clone(), value.clone());
+        }
+    }
+    saved
+}
+
+/// Files created or modified by a run, stored at the root of the library.
+#[derive(Serialize, Deserialize)]
+struct Changes {
+    /// End of the run, RFC 3339.
+    finished_at: String,
+    /// Keyed by path, relative to the library root when inside it.
+    files: BTreeMap<String, Entry>,
+}
+
+/// Replace the list of changed files of the library at `root` with `files`, unless there are
+/// none so that the changes of the previous run stay available.
+pub fn write_changes(root: &Path, files: &[PathBuf]) -> Result<()> {
+    if files.is_empty() {
+        return Ok(());
+    }
+    let root = root.canonicalize()?;
+    let files = files
+        .iter()
+        .filter_map(|file| {
+            let path = file.canonicalize().ok()?;
+            let key = path.strip_prefix(&root).unwrap_or(&path);
+            Some((
+                key.to_string_lossy().to_string(),
+                Entry::compute(&path).ok()?,
+            ))
+        })
+        .collect();
+    let changes = Changes {
+        finished_at: chrono::Utc::now().to_rfc3339(),
+        files,
+    };
+    fs::write(
+        root.join(CHANGES_FILENAME),
+        serde_json::to_string_pretty(&changes)?,
+    )?;
+    Ok(())
+}
+
+/// Files changed by the last run, relative to the library root (or absolute when outside it).
+pub fn read_changes(root: &Path) -> Result<Vec<PathBuf>> {
+    let path = root.join(CHANGES_FILENAME);
+    let contents =
+        fs::read_to_string(&path).map_err(|e| eyre!("Could not read {} : {e}", path.display()))?;
+    let changes: Changes = serde_json::from_str(&contents)?;
+    Ok(changes.files.into_keys().map(PathBuf::from).collect())
+}
+
+impl Entry {
+    pub fn compute(file: &Path) -> Result<Self> {
+        let mut hasher = Sha256::new();
+        let size = io::copy(&mut File::open(file)?, &mut hasher)?;
+        Ok(Self {
+            sha256: format!("{:x}", hasher.finalize()),
+            size,
+            modified: fs::metadata(file).ok().and_then(|m| modified_secs(&m)),
+        })
+    }
+
+    fn same_contents(&self, other: &Self) -> bool {
+        self.sha256 == other.sha256 && self.size == other.size
+    }
+
+    /// Whether `file` was modified by something else since this entry was computed,
+    /// only hashing it if its size or modification time changed.
+    pub fn changed_externally(&self, file: &Path) -> Result<bool> {
+        let metadata = fs::metadata(file)?;
+        if metadata.len() == self.size
+            && self.modified.is_some()
+            && modified_secs(&metadata) == self.modified
+        {
+            return Ok(false);
+        }
+        Ok(!Self::compute(file)?.same_contents(self))
+    }
+}
+
+fn modified_secs(metadata: &fs::Metadata) -> Option<u64> {
+    metadata
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_check() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let book = root.path().join("book.epub");
+        fs::write(&book, "contents")?;
+        let mut manifest = Manifest::load(root.path())?;
+        assert!(matches!(manifest.check(&book)?, Status::Unknown));
+
+        manifest.record(&book)?;
+        manifest.save()?;
+        let manifest = Manifest::load(root.path())?;
+        assert!(matches!(manifest.check(&book)?, Status::Valid));
+
+        fs::write(&book, "other contents")?;
+        assert!(matches!(manifest.check(&book)?, Status::Mismatch));
+
+        fs::remove_file(&book)?;
+        assert_eq!(
+            manifest.missing_files(),
+            [root.path().canonicalize()?.join("book.epub")]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_changes_made_outside_are_detected() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let book = root.path().join("book.epub");
+        fs::write(&book, "contents")?;
+        let mut manifest = Manifest::load(root.path())?;
+        assert!(manifest.entry(&book).is_none());
+
+        manifest.insert(&book, Entry::compute(&book)?)?;
+        let entry = manifest.entry(&book).ok_or_else(|| eyre!("Not recorded"))?;
+        assert!(!entry.changed_externally(&book)?);
+
+        // Touched but not modified.
+        File::options()
+            .append(true)
+            .open(&book)?
+            .set_modified(UNIX_EPOCH)?;
+        assert!(!entry.changed_externally(&book)?);
+
+        fs::write(&book, "edited elsewhere")?;
+        assert!(entry.changed_externally(&book)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_aliases_and_notes() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let book = root.path().join("book.epub");
+        fs::write(&book, "contents")?;
+        let mut manifest = Manifest::load(root.path())?;
+        assert!(manifest
+            .set_alias("b", &root.path().join("missing.epub"))
+            .is_err());
+
+        manifest.set_alias("b", &book)?;
+        manifest.set_note(&book, "Waiting for the next arc")?;
+        manifest.record(&book)?;
+        manifest.save()?;
+        let mut manifest = Manifest::load(root.path())?;
+        assert_eq!(manifest.resolve_alias("b"), Some(book.canonicalize()?));
+        assert_eq!(manifest.note(&book), Some("Waiting for the next arc"));
+
+        assert!(manifest.remove_note(&book));
+        assert_eq!(manifest.note(&book), None);
+        assert!(manifest.remove_alias("b"));
+        assert!(!manifest.remove_alias("b"));
+
+        manifest.set_alias("b", &book)?;
+        manifest.set_note(&book, "Waiting for the next arc")?;
+        manifest.remove_book(&book);
+        assert_eq!(manifest.resolve_alias("b"), None);
+        assert_eq!(manifest.note(&book), None);
+        assert!(matches!(manifest.check(&book)?, Status::Unknown));
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_saves_keep_notes_and_aliases() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let book = root.path().join("book.epub");
+        let other = root.path().join("other.epub");
+        for file in [&book, &other] {
+            fs::write(file, "contents")?;
+        }
+        let mut manifest = Manifest::load(root.path())?;
+        manifest.set_alias("o", &other)?;
+        manifest.set_note(&other, "Dropped")?;
+        manifest.save()?;
+
+        // An update loads the manifest, then `note` and `alias` save it before the update ends.
+        let mut update = Manifest::load(root.path())?;
+        let mut command = Manifest::load(root.path())?;
+        command.set_note(&book, "Waiting for the next arc")?;
+        command.set_alias("b", &book)?;
+        assert!(command.remove_alias("o"));
+        command.save()?;
+        update.record(&book)?;
+        assert!(update.remove_note(&other));
+        update.save()?;
+
+        let manifest = Manifest::load(root.path())?;
+        assert!(matches!(manifest.check(&book)?, Status::Valid));
+        assert_eq!(manifest.note(&book), Some("Waiting for the next arc"));
+        assert_eq!(manifest.resolve_alias("b"), Some(book.canonicalize()?));
+        assert_eq!(manifest.resolve_alias("o"), None);
+        assert_eq!(manifest.note(&other), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_changes_are_kept_when_nothing_changed() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let book = root.path().join("book.epub");
+        fs::write(&book, "contents")?;
+
+        write_changes(root.path(), &[book])?;
+        assert_eq!(read_changes(root.path())?, [PathBuf::from("book.epub")]);
+
+        // A run which changed nothing leaves the list of the previous one.
+        write_changes(root.path(), &[])?;
+        assert_eq!(read_changes(root.path())?, [PathBuf::from("book.epub")]);
+        Ok(())
+    }
+}
@@ -0,0 +1,274 @@
+use eyre::{eyre, Result};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use reqwest::blocking::Client;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::StatusCode;
+use serde_json::json;
+use std::fmt::Write;
+use std::thread;
+use std::time::Duration;
+
+use crate::config::Config;
+
+/// Discord allows at most 10 embeds per message.
+const DISCORD_MAX_EMBEDS: usize = 10;
+/// Embed descriptions are limited to 4096 characters.
+const DISCORD_MAX_DESCRIPTION: usize = 4000;
+/// Give up on a message after being rate limited this many times.
+const DISCORD_MAX_RETRIES: u32 = 5;
+
+/// Discord webhook to which each updated book is posted.
+#[derive(Debug)]
+pub struct Discord {
+    pub webhook_url: String,
+}
+
+/// SMTP account sending a digest of each run.
+#[derive(Debug)]
+pub struct Email {
+    pub server: String,
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// What happened during a run, for the notifiers.
+#[derive(Debug, Default)]
+pub struct RunReport {
+    pub updated: Vec<UpdatedBook>,
+    /// Title of the book and error.
+    pub errors: Vec<(String, String)>,
+}
+
+#[derive(Debug)]
+pub struct UpdatedBook {
+    pub title: String,
+    pub url: String,
+    pub cover_url: Option<String>,
+    pub nb_new_chapters: u16,
+    pub new_chapters: Vec<String>,
+}
+
+/// Whether a run report has to be gathered.
+pub fn is_enabled() -> bool {
+    let config = Config::get();
+    config.email.is_some() || config.discord.is_some()
+}
+
+/// Send the report of the run to the configured notifiers, if anything happened.
+pub fn notify(report: &RunReport) {
+    if report.updated.is_empty() && report.errors.is_empty() {
+        return;
+    }
+    if let Some(email) = &Config::get().email {
+        if let Err(e) = email.send_digest(report) {
+            eprintln!("Could not send the email digest : {e}");
+        }
+    }
+    if let Some(discord) = &Config::get().discord {
+        if let Err(e) = discord.post(&report.updated) {
+            eprintln!("Could not notify Discord : {e}");
+        }
+    }
+}
+
+impl Discord {
+    fn post(&self, books: &[UpdatedBook]) -> Result<()> {
+        let client = Client::new();
+        for batch in books.chunks(DISCORD_MAX_EMBEDS) {
+            let message = json!({
+                "embeds": batch.iter().map(embed).collect::<Vec<_>>(),
+            });
+            self.send(&client, &message)?;
+        }
+        Ok(())
+    }
+
+    /// Send the message, waiting for the rate limits given by Discord.
+    fn send(&self, client: &Client, message: &serde_json::Value) -> Result<()> {
+        for _ in 0..DISCORD_MAX_RETRIES {
+            let response = client
+                .post(&self.webhook_url)
+                .header(CONTENT_TYPE, "application/json")
+                .body(message.to_string())
+                .send()?;
+
+            if response.status() == StatusCode::TOO_MANY_REQUESTS {
+                let retry_after = response
+                    .text()
+                    .ok()
+                    .and_then(|body| serde_json::from_str::<serde_json::Value>(&body).ok())
+                    .and_then(|body| body["retry_after"].as_f64())
+                    .unwrap_or(1.0);
+                thread::sleep(Duration::from_secs_f64(retry_after.max(0.0)));
+                continue;
+            }
+            let response = response.error_for_status()?;
+
+            // Wait for the bucket to refill rather than hitting the limit on the next batch.
+            let header = |name: &str| {
+                response
+                    .headers()
+                    .get(name)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<f64>().ok())
+            };
+            if header("x-ratelimit-remaining").is_some_and(|r| r < 1.0) {
+                if let Some(reset_after) = header("x-ratelimit-reset-after") {
+                    thread::sleep(Duration::from_secs_f64(reset_after.max(0.0)));
+                }
+            }
+            return Ok(());
+        }
+        Err(eyre!(
+            "Still rate limited after {DISCORD_MAX_RETRIES} attempts"
+        ))
+    }
+}
+
+fn embed(book: &UpdatedBook) -> serde_json::Value {
+    let mut description = format!("{:+} chapter(s)", book.nb_new_chapters);
+    for chapter in &book.new_chapters {
+        if description.len() + chapter.len() > DISCORD_MAX_DESCRIPTION {
+            description.push_str("\n…");
+            break;
+        }
+        let _ = write!(description, "\n- {chapter}");
+    }
+    let mut embed = json!({
+        "title": book.title,
+        "url": book.url,
+        "description": description,
+    });
+    if let Some(cover_url) = &book.cover_url {
+        embed["thumbnail"] = json!({ "url": cover_url });
+    }
+    embed
+}
+
+impl Email {
+    fn send_digest(&self, report: &RunReport) -> Result<()> {
+        let message = self.digest_message(report)?;
+        SmtpTransport::starttls_relay(&self.server)?
+            .port(self.port)
+            .credentials(Credentials::new(
+                self.username.clone(),
+                self.password.clone(),
+            ))
+            .build()
+            .send(&message)?;
+        Ok(())
+    }
+
+    fn digest_message(&self, report: &RunReport) -> Result<Message> {
+        Ok(Message::builder()
+            .from(self.from.parse()?)
+            .to(self.to.parse()?)
+            .subject(format!(
+                "autebooks : {} book(s) updated, {} error(s)",
+                report.updated.len(),
+                report.errors.len()
+            ))
+            .body(digest(report))?)
+    }
+}
+
+fn digest(report: &RunReport) -> String {
+    let mut body = String::new();
+    for book in &report.updated {
+        let _ = writeln!(body, "{} ({})", book.title, book.url);
+        for chapter in &book.new_chapters {
+            let _ = writeln!(body, "  - {chapter}");
+        }
+        body.push('\n');
+    }
+    if !report.errors.is_empty() {
+        body.push_str("Errors :\n");
+        for (title, error) in &report.errors {
+            let _ = writeln!(body, "  - {title} : {error}");
+        }
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_digest() {
+        let report = RunReport {
+            updated: vec![UpdatedBook {
+                title: String::from("A Book"),
+                url: String::from("https://www.royalroad.com/fiction/1"),
+                cover_url: None,
+                nb_new_chapters: 1,
+                new_chapters: vec![String::from("Chapter 2")],
+            }],
+            errors: vec![(String::from("Other Book"), String::from("Timeout"))],
+        };
+        assert_eq!(
+            digest(&report),
+            "A Book (https://www.royalroad.com/fiction/1)\n  - Chapter 2\n\n\
+            Errors :\n  - Other Book : Timeout\n"
+        );
+    }
+
+    #[test]
+    fn test_digest_message() -> Result<()> {
+        let email = Email {
+            server: String::from("smtp.example.com"),
+            port: 587,
+            username: String::from("reader"),
+            password: String::from("secret"),
+            from: String::from("autebooks <books@example.com>"),
+            to: String::from("reader@example.com"),
+        };
+        let report = RunReport {
+            updated: Vec::new(),
+            errors: vec![(String::from("A Book"), String::from("Timeout"))],
+        };
+
+        let message = String::from_utf8(email.digest_message(&report)?.formatted())?;
+        assert!(message.contains("From: autebooks <books@example.com>\r\n"));
+        assert!(message.contains("To: reader@example.com\r\n"));
+        assert!(message.contains("Subject: autebooks : 0 book(s) updated, 1 error(s)\r\n"));
+        assert!(message.ends_with("Errors :\r\n  - A Book : Timeout\r\n"));
+
+        let invalid = Email {
+            to: String::from("not an address"),
+            ..email
+        };
+        assert!(invalid.digest_message(&report).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_embed() {
+        let mut book = UpdatedBook {
+            title: String::from("A Book"),
+            url: String::from("https://www.royalroad.com/fiction/1"),
+            cover_url: Some(String::from("https://www.royalroad.com/cover.jpg")),
+            nb_new_chapters: 2,
+            new_chapters: vec![String::from("Chapter 2"), String::from("Chapter 3")],
+        };
+        assert_eq!(
+            embed(&book),
+            json!({
+                "title": "A Book",
+                "url": "https://www.royalroad.com/fiction/1",
+                "description": "+2 chapter(s)\n- Chapter 2\n- Chapter 3",
+                "thumbnail": { "url": "https://www.royalroad.com/cover.jpg" },
+            })
+        );
+
+        book.cover_url = None;
+        book.new_chapters = vec!["a".repeat(DISCORD_MAX_DESCRIPTION); 2];
+        let embed = embed(&book);
+        assert!(embed.get("thumbnail").is_none());
+        assert_eq!(embed["description"], "+2 chapter(s)\n…");
+    }
+}
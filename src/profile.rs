@@ -0,0 +1,762 @@
+use chrono::{Datelike, Local, NaiveDateTime, NaiveTime, Weekday};
+use eyre::{eyre, Result};
+use lazy_regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use url::Url;
+
+use crate::config::{ImagePriority, ParallelText};
+use crate::integrations::HealthcheckEvent;
+use crate::summary;
+
+/// Named profiles are read from `<config dir>/autebooks/config.toml`, e.g.
+/// ```toml
+/// disabled_sources = ["Royal Road"]
+/// source_priority = ["FanFicFare"]
+/// site_options = ["is_adult=true"]
+/// fichub_hosts = ["archiveofourown.org"]
+///
+/// [profiles.completed]
+/// dir = "~/Books/Completed"
+/// max_images = 50
+///
+/// [passwords]
+/// "https://forums.example.com/threads/some-story.123" = "secret"
+///
+/// [[routes]]
+/// host = "royalroad.com"
+/// dir = "royalroad"
+///
+/// [book_sources]
+/// "https://www.royalroad.com/fiction/12345" = "Royal Road"
+///
+/// [[sites]]
+/// host = "novels.example.com"
+/// content = "div.chapter-text"
+/// next = "a.next-chapter"
+///
+/// [host_site_options]
+/// "archiveofourown.org" = ["view_adult=true"]
+///
+/// [royalroad]
+/// username = "someone@example.com"
+/// password = "secret"
+///
+/// [[plugins]]
+/// name = "example"
+/// command = "~/.local/bin/autebooks-example"
+/// hosts = ["stories.example.org"]
+///
+/// [[windows]]
+/// host = "royalroad.com"
+/// hours = "02:00-06:00"
+/// days = ["sat", "sun"]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    profiles: HashMap<String, Profile>,
+    /// Passwords of the protected works, by URL.
+    #[cfg_attr(not(feature = "fanficfare"), allow(dead_code))]
+    #[serde(default)]
+    passwords: HashMap<String, String>,
+    /// Sub-directories of the work directory where created books land, the first match wins.
+    #[serde(default)]
+    routes: Vec<Route>,
+    /// Sources never used, by the name shown by `autebooks sources`.
+    #[serde(default)]
+    disabled_sources: Vec<String>,
+    /// Sources tried before the others, in this order.
+    #[serde(default)]
+    source_priority: Vec<String>,
+    /// Source of a book whatever the priority, by URL.
+    #[serde(default)]
+    book_sources: HashMap<String, String>,
+    /// Sites without a dedicated source, crawled from chapter to chapter.
+    #[serde(default)]
+    sites: Vec<Site>,
+    /// External programs providing sources, see `source::plugin`.
+    #[serde(default)]
+    plugins: Vec<Plugin>,
+    /// `FanFicFare` options (`key=value`) given for every book.
+    #[cfg_attr(not(feature = "fanficfare"), allow(dead_code))]
+    #[serde(default)]
+    site_options: Vec<String>,
+    /// `FanFicFare` options given for the books of a host (and its sub-domains).
+    #[cfg_attr(not(feature = "fanficfare"), allow(dead_code))]
+    #[serde(default)]
+    host_site_options: HashMap<String, Vec<String>>,
+    /// `FanFicFare` options given for a book, by URL.
+    #[cfg_attr(not(feature = "fanficfare"), allow(dead_code))]
+    #[serde(default)]
+    book_site_options: HashMap<String, Vec<String>>,
+    /// Royal Road account, to sign in with `autebooks login` or with its session cookie.
+    royalroad: Option<Account>,
+    /// Hosts (and their sub-domains) whose books are fetched through `FicHub`, as they block
+    /// scraping.
+    #[serde(default)]
+    fichub_hosts: Vec<String>,
+    /// When the books may be updated, see `Window`.
+    #[serde(default)]
+    windows: Vec<Window>,
+    /// Selectors replacing the built-in ones of Royal Road, until a release follows its redesigns.
+    #[serde(default)]
+    royalroad_selectors: RoyalRoadSelectors,
+    /// Headers sent to a host (and its sub-domains), e.g. the `Referer` expected by an image CDN.
+    #[serde(default)]
+    host_headers: HashMap<String, HashMap<String, String>>,
+}
+
+/// Hours and days during which updates may use the network, for the books of a host (and its
+/// sub-domains) or else for every book without a window of its own.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Window {
+    host: Option<String>,
+    /// `02:00-06:00`, it may go past midnight (`22:00-06:00`), all day if absent.
+    hours: Option<String>,
+    /// `mon`, `tuesday`…, every day if empty.
+    #[serde(default)]
+    days: Vec<String>,
+}
+
+impl Window {
+    /// Whether `time` (of the local time zone) is within the window. Hours which cannot be
+    /// parsed are ignored rather than blocking the updates.
+    fn contains(&self, time: NaiveDateTime) -> bool {
+        let day_matches = self.days.is_empty()
+            || self
+                .days
+                .iter()
+                .filter_map(|day| day.parse::<Weekday>().ok())
+                .any(|day| day == time.weekday());
+        let hours_match = self
+            .hours
+            .as_ref()
+            .and_then(|hours| {
+                let (start, end) = hours.split_once('-')?;
+                let start = NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?;
+                let end = NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?;
+                let now = time.time();
+                Some(if start <= end {
+                    start <= now && now < end
+                } else {
+                    now >= start || now < end
+                })
+            })
+            .unwrap_or(true);
+        day_matches && hours_match
+    }
+}
+
+/// Whether the book of `url` may be updated now: the windows of its host apply if it has any,
+/// else the ones without a host, and it may be updated at any time without either.
+pub fn in_window(url: &str) -> bool {
+    windows_allow(
+        &loaded_config_file().windows,
+        url,
+        Local::now().naive_local(),
+    )
+}
+
+fn windows_allow(windows: &[Window], url: &str, time: NaiveDateTime) -> bool {
+    let host_windows: Vec<&Window> = windows
+        .iter()
+        .filter(|w| w.host.as_ref().is_some_and(|host| host_matches(host, url)))
+        .collect();
+    let applicable = if host_windows.is_empty() {
+        windows.iter().filter(|w| w.host.is_none()).collect()
+    } else {
+        host_windows
+    };
+    applicable.is_empty() || applicable.iter().any(|w| w.contains(time))
+}
+
+/// CSS selectors of the Royal Road pages, the built-in ones are used for those left unset.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RoyalRoadSelectors {
+    /// Text of a chapter.
+    pub content: Option<String>,
+    pub authors_note_start: Option<String>,
+    pub authors_note_end: Option<String>,
+    /// Title of the book, on its page.
+    pub title: Option<String>,
+    /// Author of the book, on its page.
+    pub author: Option<String>,
+    /// Elements removed from the text of the chapters, e.g. hidden anti-piracy notices.
+    pub watermark: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Account {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Cookies of a signed-in session (`name=value; ...`), copied from a browser.
+    pub cookie: Option<String>,
+}
+
+/// External program handling the books of some hosts.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Plugin {
+    pub name: String,
+    pub command: PathBuf,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Hosts whose books the plugin handles, their sub-domains match too.
+    pub hosts: Vec<String>,
+}
+
+impl Plugin {
+    /// Program of the plugin, `~` standing for the home directory.
+    pub fn program(&self) -> PathBuf {
+        expand_home(self.command.clone())
+    }
+}
+
+/// Selectors of a site crawled by the generic source, whose books are given by their first chapter.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Site {
+    /// Host of the site, its sub-domains match too.
+    pub host: String,
+    /// Text of the chapter.
+    pub content: String,
+    /// Link to the next chapter.
+    pub next: String,
+    /// Title of the chapter, the title of the page if unset.
+    pub chapter_title: Option<String>,
+    /// Title of the book, on its first chapter, the title of the page if unset.
+    pub title: Option<String>,
+    /// Author of the book, on its first chapter.
+    pub author: Option<String>,
+}
+
+/// Sub-directory for the books whose URL matches every given criterion.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Route {
+    /// Host of the source, its sub-domains match too.
+    host: Option<String>,
+    /// Regular expression matched against the URL.
+    pattern: Option<String>,
+    dir: PathBuf,
+}
+
+/// Whether the host of `url` is `rule` or one of its sub-domains.
+fn host_matches(rule: &str, url: &str) -> bool {
+    Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_lowercase))
+        .is_some_and(|host| {
+            let rule = rule.to_lowercase();
+            host == rule || host.ends_with(&format!(".{rule}"))
+        })
+}
+
+impl Route {
+    fn matches(&self, url: &str) -> bool {
+        let host_matches = self
+            .host
+            .as_ref()
+            .is_none_or(|rule| host_matches(rule, url));
+        let pattern_matches = self
+            .pattern
+            .as_ref()
+            .is_none_or(|pattern| Regex::new(pattern).is_ok_and(|re| re.is_match(url)));
+        host_matches && pattern_matches
+    }
+}
+
+/// Settings used when they are not given on the command line.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub dir: Option<PathBuf>,
+    pub nb_threads: Option<usize>,
+    pub temp_dir: Option<PathBuf>,
+    pub max_images: Option<usize>,
+    pub max_images_size: Option<u64>,
+    pub images_priority: Option<ImagePriority>,
+    pub cbz: Option<bool>,
+    pub warnings_page: Option<bool>,
+    pub split_size: Option<u64>,
+    pub allow_image_host: Option<Vec<String>>,
+    pub deny_image_host: Option<Vec<String>>,
+    pub skip_chapters: Option<Vec<String>>,
+    pub parallel_text: Option<ParallelText>,
+    pub respect_crawl_delay: Option<bool>,
+    pub koreader: Option<bool>,
+    pub self_test: Option<bool>,
+    pub maintenance_host: Option<Vec<String>>,
+    pub max_host_failures: Option<u32>,
+    pub site_options: Option<Vec<String>>,
+    pub fanficfare_timeout: Option<u64>,
+    pub chapter_concurrency: Option<usize>,
+    pub kavita_url: Option<String>,
+    pub kavita_api_key: Option<String>,
+    pub kavita_library_id: Option<u32>,
+    pub calibre_web_url: Option<String>,
+    pub calibre_web_user: Option<String>,
+    pub calibre_web_password: Option<String>,
+    pub summary: Option<summary::Verbosity>,
+    pub summary_format: Option<summary::Format>,
+    pub summary_theme: Option<summary::Theme>,
+    pub output: Option<summary::Output>,
+    pub smtp_server: Option<String>,
+    pub smtp_port: Option<u16>,
+    pub smtp_user: Option<String>,
+    pub smtp_password: Option<String>,
+    pub email_from: Option<String>,
+    pub email_to: Option<String>,
+    pub discord_webhook: Option<String>,
+    pub healthcheck_url: Option<String>,
+    pub healthcheck_events: Option<Vec<HealthcheckEvent>>,
+}
+
+pub fn config_file() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("autebooks").join("config.toml"))
+}
+
+pub fn load(name: &str) -> Result<Profile> {
+    let path = config_file().ok_or_else(|| eyre!("No configuration directory"))?;
+    let contents =
+        fs::read_to_string(&path).map_err(|e| eyre!("Could not read {} : {e}", path.display()))?;
+    parse(&contents, name)
+}
+
+fn parse(contents: &str, name: &str) -> Result<Profile> {
+    let mut config_file: ConfigFile = toml_edit::de::from_str(contents)?;
+    let mut profile = config_file
+        .profiles
+        .remove(name)
+        .ok_or_else(|| eyre!("No profile named '{name}'"))?;
+    profile.dir = profile.dir.map(expand_home);
+    profile.temp_dir = profile.temp_dir.map(expand_home);
+    Ok(profile)
+}
+
+/// Content of the configuration file at `path`, `None` if there is no such file.
+fn read_config_file(path: &Path) -> Result<Option<ConfigFile>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(eyre!("Could not read {} : {e}", path.display())),
+    };
+    toml_edit::de::from_str(&contents)
+        .map(Some)
+        .map_err(|e| eyre!("Could not parse {} : {e}", path.display()))
+}
+
+/// Check the configuration file, since the settings of an invalid one would all be ignored.
+pub fn check_config_file() -> Result<()> {
+    config_file().map_or(Ok(()), |path| read_config_file(&path).map(|_| ()))
+}
+
+/// Content of the configuration file, read once.
+fn loaded_config_file() -> &'static ConfigFile {
+    static CONFIG_FILE: OnceLock<ConfigFile> = OnceLock::new();
+    CONFIG_FILE.get_or_init(|| {
+        config_file()
+            .map(|path| read_config_file(&path))
+            .transpose()
+            .unwrap_or_else(|e| {
+                eprintln!("Ignoring the configuration file : {e}");
+                None
+            })
+            .flatten()
+            .unwrap_or_default()
+    })
+}
+
+impl ConfigFile {
+    #[cfg_attr(not(feature = "fanficfare"), allow(dead_code))]
+    fn password_for(&self, url: &str) -> Option<String> {
+        self.passwords.get(url.trim_end_matches('/')).cloned()
+    }
+
+    fn headers_for(&self, url: &str) -> Vec<(&String, &String)> {
+        let mut hosts: Vec<_> = self
+            .host_headers
+            .iter()
+            .filter(|(host, _)| host_matches(host, url))
+            .collect();
+        hosts.sort_by_key(|(host, _)| host.len());
+        hosts.into_iter().flat_map(|(_, headers)| headers).collect()
+    }
+
+    #[cfg_attr(not(feature = "fanficfare"), allow(dead_code))]
+    fn site_options_for(&self, url: &str) -> Vec<String> {
+        let mut hosts: Vec<_> = self
+            .host_site_options
+            .iter()
+            .filter(|(host, _)| host_matches(host, url))
+            .collect();
+        // Sub-domains are more specific than their parent domain.
+        hosts.sort_by_key(|(host, _)| host.len());
+        self.site_options
+            .iter()
+            .chain(hosts.into_iter().flat_map(|(_, options)| options))
+            .chain(
+                self.book_site_options
+                    .get(url.trim_end_matches('/'))
+                    .into_iter()
+                    .flatten(),
+            )
+            .cloned()
+            .collect()
+    }
+}
+
+/// Password of the protected work at `url`, if one is configured.
+#[cfg_attr(not(feature = "fanficfare"), allow(dead_code))]
+pub fn password_for(url: &str) -> Option<String> {
+    loaded_config_file().password_for(url)
+}
+
+/// Sub-directory of the work directory where the book of `url` is created, if routed.
+pub fn route_for(url: &str) -> Option<PathBuf> {
+    loaded_config_file()
+        .routes
+        .iter()
+        .find(|route| route.matches(url))
+        .map(|route| route.dir.clone())
+}
+
+/// Whether the source named `name` is disabled in the configuration.
+pub fn is_source_disabled(name: &str) -> bool {
+    loaded_config_file()
+        .disabled_sources
+        .iter()
+        .any(|disabled| disabled.eq_ignore_ascii_case(name))
+}
+
+/// Sources to try first, by name.
+pub fn source_priority() -> &'static [String] {
+    &loaded_config_file().source_priority
+}
+
+/// Name of the source which must handle the book at `url`, if one is configured.
+pub fn book_source(url: &str) -> Option<&'static str> {
+    loaded_config_file()
+        .book_sources
+        .get(url.trim_end_matches('/'))
+        .map(String::as_str)
+}
+
+/// Sites configured for the generic source.
+pub fn sites() -> &'static [Site] {
+    &loaded_config_file().sites
+}
+
+/// Configured site of `url`, if any.
+pub fn site_for(url: &str) -> Option<&'static Site> {
+    sites().iter().find(|site| host_matches(&site.host, url))
+}
+
+/// `FanFicFare` options of the book at `url`, the global ones first and the book's ones last
+/// so that they take precedence.
+#[cfg_attr(not(feature = "fanficfare"), allow(dead_code))]
+pub fn site_options_for(url: &str) -> Vec<String> {
+    loaded_config_file().site_options_for(url)
+}
+
+/// Royal Road account, if configured.
+pub fn royal_road_account() -> Option<&'static Account> {
+    loaded_config_file().royalroad.as_ref()
+}
+
+/// Plugins declared in the configuration.
+pub fn plugins() -> &'static [Plugin] {
+    &loaded_config_file().plugins
+}
+
+/// Plugin handling the book of `url`, if any.
+pub fn plugin_for(url: &str) -> Option<&'static Plugin> {
+    plugins()
+        .iter()
+        .find(|plugin| plugin.hosts.iter().any(|host| host_matches(host, url)))
+}
+
+/// Headers configured for the host of `url`, the ones of a sub-domain coming after (and so
+/// replacing) the ones of its parent domain.
+pub fn headers_for(url: &str) -> Vec<(&'static String, &'static String)> {
+    loaded_config_file().headers_for(url)
+}
+
+pub fn royalroad_selectors() -> &'static RoyalRoadSelectors {
+    &loaded_config_file().royalroad_selectors
+}
+
+pub fn fichub_hosts() -> &'static [String] {
+    &loaded_config_file().fichub_hosts
+}
+
+/// Whether the book of `url` is fetched through `FicHub`.
+pub fn uses_fichub(url: &str) -> bool {
+    fichub_hosts().iter().any(|host| host_matches(host, url))
+}
+
+fn expand_home(path: PathBuf) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(relative), Some(home)) => home.join(relative),
+        _ => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window() -> Result<()> {
+        let window = Window {
+            host: None,
+            hours: Some(String::from("22:00-06:00")),
+            days: vec![String::from("sat")],
+        };
+        let time = |t: &str| NaiveDateTime::parse_from_str(t, "%Y-%m-%d %H:%M");
+        // 2024-06-01 was a Saturday.
+        assert!(window.contains(time("2024-06-01 23:30")?));
+        assert!(window.contains(time("2024-06-01 05:59")?));
+        assert!(!window.contains(time("2024-06-01 12:00")?));
+        assert!(!window.contains(time("2024-06-02 23:30")?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_windows_of_hosts() -> Result<()> {
+        let config_file: ConfigFile = toml_edit::de::from_str(
+            r#"
+            [[windows]]
+            host = "royalroad.com"
+            hours = "02:00-06:00"
+
+            [[windows]]
+            hours = "22:00-23:00"
+            "#,
+        )?;
+        let allowed = |url: &str, time: &str| -> Result<bool> {
+            let time = NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M")?;
+            Ok(windows_allow(&config_file.windows, url, time))
+        };
+        // The window of the host replaces the one without a host.
+        assert!(allowed(
+            "https://www.royalroad.com/fiction/1",
+            "2024-06-01 03:00"
+        )?);
+        assert!(!allowed(
+            "https://www.royalroad.com/fiction/1",
+            "2024-06-01 22:30"
+        )?);
+        assert!(allowed(
+            "https://archiveofourown.org/works/1",
+            "2024-06-01 22:30"
+        )?);
+        assert!(!allowed(
+            "https://archiveofourown.org/works/1",
+            "2024-06-01 03:00"
+        )?);
+        // Without any window, the books may be updated at any time.
+        assert!(windows_allow(
+            &[],
+            "https://archiveofourown.org/works/1",
+            NaiveDateTime::parse_from_str("2024-06-01 03:00", "%Y-%m-%d %H:%M")?
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_profile() {
+        let contents = r#"
+            [profiles.ongoing]
+            dir = "/books/ongoing"
+            koreader = true
+
+            [profiles.completed]
+            dir = "/books/completed"
+            max_images = 50
+            cbz = false
+            summary_format = "by-directory"
+            healthcheck_events = ["failure"]
+        "#;
+        let profile = parse(contents, "completed");
+        assert!(profile
+            .as_ref()
+            .is_ok_and(|p| p.dir == Some(PathBuf::from("/books/completed"))
+                && p.max_images == Some(50)
+                && p.cbz == Some(false)
+                && p.summary_format == Some(summary::Format::ByDirectory)
+                && p.healthcheck_events == Some(vec![HealthcheckEvent::Failure])
+                && p.koreader.is_none()));
+
+        assert!(parse(contents, "nsfw").is_err());
+        assert!(parse("[profiles.ongoing]\nunknown = 1", "ongoing").is_err());
+    }
+
+    #[test]
+    fn test_routes() -> Result<()> {
+        let config_file: ConfigFile = toml_edit::de::from_str(
+            r#"
+            [[routes]]
+            host = "royalroad.com"
+            pattern = "/fiction/1\\b"
+            dir = "royalroad/favourites"
+
+            [[routes]]
+            host = "royalroad.com"
+            dir = "royalroad"
+
+            [[routes]]
+            pattern = "archiveofourown|fanfiction\\.net"
+            dir = "fanfic"
+            "#,
+        )?;
+        let route = |url: &str| {
+            config_file
+                .routes
+                .iter()
+                .find(|r| r.matches(url))
+                .map(|r| r.dir.clone())
+        };
+        // The first route matching both the host and the pattern wins.
+        assert_eq!(
+            route("https://www.royalroad.com/fiction/1"),
+            Some(PathBuf::from("royalroad/favourites"))
+        );
+        assert_eq!(
+            route("https://www.royalroad.com/fiction/12"),
+            Some(PathBuf::from("royalroad"))
+        );
+        assert_eq!(
+            route("https://archiveofourown.org/works/1"),
+            Some(PathBuf::from("fanfic"))
+        );
+        assert_eq!(route("https://example.com/story"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_config_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("config.toml");
+        assert!(read_config_file(&path)?.is_none());
+
+        fs::write(&path, "disabled_sources = [\"Wattpad\"]")?;
+        let config_file = read_config_file(&path)?.ok_or_else(|| eyre!("No config"))?;
+        assert_eq!(config_file.disabled_sources, ["Wattpad"]);
+
+        fs::write(&path, "disabled_sources = \"Wattpad\"")?;
+        let error = read_config_file(&path)
+            .err()
+            .ok_or_else(|| eyre!("The invalid file was accepted"))?;
+        assert!(error.to_string().contains(&path.display().to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_documented_config_file() -> Result<()> {
+        // The example of the documentation of `ConfigFile`, without its `/// ` prefixes.
+        let example = include_str!("profile.rs")
+            .lines()
+            .skip_while(|line| *line != "/// ```toml")
+            .skip(1)
+            .take_while(|line| *line != "/// ```")
+            .map(|line| line.trim_start_matches("///").trim_start())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let config_file: ConfigFile = toml_edit::de::from_str(&example)?;
+        assert_eq!(config_file.disabled_sources, ["Royal Road"]);
+        assert_eq!(config_file.fichub_hosts, ["archiveofourown.org"]);
+        assert_eq!(config_file.plugins.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_passwords() -> Result<()> {
+        let config_file: ConfigFile = toml_edit::de::from_str(
+            r#"
+            [passwords]
+            "https://forums.example.com/threads/some-story.123" = "secret"
+            "#,
+        )?;
+        assert_eq!(
+            config_file
+                .password_for("https://forums.example.com/threads/some-story.123/")
+                .as_deref(),
+            Some("secret")
+        );
+        assert_eq!(
+            config_file.password_for("https://forums.example.com/threads/other.4"),
+            None
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_site_options() -> Result<()> {
+        let config_file: ConfigFile = toml_edit::de::from_str(
+            r#"
+            site_options = ["is_adult=true"]
+
+            [host_site_options]
+            "archiveofourown.org" = ["view_adult=true"]
+            "www.archiveofourown.org" = ["collect_series=false"]
+
+            [book_site_options]
+            "https://www.archiveofourown.org/works/1" = ["include_images=false"]
+            "#,
+        )?;
+        assert_eq!(
+            config_file.site_options_for("https://www.archiveofourown.org/works/1/"),
+            [
+                "is_adult=true",
+                "view_adult=true",
+                "collect_series=false",
+                "include_images=false"
+            ]
+        );
+        assert_eq!(
+            config_file.site_options_for("https://www.royalroad.com/fiction/1"),
+            ["is_adult=true"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_host_headers() -> Result<()> {
+        let config_file: ConfigFile = toml_edit::de::from_str(
+            r#"
+            [host_headers."royalroadcdn.com"]
+            Referer = "https://www.royalroad.com/"
+
+            [host_headers."www.royalroadcdn.com"]
+            Referer = "https://www.royalroad.com/fictions"
+            "#,
+        )?;
+        let headers = |url: &str| -> Vec<(String, String)> {
+            config_file
+                .headers_for(url)
+                .into_iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect()
+        };
+        // The headers of the sub-domain come last, to replace the others.
+        assert_eq!(
+            headers("https://www.royalroadcdn.com/cover.jpg").last(),
+            Some(&(
+                String::from("Referer"),
+                String::from("https://www.royalroad.com/fictions")
+            ))
+        );
+        assert_eq!(headers("https://cdn.royalroadcdn.com/a.png").len(), 1);
+        assert!(headers("https://example.com/").is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,214 @@
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::config::{Config, ImageBudget, ImageHostRules, ImagePriority, ParallelText};
+use crate::{ErrorPrint, MULTI_PROGRESS};
+
+/// Settings file applying to the books of its directory and of its sub-directories.
+const SETTINGS_FILENAME: &str = ".autebook.toml";
+/// Extension of the file, next to a book, listing the chapters in reading order.
+const READING_ORDER_EXTENSION: &str = "order";
+/// Extension of the e-book, next to a translated book, of its original text.
+const ORIGINAL_TEXT_EXTENSION: &str = "original.epub";
+
+lazy_static! {
+    /// Parsed settings file of each directory, `None` if it has none (or an invalid one).
+    static ref DIR_SETTINGS: Mutex<HashMap<PathBuf, Option<DirSettings>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Content of a `.autebook.toml`, unset values are inherited from the parent directories.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct DirSettings {
+    max_images: Option<usize>,
+    /// In MB, like the command line option.
+    max_images_size: Option<u64>,
+    images_priority: Option<ImagePriority>,
+    cbz: Option<bool>,
+    allow_image_host: Option<Vec<String>>,
+    deny_image_host: Option<Vec<String>>,
+    skip_chapters: Option<Vec<String>>,
+    parallel_text: Option<ParallelText>,
+    warnings_page: Option<bool>,
+}
+
+/// Settings of a single book, the global configuration overridden by the settings files
+/// of its directory and its ancestors (the closest one wins).
+#[derive(Debug, Clone)]
+pub struct BookSettings {
+    pub image_budget: ImageBudget,
+    pub export_cbz: bool,
+    pub image_hosts: ImageHostRules,
+    pub skip_chapters: Vec<String>,
+    pub parallel_text: Option<ParallelText>,
+    pub warnings_page: bool,
+}
+
+impl BookSettings {
+    fn apply(&mut self, dir_settings: DirSettings) {
+        if let Some(max_count) = dir_settings.max_images {
+            self.image_budget.max_count = Some(max_count);
+        }
+        if let Some(max_size) = dir_settings.max_images_size {
+            self.image_budget.max_size = Some(max_size.saturating_mul(1024 * 1024));
+        }
+        if let Some(priority) = dir_settings.images_priority {
+            self.image_budget.priority = priority;
+        }
+        if let Some(export_cbz) = dir_settings.cbz {
+            self.export_cbz = export_cbz;
+        }
+        if let Some(allow) = dir_settings.allow_image_host {
+            self.image_hosts.allow = allow;
+        }
+        if let Some(deny) = dir_settings.deny_image_host {
+            self.image_hosts.deny = deny;
+        }
+        if let Some(skip_chapters) = dir_settings.skip_chapters {
+            self.skip_chapters = skip_chapters;
+        }
+        if let Some(parallel_text) = dir_settings.parallel_text {
+            self.parallel_text = Some(parallel_text);
+        }
+        if let Some(warnings_page) = dir_settings.warnings_page {
+            self.warnings_page = warnings_page;
+        }
+    }
+}
+
+pub fn for_book(book: &Path) -> BookSettings {
+    let config = Config::get();
+    let mut settings = BookSettings {
+        image_budget: config.image_budget.clone(),
+        export_cbz: config.export_cbz,
+        image_hosts: config.image_hosts.clone(),
+        skip_chapters: config.skip_chapters.clone(),
+        parallel_text: config.parallel_text,
+        warnings_page: config.warnings_page,
+    };
+
+    // The book itself may not exist yet.
+    let Some(dir) = book
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."))
+        .canonicalize()
+        .ok()
+    else {
+        return settings;
+    };
+    let ancestors: Vec<&Path> = dir.ancestors().collect();
+    for dir in ancestors.into_iter().rev() {
+        if let Some(dir_settings) = dir_settings(dir) {
+            settings.apply(dir_settings);
+        }
+    }
+    settings
+}
+
+/// Chapters (identifiers or titles, one per line) of the book's reading-order file, if it has one.
+/// Blank lines and lines starting with `#` are ignored.
+pub fn reading_order(book: &Path) -> Option<Vec<String>> {
+    let contents = fs::read_to_string(reading_order_file(book)).ok()?;
+    Some(
+        contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(String::from)
+            .collect(),
+    )
+}
+
+/// Reading-order file of the book at `book`, see `reading_order`.
+pub fn reading_order_file(book: &Path) -> PathBuf {
+    book.with_extension(READING_ORDER_EXTENSION)
+}
+
+/// The e-book of the original text of the translated book at `book`, if it has one.
+pub fn original_text(book: &Path) -> Option<PathBuf> {
+    Some(book.with_extension(ORIGINAL_TEXT_EXTENSION)).filter(|path| path.is_file())
+}
+
+/// Whether `path` is the original text of a translated book, see `original_text`.
+pub fn is_original_text(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.ends_with(&format!(".{ORIGINAL_TEXT_EXTENSION}")))
+}
+
+fn dir_settings(dir: &Path) -> Option<DirSettings> {
+    let Ok(mut cache) = DIR_SETTINGS.lock() else {
+        return read(dir);
+    };
+    cache
+        .entry(dir.to_path_buf())
+        .or_insert_with(|| read(dir))
+        .clone()
+}
+
+fn read(dir: &Path) -> Option<DirSettings> {
+    let path = dir.join(SETTINGS_FILENAME);
+    let contents = fs::read_to_string(&path).ok()?;
+    toml_edit::de::from_str(&contents)
+        .map_err(|e| MULTI_PROGRESS.eprintln(&format!("Ignored {} : {e}", path.display())))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_closest_settings_win() {
+        let mut settings = BookSettings {
+            image_budget: ImageBudget::default(),
+            export_cbz: true,
+            image_hosts: ImageHostRules::default(),
+            skip_chapters: Vec::new(),
+            parallel_text: None,
+            warnings_page: false,
+        };
+        for contents in [
+            "max_images = 10\ncbz = false\nparallel_text = \"append\"",
+            "max_images = 5\nimages_priority = \"smallest-first\"\nwarnings_page = true",
+        ] {
+            let dir_settings: Result<DirSettings, _> = toml_edit::de::from_str(contents);
+            assert!(dir_settings.is_ok());
+            settings.apply(dir_settings.unwrap_or_default());
+        }
+        assert_eq!(settings.image_budget.max_count, Some(5));
+        assert_eq!(settings.image_budget.priority, ImagePriority::SmallestFirst);
+        assert!(!settings.export_cbz);
+        assert_eq!(settings.parallel_text, Some(ParallelText::Append));
+        assert!(settings.warnings_page);
+
+        assert!(toml_edit::de::from_str::<DirSettings>("unknown = 1").is_err());
+    }
+
+    #[test]
+    fn test_settings_of_nested_directories() -> eyre::Result<()> {
+        let library = tempfile::tempdir()?;
+        let comics = library.path().join("comics");
+        fs::create_dir(&comics)?;
+        fs::write(library.path().join(SETTINGS_FILENAME), "max_images = 10")?;
+        fs::write(
+            comics.join(SETTINGS_FILENAME),
+            "max_images = 50\ncbz = true",
+        )?;
+
+        let book = for_book(&library.path().join("book.epub"));
+        assert_eq!(book.image_budget.max_count, Some(10));
+        assert!(!book.export_cbz);
+
+        let comic = for_book(&comics.join("comic.epub"));
+        assert_eq!(comic.image_budget.max_count, Some(50));
+        assert!(comic.export_cbz);
+        Ok(())
+    }
+}
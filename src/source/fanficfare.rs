@@ -1,4 +1,4 @@
-use super::Source;
+use super::{Capabilities, Source};
 use crate::updater::FanFicFare;
 use crate::updater::WebNovel;
 
@@ -10,6 +10,20 @@ impl Source for FanFicFareCompatible {
         Some(Box::new(FanFicFare::new()))
     }
 
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            name: "FanFicFare",
+            native: false,
+            auth: true,
+            early_chapters: false,
+            cover_refresh: true,
+            volumes: false,
+            feed: false,
+            ratings: true,
+            url_patterns: URLS.iter().map(|url| format!("*{url}*")).collect(),
+        }
+    }
+
     fn new(fiction_url: &str) -> Option<Self> {
         if URLS
             .iter()
@@ -0,0 +1,73 @@
+use super::{page_content, Book, Capabilities, Chapter, Source};
+use crate::updater::native::{ffnet, quick_check};
+use crate::updater::Native;
+use crate::updater::WebNovel;
+use eyre::Result;
+use lazy_regex::regex;
+use scraper::Html;
+
+/// Story of FanFiction.net or `FictionPress`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct FanFictionNet {
+    id: u32,
+}
+
+impl Source for FanFictionNet {
+    fn get_updater(&self) -> Option<Box<dyn WebNovel>> {
+        Some(Box::new(Native::new()))
+    }
+
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            name: "FanFiction.net",
+            native: true,
+            auth: false,
+            early_chapters: false,
+            cover_refresh: true,
+            volumes: false,
+            feed: false,
+            ratings: true,
+            url_patterns: vec![
+                String::from("https://www.fanfiction.net/s/<id>"),
+                String::from("https://www.fictionpress.com/s/<id>"),
+            ],
+        }
+    }
+
+    fn new(story_url: &str) -> Option<Self> {
+        let story_url_pattern =
+            regex!(r"^https://(www\.|m\.)?(fanfiction\.net|fictionpress\.com)/s/(\d+)(/.*)?$");
+        let captures = story_url_pattern.captures(story_url)?;
+        let id = captures[3].parse::<u32>().ok()?;
+        Some(Self { id })
+    }
+
+    fn fetch_book(&self, url: &str) -> Result<Book> {
+        ffnet::fetch_book(url)
+    }
+
+    fn book_id(&self, _url: &str) -> Result<u32> {
+        Ok(self.id)
+    }
+
+    fn validator(&self, url: &str) -> Option<String> {
+        quick_check::validator(url)
+    }
+
+    fn read_page(&self, chapter: &mut Chapter, page: &Html) -> Result<()> {
+        page_content(chapter, ffnet::chapter_content(page))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let source = FanFictionNet::new("https://www.fanfiction.net/s/42/1/a-story");
+        assert_eq!(source, Some(FanFictionNet { id: 42 }));
+        assert!(FanFictionNet::new("https://www.fictionpress.com/s/42/").is_some());
+        assert!(FanFictionNet::new("https://www.fanfiction.net/u/42/someone").is_none());
+    }
+}
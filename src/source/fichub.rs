@@ -0,0 +1,45 @@
+use super::{Book, Capabilities, Source};
+use crate::profile;
+use crate::updater::native::{fichub, generic};
+use crate::updater::Native;
+use crate::updater::WebNovel;
+use eyre::Result;
+
+/// Book of a site blocking scraping, fetched through the `FicHub` API.
+#[derive(Debug, PartialEq, Eq)]
+pub struct FicHub;
+
+impl Source for FicHub {
+    fn get_updater(&self) -> Option<Box<dyn WebNovel>> {
+        Some(Box::new(Native::new()))
+    }
+
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            name: "FicHub",
+            native: true,
+            auth: false,
+            early_chapters: false,
+            cover_refresh: false,
+            volumes: false,
+            feed: false,
+            ratings: false,
+            url_patterns: profile::fichub_hosts()
+                .iter()
+                .map(|host| format!("https://{host}/<story>"))
+                .collect(),
+        }
+    }
+
+    fn new(story_url: &str) -> Option<Self> {
+        profile::uses_fichub(story_url).then_some(Self)
+    }
+
+    fn fetch_book(&self, url: &str) -> Result<Book> {
+        fichub::fetch_book(url)
+    }
+
+    fn book_id(&self, url: &str) -> Result<u32> {
+        Ok(generic::book_id(url))
+    }
+}
@@ -0,0 +1,29 @@
+use super::{Book, Chapter};
+use crate::updater::native::{fimfiction, quick_check};
+use eyre::Result;
+use futures_util::future::BoxFuture;
+
+story_source! {
+    /// Story of Fimfiction.
+    Fimfiction {
+        name: "Fimfiction",
+        url: r"^https://(www\.)?fimfiction\.net/story/(?P<id>\d+)(/.*)?$",
+        url_pattern: "https://www.fimfiction.net/story/<id>",
+        ratings: true,
+    }
+
+    fn fetch_book(&self, url: &str) -> Result<Book> {
+        fimfiction::fetch_book(url)
+    }
+
+    fn chapter_content<'a>(
+        &self,
+        chapter: &'a Chapter,
+    ) -> Option<BoxFuture<'a, Result<String>>> {
+        Some(Box::pin(fimfiction::chapter_content(&chapter.identifier)))
+    }
+
+    fn validator(&self, url: &str) -> Option<String> {
+        quick_check::validator(url)
+    }
+}
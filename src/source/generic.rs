@@ -0,0 +1,45 @@
+use super::{Book, Capabilities, Source};
+use crate::profile;
+use crate::updater::native::generic;
+use crate::updater::Native;
+use crate::updater::WebNovel;
+use eyre::Result;
+
+/// Book of a site configured with selectors, given by the URL of its first chapter.
+#[derive(Debug, PartialEq, Eq)]
+pub struct GenericHtml;
+
+impl Source for GenericHtml {
+    fn get_updater(&self) -> Option<Box<dyn WebNovel>> {
+        Some(Box::new(Native::new()))
+    }
+
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            name: "Generic HTML",
+            native: true,
+            auth: false,
+            early_chapters: false,
+            cover_refresh: false,
+            volumes: false,
+            feed: false,
+            ratings: false,
+            url_patterns: profile::sites()
+                .iter()
+                .map(|site| format!("https://{}/<first chapter>", site.host))
+                .collect(),
+        }
+    }
+
+    fn new(first_chapter_url: &str) -> Option<Self> {
+        profile::site_for(first_chapter_url).map(|_| Self)
+    }
+
+    fn fetch_book(&self, url: &str) -> Result<Book> {
+        generic::fetch_book(url)
+    }
+
+    fn book_id(&self, url: &str) -> Result<u32> {
+        Ok(generic::book_id(url))
+    }
+}
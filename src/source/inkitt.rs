@@ -0,0 +1,26 @@
+use super::{page_content, Book, Chapter};
+use crate::updater::native::{inkitt, quick_check};
+use eyre::Result;
+use scraper::Html;
+
+story_source! {
+    /// Story of Inkitt.
+    Inkitt {
+        name: "Inkitt",
+        url: r"^https://(www\.)?inkitt\.com/stories/[\w-]+/(?P<id>\d+)(/.*)?$",
+        url_pattern: "https://www.inkitt.com/stories/<genre>/<id>",
+        ratings: false,
+    }
+
+    fn fetch_book(&self, url: &str) -> Result<Book> {
+        inkitt::fetch_book(url)
+    }
+
+    fn read_page(&self, chapter: &mut Chapter, page: &Html) -> Result<()> {
+        page_content(chapter, inkitt::chapter_content(page))
+    }
+
+    fn validator(&self, url: &str) -> Option<String> {
+        quick_check::validator(url)
+    }
+}
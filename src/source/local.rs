@@ -0,0 +1,70 @@
+use super::{Book, Capabilities, Source};
+use crate::updater::native::{generic, local};
+use crate::updater::Native;
+use crate::updater::WebNovel;
+use eyre::Result;
+use std::path::PathBuf;
+use url::Url;
+
+/// Directory of numbered Markdown or HTML chapters.
+#[derive(Debug, PartialEq, Eq)]
+pub struct LocalFolder {
+    dir: PathBuf,
+}
+
+impl Source for LocalFolder {
+    fn get_updater(&self) -> Option<Box<dyn WebNovel>> {
+        Some(Box::new(Native::new()))
+    }
+
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            name: "Local folder",
+            native: true,
+            auth: false,
+            early_chapters: false,
+            cover_refresh: false,
+            volumes: false,
+            feed: false,
+            ratings: false,
+            url_patterns: vec![
+                String::from("file:///<directory>"),
+                String::from("<directory> (add only)"),
+            ],
+        }
+    }
+
+    fn new(folder_url: &str) -> Option<Self> {
+        let dir = Url::parse(folder_url)
+            .ok()
+            .filter(|u| u.scheme() == "file")?
+            .to_file_path()
+            .ok()?;
+        Some(Self { dir })
+    }
+
+    fn fetch_book(&self, url: &str) -> Result<Book> {
+        local::fetch_book(url)
+    }
+
+    fn book_id(&self, url: &str) -> Result<u32> {
+        Ok(generic::book_id(url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let source = LocalFolder::new("file:///home/me/my-serial/");
+        assert_eq!(
+            source,
+            Some(LocalFolder {
+                dir: PathBuf::from("/home/me/my-serial/")
+            })
+        );
+        assert!(LocalFolder::new("https://example.com/my-serial/").is_none());
+    }
+}
@@ -1,19 +1,140 @@
+// First, for the sources declared with its macro.
+#[macro_use]
+mod story;
 #[cfg(feature = "fanficfare")]
 mod fanficfare;
+mod ffnet;
+mod fichub;
+mod fimfiction;
+mod generic;
+mod inkitt;
+mod local;
+pub mod plugin;
+mod quotev;
 mod royalroad;
-use crate::updater::WebNovel;
+mod wattpad;
+mod wuxiaworld;
+mod xenforo;
+use crate::error::Error;
+use crate::profile;
+use crate::updater::native::{Book, Chapter};
+use crate::updater::{Unsupported as UnsupportedBook, WebNovel};
+use eyre::{eyre, Result};
+use futures_util::future::BoxFuture;
+use scraper::Html;
+use url::Url;
 
 #[cfg(feature = "fanficfare")]
 use self::fanficfare::FanFicFareCompatible;
+use self::ffnet::FanFictionNet;
+use self::fichub::FicHub;
+use self::fimfiction::Fimfiction;
+use self::generic::GenericHtml;
+use self::inkitt::Inkitt;
+use self::local::LocalFolder;
+use self::plugin::PluginSource;
+use self::quotev::Quotev;
 use self::royalroad::RoyalRoad;
+use self::wattpad::Wattpad;
+use self::wuxiaworld::Wuxiaworld;
+use self::xenforo::XenForo;
 
-pub trait Source {
+/// What a source supports, as reported by `autebooks sources`.
+#[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Capabilities {
+    pub name: &'static str,
+    /// Whether autebooks writes the books itself, rather than an external program.
+    pub native: bool,
+    /// Whether protected works can be fetched, with the configured passwords.
+    pub auth: bool,
+    /// Whether chapters only available to patrons can be fetched.
+    pub early_chapters: bool,
+    /// Whether a changed cover is noticed and replaced.
+    pub cover_refresh: bool,
+    /// Whether the chapters are grouped by volume (or arc) in the table of contents.
+    pub volumes: bool,
+    /// Whether the new chapters are read from a feed, without fetching the page of the book.
+    pub feed: bool,
+    /// Whether the books are given a content rating, for the rating filter of `add`.
+    pub ratings: bool,
+    /// URLs handled by the source.
+    pub url_patterns: Vec<String>,
+}
+
+pub trait Source: Sync {
     fn new(url: &str) -> Option<Self>
+    where
+        Self: Sized;
+    fn capabilities() -> Capabilities
     where
         Self: Sized;
     fn get_updater(&self) -> Option<Box<dyn WebNovel>> {
         None
     }
+
+    /// Book at `url` with its chapters, the content of which may be left to `chapter_content`
+    /// or `read_page`.
+    fn fetch_book(&self, _url: &str) -> Result<Book> {
+        Err(UnsupportedBook.into())
+    }
+
+    /// Id of the book at `url`, naming its cache.
+    fn book_id(&self, url: &str) -> Result<u32> {
+        path_id(url)
+    }
+
+    /// Content of `chapter` from the API of the source, for the sources whose pages only show
+    /// the start of it.
+    fn chapter_content<'a>(&self, _chapter: &'a Chapter) -> Option<BoxFuture<'a, Result<String>>> {
+        None
+    }
+
+    /// Feed of the latest chapters of `book`, for the sources which have one.
+    fn feed_url(&self, _book: &Book) -> Option<String> {
+        None
+    }
+
+    /// Identifier of the chapter at `url`, for the chapters found outside of the page of their
+    /// book (in its feed, or fetched on their own).
+    fn chapter_id(&self, _url: &str) -> Option<String> {
+        None
+    }
+
+    /// Current validator of the book at `url` (a modification date), for the sources without a
+    /// feed which are first checked against the one stored in the book.
+    fn validator(&self, _url: &str) -> Option<String> {
+        None
+    }
+
+    /// Read the content (and the author's notes) of `chapter` from its page.
+    fn read_page(&self, chapter: &mut Chapter, _page: &Html) -> Result<()> {
+        Err(eyre!(
+            "The chapters of this source cannot be read from their page : {}",
+            chapter.url
+        ))
+    }
+}
+
+/// Id of the book given by the first segment of the path of `url`, as in
+/// `https://www.royalroad.com/fiction/<id>`.
+fn path_id(url: &str) -> Result<u32> {
+    Url::parse(url)?
+        .path_segments()
+        .and_then(|mut s| s.nth(1))
+        // XenForo threads end their slug with their id (`a-quest.123`).
+        .and_then(|f| {
+            f.parse()
+                .ok()
+                .or_else(|| f.rsplit('.').next()?.parse().ok())
+        })
+        .ok_or_else(|| eyre!("Invalid book URL: {url}"))
+}
+
+/// Set `content`, found in the page of `chapter`, as its content.
+fn page_content(chapter: &mut Chapter, content: Option<String>) -> Result<()> {
+    chapter.content = Some(content.ok_or_else(|| Error::Parse(String::from("No content found")))?);
+    Ok(())
 }
 
 pub struct Unsupported;
@@ -21,19 +142,202 @@ impl Source for Unsupported {
     fn new(_url: &str) -> Option<Self> {
         None
     }
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            name: "Unsupported",
+            native: false,
+            auth: false,
+            early_chapters: false,
+            cover_refresh: false,
+            volumes: false,
+            feed: false,
+            ratings: false,
+            url_patterns: Vec::new(),
+        }
+    }
 }
 
-macro_rules! try_source {
-    ($book_source:ident, $url:expr) => {{
-        if let Some(fiction) = $book_source::new($url) {
-            return Box::new(fiction);
-        }
-    }};
+/// A source, to be tried on URLs.
+pub struct Provider {
+    pub capabilities: fn() -> Capabilities,
+    new: fn(&str) -> Option<Box<dyn Source>>,
 }
 
-pub fn get(url: &str) -> Box<dyn Source> {
-    try_source!(RoyalRoad, url);
+fn boxed<S: Source + 'static>(url: &str) -> Option<Box<dyn Source>> {
+    S::new(url).map(|source| Box::new(source) as Box<dyn Source>)
+}
+
+fn provider<S: Source + 'static>() -> Provider {
+    Provider {
+        capabilities: S::capabilities,
+        new: boxed::<S>,
+    }
+}
+
+/// Every compiled-in source, in the order they are tried : the ones of the configured priority
+/// first, then the others in their default order.
+pub fn providers() -> Vec<Provider> {
+    prioritized(default_providers(), profile::source_priority())
+}
+
+/// `providers` with the ones named in `priority` first, in this order.
+fn prioritized(mut providers: Vec<Provider>, priority: &[String]) -> Vec<Provider> {
+    providers.sort_by_key(|provider| {
+        let name = (provider.capabilities)().name;
+        priority
+            .iter()
+            .position(|p| p.eq_ignore_ascii_case(name))
+            .unwrap_or(priority.len())
+    });
+    providers
+}
+
+fn default_providers() -> Vec<Provider> {
+    let mut providers = vec![
+        // Only for the configured hosts, which block scraping.
+        provider::<FicHub>(),
+        provider::<RoyalRoad>(),
+        provider::<XenForo>(),
+        provider::<FanFictionNet>(),
+        provider::<Wattpad>(),
+        provider::<Fimfiction>(),
+        provider::<Wuxiaworld>(),
+        provider::<Quotev>(),
+        provider::<Inkitt>(),
+        provider::<LocalFolder>(),
+        provider::<PluginSource>(),
+    ];
     #[cfg(feature = "fanficfare")]
-    try_source!(FanFicFareCompatible, url);
-    Box::new(Unsupported {})
+    providers.push(provider::<FanFicFareCompatible>());
+    // Last resort, for the sites configured with selectors.
+    providers.push(provider::<GenericHtml>());
+    providers
+}
+
+/// The sources tried on `url` : the one configured for this book if any,
+/// then the ones which are not disabled.
+fn providers_for(url: &str) -> impl Iterator<Item = Provider> {
+    selected(
+        providers(),
+        profile::book_source(url),
+        profile::is_source_disabled,
+    )
+}
+
+/// `providers` with the one named `book_source` first, whether it is disabled or not, and
+/// without the other disabled ones.
+fn selected(
+    providers: Vec<Provider>,
+    book_source: Option<&str>,
+    is_disabled: impl Fn(&str) -> bool,
+) -> impl Iterator<Item = Provider> {
+    let (mut chosen, others): (Vec<Provider>, Vec<Provider>) =
+        providers.into_iter().partition(|provider| {
+            book_source
+                .is_some_and(|name| name.eq_ignore_ascii_case((provider.capabilities)().name))
+        });
+    chosen.extend(
+        others
+            .into_iter()
+            .filter(|provider| !is_disabled((provider.capabilities)().name)),
+    );
+    chosen.into_iter()
+}
+
+pub fn get(url: &str) -> Box<dyn Source> {
+    providers_for(url)
+        .find_map(|provider| (provider.new)(url))
+        .unwrap_or_else(|| Box::new(Unsupported {}))
+}
+
+/// The source of `url` whose books are written by autebooks, as `get` but skipping the
+/// external programs.
+#[cfg_attr(not(feature = "fanficfare"), allow(dead_code))]
+pub fn native(url: &str) -> Box<dyn Source> {
+    providers_for(url)
+        .filter(|provider| (provider.capabilities)().native)
+        .find_map(|provider| (provider.new)(url))
+        .unwrap_or_else(|| Box::new(Unsupported {}))
+}
+
+/// Capabilities of the source which handles `url`, if any.
+pub fn route(url: &str) -> Option<Capabilities> {
+    providers_for(url)
+        .find(|provider| (provider.new)(url).is_some())
+        .map(|provider| (provider.capabilities)())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Name of the first of `providers` handling `url`.
+    fn handler(providers: Vec<Provider>, url: &str) -> Option<&'static str> {
+        providers
+            .into_iter()
+            .find(|provider| (provider.new)(url).is_some())
+            .map(|provider| (provider.capabilities)().name)
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let names: Vec<&str> = default_providers()
+            .iter()
+            .map(|provider| (provider.capabilities)().name)
+            .collect();
+        let mut distinct = names.clone();
+        distinct.sort_unstable();
+        distinct.dedup();
+        assert_eq!(distinct.len(), names.len());
+
+        assert_eq!(
+            handler(
+                default_providers(),
+                "https://www.royalroad.com/fiction/1/a-story"
+            ),
+            Some("Royal Road")
+        );
+        assert_eq!(
+            handler(
+                default_providers(),
+                "https://www.fanfiction.net/s/1/1/A-Story"
+            ),
+            Some("FanFiction.net")
+        );
+        assert_eq!(handler(default_providers(), "https://example.com/"), None);
+    }
+
+    #[test]
+    fn test_disabled_sources() {
+        let royal_road = "https://www.royalroad.com/fiction/1/a-story";
+        let disabled = |name: &str| name == "Royal Road";
+        let providers: Vec<_> = selected(default_providers(), None, disabled).collect();
+        // Another source may still handle it, e.g. FanFicFare.
+        assert_ne!(handler(providers, royal_road), Some("Royal Road"));
+        let providers: Vec<_> = selected(default_providers(), None, |_| false).collect();
+        assert_eq!(handler(providers, royal_road), Some("Royal Road"));
+    }
+
+    #[test]
+    fn test_source_priority() {
+        let names = |providers: Vec<Provider>| -> Vec<&str> {
+            providers
+                .iter()
+                .map(|provider| (provider.capabilities)().name)
+                .collect()
+        };
+        let priority = ["fimfiction", "Wattpad"].map(String::from);
+        let providers = names(prioritized(default_providers(), &priority));
+        assert_eq!(providers[..3], ["Fimfiction", "Wattpad", "FicHub"]);
+        assert_eq!(providers.len(), default_providers().len());
+
+        // The source of a book is used even when disabled.
+        let disabled = |name: &str| name == "Royal Road";
+        let providers: Vec<_> =
+            selected(default_providers(), Some("royal road"), disabled).collect();
+        assert_eq!(
+            handler(providers, "https://www.royalroad.com/fiction/1"),
+            Some("Royal Road")
+        );
+    }
 }
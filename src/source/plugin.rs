@@ -0,0 +1,184 @@
+//! Sources provided by external programs, so that sites can be supported without recompiling.
+//!
+//! A plugin is a program declared in the configuration file, run once per request with a JSON
+//! request on its standard input and answering with a JSON response on its standard output :
+//! - `{"protocol": 1, "method": "book", "url": "..."}` is answered with a [`PluginBook`],
+//! - `{"protocol": 1, "method": "chapter", "url": "...", "identifier": "..."}` is answered
+//!   with a [`PluginChapter`].
+//!
+//! Dates are RFC 3339, contents are HTML, and the URLs of the chapters must be on one of the
+//! hosts of the plugin. A non-zero exit status is an error, whose message is
+//! the standard error of the plugin.
+
+use chrono::{DateTime, Utc};
+use eyre::{bail, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use super::{Book, Capabilities, Chapter, Source};
+use crate::profile::{self, Plugin};
+use crate::updater::native::{self, generic};
+use crate::updater::Native;
+use crate::updater::WebNovel;
+use futures_util::future::BoxFuture;
+
+/// Version of the protocol, increased on incompatible changes.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "method", rename_all = "lowercase")]
+pub enum Request<'a> {
+    Book { url: &'a str },
+    Chapter { url: &'a str, identifier: &'a str },
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    protocol: u32,
+    #[serde(flatten)]
+    request: Request<'a>,
+}
+
+/// Answer to the `book` method : the book with its chapters, without their content.
+#[derive(Debug, Deserialize)]
+pub struct PluginBook {
+    pub title: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub description: String,
+    pub date_published: DateTime<Utc>,
+    #[serde(default)]
+    pub cover_url: String,
+    /// Rating of the book, e.g. `Teen`.
+    pub rating: Option<String>,
+    pub chapters: Vec<PluginChapterInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PluginChapterInfo {
+    pub identifier: String,
+    pub title: String,
+    pub url: String,
+    /// A more recent date makes the chapter download again.
+    pub date_published: DateTime<Utc>,
+    /// Volume (or arc) grouping the chapter in the table of contents.
+    #[serde(default)]
+    pub volume: Option<String>,
+}
+
+/// Answer to the `chapter` method.
+#[derive(Debug, Deserialize)]
+pub struct PluginChapter {
+    pub content: String,
+}
+
+/// Run `plugin` with `request` and parse its answer.
+pub fn call<T: DeserializeOwned>(plugin: &Plugin, request: Request) -> Result<T> {
+    let mut child = Command::new(plugin.program())
+        .args(&plugin.args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(&serde_json::to_vec(&Envelope {
+            protocol: PROTOCOL_VERSION,
+            request,
+        })?)?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!(
+            "The plugin {} failed : {}",
+            plugin.name,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+/// Book handled by one of the configured plugins.
+#[derive(Debug, PartialEq, Eq)]
+pub struct PluginSource {
+    name: String,
+}
+
+impl Source for PluginSource {
+    fn get_updater(&self) -> Option<Box<dyn WebNovel>> {
+        Some(Box::new(Native::new()))
+    }
+
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            name: "Plugins",
+            native: false,
+            auth: false,
+            early_chapters: false,
+            cover_refresh: true,
+            volumes: true,
+            feed: false,
+            ratings: true,
+            url_patterns: profile::plugins()
+                .iter()
+                .flat_map(|plugin| {
+                    plugin
+                        .hosts
+                        .iter()
+                        .map(|host| format!("https://{host}/... ({})", plugin.name))
+                })
+                .collect(),
+        }
+    }
+
+    fn new(url: &str) -> Option<Self> {
+        profile::plugin_for(url).map(|plugin| Self {
+            name: plugin.name.clone(),
+        })
+    }
+
+    fn fetch_book(&self, url: &str) -> Result<Book> {
+        native::plugin::fetch_book(url)
+    }
+
+    fn book_id(&self, url: &str) -> Result<u32> {
+        Ok(generic::book_id(url))
+    }
+
+    fn chapter_content<'a>(&self, chapter: &'a Chapter) -> Option<BoxFuture<'a, Result<String>>> {
+        Some(Box::pin(native::plugin::chapter_content(
+            chapter.url.clone(),
+            chapter.identifier.clone(),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wire_format() -> Result<()> {
+        let request = serde_json::to_string(&Envelope {
+            protocol: PROTOCOL_VERSION,
+            request: Request::Chapter {
+                url: "https://stories.example.org/1/2",
+                identifier: "2",
+            },
+        })?;
+        assert_eq!(
+            request,
+            r#"{"protocol":1,"method":"chapter","url":"https://stories.example.org/1/2","identifier":"2"}"#
+        );
+        let book: PluginBook = serde_json::from_str(
+            r#"{"title":"A Story","date_published":"2024-01-01T00:00:00Z","chapters":[
+            {"identifier":"2","title":"Prologue","url":"https://stories.example.org/1/2",
+            "date_published":"2024-01-01T00:00:00Z"}]}"#,
+        )?;
+        assert_eq!(book.chapters[0].identifier, "2");
+        assert!(book.rating.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,26 @@
+use super::{page_content, Book, Chapter};
+use crate::updater::native::{quick_check, quotev};
+use eyre::Result;
+use scraper::Html;
+
+story_source! {
+    /// Story of Quotev.
+    Quotev {
+        name: "Quotev",
+        url: r"^https://(www\.)?quotev\.com/story/(?P<id>\d+)/[\w-]+(/.*)?$",
+        url_pattern: "https://www.quotev.com/story/<id>/<slug>",
+        ratings: false,
+    }
+
+    fn fetch_book(&self, url: &str) -> Result<Book> {
+        quotev::fetch_book(url)
+    }
+
+    fn read_page(&self, chapter: &mut Chapter, page: &Html) -> Result<()> {
+        page_content(chapter, quotev::chapter_content(page))
+    }
+
+    fn validator(&self, url: &str) -> Option<String> {
+        quick_check::validator(url)
+    }
+}
@@ -1,7 +1,10 @@
-use super::Source;
+use super::{Book, Capabilities, Chapter, Source};
+use crate::updater::native::rss;
 use crate::updater::Native;
 use crate::updater::WebNovel;
+use eyre::Result;
 use lazy_regex::regex;
+use scraper::Html;
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct RoyalRoad {
@@ -13,6 +16,24 @@ impl Source for RoyalRoad {
         Some(Box::new(Native::new()))
     }
 
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            name: "Royal Road",
+            native: true,
+            auth: true,
+            early_chapters: true,
+            cover_refresh: true,
+            volumes: true,
+            feed: true,
+            ratings: true,
+            url_patterns: vec![
+                String::from("https://www.royalroad.com/fiction/<id>"),
+                String::from("https://www.royalroad.com/profile/<id> (add only)"),
+                String::from("https://www.royalroad.com/my/follows (add only)"),
+            ],
+        }
+    }
+
     fn new(fiction_url: &str) -> Option<Self> {
         let fiction_url_pattern =
             regex!(r"^https://www\.royalroad\.com/fiction/(\d+)(/.{0,100})?$");
@@ -20,6 +41,26 @@ impl Source for RoyalRoad {
         let id = captures[1].parse::<u32>().ok()?;
         Some(Self { id })
     }
+
+    fn fetch_book(&self, url: &str) -> Result<Book> {
+        Book::from_royal_road(url)
+    }
+
+    fn book_id(&self, _url: &str) -> Result<u32> {
+        Ok(self.id)
+    }
+
+    fn feed_url(&self, book: &Book) -> Option<String> {
+        Some(rss::feed_url(book))
+    }
+
+    fn chapter_id(&self, url: &str) -> Option<String> {
+        rss::royal_road_chapter_id(url)
+    }
+
+    fn read_page(&self, chapter: &mut Chapter, page: &Html) -> Result<()> {
+        chapter.read_royal_road_page(page)
+    }
 }
 
 #[cfg(test)]
@@ -34,4 +75,24 @@ mod tests {
         let source = RoyalRoad::new("https://www.df.com/fiction/36049/the-primal-hunter");
         assert!(source.is_none());
     }
+
+    #[test]
+    fn test_chapter_id() {
+        let source = RoyalRoad::new("https://www.royalroad.com/fiction/1").expect("Royal Road");
+        assert_eq!(
+            source
+                .chapter_id("https://www.royalroad.com/fiction/1/a/chapter/22/b")
+                .as_deref(),
+            Some("22")
+        );
+        assert_eq!(
+            source
+                .feed_url(&Book {
+                    id: 1,
+                    ..Book::default()
+                })
+                .as_deref(),
+            Some("https://www.royalroad.com/fiction/syndication/1")
+        );
+    }
 }
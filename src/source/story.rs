@@ -0,0 +1,103 @@
+//! Sites written natively whose stories are identified by the number in their URL.
+
+/// Declare the source `$source` of the stories matching `$url`, whose `id` group is the id of
+/// the story, followed by the hooks of the site. `ratings` tells whether the stories are rated.
+macro_rules! story_source {
+    (
+        $(#[$attr:meta])*
+        $source:ident {
+            name: $name:literal,
+            url: $url:literal,
+            url_pattern: $url_pattern:literal,
+            ratings: $ratings:literal $(,)?
+        }
+        $($hooks:tt)*
+    ) => {
+        $(#[$attr])*
+        #[derive(Debug, PartialEq, Eq)]
+        pub struct $source {
+            id: u32,
+        }
+
+        impl $crate::source::Source for $source {
+            fn get_updater(&self) -> Option<Box<dyn $crate::updater::WebNovel>> {
+                Some(Box::new(
+                    <$crate::updater::Native as $crate::updater::WebNovel>::new(),
+                ))
+            }
+
+            fn capabilities() -> $crate::source::Capabilities {
+                $crate::source::Capabilities {
+                    name: $name,
+                    native: true,
+                    auth: false,
+                    early_chapters: false,
+                    cover_refresh: true,
+                    volumes: false,
+                    feed: false,
+                    ratings: $ratings,
+                    url_patterns: vec![String::from($url_pattern)],
+                }
+            }
+
+            fn new(story_url: &str) -> Option<Self> {
+                let id = lazy_regex::regex!($url)
+                    .captures(story_url)?
+                    .name("id")?
+                    .as_str()
+                    .parse()
+                    .ok()?;
+                Some(Self { id })
+            }
+
+            fn book_id(&self, _url: &str) -> eyre::Result<u32> {
+                Ok(self.id)
+            }
+
+            $($hooks)*
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::source::fimfiction::Fimfiction;
+    use crate::source::inkitt::Inkitt;
+    use crate::source::quotev::Quotev;
+    use crate::source::wattpad::Wattpad;
+    use crate::source::Source;
+
+    /// Id of the story at `url`, if `S` handles it.
+    fn story_id<S: Source>(url: &str) -> Option<u32> {
+        S::new(url).and_then(|source| source.book_id(url).ok())
+    }
+
+    #[test]
+    fn test_new() {
+        let wattpad = story_id::<Wattpad>;
+        assert_eq!(
+            wattpad("https://www.wattpad.com/story/42-a-story"),
+            Some(42)
+        );
+        assert_eq!(wattpad("https://www.wattpad.com/7-prologue"), None);
+        let quotev = story_id::<Quotev>;
+        assert_eq!(
+            quotev("https://www.quotev.com/story/42/a-story/3"),
+            Some(42)
+        );
+        assert_eq!(quotev("https://www.quotev.com/quiz/42/a-quiz"), None);
+        let fimfiction = story_id::<Fimfiction>;
+        assert_eq!(
+            fimfiction("https://www.fimfiction.net/story/42/a-story"),
+            Some(42)
+        );
+        assert_eq!(
+            fimfiction("https://www.fimfiction.net/user/42/someone"),
+            None
+        );
+        let inkitt = story_id::<Inkitt>;
+        let chapter_url = "https://www.inkitt.com/stories/fantasy/42/chapters/3";
+        assert_eq!(inkitt(chapter_url), Some(42));
+        assert_eq!(inkitt("https://www.inkitt.com/stories/fantasy"), None);
+    }
+}
@@ -0,0 +1,31 @@
+use super::{Book, Chapter};
+use crate::updater::native::wattpad;
+use eyre::Result;
+use futures_util::future::BoxFuture;
+
+story_source! {
+    /// Story of Wattpad.
+    Wattpad {
+        name: "Wattpad",
+        url: r"^https://(www\.)?wattpad\.com/story/(?P<id>\d+)(-[^/]*)?/?$",
+        url_pattern: "https://www.wattpad.com/story/<id>",
+        ratings: true,
+    }
+
+    fn fetch_book(&self, url: &str) -> Result<Book> {
+        wattpad::fetch_book(url)
+    }
+
+    // The text of the parts comes from the API, their pages only show the start of it.
+    fn chapter_content<'a>(
+        &self,
+        chapter: &'a Chapter,
+    ) -> Option<BoxFuture<'a, Result<String>>> {
+        Some(Box::pin(wattpad::part_content(&chapter.identifier)))
+    }
+
+    // Parts are edited in place, the API gives the date of the last change of the story.
+    fn validator(&self, url: &str) -> Option<String> {
+        wattpad::modify_date(url)
+    }
+}
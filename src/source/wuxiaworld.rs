@@ -0,0 +1,86 @@
+use super::{page_content, Book, Capabilities, Chapter, Source};
+use crate::updater::native::{generic, quick_check, wuxiaworld};
+use crate::updater::Native;
+use crate::updater::WebNovel;
+use eyre::Result;
+use lazy_regex::regex;
+use scraper::Html;
+
+/// Novel of Wuxiaworld.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Wuxiaworld {
+    slug: String,
+}
+
+impl Source for Wuxiaworld {
+    fn get_updater(&self) -> Option<Box<dyn WebNovel>> {
+        Some(Box::new(Native::new()))
+    }
+
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            name: "Wuxiaworld",
+            native: true,
+            auth: false,
+            early_chapters: false,
+            cover_refresh: true,
+            volumes: false,
+            feed: false,
+            ratings: false,
+            url_patterns: vec![String::from("https://www.wuxiaworld.com/novel/<slug>")],
+        }
+    }
+
+    fn new(novel_url: &str) -> Option<Self> {
+        let novel_url_pattern = regex!(r"^https://(www\.)?wuxiaworld\.com/novel/([\w-]+)(/.*)?$");
+        let captures = novel_url_pattern.captures(novel_url)?;
+        Some(Self {
+            slug: captures[2].to_string(),
+        })
+    }
+
+    fn fetch_book(&self, url: &str) -> Result<Book> {
+        wuxiaworld::fetch_book(url)
+    }
+
+    /// Keyed on the slug, so that the novel has the same id from any of its pages.
+    fn book_id(&self, _url: &str) -> Result<u32> {
+        Ok(generic::book_id(&self.slug))
+    }
+
+    fn validator(&self, url: &str) -> Option<String> {
+        quick_check::validator(url)
+    }
+
+    fn read_page(&self, chapter: &mut Chapter, page: &Html) -> Result<()> {
+        page_content(chapter, wuxiaworld::chapter_content(page))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        let source = Wuxiaworld::new("https://www.wuxiaworld.com/novel/a-novel/chapter-1");
+        assert_eq!(
+            source,
+            Some(Wuxiaworld {
+                slug: String::from("a-novel")
+            })
+        );
+        assert!(Wuxiaworld::new("https://www.wuxiaworld.com/novels").is_none());
+    }
+
+    #[test]
+    fn test_book_id() -> Result<()> {
+        let id = |url: &str| Wuxiaworld::new(url).map(|source| source.book_id(url));
+        let novel = id("https://www.wuxiaworld.com/novel/a-novel").transpose()?;
+        let chapter = id("https://wuxiaworld.com/novel/a-novel/chapter-1").transpose()?;
+        let other = id("https://www.wuxiaworld.com/novel/another-novel").transpose()?;
+        assert_eq!(novel, chapter);
+        assert_ne!(novel, other);
+        Ok(())
+    }
+}
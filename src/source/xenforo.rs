@@ -0,0 +1,71 @@
+use super::{page_content, Book, Capabilities, Chapter, Source};
+use crate::updater::native::xenforo;
+use crate::updater::Native;
+use crate::updater::WebNovel;
+use eyre::Result;
+use lazy_regex::regex;
+use scraper::Html;
+
+/// Thread of a `XenForo` forum, updated from its threadmarks.
+#[derive(Debug, PartialEq, Eq)]
+pub struct XenForo {}
+
+impl Source for XenForo {
+    fn get_updater(&self) -> Option<Box<dyn WebNovel>> {
+        Some(Box::new(Native::new()))
+    }
+
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            name: "XenForo forums",
+            native: true,
+            auth: false,
+            early_chapters: false,
+            cover_refresh: false,
+            volumes: false,
+            feed: true,
+            ratings: false,
+            url_patterns: xenforo::HOSTS
+                .iter()
+                .map(|host| format!("https://{host}/threads/<thread>"))
+                .collect(),
+        }
+    }
+
+    fn new(thread_url: &str) -> Option<Self> {
+        let thread_url_pattern = regex!(
+            r"^https://(forums\.spacebattles\.com|forums\.sufficientvelocity\.com|forum\.questionablequesting\.com)/threads/"
+        );
+        thread_url_pattern.is_match(thread_url).then_some(Self {})
+    }
+
+    fn fetch_book(&self, url: &str) -> Result<Book> {
+        xenforo::fetch_book(url)
+    }
+
+    fn feed_url(&self, book: &Book) -> Option<String> {
+        xenforo::feed_url(book)
+    }
+
+    fn chapter_id(&self, url: &str) -> Option<String> {
+        xenforo::post_id(url)
+    }
+
+    // Threadmarked posts are among the other posts of their page, without author's notes.
+    fn read_page(&self, chapter: &mut Chapter, page: &Html) -> Result<()> {
+        page_content(chapter, xenforo::post_content(page, &chapter.identifier))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        assert!(XenForo::new("https://forums.spacebattles.com/threads/a-quest.123/").is_some());
+        assert!(
+            XenForo::new("https://forums.spacebattles.com/forums/creative-writing.18/").is_none()
+        );
+    }
+}
@@ -0,0 +1,155 @@
+//! Differential stash : instead of a whole e-book, only the files which differ from the book
+//! recreated in its place are kept, along with the list of every file of the stashed book.
+
+use eyre::{bail, eyre, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// Extension of the differential stashes, instead of `epub`.
+pub const DELTA_EXTENSION: &str = "epub-delta";
+/// File of the delta listing the files of the stashed book.
+const INDEX_FILENAME: &str = "autebooks-delta.json";
+
+#[derive(Serialize, Deserialize)]
+struct Index {
+    /// Every file of the stashed book, in order, with the sha256 of its contents.
+    files: Vec<(String, String)>,
+}
+
+fn read_files(book: &Path) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut archive = ZipArchive::new(File::open(book)?)?;
+    (0..archive.len())
+        .map(|i| {
+            let mut file = archive.by_index(i)?;
+            let mut contents = Vec::new();
+            file.read_to_end(&mut contents)?;
+            Ok((file.name().to_string(), contents))
+        })
+        .collect()
+}
+
+fn sha256(contents: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(contents))
+}
+
+fn options(name: &str) -> SimpleFileOptions {
+    // The mimetype of an EPUB must not be compressed.
+    let method = if name == "mimetype" {
+        CompressionMethod::Stored
+    } else {
+        CompressionMethod::Deflated
+    };
+    SimpleFileOptions::default().compression_method(method)
+}
+
+/// Write to `delta` the files of `stashed` which `base` does not have as is.
+pub fn write_delta(stashed: &Path, base: &Path, delta: &Path) -> Result<()> {
+    let base_hashes: HashMap<String, String> = read_files(base)?
+        .into_iter()
+        .map(|(name, contents)| (name, sha256(&contents)))
+        .collect();
+    let stashed_files = read_files(stashed)?;
+
+    let mut zip = ZipWriter::new(File::create(delta)?);
+    let mut index = Index { files: Vec::new() };
+    for (name, contents) in stashed_files {
+        let hash = sha256(&contents);
+        if base_hashes.get(&name) != Some(&hash) {
+            zip.start_file(name.as_str(), options(&name))?;
+            zip.write_all(&contents)?;
+        }
+        index.files.push((name, hash));
+    }
+    zip.start_file(INDEX_FILENAME, options(INDEX_FILENAME))?;
+    zip.write_all(serde_json::to_string(&index)?.as_bytes())?;
+    zip.finish()?;
+    Ok(())
+}
+
+/// Rebuild the stashed book from its `delta` and the current `base` book into `out`.
+pub fn reconstruct(delta: &Path, base: &Path, out: &Path) -> Result<()> {
+    let mut delta_files: HashMap<String, Vec<u8>> = read_files(delta)?.into_iter().collect();
+    let index: Index = serde_json::from_slice(
+        &delta_files
+            .remove(INDEX_FILENAME)
+            .ok_or_else(|| eyre!("{} is not a differential stash", delta.display()))?,
+    )?;
+    let mut base_files: HashMap<String, Vec<u8>> = read_files(base)?.into_iter().collect();
+
+    let mut zip = ZipWriter::new(File::create(out)?);
+    for (name, hash) in index.files {
+        let contents = delta_files
+            .remove(&name)
+            .or_else(|| base_files.remove(&name))
+            .ok_or_else(|| eyre!("'{name}' is missing from {}", base.display()))?;
+        if sha256(&contents) != hash {
+            bail!(
+                "'{name}' changed in {} since it was stashed, the stash cannot be rebuilt",
+                base.display()
+            );
+        }
+        zip.start_file(name.as_str(), options(&name))?;
+        zip.write_all(&contents)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_book(path: &Path, files: &[(&str, &str)]) -> Result<()> {
+        let mut zip = ZipWriter::new(File::create(path)?);
+        for (name, contents) in files {
+            zip.start_file(*name, options(name))?;
+            zip.write_all(contents.as_bytes())?;
+        }
+        zip.finish()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_reconstruct_stashed_book() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (stashed, base, delta, out) = (
+            dir.path().join("stashed.epub"),
+            dir.path().join("base.epub"),
+            dir.path().join("stashed.epub-delta"),
+            dir.path().join("out.epub"),
+        );
+        write_book(
+            &stashed,
+            &[
+                ("mimetype", "application/epub+zip"),
+                ("chapter_1.xhtml", "One"),
+                ("chapter_2.xhtml", "Two, since removed"),
+            ],
+        )?;
+        write_book(
+            &base,
+            &[
+                ("mimetype", "application/epub+zip"),
+                ("chapter_1.xhtml", "One"),
+                ("chapter_3.xhtml", "Three"),
+            ],
+        )?;
+
+        write_delta(&stashed, &base, &delta)?;
+        let delta_names: Vec<String> = read_files(&delta)?.into_iter().map(|(n, _)| n).collect();
+        assert_eq!(delta_names, ["chapter_2.xhtml", INDEX_FILENAME]);
+
+        reconstruct(&delta, &base, &out)?;
+        assert_eq!(read_files(&out)?, read_files(&stashed)?);
+
+        write_book(&base, &[("mimetype", "application/epub+zip")])?;
+        assert!(reconstruct(&delta, &base, &out).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,194 @@
+//! Portable archive of an autebooks setup (library index, configuration, settings files and
+//! cache metadata), the e-books themselves and the cached images are left out.
+
+use eyre::{eyre, Result};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, Write};
+use std::path::{Component, Path, PathBuf};
+use walkdir::WalkDir;
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+use crate::profile;
+use crate::updater;
+
+const LIBRARY_PREFIX: &str = "library";
+const CONFIG_PREFIX: &str = "config";
+const CACHE_PREFIX: &str = "cache";
+
+/// Files of the library which describe the setup rather than the books.
+fn is_library_state(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy())
+        .unwrap_or_default();
+    name.starts_with(".autebook") || path.extension().is_some_and(|e| e == "order")
+}
+
+/// Cache entries which are metadata rather than images.
+fn is_cache_metadata(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "json")
+}
+
+pub fn export(work_dir: &Path, archive: &Path) -> Result<()> {
+    let mut zip = ZipWriter::new(File::create(archive)?);
+    add_dir(&mut zip, LIBRARY_PREFIX, work_dir, is_library_state)?;
+    if let Some(config_file) = profile::config_file().filter(|f| f.is_file()) {
+        zip.start_file(format!("{CONFIG_PREFIX}/config.toml"), options())?;
+        io::copy(&mut File::open(config_file)?, &mut zip)?;
+    }
+    add_dir(
+        &mut zip,
+        CACHE_PREFIX,
+        &updater::cache_dir()?,
+        is_cache_metadata,
+    )?;
+    zip.finish()?;
+    Ok(())
+}
+
+/// Restore an archive written by `export`, existing files are only replaced if `force`.
+pub fn import(archive: &Path, work_dir: &Path, force: bool) -> Result<()> {
+    let mut zip = ZipArchive::new(File::open(archive)?)?;
+    extract(&mut zip, LIBRARY_PREFIX, work_dir, force)?;
+    if let Some(config_dir) = profile::config_file().and_then(|f| f.parent().map(Path::to_path_buf))
+    {
+        extract(&mut zip, CONFIG_PREFIX, &config_dir, force)?;
+    }
+    extract(&mut zip, CACHE_PREFIX, &updater::cache_dir()?, force)?;
+    Ok(())
+}
+
+fn options() -> SimpleFileOptions {
+    SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated)
+}
+
+/// Add the files of `dir` kept by `filter` under `prefix`.
+fn add_dir<W: Write + Seek>(
+    zip: &mut ZipWriter<W>,
+    prefix: &str,
+    dir: &Path,
+    filter: fn(&Path) -> bool,
+) -> Result<()> {
+    for entry in WalkDir::new(dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file() && filter(e.path()))
+    {
+        let relative = entry.path().strip_prefix(dir)?;
+        let name = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        zip.start_file(format!("{prefix}/{name}"), options())?;
+        io::copy(&mut File::open(entry.path())?, zip)?;
+    }
+    Ok(())
+}
+
+/// Write the files of the archive under `prefix` to `dest`.
+fn extract<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    prefix: &str,
+    dest: &Path,
+    force: bool,
+) -> Result<()> {
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        let Some(relative) = file
+            .enclosed_name()
+            .and_then(|p| p.strip_prefix(prefix).ok().map(Path::to_path_buf))
+        else {
+            continue;
+        };
+        if relative
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            return Err(eyre!("Invalid path in the archive : {}", file.name()));
+        }
+        let path: PathBuf = dest.join(relative);
+        if path.exists() && !force {
+            eprintln!("Kept the existing {}", path.display());
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut file, &mut File::create(&path)?)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_library_round_trip() -> Result<()> {
+        let library = tempfile::tempdir()?;
+        fs::create_dir_all(library.path().join("ongoing"))?;
+        fs::write(library.path().join(".autebooks-manifest.json"), "{}")?;
+        fs::write(library.path().join("ongoing/.autebook.toml"), "cbz = true")?;
+        fs::write(library.path().join("ongoing/Book.order"), "2\n1")?;
+        fs::write(library.path().join("ongoing/Book.epub"), "not state")?;
+
+        let archive = tempfile::NamedTempFile::new()?;
+        let mut zip = ZipWriter::new(archive.reopen()?);
+        add_dir(&mut zip, LIBRARY_PREFIX, library.path(), is_library_state)?;
+        zip.finish()?;
+
+        let restored = tempfile::tempdir()?;
+        let mut zip = ZipArchive::new(archive.reopen()?)?;
+        extract(&mut zip, LIBRARY_PREFIX, restored.path(), false)?;
+        assert_eq!(
+            fs::read_to_string(restored.path().join("ongoing/.autebook.toml"))?,
+            "cbz = true"
+        );
+        assert!(restored.path().join(".autebooks-manifest.json").is_file());
+        assert!(restored.path().join("ongoing/Book.order").is_file());
+        assert!(!restored.path().join("ongoing/Book.epub").exists());
+        Ok(())
+    }
+
+    /// Archive made of `files` (name and contents).
+    fn archive(files: &[(&str, &str)]) -> Result<tempfile::NamedTempFile> {
+        let archive = tempfile::NamedTempFile::new()?;
+        let mut zip = ZipWriter::new(archive.reopen()?);
+        for (name, contents) in files {
+            zip.start_file(*name, options())?;
+            zip.write_all(contents.as_bytes())?;
+        }
+        zip.finish()?;
+        Ok(archive)
+    }
+
+    #[test]
+    fn test_import_keeps_existing_files() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let library = root.path().join("library");
+        fs::create_dir(&library)?;
+        fs::write(library.join(".autebook.toml"), "cbz = false")?;
+        let import = |files: &[(&str, &str)], force| {
+            let mut zip = ZipArchive::new(archive(files)?.reopen()?)?;
+            extract(&mut zip, LIBRARY_PREFIX, &library, force)
+        };
+
+        import(&[("library/.autebook.toml", "cbz = true")], false)?;
+        assert_eq!(
+            fs::read_to_string(library.join(".autebook.toml"))?,
+            "cbz = false"
+        );
+        import(&[("library/.autebook.toml", "cbz = true")], true)?;
+        assert_eq!(
+            fs::read_to_string(library.join(".autebook.toml"))?,
+            "cbz = true"
+        );
+
+        // Nothing is written out of the restored directory.
+        assert!(import(&[("library/../outside.toml", "cbz = true")], true).is_err());
+        assert!(!root.path().join("outside.toml").exists());
+        Ok(())
+    }
+}
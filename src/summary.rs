@@ -0,0 +1,505 @@
+use colorful::{Color, Colorful};
+use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::collation;
+use crate::config::Config;
+use crate::error::Category;
+use crate::updater::Warning;
+use crate::ErrorPrint;
+
+/// Which books are reported at the end of an update.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Verbosity {
+    /// Only the books which could not be updated.
+    Errors,
+    /// The books which changed, and the errors.
+    #[default]
+    Changes,
+    /// Every book, including the ones already up to date.
+    All,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Format {
+    /// One line per book, printed as soon as it is processed.
+    #[default]
+    Compact,
+    /// An aligned table, printed at the end.
+    Table,
+    /// One section per directory, printed at the end.
+    ByDirectory,
+}
+
+/// Look of the lines, the same for the summary and the other commands.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    /// Colored labels.
+    #[default]
+    Color,
+    /// Colored labels preceded by an emoji.
+    Emoji,
+    /// Labels without colors.
+    Plain,
+    /// Without colors nor any non-ASCII character, for logs.
+    Ascii,
+}
+
+/// How the outcomes of `update`, `add` and `check`, and the books of `list`, are printed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Output {
+    /// Lines for people, shaped by the other summary options.
+    #[default]
+    Text,
+    /// A JSON array of the outcomes, printed at the end.
+    Json,
+    /// One JSON object per outcome, printed as soon as it is known.
+    Ndjson,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Options {
+    pub verbosity: Verbosity,
+    pub format: Format,
+    pub theme: Theme,
+    pub output: Output,
+}
+
+impl Options {
+    /// Whether the outcome is listed: the JSON outputs list every outcome.
+    fn lists(self, status: &Status) -> bool {
+        self.output != Output::Text || status.is_shown(self.verbosity)
+    }
+}
+
+/// Whether the outcomes are printed as JSON rather than as lines.
+pub fn is_machine_readable() -> bool {
+    Config::get().summary.output != Output::Text
+}
+
+/// Titles are cut to this length when the width of the terminal is unknown.
+const DEFAULT_TITLE_WIDTH: usize = 50;
+/// Titles are never cut shorter than this, however narrow the terminal.
+const MIN_TITLE_WIDTH: usize = 20;
+
+// Only the colors of the labels have an emoji, out of the hundreds of terminal colors.
+#[allow(clippy::wildcard_enum_match_arm)]
+const fn emoji(color: Color) -> &'static str {
+    match color {
+        Color::Green | Color::LightGreen => "✅",
+        Color::Red => "❌",
+        Color::Yellow => "⚠️",
+        Color::Cyan => "🔄",
+        Color::Blue => "⏭️",
+        Color::Magenta => "✋",
+        _ => "💤",
+    }
+}
+
+/// Widest title fitting on a line of the terminal after `prefix_width` characters.
+fn max_title_width(prefix_width: usize) -> usize {
+    console::Term::stdout()
+        .size_checked()
+        .map_or(DEFAULT_TITLE_WIDTH, |(_, columns)| {
+            usize::from(columns)
+                .saturating_sub(prefix_width)
+                .max(MIN_TITLE_WIDTH)
+        })
+}
+
+/// Line made of the `label` of an outcome and of the `title` of its book (padded to
+/// `title_width`), then of its `details` if any, as the configured theme shows them.
+pub fn line(label: &str, color: Color, title: &str, title_width: usize, details: &str) -> String {
+    let theme = Config::get().summary.theme;
+    let label = format!("[{label:>4}]");
+    // An emoji and its space take three columns.
+    let prefix_width = label.len() + if theme == Theme::Emoji { 3 } else { 0 };
+    let prefix = match theme {
+        Theme::Color => label.bold().color(color).to_string(),
+        Theme::Emoji => format!("{} {}", emoji(color), label.bold().color(color)),
+        Theme::Plain | Theme::Ascii => label,
+    };
+    let title: String = title
+        .chars()
+        .take(max_title_width(prefix_width + 1))
+        .collect();
+    let line = format!("{prefix} {title:<title_width$}{details}");
+    if theme == Theme::Ascii {
+        line.chars()
+            .map(|c| if c.is_ascii() { c } else { '?' })
+            .collect()
+    } else {
+        line
+    }
+}
+
+pub enum Status {
+    Updated(u16),
+    /// Added to the work directory, from `url`.
+    Created {
+        url: String,
+        nb_chapters: Option<usize>,
+        size: Option<u64>,
+    },
+    /// Chapters of the source not yet downloaded.
+    Pending(usize),
+    MetadataUpdated,
+    CoverUpdated,
+    /// Rewritten in the current format of the e-books.
+    FormatUpgraded,
+    Recreated,
+    MoreChapterThanSource(u16),
+    Skipped,
+    UpToDate,
+    /// The source is down for maintenance, the book was not checked.
+    SourceDown,
+    /// The previous books of the source kept failing, the book was not attempted.
+    HostFailing,
+    /// The source may not be used at this time, the book is left for a later run.
+    Deferred,
+    /// The book was modified by something else since it was last written, it was left as is.
+    Conflict,
+    /// Reported in addition to the outcome of the book.
+    Warning(Warning),
+    Error(Option<Category>, String),
+}
+
+impl Status {
+    fn label(&self) -> String {
+        match self {
+            Self::Updated(n) => format!("{n:+}"),
+            Self::Created { .. } | Self::Recreated => String::from("New"),
+            Self::Pending(n) => format!("{n:+}"),
+            Self::MetadataUpdated => String::from("Meta"),
+            Self::CoverUpdated => String::from("Cov"),
+            Self::FormatUpgraded => String::from("Upgr"),
+            Self::MoreChapterThanSource(n) => format!("{:+}", -i32::from(*n)),
+            Self::Skipped => String::from("Skip"),
+            Self::UpToDate => String::from("Ok"),
+            Self::SourceDown => String::from("Down"),
+            Self::Conflict => String::from("Conf"),
+            Self::Warning(_) => String::from("Warn"),
+            Self::HostFailing => String::from("Fail"),
+            Self::Deferred => String::from("Wait"),
+            Self::Error(category, _) => String::from(category.map_or("Err", Category::label)),
+        }
+    }
+
+    /// Name of the outcome in the JSON outputs.
+    const fn name(&self) -> &'static str {
+        match self {
+            Self::Updated(_) => "Updated",
+            Self::Created { .. } => "Created",
+            Self::Pending(_) => "Pending",
+            Self::MetadataUpdated => "MetadataUpdated",
+            Self::CoverUpdated => "CoverUpdated",
+            Self::FormatUpgraded => "FormatUpgraded",
+            Self::Recreated => "Recreated",
+            Self::MoreChapterThanSource(_) => "MoreChapterThanSource",
+            Self::Skipped => "Skipped",
+            Self::UpToDate => "UpToDate",
+            Self::SourceDown => "SourceDown",
+            Self::HostFailing => "HostFailing",
+            Self::Deferred => "Deferred",
+            Self::Conflict => "Conflict",
+            Self::Warning(_) => "Warning",
+            Self::Error(..) => "Error",
+        }
+    }
+
+    const fn color(&self) -> Color {
+        match self {
+            Self::Updated(_) | Self::Pending(_) => Color::Green,
+            Self::MetadataUpdated | Self::CoverUpdated | Self::FormatUpgraded => Color::Cyan,
+            Self::Recreated | Self::Created { .. } => Color::LightGreen,
+            Self::MoreChapterThanSource(_) | Self::Error(..) => Color::Red,
+            Self::Skipped | Self::Deferred => Color::Blue,
+            Self::Conflict => Color::Magenta,
+            Self::SourceDown | Self::HostFailing | Self::Warning(_) => Color::Yellow,
+            Self::UpToDate => Color::LightGray,
+        }
+    }
+
+    const fn is_shown(&self, verbosity: Verbosity) -> bool {
+        match self {
+            Self::Error(..) | Self::Conflict => true,
+            Self::UpToDate => matches!(verbosity, Verbosity::All),
+            Self::Updated(_)
+            | Self::Created { .. }
+            | Self::Pending(_)
+            | Self::MetadataUpdated
+            | Self::CoverUpdated
+            | Self::FormatUpgraded
+            | Self::Recreated
+            | Self::MoreChapterThanSource(_)
+            | Self::Skipped
+            | Self::SourceDown
+            | Self::HostFailing
+            | Self::Deferred
+            | Self::Warning(_) => !matches!(verbosity, Verbosity::Errors),
+        }
+    }
+}
+
+struct Entry {
+    path: PathBuf,
+    title: String,
+    status: Status,
+}
+
+/// Outcome of a book, as printed by the JSON outputs.
+#[derive(Serialize)]
+struct Event<'a> {
+    path: &'a Path,
+    title: &'a str,
+    result: &'static str,
+    /// Source of a created book.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<&'a str>,
+    /// Chapters added, in excess of the source, left to download, or of a created book.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chapters: Option<usize>,
+    /// Size in bytes of a created book.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    category: Option<String>,
+    /// Error or warning message.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+impl Entry {
+    fn event(&self) -> Event<'_> {
+        let (url, chapters, size, category, message) = match &self.status {
+            Status::Updated(n) | Status::MoreChapterThanSource(n) => {
+                (None, Some(usize::from(*n)), None, None, None)
+            }
+            Status::Pending(n) => (None, Some(*n), None, None, None),
+            Status::Created {
+                url,
+                nb_chapters,
+                size,
+            } => (Some(url.as_str()), *nb_chapters, *size, None, None),
+            Status::Error(category, e) => (
+                None,
+                None,
+                None,
+                category.map(|c| format!("{c:?}")),
+                Some(e.clone()),
+            ),
+            Status::Warning(w) => (None, None, None, None, Some(w.to_string())),
+            Status::MetadataUpdated
+            | Status::CoverUpdated
+            | Status::FormatUpgraded
+            | Status::Recreated
+            | Status::Skipped
+            | Status::UpToDate
+            | Status::SourceDown
+            | Status::HostFailing
+            | Status::Deferred
+            | Status::Conflict => (None, None, None, None, None),
+        };
+        Event {
+            path: &self.path,
+            title: &self.title,
+            result: self.status.name(),
+            url,
+            chapters,
+            size,
+            category,
+            message,
+        }
+    }
+
+    fn line(&self, title_width: usize) -> String {
+        let details = match &self.status {
+            Status::Error(_, e) => format!(" : {e}"),
+            Status::Warning(w) => format!(" : {w}"),
+            Status::Updated(_)
+            | Status::Created { .. }
+            | Status::Pending(_)
+            | Status::MetadataUpdated
+            | Status::CoverUpdated
+            | Status::FormatUpgraded
+            | Status::Recreated
+            | Status::MoreChapterThanSource(_)
+            | Status::Skipped
+            | Status::UpToDate
+            | Status::SourceDown
+            | Status::HostFailing
+            | Status::Deferred
+            | Status::Conflict => String::new(),
+        };
+        line(
+            &self.status.label(),
+            self.status.color(),
+            &self.title,
+            title_width,
+            &details,
+        )
+    }
+}
+
+/// Outcome of each book of a run, printed according to the configured summary options.
+#[derive(Default)]
+pub struct Summary {
+    entries: Mutex<Vec<Entry>>,
+}
+
+impl Summary {
+    pub fn add(&self, bar: &ProgressBar, path: &Path, title: &str, status: Status) {
+        let options = Config::get().summary;
+        if !options.lists(&status) {
+            return;
+        }
+        let entry = Entry {
+            path: path.to_path_buf(),
+            title: title.to_string(),
+            status,
+        };
+        if options.output == Output::Ndjson {
+            match serde_json::to_string(&entry.event()) {
+                Ok(line) => bar.suspend(|| println!("{line}")),
+                Err(e) => bar.eprintln(&e.to_string()),
+            }
+        } else if options.output == Output::Text && options.format == Format::Compact {
+            if matches!(entry.status, Status::Error(..)) {
+                bar.eprintln(&entry.line(0));
+            } else {
+                bar.println(entry.line(0));
+            }
+        } else if let Ok(mut entries) = self.entries.lock() {
+            entries.push(entry);
+        }
+    }
+
+    pub fn print(self) {
+        let Ok(mut entries) = self.entries.into_inner() else {
+            return;
+        };
+        match Config::get().summary.output {
+            Output::Text => (),
+            Output::Json => {
+                let events: Vec<Event> = entries.iter().map(Entry::event).collect();
+                match serde_json::to_string_pretty(&events) {
+                    Ok(json) => println!("{json}"),
+                    Err(e) => eprintln!("{e}"),
+                }
+                return;
+            }
+            Output::Ndjson => return,
+        }
+        match Config::get().summary.format {
+            Format::Compact => (),
+            Format::Table => {
+                entries.sort_by(|a, b| collation::compare(&a.title, &b.title));
+                let title_width = entries
+                    .iter()
+                    .map(|e| e.title.chars().count().min(max_title_width(0)))
+                    .max()
+                    .unwrap_or_default();
+                for entry in entries {
+                    println!("{}  {}", entry.line(title_width), entry.path.display());
+                }
+            }
+            Format::ByDirectory => {
+                let mut by_directory: BTreeMap<PathBuf, Vec<Entry>> = BTreeMap::new();
+                for entry in entries {
+                    let dir = entry
+                        .path
+                        .parent()
+                        .map(Path::to_path_buf)
+                        .unwrap_or_default();
+                    by_directory.entry(dir).or_default().push(entry);
+                }
+                for (dir, mut entries) in by_directory {
+                    println!("{}", dir.display().to_string().bold());
+                    entries.sort_by(|a, b| collation::compare(&a.title, &b.title));
+                    for entry in entries {
+                        println!("  {}", entry.line(0));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verbosity() {
+        assert!(!Status::UpToDate.is_shown(Verbosity::Changes));
+        assert!(Status::UpToDate.is_shown(Verbosity::All));
+        assert!(Status::Updated(2).is_shown(Verbosity::Changes));
+        assert!(!Status::Updated(2).is_shown(Verbosity::Errors));
+        assert!(Status::Error(None, String::from("Timed out")).is_shown(Verbosity::Errors));
+        assert!(Status::Conflict.is_shown(Verbosity::Errors));
+    }
+
+    #[test]
+    fn test_json_outputs_ignore_verbosity() {
+        let options = |output| Options {
+            verbosity: Verbosity::Errors,
+            output,
+            ..Options::default()
+        };
+        assert!(!options(Output::Text).lists(&Status::UpToDate));
+        assert!(options(Output::Json).lists(&Status::UpToDate));
+        assert!(options(Output::Ndjson).lists(&Status::Updated(2)));
+    }
+
+    #[test]
+    fn test_long_titles_are_cut() {
+        let title = "x".repeat(500);
+        let line = line("Ok", Color::LightGray, &title, 0, " : details");
+        let kept = line.matches('x').count();
+        assert!((MIN_TITLE_WIDTH..500).contains(&kept));
+        assert!(line.ends_with(" : details"));
+        assert_eq!(emoji(Color::Red), "❌");
+    }
+
+    fn entry(status: Status) -> Entry {
+        Entry {
+            path: PathBuf::from("books/Mother of Learning.epub"),
+            title: String::from("Mother of Learning"),
+            status,
+        }
+    }
+
+    #[test]
+    fn test_json_events() -> serde_json::Result<()> {
+        assert_eq!(
+            serde_json::to_string(&entry(Status::Updated(3)).event())?,
+            r#"{"path":"books/Mother of Learning.epub","title":"Mother of Learning","result":"Updated","chapters":3}"#
+        );
+        let error = Status::Error(Some(Category::Network), String::from("Timed out"));
+        assert_eq!(
+            serde_json::to_string(&entry(error).event())?,
+            r#"{"path":"books/Mother of Learning.epub","title":"Mother of Learning","result":"Error","category":"Network","message":"Timed out"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&entry(Status::UpToDate).event())?,
+            r#"{"path":"books/Mother of Learning.epub","title":"Mother of Learning","result":"UpToDate"}"#
+        );
+        let created = Status::Created {
+            url: String::from("https://www.royalroad.com/fiction/21220"),
+            nb_chapters: Some(12),
+            size: Some(2048),
+        };
+        assert_eq!(
+            serde_json::to_string(&entry(created).event())?,
+            r#"{"path":"books/Mother of Learning.epub","title":"Mother of Learning","result":"Created","url":"https://www.royalroad.com/fiction/21220","chapters":12,"size":2048}"#
+        );
+        Ok(())
+    }
+}
@@ -1,6 +1,9 @@
 use crate::book::Book;
+use crate::config::Config;
+use crate::profile;
 use crate::updater::UpdateResult;
 use crate::updater::WebNovel;
+use crate::updater::{fetch_cover, set_cover, set_provenance, set_rating, warn, Rating, Warning};
 
 // use rss::Channel;
 use eyre::{bail, eyre, Result};
@@ -8,13 +11,24 @@ use lazy_regex::regex;
 use serde::Deserialize;
 use std::ffi::OsStr;
 use std::fs;
-use std::io::{BufRead, BufReader};
+use std::io::Read;
 use std::path::Path;
-use std::process::{Command, Stdio};
+use std::process::{Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Name of the backend recorded in the e-books.
+const BACKEND: &str = "fanficfare";
+/// Interval at which a running `FanFicFare` is checked for completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
 
 #[derive(Deserialize)]
 struct FanFicFareJson {
+    #[serde(default)]
     output_filename: String,
+    /// Rating as named by the site.
+    #[serde(default)]
+    rating: String,
 }
 
 pub struct FanFicFare;
@@ -24,53 +38,134 @@ impl WebNovel for FanFicFare {
         Self {}
     }
     fn create(&self, dir: &Path, filename: Option<&OsStr>, url: &str) -> Result<Book> {
-        let cmd = Command::new("fanficfare")
-            .arg("--non-interactive")
+        let mut cmd = Command::new("fanficfare");
+        cmd.arg("--non-interactive")
             .arg("--json-meta")
+            .args(fanficfare_options(url))
             .arg(url)
-            .current_dir(dir)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()?;
+            .current_dir(dir);
+        let output = run(cmd, url)?;
 
         // Retrieve the metadata of the newly created book
-        let stdout = cmd.stdout.ok_or_else(|| eyre!("Stdout is unavailable"))?;
-        let book_metadata = BufReader::new(stdout)
-            .lines()
-            .map_while(Result::ok)
-            .reduce(|accum, line| accum + &line)
-            .ok_or_else(|| eyre!("Failed to read book metadata."))?;
+        let book_metadata: String = String::from_utf8_lossy(&output.stdout).lines().collect();
+        if book_metadata.is_empty() {
+            bail!("Failed to read book metadata.");
+        }
 
-        let generated_filename =
-            serde_json::from_str::<FanFicFareJson>(&book_metadata).map(|e| e.output_filename)?;
+        let book_metadata = serde_json::from_str::<FanFicFareJson>(&book_metadata)?;
 
         // Manage error cases
-        let err_lines: String = cmd.stderr.map_or(String::new(), |stderr| {
-            BufReader::new(stderr)
-                .lines()
-                .map_while(Result::ok)
-                .collect()
-        });
+        let err_lines: String = String::from_utf8_lossy(&output.stderr).lines().collect();
 
         if !err_lines.is_empty() {
             bail!("The execution of Fanficfare for '{url}'' ended with an error \n{err_lines}");
         }
 
-        let mut file_path = dir.join(generated_filename);
+        let mut file_path = dir.join(book_metadata.output_filename);
         if let Some(filename) = filename {
             let new_file_path = dir.join(filename);
-            fs::rename(file_path, &new_file_path)?;
+            fs::rename(&file_path, &new_file_path)?;
             file_path = new_file_path;
         }
+        set_provenance(&file_path, BACKEND)?;
+        if let Some(rating) = Rating::from_label(&book_metadata.rating) {
+            set_rating(&file_path, rating)?;
+        }
 
         Ok(Book::new(&file_path))
     }
 
+    fn rating(&self, url: &str) -> Result<Option<Rating>> {
+        let mut cmd = Command::new("fanficfare");
+        cmd.arg("--non-interactive")
+            .arg("--meta-only")
+            .arg("--json-meta")
+            .args(fanficfare_options(url))
+            .arg(url);
+        let output = run(cmd, url)?;
+        let book_metadata =
+            serde_json::from_slice::<FanFicFareJson>(&output.stdout).map_err(|e| {
+                eyre!(
+                    "Could not read the metadata of '{url}' ({e})\n{}",
+                    String::from_utf8_lossy(&output.stderr)
+                )
+            })?;
+        Ok(Rating::from_label(&book_metadata.rating))
+    }
+
     fn update(&self, path: &Path) -> UpdateResult {
-        do_update(path).unwrap_or(UpdateResult::Unsupported)
+        match do_update(path) {
+            Some(UpdateResult::Updated(n)) => set_provenance(path, BACKEND)
+                .map_or_else(UpdateResult::Error, |()| UpdateResult::Updated(n)),
+            Some(result) => result,
+            None => UpdateResult::Unsupported,
+        }
     }
 }
 
+/// `FanFicFare` options of the work : its configured site options, those given on the command
+/// line and its password, if any.
+fn fanficfare_options(url: &str) -> Vec<String> {
+    profile::site_options_for(url)
+        .into_iter()
+        .chain(Config::get().site_options.iter().cloned())
+        .chain(profile::password_for(url).map(|password| format!("password={password}")))
+        .flat_map(|option| ["--option".to_string(), option])
+        .collect()
+}
+
+/// Read the whole `pipe` in the background.
+fn drain<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut contents = Vec::new();
+        if let Some(mut pipe) = pipe {
+            // What could be read is kept.
+            let _ = pipe.read_to_end(&mut contents);
+        }
+        contents
+    })
+}
+
+/// Run `FanFicFare` for `url`, killing it once it ran for longer than the configured timeout
+/// (e.g. stalled on the network or waiting for an answer).
+fn run(cmd: Command, url: &str) -> Result<Output> {
+    run_with_timeout(cmd, url, Config::get().fanficfare_timeout)
+}
+
+fn run_with_timeout(mut cmd: Command, url: &str, timeout: Option<Duration>) -> Result<Output> {
+    let started = Instant::now();
+    let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+
+    // Both pipes are drained while waiting, so that FanFicFare never blocks on a full one.
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if timeout.is_some_and(|timeout| started.elapsed() > timeout) {
+            child.kill()?;
+            child.wait()?;
+            bail!(
+                "FanFicFare was stopped after running for {}s on '{url}'",
+                started.elapsed().as_secs()
+            );
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    let join = |reader: thread::JoinHandle<Vec<u8>>| {
+        reader
+            .join()
+            .map_err(|_| eyre!("Could not read the output of FanFicFare for '{url}'"))
+    };
+    Ok(Output {
+        status,
+        stdout: join(stdout)?,
+        stderr: join(stderr)?,
+    })
+}
+
 fn do_update(path: &Path) -> Option<UpdateResult> {
     let updating = regex!(r"^Updating .*, URL: .*$");
     let up_to_date = regex!(r"^.* already contains \d+ chapters\.$");
@@ -78,24 +173,34 @@ fn do_update(path: &Path) -> Option<UpdateResult> {
     let more_chapter_than_source =
         regex!(r"^.* contains (\d+) chapters, more than source: (\d+)\.$");
     let skipped = " - Skipping";
+    let cover_failed = regex!(r"(?i)(fail|error|unable|could not).*cover|cover.*(fail|error)");
 
-    let cmd = Command::new("fanficfare")
-        .arg("--non-interactive")
+    let book = Book::new(path);
+    let mut cmd = Command::new("fanficfare");
+    cmd.arg("--non-interactive")
         .arg("--update-epub")
         .arg("--update-cover")
+        .args(fanficfare_options(book.url()))
         // .arg("--no-output") // TODO : remove line
-        .arg(path)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .ok()?;
-
-    let stdout = cmd.stdout?;
-    let stderr = cmd.stderr?;
-    let update_result = BufReader::new(stderr)
+        .arg(path);
+    // The e-book may be written to afterwards (e.g. its provenance), so FanFicFare must be done with it.
+    let output = match run(cmd, book.url()) {
+        Ok(output) => output,
+        Err(e) => return Some(UpdateResult::Error(e)),
+    };
+    let lines: Vec<String> = String::from_utf8_lossy(&output.stderr)
         .lines()
-        .chain(BufReader::new(stdout).lines())
-        .map_while(Result::ok)
+        .chain(String::from_utf8_lossy(&output.stdout).lines())
+        .map(String::from)
+        .collect();
+
+    let cover_failure = lines
+        .iter()
+        .find(|line| cover_failed.is_match(line))
+        .cloned();
+
+    let update_result = lines
+        .into_iter()
         .filter(|line| updating.captures(line).is_none())
         .find_map(|line| {
             if up_to_date.captures(&line).is_some() {
@@ -119,5 +224,57 @@ fn do_update(path: &Path) -> Option<UpdateResult> {
             None
         })?;
 
-    Some(update_result)
+    match cover_failure {
+        Some(reason) => Some(retry_cover(path, book.url(), reason, update_result)),
+        None => Some(update_result),
+    }
+}
+
+/// Report that `FanFicFare` could not update the cover, after trying the native backend if enabled.
+fn retry_cover(
+    path: &Path,
+    url: &str,
+    reason: String,
+    update_result: UpdateResult,
+) -> UpdateResult {
+    if !Config::get().retry_cover_natively {
+        warn(url, Warning::CoverFailed { reason });
+        return update_result;
+    }
+    match fetch_cover(url).and_then(|cover| set_cover(path, &cover)) {
+        Ok(()) if matches!(update_result, UpdateResult::UpToDate) => UpdateResult::CoverUpdated,
+        Ok(()) => update_result,
+        Err(e) => {
+            warn(
+                url,
+                Warning::CoverFailed {
+                    reason: format!("{reason} (natively : {e})"),
+                },
+            );
+            update_result
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeout() -> Result<()> {
+        let url = "https://archiveofourown.org/works/1";
+        let mut echo = Command::new("sh");
+        echo.args(["-c", "echo out; echo err >&2"]);
+        let output = run_with_timeout(echo, url, Some(Duration::from_secs(60)))?;
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"out\n");
+        assert_eq!(output.stderr, b"err\n");
+
+        let mut stalled = Command::new("sleep");
+        stalled.arg("60");
+        let started = Instant::now();
+        assert!(run_with_timeout(stalled, url, Some(Duration::from_millis(100))).is_err());
+        assert!(started.elapsed() < Duration::from_secs(30));
+        Ok(())
+    }
 }
@@ -1,30 +1,217 @@
+#[cfg(feature = "fanficfare")]
 mod fanficfare;
-mod native;
+pub mod native;
 
+use epub::doc::EpubDoc;
 use eyre::{eyre, Error, Result};
+use lazy_regex::regex;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use std::sync::Mutex;
 use std::{ffi::OsStr, fs, path::Path};
 use thiserror::Error;
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
 
 #[cfg(feature = "fanficfare")]
 pub use fanficfare::FanFicFare;
-pub use native::Native;
+#[cfg(feature = "fanficfare")]
+pub use native::fetch_cover;
+pub use native::{
+    cache_dir, compile_time_selector, fetch_chapter, fiction_urls, folder_url, is_volume,
+    listing_url, login, migrate_rr_to_epub, remove_cache, remove_exports, save_usage, search,
+    self_test, stats, Native, SearchSource, Usage,
+};
 
 use crate::book::Book;
+use crate::config::Config;
+use crate::disk::ensure_available_space;
+use crate::koreader;
+use crate::settings;
+use crate::stash;
+use crate::{ErrorPrint, MULTI_PROGRESS};
 
 #[derive(Debug)]
+#[cfg_attr(not(feature = "fanficfare"), allow(dead_code))]
 pub enum UpdateResult {
     Unsupported,
     UpToDate,
     Updated(u16),
+    /// No new chapter, but the title, author or description changed.
+    MetadataUpdated,
+    /// No new chapter, but the cover changed.
+    CoverUpdated,
+    /// No new chapter, but the book was rewritten in the current format.
+    FormatUpgraded,
     Skipped,
     MoreChapterThanSource(u16),
     Error(Error),
 }
 
+/// OPF metadata naming the backend which last wrote the e-book.
+pub const BACKEND_META: &str = "autebooks:backend";
+/// OPF metadata holding the version of autebooks which last wrote the e-book.
+pub const VERSION_META: &str = "autebooks:version";
+/// OPF metadata holding the version of the layout of the native e-books, see `FORMAT_VERSION`.
+pub const FORMAT_META: &str = "autebooks:format";
+/// Version of the layout written by the native backend, books of an older one are rewritten by
+/// their next update. Unstamped books are of version 1 (e.g. without `nav.xhtml`, chapter
+/// volumes or the metadata of the source), version 2 is the current layout.
+pub const FORMAT_VERSION: u32 = 2;
+/// OPF metadata holding the URL of the cover, to detect when it changes.
+pub const COVER_URL_META: &str = "autebooks:cover-url";
+/// OPF metadata holding the content rating of the work, for library software.
+pub const RATING_META: &str = "autebooks:content-rating";
+/// OPF metadata holding the validator of the source, to skip fetching unchanged books.
+pub const LAST_MODIFIED_META: &str = "autebooks:last-modified";
+/// OPF metadata telling which of the subjects are genre tags, comma separated.
+pub const TAGS_META: &str = "autebooks:tags";
+/// OPF metadata holding the content warnings of the source, comma separated.
+pub const WARNINGS_META: &str = "autebooks:content-warnings";
+/// OPF metadata holding the completion status of the work (e.g. `Completed`).
+pub const STATUS_META: &str = "autebooks:status";
+/// Suffix of the stem of stashed books, telling when they were stashed.
+pub const STASH_TIMESTAMP_FORMAT: &str = "_%Y-%m-%d_%Hh%M";
+
 #[derive(Error, Debug)]
 #[error("This webnovel does not contain a supported source URL")]
 pub struct Unsupported;
 
+/// Content rating of a work, from the most to the least suitable for everyone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+pub enum Rating {
+    General,
+    Teen,
+    Mature,
+    Explicit,
+}
+
+impl Rating {
+    /// Rating given by a source under its own name (e.g. `Teen And Up Audiences`, `M`).
+    pub fn from_label(label: &str) -> Option<Self> {
+        let label = label.trim().to_lowercase();
+        if label.starts_with("general") || ["g", "k", "k+", "everyone"].contains(&label.as_str()) {
+            Some(Self::General)
+        } else if label.starts_with("teen") || label == "t" {
+            Some(Self::Teen)
+        } else if label.starts_with("mature") || label == "m" {
+            Some(Self::Mature)
+        } else if label.starts_with("explicit")
+            || ["e", "ma", "nc-17", "adult"].contains(&label.as_str())
+        {
+            Some(Self::Explicit)
+        } else {
+            None
+        }
+    }
+
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::General => "General",
+            Self::Teen => "Teen",
+            Self::Mature => "Mature",
+            Self::Explicit => "Explicit",
+        }
+    }
+}
+
+/// Something suspicious noticed while updating a book, which did not prevent the update.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// The numbering of the source skips chapters, which were possibly deleted.
+    ChapterGap { after: String, nb_missing: u32 },
+    /// The numbering of the source goes backwards, the chapters were possibly restructured.
+    ChapterRegression { title: String },
+    /// A new chapter repeats the one before it, likely a repost under a new identifier.
+    Repost { title: String, original: String },
+    /// The cover could not be updated, the previous one is kept.
+    #[cfg_attr(not(feature = "fanficfare"), allow(dead_code))]
+    CoverFailed { reason: String },
+    /// Chapters only readable by subscribers were left out, until they become free.
+    LockedChapters { count: u32 },
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ChapterGap { after, nb_missing } => write!(
+                f,
+                "{nb_missing} chapter(s) missing from the source after '{after}', possibly deleted"
+            ),
+            Self::ChapterRegression { title } => write!(
+                f,
+                "The numbering of the source goes backwards at '{title}', possibly restructured"
+            ),
+            Self::Repost { title, original } => {
+                write!(f, "'{title}' looks like a repost of '{original}'")
+            }
+            Self::CoverFailed { reason } => write!(f, "The cover could not be updated : {reason}"),
+            Self::LockedChapters { count } => {
+                write!(
+                    f,
+                    "{count} locked chapter(s) left out until they become free"
+                )
+            }
+        }
+    }
+}
+
+lazy_static! {
+    /// Warnings raised for each book, keyed by source URL, until they are reported.
+    static ref WARNINGS: Mutex<HashMap<String, Vec<Warning>>> = Mutex::new(HashMap::new());
+}
+
+pub fn warn(url: &str, warning: Warning) {
+    if let Ok(mut warnings) = WARNINGS.lock() {
+        warnings.entry(url.to_string()).or_default().push(warning);
+    }
+}
+
+/// The warnings raised for the book of this source URL since the last call.
+pub fn take_warnings(url: &str) -> Vec<Warning> {
+    WARNINGS
+        .lock()
+        .ok()
+        .and_then(|mut warnings| warnings.remove(url))
+        .unwrap_or_default()
+}
+
+/// Differences between the chapters of a book and the complete list of its source.
+#[derive(Debug, Default)]
+pub struct Audit {
+    /// Titles of the chapters of the source which are not in the book.
+    pub missing: Vec<String>,
+    /// Titles of the chapters which are in the book more than once.
+    pub duplicated: Vec<String>,
+    /// Whether the chapters of the book are not in the order of the source.
+    pub out_of_order: bool,
+    /// Whether the book was rewritten to repair the inconsistencies.
+    pub fixed: bool,
+}
+
+impl Audit {
+    pub const fn is_consistent(&self) -> bool {
+        self.missing.is_empty() && self.duplicated.is_empty() && !self.out_of_order
+    }
+}
+
+impl std::fmt::Display for Audit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for title in &self.missing {
+            writeln!(f, "  missing    : {title}")?;
+        }
+        for title in &self.duplicated {
+            writeln!(f, "  duplicated : {title}")?;
+        }
+        if self.out_of_order {
+            writeln!(f, "  the chapters are out of order")?;
+        }
+        Ok(())
+    }
+}
+
 pub trait WebNovel {
     fn new() -> Self
     where
@@ -34,11 +221,46 @@ pub trait WebNovel {
     fn create(&self, dir: &Path, filename: Option<&OsStr>, url: &str) -> Result<Book> {
         Err(Unsupported.into())
     }
+    /// Create the book with its metadata and title page only, its chapters are downloaded by the
+    /// next update.
+    #[allow(unused_variables)]
+    fn create_stub(&self, dir: &Path, url: &str) -> Result<Book> {
+        Err(Unsupported.into())
+    }
     #[allow(unused_variables)]
     fn update(&self, path: &Path) -> UpdateResult {
         UpdateResult::Unsupported
     }
 
+    /// Number of new or updated chapters of the book at `path`, without downloading them.
+    #[allow(unused_variables)]
+    fn check(&self, path: &Path) -> Result<usize> {
+        Err(Unsupported.into())
+    }
+
+    /// Make a book written by another tool updatable, by recording `url` as its source.
+    fn adopt(&self, path: &Path, url: &str) -> Result<()> {
+        set_source_metadata(path, url)
+    }
+
+    /// Content rating of the work at `url`, without downloading its chapters.
+    #[allow(unused_variables)]
+    fn rating(&self, url: &str) -> Result<Option<Rating>> {
+        Ok(None)
+    }
+
+    /// Compare the chapters of the book with the complete list of its source, repairing them if `fix`.
+    #[allow(unused_variables)]
+    fn audit(&self, path: &Path, fix: bool) -> Result<Audit> {
+        Err(Unsupported.into())
+    }
+
+    /// Rewrite the book with the current cleaning rules and stylesheet, without using the network.
+    #[allow(unused_variables)]
+    fn rerender(&self, path: &Path) -> Result<()> {
+        Err(Unsupported.into())
+    }
+
     fn stash_and_recreate(&self, book: &Path, stash_folder: &Path, url: &str) -> Result<Book> {
         let parent_dir = book
             .parent()
@@ -50,7 +272,9 @@ pub trait WebNovel {
             .to_owned();
 
         // Stashing of the current instance of the book in an sub-directory
-        let timestamp = chrono::Utc::now().format("_%Y-%m-%d_%Hh%M").to_string();
+        let timestamp = chrono::Utc::now()
+            .format(STASH_TIMESTAMP_FORMAT)
+            .to_string();
         let extension = book
             .extension()
             .ok_or_else(|| eyre!("Could not retrieve the book's extension."))?;
@@ -63,10 +287,312 @@ pub trait WebNovel {
         stashed_filename.push(".");
         stashed_filename.push(extension);
 
+        // The stashed book is kept alongside the recreated one, which should be about the same size.
+        ensure_available_space(parent_dir, fs::metadata(book)?.len())?;
+
         fs::create_dir_all(stash_folder)?;
-        fs::rename(book, stash_folder.join(stashed_filename))?;
+        let stashed_book = stash_folder.join(stashed_filename);
+        // The reading order stays in place for the recreation, the stashed book gets a copy of it
+        // before being stashed so that a failure leaves the book where it was.
+        let reading_order = settings::reading_order_file(book);
+        if reading_order.is_file() {
+            fs::copy(reading_order, settings::reading_order_file(&stashed_book))?;
+        }
+        fs::rename(book, &stashed_book)?;
+        // The KOReader sidecar follows the stashed book and the recreated one gets a copy of it.
+        if let Err(e) = koreader::move_sidecar(book, &stashed_book) {
+            let _ = fs::rename(&stashed_book, book);
+            return Err(e);
+        }
+
+        // Creation of the new instance of the book, under its original name whatever its new title.
+        let recreated = self
+            .create(parent_dir, Some(&original_filename), url)
+            .inspect_err(|_| {
+                // Put the book back rather than leaving a hole in the library.
+                if !book.exists() && fs::rename(&stashed_book, book).is_ok() {
+                    let _ = koreader::move_sidecar(&stashed_book, book);
+                }
+            })?;
+        koreader::copy_sidecar(&stashed_book, &recreated.path)?;
+
+        if Config::get().delta_stash {
+            let delta = stashed_book.with_extension(stash::DELTA_EXTENSION);
+            match stash::write_delta(&stashed_book, &recreated.path, &delta) {
+                Ok(()) => fs::remove_file(&stashed_book)?,
+                Err(e) => MULTI_PROGRESS.eprintln(&format!(
+                    "Kept the whole stashed book, its delta could not be written : {e}"
+                )),
+            }
+        }
+        Ok(recreated)
+    }
+}
+
+/// Replace the `dc:source` of the e-book's OPF.
+fn set_source_metadata(book: &Path, url: &str) -> Result<()> {
+    let source = format!(
+        "<dc:source>{}</dc:source>",
+        xml::escape::escape_str_pcdata(url)
+    );
+    edit_opf(book, |opf| {
+        let opf = regex!(r"(?s)<dc:source[^>]*>.*?</dc:source>\s*").replace_all(opf, "");
+        insert_in_metadata(&opf, &source)
+    })
+}
+
+/// Record the content rating of the work in the e-book's OPF.
+#[cfg_attr(not(feature = "fanficfare"), allow(dead_code))]
+pub fn set_rating(book: &Path, rating: Rating) -> Result<()> {
+    let meta = format!(
+        "<meta name=\"{RATING_META}\" content=\"{}\"/>",
+        rating.label()
+    );
+    edit_opf(book, |opf| {
+        let opf = regex!(r#"<meta name="autebooks:content-rating"[^>]*>\s*"#).replace_all(opf, "");
+        insert_in_metadata(&opf, &meta)
+    })
+}
+
+/// Record in the e-book's OPF which backend (and version of autebooks) last wrote it.
+#[cfg_attr(not(feature = "fanficfare"), allow(dead_code))]
+pub fn set_provenance(book: &Path, backend: &str) -> Result<()> {
+    let metas = format!(
+        "<meta name=\"{BACKEND_META}\" content=\"{backend}\"/>\n\
+        <meta name=\"{VERSION_META}\" content=\"{}\"/>",
+        env!("CARGO_PKG_VERSION")
+    );
+    edit_opf(book, |opf| {
+        let opf =
+            regex!(r#"<meta name="autebooks:(backend|version)"[^>]*>\s*"#).replace_all(opf, "");
+        insert_in_metadata(&opf, &metas)
+    })
+}
+
+fn insert_in_metadata(opf: &str, elements: &str) -> Result<String> {
+    let metadata_end = regex!(r"</(\w+:)?metadata>")
+        .find(opf)
+        .ok_or_else(|| eyre!("No metadata found in the OPF"))?;
+    Ok(format!(
+        "{}{elements}\n{}",
+        &opf[..metadata_end.start()],
+        &opf[metadata_end.start()..]
+    ))
+}
+
+/// Rewrite the OPF of the e-book with `edit`, the other files are copied as is.
+fn edit_opf(book: &Path, edit: impl FnOnce(&str) -> Result<String>) -> Result<()> {
+    let opf_path = EpubDoc::new(book)?
+        .root_file
+        .to_string_lossy()
+        .replace('\\', "/");
+    let mut opf = String::new();
+    ZipArchive::new(File::open(book)?)?
+        .by_name(&opf_path)?
+        .read_to_string(&mut opf)?;
+    let opf = edit(&opf)?;
+    replace_file(book, &opf_path, opf.as_bytes())
+}
+
+/// Replace the cover image of the e-book, converted to the format of the current one.
+#[cfg_attr(not(feature = "fanficfare"), allow(dead_code))]
+pub fn set_cover(book: &Path, cover: &[u8]) -> Result<()> {
+    let epub_doc = EpubDoc::new(book)?;
+    let cover_id = epub_doc
+        .get_cover_id()
+        .ok_or_else(|| eyre!("The e-book has no cover"))?;
+    let (cover_path, mime) = epub_doc
+        .resources
+        .get(&cover_id)
+        .cloned()
+        .ok_or_else(|| eyre!("The cover of the e-book is missing"))?;
+    let format = ::image::ImageFormat::from_mime_type(&mime)
+        .ok_or_else(|| eyre!("Unsupported cover format : {mime}"))?;
+    let mut converted = Cursor::new(Vec::new());
+    ::image::load_from_memory(cover)?.write_to(&mut converted, format)?;
+    replace_file(
+        book,
+        &cover_path.to_string_lossy().replace('\\', "/"),
+        converted.get_ref(),
+    )
+}
+
+/// Algorithms of `encryption.xml` which only obfuscate the embedded fonts.
+const FONT_OBFUSCATIONS: [&str; 2] = [
+    "http://www.idpf.org/2008/embedding",
+    "http://ns.adobe.com/pdf/enc#RC",
+];
+
+/// Fail early, with a hint, on the e-books which cannot be updated whatever the source says :
+/// the ones protected by DRM and the archives which are not EPUBs.
+pub fn check_readable(book: &Path) -> Result<()> {
+    let unreadable = |reason: String| -> Error {
+        crate::error::Error::Unreadable(format!("{} {reason}", book.display())).into()
+    };
+    let mut archive = ZipArchive::new(File::open(book)?).map_err(|e| {
+        unreadable(format!(
+            "is not a valid EPUB archive ({e}), create it again with `autebooks add`"
+        ))
+    })?;
+    if archive.by_name("META-INF/rights.xml").is_ok() {
+        return Err(unreadable(String::from(
+            "is protected by Adobe DRM, only DRM-free books can be updated",
+        )));
+    }
+    if let Ok(mut encryption) = archive.by_name("META-INF/encryption.xml") {
+        let mut contents = String::new();
+        encryption.read_to_string(&mut contents)?;
+        let encrypted = regex!(r#"Algorithm="([^"]+)""#)
+            .captures_iter(&contents)
+            .any(|c| !FONT_OBFUSCATIONS.contains(&&c[1]));
+        if encrypted {
+            return Err(unreadable(String::from(
+                "is encrypted (DRM), only DRM-free books can be updated",
+            )));
+        }
+    }
+    if archive.by_name("META-INF/container.xml").is_err() {
+        return Err(unreadable(String::from(
+            "has no META-INF/container.xml, it is not a standard EPUB archive",
+        )));
+    }
+    Ok(())
+}
+
+/// Rewrite the e-book with `contents` as the file `name` of the archive.
+fn replace_file(book: &Path, name: &str, contents: &[u8]) -> Result<()> {
+    let mut archive = ZipArchive::new(File::open(book)?)?;
+    let parent_dir = book
+        .parent()
+        .ok_or_else(|| eyre!("Could not retrieve the book's parent directory."))?;
+    let temp_file = tempfile::NamedTempFile::new_in(parent_dir)?;
+    let mut writer = ZipWriter::new(temp_file.reopen()?);
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        if file.name() == name {
+            writer.start_file(name, SimpleFileOptions::default())?;
+            writer.write_all(contents)?;
+        } else {
+            writer.raw_copy_file(file)?;
+        }
+    }
+    writer.finish()?;
+    temp_file.persist(book)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Category;
+
+    fn archive_with(files: &[(&str, &str)]) -> Result<tempfile::NamedTempFile> {
+        let file = tempfile::NamedTempFile::new()?;
+        let mut zip = ZipWriter::new(file.reopen()?);
+        for (name, contents) in files {
+            zip.start_file(*name, SimpleFileOptions::default())?;
+            zip.write_all(contents.as_bytes())?;
+        }
+        zip.finish()?;
+        Ok(file)
+    }
+
+    #[test]
+    fn ratings_are_read_from_their_labels() {
+        assert_eq!(
+            Rating::from_label("General Audiences"),
+            Some(Rating::General)
+        );
+        assert_eq!(Rating::from_label("K+"), Some(Rating::General));
+        assert_eq!(
+            Rating::from_label(" Teen And Up Audiences"),
+            Some(Rating::Teen)
+        );
+        assert_eq!(Rating::from_label("M"), Some(Rating::Mature));
+        assert_eq!(Rating::from_label("MA"), Some(Rating::Explicit));
+        assert_eq!(Rating::from_label("Not Rated"), None);
+        assert!(Rating::General < Rating::Explicit);
+    }
+
+    #[test]
+    fn encrypted_books_are_unreadable() -> Result<()> {
+        let container = ("META-INF/container.xml", "<container/>");
+        let fonts = archive_with(&[
+            container,
+            (
+                "META-INF/encryption.xml",
+                r#"<EncryptionMethod Algorithm="http://www.idpf.org/2008/embedding"/>"#,
+            ),
+        ])?;
+        assert!(check_readable(fonts.path()).is_ok());
+
+        let drm = archive_with(&[
+            container,
+            (
+                "META-INF/encryption.xml",
+                r#"<EncryptionMethod Algorithm="http://www.w3.org/2001/04/xmlenc#aes128-cbc"/>"#,
+            ),
+        ])?;
+        let report = check_readable(drm.path()).err();
+        assert_eq!(
+            report.as_ref().and_then(Category::of),
+            Some(Category::Unreadable)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unreadable_books_are_not_read_back() -> Result<()> {
+        let url = "https://www.royalroad.com/fiction/12345/book";
+        let category = |book: &Path| {
+            native::Book::from_path(url, book)
+                .err()
+                .as_ref()
+                .and_then(Category::of)
+        };
+
+        let adobe = archive_with(&[
+            ("META-INF/container.xml", "<container/>"),
+            ("META-INF/rights.xml", "<rights/>"),
+        ])?;
+        assert_eq!(category(adobe.path()), Some(Category::Unreadable));
+
+        let not_an_archive = tempfile::NamedTempFile::new()?;
+        std::fs::write(not_an_archive.path(), "<html>Not an e-book</html>")?;
+        assert_eq!(category(not_an_archive.path()), Some(Category::Unreadable));
+        Ok(())
+    }
+
+    #[test]
+    fn failed_stashes_leave_the_book_in_place() -> Result<()> {
+        struct Offline;
+        impl WebNovel for Offline {
+            fn new() -> Self {
+                Self
+            }
+        }
+        let dir = tempfile::tempdir()?;
+        let book = dir.path().join("book.epub");
+        fs::write(&book, "epub")?;
+        fs::write(settings::reading_order_file(&book), "1\n2")?;
+        let stash_dir = dir.path().join("stashed");
+
+        // The copy of the reading order cannot replace a directory, whether the minute of the
+        // stash changes meanwhile or not.
+        let now = chrono::Utc::now();
+        for time in [now, now + chrono::Duration::minutes(1)] {
+            let timestamp = time.format(STASH_TIMESTAMP_FORMAT);
+            let stashed_book = stash_dir.join(format!("book{timestamp}.epub"));
+            fs::create_dir_all(settings::reading_order_file(&stashed_book))?;
+        }
+        let url = "https://www.royalroad.com/fiction/1";
+        assert!(Offline.stash_and_recreate(&book, &stash_dir, url).is_err());
+        assert!(book.is_file());
 
-        // Creation of the new instance of the book
-        self.create(parent_dir, Some(&original_filename), url)
+        // Books which could not be recreated are put back.
+        fs::remove_dir_all(&stash_dir)?;
+        assert!(Offline.stash_and_recreate(&book, &stash_dir, url).is_err());
+        assert!(book.is_file());
+        Ok(())
     }
 }
@@ -1,36 +1,417 @@
-use std::path::PathBuf;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs::File;
+use std::path::{Path, PathBuf};
 
 use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use eyre::eyre;
+use reqwest::header::ETAG;
+use serde::{Deserialize, Serialize};
+use url::Url;
+use uuid::Uuid;
 
 use crate::updater::native::epub::Book;
+use crate::updater::native::request::Fetched;
+
+/// Entries used to be keyed by the book id alone, which was only ever used by Royal Road.
+const LEGACY_HOST: &str = "www.royalroad.com";
+
+/// Host under which the entries of the books of local folders (`file://` URLs) are kept.
+const LOCAL_HOST: &str = "local";
+
+/// Book metadata cached by rr-to-epub, along with its images.
+const LEGACY_BOOK_FILENAME: &str = "book.json";
+
+/// Suffix of the files storing where a cached image comes from.
+const IMAGE_SOURCE_SUFFIX: &str = ".source.json";
+
+/// Entry holding when the metadata of the book was last fetched from its source.
+const METADATA_CHECK_FILENAME: &str = "metadata-checked";
+
+/// Entry listing the images of each e-book written from the book's entries, since the same
+/// story may be kept in several places.
+const IMAGE_USERS_FILENAME: &str = "image-users.json";
+
+/// File locked while the entries of its directory are written, so that the threads and the
+/// concurrent runs writing them do it one at a time.
+const LOCK_FILENAME: &str = ".lock";
+
+/// Validators sent by the server along with an image, used to detect when it is replaced.
+#[derive(Default, Serialize, Deserialize)]
+pub struct ImageSource {
+    etag: Option<String>,
+    content_length: Option<u64>,
+}
+impl ImageSource {
+    pub fn from_response(response: &Fetched) -> Self {
+        Self {
+            etag: response
+                .headers
+                .get(ETAG)
+                .and_then(|v| v.to_str().ok())
+                .map(ToString::to_string),
+            content_length: response.content_length(),
+        }
+    }
+
+    /// Prefer the `ETag`, fallback on the length and consider it changed if neither can be compared.
+    pub fn differs_from(&self, other: &Self) -> bool {
+        match (&self.etag, &other.etag) {
+            (Some(etag), Some(other_etag)) => etag != other_etag,
+            _ => match (self.content_length, other.content_length) {
+                (Some(length), Some(other_length)) => length != other_length,
+                _ => true,
+            },
+        }
+    }
+}
 
 pub struct Cache;
 impl Cache {
-    fn cache_path() -> eyre::Result<PathBuf> {
+    pub fn cache_path() -> eyre::Result<PathBuf> {
         let home_dir = dirs::home_dir().ok_or_else(|| eyre!("No home directory"))?;
         let cache_dir = home_dir.join(".cache/rr-to-epub");
         std::fs::create_dir_all(&cache_dir)?;
         Ok(cache_dir)
     }
 
+    /// Directory of the book's entries, keyed by the source host and the book id
+    /// since ids are only unique within a site.
+    fn book_path(book: &Book) -> eyre::Result<PathBuf> {
+        book_dir(&Self::cache_path()?, book)
+    }
+
+    /// Returns whether the book had cached entries.
+    pub fn remove_book(book: &Book) -> eyre::Result<bool> {
+        let book_dir = Self::book_path(book)?;
+        if !book_dir.is_dir() {
+            return Ok(false);
+        }
+        std::fs::remove_dir_all(book_dir)?;
+        Ok(true)
+    }
+
     pub fn write_inline_image(book: &Book, filename: &str, image: &[u8]) -> eyre::Result<()> {
-        let cache_dir = Self::cache_path()?.join(book.id.to_string());
+        let cache_dir = Self::book_path(book)?;
         std::fs::create_dir_all(&cache_dir)?;
 
         // Write the image to the cache.
         let cache_file = cache_dir.join(filename);
-        std::fs::write(cache_file, image)?;
-        Ok(())
+        Self::write_atomically(&cache_file, image)
     }
 
     pub fn read_inline_image(book: &Book, filename: &str) -> eyre::Result<Option<Bytes>> {
-        let cache_dir = Self::cache_path()?;
-        let cache_file = cache_dir.join(book.id.to_string()).join(filename);
+        let cache_file = Self::book_path(book)?.join(filename);
         if !cache_file.exists() {
             return Ok(None);
         }
         let contents = std::fs::read(cache_file)?;
         Ok(Some(contents.into()))
     }
+
+    pub fn write_image_source(
+        book: &Book,
+        filename: &str,
+        source: &ImageSource,
+    ) -> eyre::Result<()> {
+        let cache_file = Self::book_path(book)?.join(format!("{filename}{IMAGE_SOURCE_SUFFIX}"));
+        Self::write_atomically(&cache_file, serde_json::to_string(source)?.as_bytes())
+    }
+
+    pub fn read_image_source(book: &Book, filename: &str) -> eyre::Result<Option<ImageSource>> {
+        let cache_file = Self::book_path(book)?.join(format!("{filename}{IMAGE_SOURCE_SUFFIX}"));
+        if !cache_file.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_str(&std::fs::read_to_string(
+            cache_file,
+        )?)?))
+    }
+
+    /// When the metadata of the book was last fetched, `None` if it never was since this is
+    /// recorded.
+    pub fn read_metadata_check(book: &Book) -> eyre::Result<Option<DateTime<Utc>>> {
+        let cache_file = Self::book_path(book)?.join(METADATA_CHECK_FILENAME);
+        if !cache_file.exists() {
+            return Ok(None);
+        }
+        let date = DateTime::parse_from_rfc3339(std::fs::read_to_string(cache_file)?.trim())?;
+        Ok(Some(date.into()))
+    }
+
+    pub fn write_metadata_check(book: &Book, date: DateTime<Utc>) -> eyre::Result<()> {
+        let cache_dir = Self::book_path(book)?;
+        std::fs::create_dir_all(&cache_dir)?;
+        Self::write_atomically(
+            &cache_dir.join(METADATA_CHECK_FILENAME),
+            date.to_rfc3339().as_bytes(),
+        )
+    }
+
+    /// Book as cached by rr-to-epub, if it is still around.
+    pub fn read_legacy_book(book: &Book) -> eyre::Result<Option<Book>> {
+        let cache_file = Self::book_path(book)?.join(LEGACY_BOOK_FILENAME);
+        if !cache_file.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_str(&std::fs::read_to_string(
+            cache_file,
+        )?)?))
+    }
+
+    /// Delete the cached images of the book which are neither in `referenced_filenames`, the
+    /// images of the e-book at `outfile`, nor used by another e-book of the same story,
+    /// e.g. because their chapter was removed upstream.
+    pub fn remove_orphaned_images(
+        book: &Book,
+        outfile: &Path,
+        referenced_filenames: &HashSet<String>,
+    ) -> eyre::Result<()> {
+        let cache_dir = Self::book_path(book)?;
+        if !cache_dir.is_dir() {
+            return Ok(());
+        }
+        let _lock = Self::lock_dir(&cache_dir)?;
+        remove_orphans(&cache_dir, outfile, referenced_filenames)
+    }
+
+    /// Write to a temporary file then rename it, so that a reader (or another run)
+    /// never sees a partially written entry.
+    pub(super) fn write_atomically(path: &Path, contents: &[u8]) -> eyre::Result<()> {
+        let dir = path
+            .parent()
+            .ok_or_else(|| eyre!("Invalid cache path : {}", path.display()))?;
+        let _lock = Self::lock_dir(dir)?;
+        Self::replace(path, contents)
+    }
+
+    /// Lock the entries of `dir` until the returned file is dropped.
+    fn lock_dir(dir: &Path) -> eyre::Result<File> {
+        let lock = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(dir.join(LOCK_FILENAME))?;
+        lock.lock()?;
+        Ok(lock)
+    }
+
+    /// Replace the entry at `path`, whose directory must be locked.
+    fn replace(path: &Path, contents: &[u8]) -> eyre::Result<()> {
+        let filename = path
+            .file_name()
+            .ok_or_else(|| eyre!("Invalid cache path : {}", path.display()))?
+            .to_string_lossy();
+        let temp_file = path.with_file_name(format!(".{filename}.{}.tmp", Uuid::new_v4()));
+
+        std::fs::write(&temp_file, contents)?;
+        if let Err(e) = std::fs::rename(&temp_file, path) {
+            let _ = std::fs::remove_file(&temp_file);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+}
+
+/// Directory of the book's entries in `cache_dir`, see `Cache::book_path`.
+fn book_dir(cache_dir: &Path, book: &Book) -> eyre::Result<PathBuf> {
+    let host = Url::parse(&book.url)
+        .ok()
+        .and_then(|u| match u.scheme() {
+            "file" => Some(LOCAL_HOST.to_string()),
+            _ => u.host_str().map(ToString::to_string),
+        })
+        .ok_or_else(|| eyre!("Invalid book URL : {}", book.url))?;
+    let book_dir = cache_dir.join(&host).join(book.id.to_string());
+
+    // Migrate the entries cached before they were keyed by host.
+    let legacy_dir = cache_dir.join(book.id.to_string());
+    if host == LEGACY_HOST && legacy_dir.is_dir() && !book_dir.exists() {
+        std::fs::create_dir_all(cache_dir.join(&host))?;
+        // Another thread may have migrated it in the meantime.
+        if let Err(e) = std::fs::rename(&legacy_dir, &book_dir) {
+            if !book_dir.exists() {
+                return Err(e.into());
+            }
+        }
+    }
+    Ok(book_dir)
+}
+
+fn remove_orphans(
+    cache_dir: &Path,
+    outfile: &Path,
+    referenced_filenames: &HashSet<String>,
+) -> eyre::Result<()> {
+    // The e-books which no longer exist do not keep their images.
+    let users_file = cache_dir.join(IMAGE_USERS_FILENAME);
+    let mut users: BTreeMap<PathBuf, BTreeSet<String>> = std::fs::read(&users_file)
+        .ok()
+        .and_then(|contents| serde_json::from_slice(&contents).ok())
+        .unwrap_or_default();
+    users.retain(|path, _| path.is_file());
+    users.insert(
+        std::path::absolute(outfile)?,
+        referenced_filenames.iter().cloned().collect(),
+    );
+    Cache::replace(
+        &users_file,
+        serde_json::to_string_pretty(&users)?.as_bytes(),
+    )?;
+
+    let used_filenames: HashSet<&String> = users.values().flatten().collect();
+    for entry in std::fs::read_dir(cache_dir)? {
+        let entry = entry?;
+        let filename = entry.file_name().to_string_lossy().to_string();
+        let image_filename = filename
+            .strip_suffix(IMAGE_SOURCE_SUFFIX)
+            .unwrap_or(&filename)
+            .to_string();
+        // Hidden files are temporary files of writes in progress.
+        if filename.starts_with('.')
+            || filename == LEGACY_BOOK_FILENAME
+            || filename == METADATA_CHECK_FILENAME
+            || filename == IMAGE_USERS_FILENAME
+            || used_filenames.contains(&image_filename)
+        {
+            continue;
+        }
+        std::fs::remove_file(entry.path())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use reqwest::StatusCode;
+
+    fn filenames(dir: &Path) -> BTreeSet<String> {
+        std::fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .filter(|f| !f.starts_with('.') && f != IMAGE_USERS_FILENAME)
+            .collect()
+    }
+
+    #[test]
+    fn test_image_source_changes() {
+        let source = |etag: Option<&'static str>, length: usize| {
+            let mut headers = HeaderMap::new();
+            if let Some(etag) = etag {
+                headers.insert(ETAG, HeaderValue::from_static(etag));
+            }
+            ImageSource::from_response(&Fetched {
+                status: StatusCode::OK,
+                headers,
+                body: vec![0; length].into(),
+            })
+        };
+        let cached = source(Some("\"v1\""), 10);
+        assert!(!source(Some("\"v1\""), 20).differs_from(&cached));
+        assert!(source(Some("\"v2\""), 10).differs_from(&cached));
+        assert!(!source(None, 10).differs_from(&cached));
+        assert!(source(None, 20).differs_from(&cached));
+        assert!(source(None, 0).differs_from(&cached));
+    }
+
+    #[test]
+    fn test_concurrent_writes() -> eyre::Result<()> {
+        let cache_dir = tempfile::tempdir()?;
+        let path = cache_dir.path().join("map.png");
+        let contents: Vec<Vec<u8>> = (0..8u8).map(|i| vec![i; 64 * 1024]).collect();
+        std::thread::scope(|scope| {
+            // Every writer is started before any is waited for.
+            let mut writers = Vec::new();
+            for image in &contents {
+                writers.push(scope.spawn(|| Cache::write_atomically(&path, image)));
+            }
+            for writer in writers {
+                assert!(writer.join().is_ok_and(|result| result.is_ok()));
+            }
+        });
+
+        // One of the writes wins whole, and no temporary file is left behind.
+        assert!(contents.contains(&std::fs::read(&path)?));
+        let leftovers = std::fs::read_dir(cache_dir.path())?
+            .flatten()
+            .filter(|e| e.file_name().to_string_lossy().ends_with(".tmp"))
+            .count();
+        assert_eq!(leftovers, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_referenced_images_are_kept() -> eyre::Result<()> {
+        let cache_dir = tempfile::tempdir()?;
+        let books = tempfile::tempdir()?;
+
+        // Another e-book of the same story uses `other.png`.
+        let other_book = books.path().join("other.epub");
+        std::fs::write(&other_book, b"epub")?;
+        let referenced = HashSet::from([String::from("other.png")]);
+        remove_orphans(cache_dir.path(), &other_book, &referenced)?;
+
+        for filename in ["kept.png", "failed.png", "orphan.png", "other.png"] {
+            std::fs::write(cache_dir.path().join(filename), b"image")?;
+        }
+        std::fs::write(
+            cache_dir
+                .path()
+                .join(format!("orphan.png{IMAGE_SOURCE_SUFFIX}")),
+            b"{}",
+        )?;
+
+        // `failed.png` could not be obtained but is still referenced by a chapter.
+        let book = books.path().join("book.epub");
+        let referenced = HashSet::from([String::from("kept.png"), String::from("failed.png")]);
+        remove_orphans(cache_dir.path(), &book, &referenced)?;
+        assert_eq!(
+            filenames(cache_dir.path()),
+            BTreeSet::from(["failed.png", "kept.png", "other.png"].map(String::from))
+        );
+
+        // Once the other e-book is deleted, its images are not kept anymore.
+        std::fs::remove_file(&other_book)?;
+        remove_orphans(cache_dir.path(), &book, &referenced)?;
+        assert_eq!(
+            filenames(cache_dir.path()),
+            BTreeSet::from(["failed.png", "kept.png"].map(String::from))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_entries_are_keyed_by_host() -> eyre::Result<()> {
+        let cache_dir = tempfile::tempdir()?;
+        let book = |url: &str| Book {
+            id: 42,
+            url: url.to_owned(),
+            ..Book::default()
+        };
+        let legacy_dir = cache_dir.path().join("42");
+        std::fs::create_dir(&legacy_dir)?;
+        std::fs::write(legacy_dir.join("map.png"), b"image")?;
+
+        let other = book_dir(cache_dir.path(), &book("https://www.wattpad.com/story/42"))?;
+        assert_eq!(other, cache_dir.path().join("www.wattpad.com/42"));
+        assert!(legacy_dir.is_dir());
+
+        let royal_road = book_dir(
+            cache_dir.path(),
+            &book("https://www.royalroad.com/fiction/42"),
+        )?;
+        assert_eq!(royal_road, cache_dir.path().join("www.royalroad.com/42"));
+        assert!(!legacy_dir.exists());
+        assert!(royal_road.join("map.png").is_file());
+
+        let local = book_dir(cache_dir.path(), &book("file:///home/user/story/"))?;
+        assert_eq!(local, cache_dir.path().join("local/42"));
+
+        assert!(book_dir(cache_dir.path(), &book("not a URL")).is_err());
+        Ok(())
+    }
 }
@@ -0,0 +1,97 @@
+//! Royal Road pages listing several fictions : the fictions of an author, the favorites
+//! (public reading list) of a user and the follow list of the signed-in account.
+
+use eyre::Result;
+use lazy_regex::regex;
+use lazy_static::lazy_static;
+use scraper::{Html, Selector};
+use std::collections::HashSet;
+
+use crate::updater::native::epub::{compile_time_selector, next_page_url};
+use crate::updater::native::request;
+
+lazy_static! {
+    static ref FICTION_LINK_SELECTOR: Selector = compile_time_selector("a[href^=\"/fiction/\"]");
+}
+
+/// Page listing the fictions of `url`, if it is a profile or its fictions or favorites,
+/// or the follow list.
+pub fn listing_url(url: &str) -> Option<String> {
+    if regex!(r"^https://www\.royalroad\.com/my/follows/?(?:[?#].*)?$").is_match(url) {
+        return Some(String::from("https://www.royalroad.com/my/follows"));
+    }
+    let captures = regex!(
+        r"^https://www\.royalroad\.com/profile/(\d+)(?:/(fictions|favorites))?/?(?:[?#].*)?$"
+    )
+    .captures(url)?;
+    let listing = captures.get(2).map_or("fictions", |m| m.as_str());
+    Some(format!(
+        "https://www.royalroad.com/profile/{}/{listing}",
+        &captures[1]
+    ))
+}
+
+/// URLs of the fictions listed by the profile page `url`, following its pagination.
+pub fn fiction_urls(url: &str) -> Result<Vec<String>> {
+    let mut urls = Vec::new();
+    let mut next_page = listing_url(url);
+    let mut visited = HashSet::new();
+    while let Some(page_url) = next_page.filter(|u| visited.insert(u.clone())) {
+        let response = request::block_on(request::get(&page_url))?
+            .error_for_status(&page_url)?
+            .text();
+        let page = Html::parse_document(&response);
+        for fiction_url in page_fiction_urls(&page) {
+            if !urls.contains(&fiction_url) {
+                urls.push(fiction_url);
+            }
+        }
+        next_page = next_page_url(&page, &page_url);
+    }
+    Ok(urls)
+}
+
+/// Links to fictions (not to their chapters or reviews) of the page, in order.
+fn page_fiction_urls(page: &Html) -> Vec<String> {
+    page.select(&FICTION_LINK_SELECTOR)
+        .filter_map(|a| {
+            let captures = regex!(r"^/fiction/(\d+)(/[^/?#]+)?/?$").captures(a.attr("href")?)?;
+            Some(format!(
+                "https://www.royalroad.com/fiction/{}{}",
+                &captures[1],
+                captures.get(2).map_or("", |m| m.as_str())
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listing() {
+        assert_eq!(
+            listing_url("https://www.royalroad.com/profile/42").as_deref(),
+            Some("https://www.royalroad.com/profile/42/fictions")
+        );
+        assert_eq!(
+            listing_url("https://www.royalroad.com/profile/42/favorites?page=2").as_deref(),
+            Some("https://www.royalroad.com/profile/42/favorites")
+        );
+        assert!(listing_url("https://www.royalroad.com/fiction/1/a-story").is_none());
+
+        let page = Html::parse_document(
+            "<a href=\"/fiction/1/a-story\">A Story</a>\
+            <a href=\"/fiction/1/a-story/chapter/2/prologue\">Prologue</a>\
+            <a href=\"/fiction/3/another\">Another</a>",
+        );
+        assert_eq!(
+            page_fiction_urls(&page),
+            [
+                "https://www.royalroad.com/fiction/1/a-story",
+                "https://www.royalroad.com/fiction/3/another"
+            ]
+        );
+    }
+}
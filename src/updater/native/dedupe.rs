@@ -0,0 +1,73 @@
+use scraper::Html;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+
+use crate::updater::native::epub::Chapter;
+
+/// Share of word shingles two chapters must have in common to be near-identical, in percent.
+const MIN_SIMILARITY: usize = 90;
+/// Number of consecutive words hashed together.
+const SHINGLE_SIZE: usize = 3;
+
+/// Hashes of the groups of consecutive words of the chapter, reposts share almost all of them.
+fn shingle_hashes(html: &str) -> HashSet<u64> {
+    let text = Html::parse_fragment(html)
+        .root_element()
+        .text()
+        .collect::<String>()
+        .to_lowercase();
+    let words: Vec<&str> = text.split_whitespace().collect();
+    words
+        .windows(SHINGLE_SIZE.min(words.len()).max(1))
+        .map(|shingle| {
+            let mut hasher = DefaultHasher::new();
+            shingle.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect()
+}
+
+fn are_near_duplicates(a: &str, b: &str) -> bool {
+    let (a, b) = (shingle_hashes(a), shingle_hashes(b));
+    // Chapters without text (e.g. only images) cannot be compared.
+    if a.is_empty() || b.is_empty() {
+        return false;
+    }
+    let common = a.intersection(&b).count();
+    common * 100 >= a.union(&b).count() * MIN_SIMILARITY
+}
+
+/// Chapters among `new_ids` which repeat the chapter just before them,
+/// along with the title of that chapter.
+pub fn find_duplicates<'a>(
+    chapters: &'a [Chapter],
+    new_ids: &HashSet<String>,
+) -> Vec<(&'a Chapter, &'a str)> {
+    chapters
+        .windows(2)
+        .filter(|pair| new_ids.contains(&pair[1].identifier))
+        .filter_map(|pair| {
+            let previous = pair[0].content.as_deref()?;
+            let content = pair[1].content.as_deref()?;
+            are_near_duplicates(previous, content).then(|| (&pair[1], pair[0].title.as_str()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_near_duplicates() {
+        let chapter = "<p>The rain kept falling on the old town, and nobody dared to go out \
+            until the bells rang at noon. Then the doors opened one after the other.</p>";
+        let repost = "<div><p>The rain kept falling on the old town, and nobody dared to go out\n\
+            until the bells rang at noon.</p> <p>Then the doors opened one after the other.</p></div>";
+        let other = "<p>Far away, in the capital, the council was arguing about taxes \
+            for the third day in a row, and the king was getting bored of it.</p>";
+        assert!(are_near_duplicates(chapter, repost));
+        assert!(!are_near_duplicates(chapter, other));
+    }
+}
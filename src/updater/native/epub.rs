@@ -1,30 +1,44 @@
-use crate::updater::native::image;
-use crate::updater::native::{cache::Cache, xml_ext::write_elements};
+use crate::book::ORIGINAL_CHAPTER_PREFIX;
+use crate::config::{Config, ParallelText, TEMP_MARKER, TEMP_PREFIX};
+use crate::disk::ensure_available_space;
+use crate::error::Error;
+use crate::profile::{self, RoyalRoadSelectors};
+use crate::settings::{self, BookSettings};
+use crate::source::{self, Source};
+use crate::updater::native::cache::{Cache, ImageSource};
+use crate::updater::native::image::{self, DownloadedImage};
+use crate::updater::native::xml_ext::write_elements;
+use crate::updater::native::{fanficfare_pages, request};
+use crate::updater::{
+    check_readable, warn, Rating, Warning, BACKEND_META, COVER_URL_META, FORMAT_META,
+    FORMAT_VERSION, LAST_MODIFIED_META, RATING_META, STATUS_META, TAGS_META, VERSION_META,
+    WARNINGS_META,
+};
 use crate::{ErrorPrint, MULTI_PROGRESS};
 use chrono::{DateTime, Utc};
 use derive_more::derive::Debug;
 use epub::doc::EpubDoc;
-use eyre::{bail, eyre};
-use governor::{DefaultKeyedRateLimiter, Quota, RateLimiter};
+use eyre::eyre;
+use futures_util::future::join_all;
 use lazy_regex::regex;
 use lazy_static::lazy_static;
-use reqwest::blocking::{Client, Response};
-use scraper::{Html, Selector};
+use scraper::{ElementRef, Html, Selector};
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io::Write;
-use std::num::NonZeroU32;
 use std::path::Path;
-use std::sync::OnceLock;
-use std::thread;
-use std::time::Duration;
 use url::Url;
 use uuid::Uuid;
+use xml::escape::{escape_str_attribute, escape_str_pcdata};
 use xml::writer::XmlEvent;
 use xml::EmitterConfig;
 use zip::write::SimpleFileOptions;
 
-const USER_AGENT: &str = "rr-to-epub <https://github.com/isaac-mcfadyen/rr-to-epub>";
+/// Normalized copy of the cover, in addition to the original image.
+const COVER_FILENAME: &str = "cover.jpg";
+/// Spine item of the content warnings page, see `warnings_html`.
+const WARNINGS_PAGE_ID: &str = "warnings";
 pub const FORBIDDEN_CHARACTERS: [char; 13] = [
     '/', '\\', ':', '*', '?', '"', '<', '>', '|', '%', '"', '[', ']',
 ];
@@ -34,50 +48,61 @@ pub fn compile_time_selector(selector: &str) -> scraper::Selector {
     Selector::parse(selector).unwrap()
 }
 
-pub fn send_get_request(url: &str) -> std::result::Result<Response, reqwest::Error> {
-    static CLIENT_CELL: OnceLock<Client> = OnceLock::new();
-    static RATE_LIMITER_CELL: OnceLock<DefaultKeyedRateLimiter<String>> = OnceLock::new();
-
-    #[allow(clippy::unwrap_used)]
-    let rate_limiter = RATE_LIMITER_CELL.get_or_init(|| {
-        RateLimiter::keyed(
-            Quota::per_second(NonZeroU32::new(5u32).unwrap())
-                .allow_burst(NonZeroU32::new(1u32).unwrap()),
-        )
-    });
-
-    let host = Url::parse(url)
+/// Selector set by the configuration file, invalid ones are reported and ignored.
+fn configured_selector(selector: Option<&String>) -> Option<Selector> {
+    let selector = selector?;
+    Selector::parse(selector)
+        .map_err(|e| {
+            MULTI_PROGRESS.eprintln(&format!(
+                "Ignored the selector '{selector}' of the configuration file : {e}"
+            ));
+        })
         .ok()
-        .and_then(|u| u.host().map(|h| h.to_string()))
-        .unwrap_or_default();
-
-    while rate_limiter.check_key(&host).is_err() {
-        thread::sleep(Duration::from_millis(50));
-    }
+}
 
-    CLIENT_CELL
-        .get_or_init(Client::new)
-        .get(url)
-        .header("User-Agent", USER_AGENT)
-        .send()
+/// Selector of the Royal Road pages, unless the configuration file overrides it.
+fn royal_road_selector(
+    configured: fn(&RoyalRoadSelectors) -> Option<&String>,
+    default: &str,
+) -> Selector {
+    configured_selector(configured(profile::royalroad_selectors()))
+        .unwrap_or_else(|| compile_time_selector(default))
 }
 
 lazy_static! {
-    static ref CONTENT_SELECTOR: Selector = compile_time_selector(".chapter-inner.chapter-content");
+    static ref CONTENT_SELECTOR: Selector =
+        royal_road_selector(|s| s.content.as_ref(), ".chapter-inner.chapter-content");
 
     // Strange selectors are because RR doesn't have a way to tell if the author's note is
     // at the start or the end in the HTML.
-    static ref AUTHORS_NOTE_START_SELECTOR: Selector = compile_time_selector("hr + .portlet > .author-note");
-    static ref AUTHORS_NOTE_END_SELECTOR: Selector = compile_time_selector("div + .portlet > .author-note");
-
-    static ref TITLE_SELECTOR : Selector = compile_time_selector("h1");
-    static ref AUTHOR_SELECTOR : Selector = compile_time_selector("h4 a");
+    static ref AUTHORS_NOTE_START_SELECTOR: Selector =
+        royal_road_selector(|s| s.authors_note_start.as_ref(), "hr + .portlet > .author-note");
+    static ref AUTHORS_NOTE_END_SELECTOR: Selector =
+        royal_road_selector(|s| s.authors_note_end.as_ref(), "div + .portlet > .author-note");
+    static ref WATERMARK_SELECTOR: Option<Selector> =
+        configured_selector(profile::royalroad_selectors().watermark.as_ref());
+    static ref WIDGET_SELECTOR: Selector =
+        compile_time_selector("iframe, form, script, object, embed, [class*=\"poll\"]");
+    static ref CONTROL_SELECTOR: Selector = compile_time_selector("button, input, select, textarea");
+
+    static ref TITLE_SELECTOR : Selector = royal_road_selector(|s| s.title.as_ref(), "h1");
+    static ref PAGE_HEADING_SELECTOR : Selector = compile_time_selector("h1, title");
+    static ref AUTHOR_SELECTOR : Selector = royal_road_selector(|s| s.author.as_ref(), "h4 a");
     static ref DESCRIPTION_SELECTOR : Selector = compile_time_selector(".description > .hidden-content");
+    static ref CHAPTER_ROW_SELECTOR : Selector = compile_time_selector("#chapters tr.chapter-row");
+    static ref CHAPTER_LINK_SELECTOR : Selector = compile_time_selector("td a");
+    static ref CHAPTER_TIME_SELECTOR : Selector = compile_time_selector("time");
+    static ref NEXT_PAGE_SELECTOR : Selector = compile_time_selector(".pagination a[rel=next]");
+    static ref CONTENT_WARNING_SELECTOR : Selector = compile_time_selector(".font-red-sunglo ul.list-inline li");
+    static ref TAG_SELECTOR : Selector = compile_time_selector(".tags a.fiction-tag");
+    static ref LABEL_SELECTOR : Selector = compile_time_selector(".fiction-info span.label");
 
     static ref TITLE_ELEMENT_SELECTOR : Selector = compile_time_selector("title");
     static ref BODY_ELEMENT_SELECTOR : Selector = compile_time_selector("body");
     static ref META_CHAPTER_URL_SELECTOR : Selector = compile_time_selector("meta[name=chapterurl]");
+    static ref META_CHAPTER_ID_SELECTOR : Selector = compile_time_selector("meta[name=chapterid]");
     static ref META_CHAPTER_DATE_PUBLISHED_SELECTOR : Selector = compile_time_selector("meta[name=published]");
+    static ref META_CHAPTER_VOLUME_SELECTOR : Selector = compile_time_selector("meta[name=volume]");
 }
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
@@ -90,49 +115,106 @@ pub struct Book {
     pub description: String,
     pub date_published: String,
     pub cover_url: String,
+    /// Content warnings of the source (e.g. `Graphic Violence`).
+    #[serde(default)]
+    pub content_warnings: Vec<String>,
+    /// Genre tags of the source (e.g. `Fantasy`).
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Completion status given by the source (e.g. `Completed`).
+    #[serde(default)]
+    pub status: Option<String>,
+    /// Rating given by the source, under its own name (e.g. `T`).
+    #[serde(default)]
+    pub rating_label: Option<String>,
+    /// Validator of the source when the book was fetched, see `quick_check`.
+    #[serde(default)]
+    pub last_modified: Option<String>,
     pub chapters: Vec<Chapter>,
+    /// Index and number of the volumes, when written as one volume of a split book.
+    #[serde(skip)]
+    pub part: Option<(usize, usize)>,
 }
 impl Book {
     pub fn new(url: &str) -> eyre::Result<Self> {
+        source::get(url).fetch_book(url)
+    }
+
+    /// Book of the Royal Road fiction at `url`.
+    pub fn from_royal_road(url: &str) -> eyre::Result<Self> {
+        let response = request::block_on(request::get(url))?
+            .error_for_status(url)?
+            .text();
+        Self::from_royal_road_page(url, &response)
+    }
+
+    /// Book described by the Royal Road page of the fiction at `url`.
+    pub fn from_royal_road_page(url: &str, response: &str) -> eyre::Result<Self> {
         // Cover in script tag: window.fictionCover = "...";
         let cover_regex = regex!(r#"window\.fictionCover = "(.*)";"#);
         // Chapters array in script tag: window.chapters = [...];
         let chapters_regex = regex!(r"window\.chapters = (\[.*]);");
-
-        let request = send_get_request(url)?.error_for_status()?;
-        let response = request.text()?;
+        // Volumes array in script tag: window.volumes = [...];
+        let volumes_regex = regex!(r"window\.volumes = (\[.*]);");
 
         // Parse book metadata.
-        let parsed = Html::parse_document(&response);
+        let parsed = Html::parse_document(response);
         let title = parsed
             .select(&TITLE_SELECTOR)
             .next()
-            .ok_or_else(|| eyre!("No title found"))?
+            .ok_or_else(|| Error::Parse(String::from("No title found")))?
             .inner_html();
         let author = parsed
             .select(&AUTHOR_SELECTOR)
             .next()
-            .ok_or_else(|| eyre!("No author found"))?
+            .ok_or_else(|| Error::Parse(String::from("No author found")))?
             .inner_html();
         let description = parsed
             .select(&DESCRIPTION_SELECTOR)
             .next()
-            .ok_or_else(|| eyre!("No description found"))?
+            .ok_or_else(|| Error::Parse(String::from("No description found")))?
             .inner_html();
+        let content_warnings = parsed
+            .select(&CONTENT_WARNING_SELECTOR)
+            .map(|e| e.text().collect::<String>().trim().to_string())
+            .filter(|w| !w.is_empty())
+            .collect();
+        let tags = parsed
+            .select(&TAG_SELECTOR)
+            .map(|e| e.text().collect::<String>().trim().to_string())
+            .filter(|t| !t.is_empty())
+            .collect();
+        let status = parsed
+            .select(&LABEL_SELECTOR)
+            .map(|e| e.text().collect::<String>())
+            .find_map(|label| royal_road_status(&label));
 
         // Parse chapter metadata.
         let cover = cover_regex
-            .captures(&response)
-            .ok_or_else(|| eyre!("No cover found"))?[1]
+            .captures(response)
+            .ok_or_else(|| Error::Parse(String::from("No cover found")))?[1]
             .to_string();
+        let volumes: Vec<RoyalRoadVolume> = volumes_regex
+            .captures(response)
+            .and_then(|c| serde_json::from_str(&c[1]).ok())
+            .unwrap_or_default();
         let chapters = chapters_regex
-            .captures(&response)
-            .ok_or_else(|| eyre!("No chapters found"))?[1]
-            .to_string();
-        let chapters: Vec<Chapter> = serde_json::from_str::<Vec<RoyalRoadChapter>>(&chapters)?
-            .iter()
-            .map(RoyalRoadChapter::to_chapter)
-            .collect();
+            .captures(response)
+            .and_then(|c| serde_json::from_str::<Vec<RoyalRoadChapter>>(&c[1]).ok())
+            .map(|chapters| {
+                for warning in numbering_warnings(&chapters) {
+                    warn(url, warning);
+                }
+                chapters.iter().map(|c| c.to_chapter(&volumes)).collect()
+            });
+        // The inline script changes from time to time, the chapter table is more stable.
+        let chapters: Vec<Chapter> = match chapters {
+            Some(chapters) => chapters,
+            None => chapters_from_table(&parsed, url)?,
+        };
+        if chapters.is_empty() {
+            return Err(Error::Parse(String::from("No chapters found")).into());
+        }
 
         Ok(Self {
             id: Self::get_id_from_url(url)?,
@@ -141,18 +223,44 @@ impl Book {
             title,
             author,
             description,
+            content_warnings,
+            tags,
+            status,
             date_published: chapters
                 .first()
-                .ok_or_else(|| eyre!("No chapter"))?
+                .ok_or_else(|| Error::Parse(String::from("No chapter")))?
                 .date_published
                 .to_rfc3339(),
             chapters,
+            ..Self::default()
         })
     }
 
+    #[allow(clippy::too_many_lines)]
     pub fn from_path(url: &str, path: &Path) -> eyre::Result<Self> {
+        check_readable(path)?;
         let now = chrono::Utc::now();
         let mut epub_doc = EpubDoc::new(path)?;
+        // The tags and the status are among the subjects, with the content warnings.
+        let tags: Vec<String> = epub_doc
+            .mdata(TAGS_META)
+            .map(|tags| tags.split(',').map(String::from).collect())
+            .unwrap_or_default();
+        let status = epub_doc.mdata(STATUS_META);
+        // Books written before the warnings had their own metadata only have the subjects.
+        let content_warnings = epub_doc.mdata(WARNINGS_META).map_or_else(
+            || {
+                epub_doc
+                    .metadata
+                    .get("subject")
+                    .cloned()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|s| !tags.contains(s) && status.as_ref() != Some(s))
+                    .collect()
+            },
+            |warnings| warnings.split(',').map(String::from).collect(),
+        );
         let mut book = Self {
             id: Self::get_id_from_url(url)?,
             url: epub_doc.mdata("source").unwrap_or_default(),
@@ -160,8 +268,14 @@ impl Book {
             author: epub_doc.mdata("creator").unwrap_or_default(),
             description: epub_doc.mdata("description").unwrap_or_default(),
             date_published: epub_doc.mdata("date").unwrap_or_else(|| now.to_rfc3339()),
-            cover_url: String::new(),
+            cover_url: epub_doc.mdata(COVER_URL_META).unwrap_or_default(),
+            content_warnings,
+            tags,
+            status,
+            rating_label: epub_doc.mdata(RATING_META),
+            last_modified: epub_doc.mdata(LAST_MODIFIED_META),
             chapters: Vec::new(),
+            part: None,
         };
 
         let image_ids: Vec<_> = epub_doc
@@ -177,13 +291,19 @@ impl Book {
             .for_each(|(id, image)| {
                 if let Err(e) = Cache::write_inline_image(&book, &id, &image) {
                     MULTI_PROGRESS.eprintln(&format!("{e}"));
-                };
+                }
             });
 
+        // Chapters without a date, dated afterwards from the pages of FanFicFare if any.
+        let mut undated = Vec::new();
+        let mut log_updates = Vec::new();
+        let mut title_page_dates = (None, None);
         while epub_doc.go_next() {
-            if epub_doc
-                .get_current_id()
-                .is_some_and(|id| id == "nav.xhtml")
+            let current_id = epub_doc.get_current_id().unwrap_or_default();
+            // The warnings page and the chapters of the original text are added anew on each write.
+            if current_id == "nav.xhtml"
+                || current_id == WARNINGS_PAGE_ID
+                || current_id.starts_with(ORIGINAL_CHAPTER_PREFIX)
             {
                 continue;
             }
@@ -195,6 +315,15 @@ impl Book {
 
             let parsed = Html::parse_document(&xhtml);
 
+            if fanficfare_pages::PAGE_IDS.contains(&current_id.as_str()) {
+                if current_id == fanficfare_pages::LOG_PAGE_ID {
+                    log_updates = fanficfare_pages::log_updates(&parsed);
+                } else if current_id == fanficfare_pages::TITLE_PAGE_ID {
+                    title_page_dates = fanficfare_pages::title_page_dates(&parsed);
+                }
+                continue;
+            }
+
             let title = parsed
                 .select(&TITLE_ELEMENT_SELECTOR)
                 .next()
@@ -220,33 +349,59 @@ impl Book {
                 .next()
                 .and_then(|e| e.attr("content"))
                 .and_then(|d| DateTime::parse_from_rfc3339(d).ok())
-                .unwrap_or_else(|| now.into())
-                .into();
-
-            let identifier: String = Url::parse(&url)
-                .ok()
-                .and_then(|url| {
-                    url.path_segments()
-                        .and_then(|mut x| x.nth(4).map(ToString::to_string))
-                })
-                .unwrap_or_else(|| {
-                    epub_doc
-                        .get_current_id()
-                        .map(|s| s.replace(".xhtml", ""))
-                        .unwrap_or_default()
+                .map_or_else(
+                    || {
+                        undated.push(book.chapters.len());
+                        now
+                    },
+                    Into::into,
+                );
+
+            let volume = parsed
+                .select(&META_CHAPTER_VOLUME_SELECTOR)
+                .next()
+                .and_then(|e| e.attr("content"))
+                .filter(|v| !v.is_empty())
+                .map(ToString::to_string);
+
+            // Books written before the identifier was recorded are named after it, except the
+            // Royal Road books written by FanFicFare, whose chapter URLs end with it.
+            let identifier: String = parsed
+                .select(&META_CHAPTER_ID_SELECTOR)
+                .next()
+                .and_then(|e| e.attr("content"))
+                .map(ToString::to_string)
+                .or_else(|| {
+                    Url::parse(&url)
+                        .ok()
+                        .filter(|_| is_royal_road(&url))
+                        .and_then(|url| url.path_segments()?.nth(4).map(ToString::to_string))
                 })
-                .to_string();
+                .unwrap_or_else(|| current_id.replace(".xhtml", ""));
 
             book.chapters.push(Chapter {
                 identifier,
                 date_published,
                 title,
                 url,
+                volume,
                 content,
                 authors_note_start: None,
                 authors_note_end: None,
             });
         }
+
+        // The last chapter was published when the story was last updated, the others are
+        // assumed to be as old as the story.
+        let log_dates = fanficfare_pages::chapter_dates(&log_updates, book.chapters.len());
+        let (published, latest) = title_page_dates;
+        let last = book.chapters.len().saturating_sub(1);
+        for index in undated {
+            let fallback = if index == last { latest } else { published };
+            if let Some(date) = log_dates[index].or(fallback) {
+                book.chapters[index].date_published = date;
+            }
+        }
         Ok(book)
     }
 
@@ -259,36 +414,162 @@ impl Book {
             description: self.description.clone(),
             date_published: self.date_published.clone(),
             cover_url: self.cover_url.clone(),
+            content_warnings: self.content_warnings.clone(),
+            tags: self.tags.clone(),
+            status: self.status.clone(),
+            rating_label: self.rating_label.clone(),
+            last_modified: self.last_modified.clone(),
             chapters: Vec::new(),
+            part: self.part,
+        }
+    }
+
+    /// Identifier of the e-book, distinct for each volume of a split book.
+    fn identifier(&self) -> String {
+        self.part.map_or_else(
+            || self.id.to_string(),
+            |(index, _)| format!("{}-{index}", self.id),
+        )
+    }
+
+    /// Rating given by the source, or else implied by the content warnings of Royal Road
+    /// (which does not allow explicit works), `None` for sources which do not rate their works.
+    pub fn rating(&self) -> Option<Rating> {
+        if let Some(label) = &self.rating_label {
+            return Rating::from_label(label);
+        }
+        if !is_royal_road(&self.url) {
+            return None;
         }
+        let has_warning = |w: &str| self.content_warnings.iter().any(|c| c == w);
+        Some(
+            if has_warning("Sexual Content") || has_warning("Graphic Violence") {
+                Rating::Mature
+            } else if self.content_warnings.is_empty() {
+                Rating::General
+            } else {
+                Rating::Teen
+            },
+        )
+    }
+
+    pub fn get_id_from_url(url: &str) -> Result<u32, eyre::Error> {
+        source::get(url).book_id(url)
     }
+}
 
-    fn get_id_from_url(url: &str) -> Result<u32, eyre::Error> {
-        let url = Url::parse(url)?;
-        let id = url
-            .path_segments()
-            .and_then(|mut s| s.nth(1))
-            .and_then(|f| f.parse().ok())
-            .ok_or_else(|| eyre!("Invalid book URL: {url}"))?;
-        Ok(id)
+/// Chapters listed by the table of the fiction's page, following its pagination if any.
+fn chapters_from_table(page: &Html, url: &str) -> eyre::Result<Vec<Chapter>> {
+    let mut chapters = table_chapters(page);
+    let mut next_page = next_page_url(page, url);
+    let mut visited = HashSet::new();
+    while let Some(page_url) = next_page.filter(|u| visited.insert(u.clone())) {
+        let response = request::block_on(request::get(&page_url))?
+            .error_for_status(&page_url)?
+            .text();
+        let page = Html::parse_document(&response);
+        chapters.extend(table_chapters(&page));
+        next_page = next_page_url(&page, &page_url);
     }
+    Ok(chapters)
+}
+
+fn table_chapters(page: &Html) -> Vec<Chapter> {
+    page.select(&CHAPTER_ROW_SELECTOR)
+        .filter_map(|row| {
+            let link = row.select(&CHAPTER_LINK_SELECTOR).next()?;
+            let path = row.attr("data-url").or_else(|| link.attr("href"))?;
+            let identifier = regex!(r"/chapter/(\d+)").captures(path)?[1].to_string();
+            let time = row.select(&CHAPTER_TIME_SELECTOR).next();
+            let date_published = time
+                .and_then(|t| t.attr("unixtime"))
+                .and_then(|t| t.parse().ok())
+                .and_then(|t| DateTime::from_timestamp(t, 0))
+                .or_else(|| {
+                    time.and_then(|t| t.attr("datetime"))
+                        .and_then(|d| DateTime::parse_from_rfc3339(d).ok())
+                        .map(Into::into)
+                })
+                .unwrap_or_default();
+            Some(Chapter {
+                identifier,
+                date_published,
+                title: link.text().collect::<String>().trim().to_string(),
+                url: format!("https://www.royalroad.com{path}"),
+                volume: None,
+                content: None,
+                authors_note_start: None,
+                authors_note_end: None,
+            })
+        })
+        .collect()
+}
+
+pub fn next_page_url(page: &Html, url: &str) -> Option<String> {
+    let href = page.select(&NEXT_PAGE_SELECTOR).next()?.attr("href")?;
+    Url::parse(url).ok()?.join(href).ok().map(String::from)
+}
+
+/// Completion status of a label of the fiction's page (`ONGOING` gives `Ongoing`), `None` for
+/// the other labels (`ORIGINAL`, `FAN FICTION`…).
+fn royal_road_status(label: &str) -> Option<String> {
+    let label = label.trim();
+    [
+        "ONGOING",
+        "COMPLETED",
+        "HIATUS",
+        "STUB",
+        "DROPPED",
+        "INACTIVE",
+    ]
+    .iter()
+    .find(|status| label.eq_ignore_ascii_case(status))
+    .map(|status| format!("{}{}", &status[..1], status[1..].to_lowercase()))
+}
+
+/// Gaps and regressions in the `order` of the chapters listed by Royal Road.
+fn numbering_warnings(chapters: &[RoyalRoadChapter]) -> Vec<Warning> {
+    chapters
+        .windows(2)
+        .filter_map(|pair| {
+            let (previous, chapter) = (&pair[0], &pair[1]);
+            if chapter.order <= previous.order {
+                Some(Warning::ChapterRegression {
+                    title: chapter.title.clone(),
+                })
+            } else if chapter.order > previous.order + 1 {
+                Some(Warning::ChapterGap {
+                    after: previous.title.clone(),
+                    nb_missing: chapter.order - previous.order - 1,
+                })
+            } else {
+                None
+            }
+        })
+        .collect()
 }
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct RoyalRoadChapter {
     pub id: u32,
+    #[serde(default, rename = "volumeId")]
+    pub volume_id: Option<u32>,
     pub order: u32,
     pub date: DateTime<Utc>,
     pub title: String,
     pub url: String,
 }
 impl RoyalRoadChapter {
-    pub fn to_chapter(&self) -> Chapter {
+    pub fn to_chapter(&self, volumes: &[RoyalRoadVolume]) -> Chapter {
         Chapter {
             identifier: self.id.to_string(),
             date_published: self.date,
             title: self.title.clone(),
             url: format!("https://www.royalroad.com{}", self.url),
+            volume: self
+                .volume_id
+                .and_then(|id| volumes.iter().find(|v| v.id == id))
+                .map(|v| v.title.clone()),
             content: None,
             authors_note_start: None,
             authors_note_end: None,
@@ -296,12 +577,78 @@ impl RoyalRoadChapter {
     }
 }
 
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct RoyalRoadVolume {
+    pub id: u32,
+    pub title: String,
+}
+
+/// Move the chapters listed in `order` (by identifier or title) so that they follow its order,
+/// within the places they occupied, the other chapters stay where they are.
+fn apply_reading_order(chapters: &mut [Chapter], order: &[String]) {
+    let rank = |c: &Chapter| {
+        order
+            .iter()
+            .position(|o| *o == c.identifier || *o == c.title)
+    };
+    let slots: Vec<usize> = (0..chapters.len())
+        .filter(|i| rank(&chapters[*i]).is_some())
+        .collect();
+    let mut listed: Vec<Chapter> = slots.iter().map(|i| chapters[*i].clone()).collect();
+    listed.sort_by_key(rank);
+    for (slot, chapter) in slots.into_iter().zip(listed) {
+        chapters[slot] = chapter;
+    }
+}
+
+/// Chapters of the e-book of the original text at `path`, identified apart from the translated
+/// ones.
+fn original_chapters(path: &Path) -> eyre::Result<Vec<Chapter>> {
+    Ok(crate::chapter::Chapter::extract_from_epub(path)?
+        .into_iter()
+        .map(|chapter| Chapter {
+            identifier: format!("{ORIGINAL_CHAPTER_PREFIX}{}", chapter.number),
+            title: chapter.title,
+            content: Some(chapter.content),
+            ..Chapter::default()
+        })
+        .collect())
+}
+
+/// Add the chapters of the original text to the translated `chapters`, matched by position.
+fn add_original_text(chapters: &mut Vec<Chapter>, originals: Vec<Chapter>, mode: ParallelText) {
+    let latest = chapters
+        .last()
+        .map(|c| c.date_published)
+        .unwrap_or_default();
+    let mut originals = originals.into_iter();
+    if mode == ParallelText::Interleave {
+        for chapter in std::mem::take(chapters) {
+            let original = originals.next().map(|original| Chapter {
+                date_published: chapter.date_published,
+                volume: chapter.volume.clone(),
+                ..original
+            });
+            chapters.push(chapter);
+            chapters.extend(original);
+        }
+    }
+    // The others come after the translated chapters, all of them when appended.
+    chapters.extend(originals.map(|original| Chapter {
+        date_published: latest,
+        ..original
+    }));
+}
+
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct Chapter {
     pub identifier: String,
     pub date_published: DateTime<Utc>,
     pub title: String,
     pub url: String,
+    /// Volume (or arc) of the chapter, if its source groups them.
+    #[serde(default)]
+    pub volume: Option<String>,
 
     #[debug("{:?}", content.as_ref().map(|s| format!("{s:.100}")))]
     pub content: Option<String>,
@@ -318,23 +665,79 @@ impl PartialEq for Chapter {
 }
 impl Eq for Chapter {}
 impl Chapter {
-    pub fn update_chapter_content(&mut self) -> eyre::Result<()> {
+    /// Chapters made only of images, without any text.
+    pub fn is_image_only(&self) -> bool {
+        self.content.as_deref().is_some_and(image::is_image_only)
+    }
+
+    /// Download the content of the chapter of the book of `source`, if it has none.
+    pub async fn update_chapter_content(&mut self, source: &dyn Source) -> eyre::Result<()> {
         if self.content.is_some() {
             return Ok(());
         }
+        let api_content = match source.chapter_content(self) {
+            Some(content) => Some(content.await?),
+            None => None,
+        };
+        if api_content.is_some() {
+            self.content = api_content;
+            return Ok(());
+        }
 
-        let request = send_get_request(&self.url)?.error_for_status()?;
-        let text = request.text()?;
+        let text = request::get(&self.url)
+            .await?
+            .error_for_status(&self.url)?
+            .text();
+        source.read_page(self, &Html::parse_document(&text))
+    }
 
+    /// Chapter at `url` on its own, for the sources whose chapters are read from their page.
+    pub async fn fetch(url: &str) -> eyre::Result<Self> {
+        let text = request::get(url).await?.error_for_status(url)?.text();
         let parsed = Html::parse_document(&text);
+        let source = source::get(url);
+        let mut chapter = Self {
+            identifier: source.chapter_id(url).unwrap_or_default(),
+            title: parsed
+                .select(&PAGE_HEADING_SELECTOR)
+                .next()
+                .map(|e| e.text().collect::<String>().trim().to_string())
+                .unwrap_or_default(),
+            url: url.to_string(),
+            ..Self::default()
+        };
+        source.read_page(&mut chapter, &parsed)?;
+        Ok(chapter)
+    }
+
+    /// Content and author's notes, cleaned as when written in the e-book.
+    pub fn cleaned_html(&self) -> String {
+        let note = |note: &Option<String>| {
+            note.as_deref()
+                .map(|note| clean_html(&replace_widgets(note, &self.url)))
+        };
+        [
+            note(&self.authors_note_start),
+            self.content.as_deref().map(clean_html),
+            note(&self.authors_note_end),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join("\n")
+    }
 
+    /// Read the content and the author's notes of the chapter from its Royal Road page.
+    pub fn read_royal_road_page(&mut self, parsed: &Html) -> eyre::Result<()> {
         // Parse content.
         let content = parsed
             .select(&CONTENT_SELECTOR)
             .next()
-            .ok_or_else(|| eyre!("No content found"))?
-            .inner_html();
-        self.content = Some(content);
+            .ok_or_else(|| Error::Parse(String::from("No content found")))?;
+        self.content = Some(WATERMARK_SELECTOR.as_ref().map_or_else(
+            || content.inner_html(),
+            |watermark| without_matches(content, watermark),
+        ));
 
         // Parse starting author note.
         if let Some(authors_note) = parsed.select(&AUTHORS_NOTE_START_SELECTOR).next() {
@@ -355,13 +758,168 @@ impl Chapter {
     }
 }
 
+/// Inner HTML of `element` without the elements matching `selector`.
+fn without_matches(element: ElementRef, selector: &Selector) -> String {
+    element
+        .select(selector)
+        .fold(element.inner_html(), |html, matched| {
+            html.replacen(&matched.html(), "", 1)
+        })
+}
+
+pub fn default_filename(book: &Book) -> String {
+    format!("{}.epub", book.title.replace(FORBIDDEN_CHARACTERS, "_"))
+}
+
 pub fn write(book: &Book, outfile: Option<String>) -> eyre::Result<String> {
-    // Create a temp dir.
-    let temp_folder = tempfile::tempdir()?;
+    let outfile = write_epub(book, outfile)?;
+    if let Some(max_size) = Config::get().split_size {
+        if let Err(e) = write_volumes(book, &outfile, max_size) {
+            MULTI_PROGRESS.eprintln(&format!("Could not split '{}' : {e}", book.title));
+        }
+    }
+    Ok(outfile)
+}
+
+/// Whether `path` is one of the volumes of a split book, see `write_volumes`.
+pub fn is_volume(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| regex!(r" \(\d+ of \d+\)\.epub$").is_match(n))
+}
+
+fn volume_path(outfile: &Path, index: usize, nb_volumes: usize) -> std::path::PathBuf {
+    let stem = outfile.file_stem().unwrap_or_default().to_string_lossy();
+    outfile.with_file_name(format!("{stem} ({index} of {nb_volumes}).epub"))
+}
+
+/// Remove the volumes previously written for the book at `outfile`.
+fn remove_volumes(outfile: &Path) -> eyre::Result<()> {
+    let dir = outfile
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let stem = outfile.file_stem().unwrap_or_default().to_string_lossy();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_own_volume = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_prefix(stem.as_ref()))
+            .is_some_and(|rest| regex!(r"^ \(\d+ of \d+\)\.epub$").is_match(rest));
+        if is_own_volume {
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove the files written along with the book at `outfile`: its volumes and its CBZ export.
+pub fn remove_exports(outfile: &Path) -> eyre::Result<()> {
+    remove_volumes(outfile)?;
+    let cbz = outfile.with_extension("cbz");
+    if cbz.is_file() {
+        std::fs::remove_file(cbz)?;
+    }
+    Ok(())
+}
+
+/// Also write the book at `outfile` as `Title (1 of 2).epub` volumes of consecutive chapters
+/// when it is larger than `max_size` bytes, as some older readers cannot open large files.
+/// The number of volumes grows until each fits, or each holds a single chapter.
+fn write_volumes(book: &Book, outfile: &str, max_size: u64) -> eyre::Result<()> {
+    let outfile = Path::new(outfile);
+    remove_volumes(outfile)?;
+    let size = std::fs::metadata(outfile)?.len();
+    if size <= max_size || book.chapters.len() < 2 {
+        return Ok(());
+    }
+
+    // The volumes follow the reading order of the book, like its tables of contents.
+    let mut chapters = book.chapters.clone();
+    if let Some(order) = settings::reading_order(outfile) {
+        apply_reading_order(&mut chapters, &order);
+    }
+
+    let mut nb_volumes = usize::try_from(size.div_ceil(max_size)).unwrap_or(usize::MAX);
+    loop {
+        nb_volumes = nb_volumes.clamp(2, chapters.len());
+        let volumes: Vec<&[Chapter]> = chapters
+            .chunks(chapters.len().div_ceil(nb_volumes))
+            .collect();
+        let mut first_chapter = 1;
+        let mut fits = true;
+        for (index, volume_chapters) in volumes.iter().enumerate() {
+            let (index, last_chapter) = (index + 1, first_chapter + volume_chapters.len() - 1);
+            let volume = Book {
+                title: format!("{} ({index} of {})", book.title, volumes.len()),
+                description: format!(
+                    "Chapters {first_chapter} to {last_chapter} of {}.\n\n{}",
+                    chapters.len(),
+                    book.description
+                ),
+                chapters: volume_chapters.to_vec(),
+                part: Some((index, volumes.len())),
+                ..book.clone_without_chapters()
+            };
+            let path = volume_path(outfile, index, volumes.len());
+            write_epub(&volume, Some(path.to_string_lossy().into_owned()))?;
+            fits &= std::fs::metadata(&path)?.len() <= max_size;
+            first_chapter = last_chapter + 1;
+        }
+        if fits || volumes.len() == chapters.len() {
+            return Ok(());
+        }
+        remove_volumes(outfile)?;
+        nb_volumes = volumes.len() + 1;
+    }
+}
 
+#[allow(clippy::too_many_lines)]
+fn write_epub(book: &Book, outfile: Option<String>) -> eyre::Result<String> {
     // Choose the filename.
-    let outfile = outfile
-        .unwrap_or_else(|| format!("{}.epub", book.title.replace(FORBIDDEN_CHARACTERS, "_")));
+    let outfile = outfile.unwrap_or_else(|| default_filename(book));
+    let settings = settings::for_book(Path::new(&outfile));
+
+    // The spine and the tables of contents follow the reading order of the book, if it has one.
+    let mut book = Cow::Borrowed(book);
+    if let Some(order) = settings::reading_order(Path::new(&outfile)) {
+        apply_reading_order(&mut book.to_mut().chapters, &order);
+    }
+    // Translated books get the chapters of their original text, if enabled.
+    if let Some((mode, original)) = settings
+        .parallel_text
+        .zip(settings::original_text(Path::new(&outfile)))
+    {
+        match original_chapters(&original) {
+            Ok(originals) => add_original_text(&mut book.to_mut().chapters, originals, mode),
+            Err(e) => MULTI_PROGRESS.eprintln(&format!(
+                "Could not read the original text {} : {e}",
+                original.display()
+            )),
+        }
+    }
+    let book = book.as_ref();
+
+    // Make sure the e-book fits both in the temp dir and at its destination.
+    let estimated_size = estimate_size(book, Path::new(&outfile));
+    let destination_dir = Path::new(&outfile)
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    ensure_available_space(destination_dir, estimated_size)?;
+
+    // Create a temp dir, it is only removed on drop so a crash leaves it behind
+    // for the startup cleanup to find. Its marker stays locked until then, so that
+    // the cleanup of another run leaves it alone.
+    let temp_dir = Config::get().temp_dir();
+    std::fs::create_dir_all(&temp_dir)?;
+    ensure_available_space(&temp_dir, estimated_size)?;
+    let temp_folder = tempfile::Builder::new()
+        .prefix(TEMP_PREFIX)
+        .tempdir_in(&temp_dir)?;
+    let marker = std::fs::File::create(temp_folder.path().join(TEMP_MARKER))?;
+    marker.lock()?;
 
     // Open the file.
     let epub_path = temp_folder
@@ -392,30 +950,58 @@ pub fn write(book: &Book, outfile: Option<String>) -> eyre::Result<String> {
     epub_file.start_file("OEBPS/nav.xhtml", options)?;
     toc_nav(book, &mut epub_file)?;
 
-    // Store image urls
-    let mut images: HashSet<String> = HashSet::new();
-    // Add the cover.
-    images.insert(book.cover_url.clone());
-
-    // Write each chapter.
+    // Find each inline image in the content, as well as Author's Notes, starting with the cover.
+    let mut image_urls: Vec<String> = Some(book.cover_url.clone())
+        .filter(|u| !u.is_empty())
+        .into_iter()
+        .collect();
+    let mut seen_urls: HashSet<String> = image_urls.iter().cloned().collect();
+    // Page on which each image was first found, sent as referer when downloading it.
+    let mut referers: HashMap<String, String> = image_urls
+        .iter()
+        .map(|url| (url.clone(), book.url.clone()))
+        .collect();
+    let mut stripped_urls: HashSet<String> = HashSet::new();
+    let mut stripped_by_rule: BTreeMap<String, usize> = BTreeMap::new();
     for chapter in &book.chapters {
-        // Write the chapter file.
-        epub_file.start_file(format!("OEBPS/text/{}.xhtml", chapter.identifier), options)?;
-        chapter_html(chapter, &mut epub_file)?;
-
-        // Find each inline image in the content, as well as Author's Notes.
-        images.extend(image::extract_urls_from_html(&chapter.content));
-        images.extend(image::extract_urls_from_html(&chapter.authors_note_start));
-        images.extend(image::extract_urls_from_html(&chapter.authors_note_end));
+        // Relative URLs are resolved against the chapter's URL.
+        let base = Url::parse(&chapter.url).ok();
+        for html in [
+            &chapter.content,
+            &chapter.authors_note_start,
+            &chapter.authors_note_end,
+        ] {
+            for url in image::extract_urls_from_html(html.as_ref(), base.as_ref()) {
+                if !seen_urls.insert(url.clone()) {
+                    continue;
+                }
+                referers.insert(url.clone(), chapter.url.clone());
+                // Strip the images of the hosts excluded by the configuration.
+                match image::rejecting_rule(&url, &settings.image_hosts) {
+                    Some(rule) => {
+                        *stripped_by_rule.entry(rule).or_default() += 1;
+                        stripped_urls.insert(url);
+                    }
+                    None => image_urls.push(url),
+                }
+            }
+        }
+    }
+    for (rule, count) in stripped_by_rule {
+        MULTI_PROGRESS.eprintln(&format!(
+            "Stripped {count} image(s) of '{}' ({rule})",
+            book.title
+        ));
     }
 
-    // Store image filenames to add them to the content_opf
-    let mut image_filenames: HashSet<String> = HashSet::new();
     let mut disambiguation_integer: u16 = 0;
+    let mut downloaded_images: Vec<DownloadedImage> = Vec::new();
 
-    // Download the images and add them to the e-book
-    for url in &images {
-        let mut filename = match image::extract_file_name(url) {
+    // Name the images
+    let mut named_images: Vec<(String, String)> = Vec::new();
+    let mut used_filenames: HashSet<String> = HashSet::new();
+    for url in image_urls {
+        let mut filename = match image::extract_file_name(&url) {
             Ok(f) => f,
             Err(e) => {
                 MULTI_PROGRESS.eprintln(&format!("{e} (URL : {url})"));
@@ -425,30 +1011,125 @@ pub fn write(book: &Book, outfile: Option<String>) -> eyre::Result<String> {
 
         // In some case images can have the same name, we prefix it
         // with an integer to disambiguate.
-        if image_filenames.contains(&filename) {
+        if used_filenames.contains(&filename) || filename == COVER_FILENAME {
             filename = format!("{disambiguation_integer}_{filename}");
             disambiguation_integer += 1;
         }
-
-        match download_image(book, url, &filename) {
+        used_filenames.insert(filename.clone());
+        named_images.push((url, filename));
+    }
+    // Every image of the chapters stays in the cache, even the ones which cannot be obtained
+    // this time or which are left out of the e-book.
+    let mut referenced_filenames = used_filenames;
+    referenced_filenames.extend(
+        stripped_urls
+            .iter()
+            .filter_map(|url| image::extract_file_name(url).ok()),
+    );
+
+    // Download them concurrently, the rate limiter spacing the requests to each host.
+    let buffers = request::block_on(join_all(named_images.iter().map(|(url, filename)| {
+        let referer = referers.get(url).map_or(book.url.as_str(), String::as_str);
+        download_image(book, url, referer, filename)
+    })));
+    for ((url, filename), buffer) in named_images.into_iter().zip(buffers) {
+        match buffer {
             Ok(buffer) => {
-                // Write the image to the file.
-                epub_file.start_file(format!("OEBPS/images/{filename}"), options)?;
-                epub_file.write_all(&buffer)?;
-
-                image_filenames.insert(filename);
+                downloaded_images.push(DownloadedImage {
+                    url,
+                    filename,
+                    buffer,
+                });
             }
             Err(err) => MULTI_PROGRESS.eprintln(&err.to_string()),
         }
     }
 
+    // Leave out the images which do not fit in the budget.
+    let (images, skipped_images) =
+        image::apply_budget(downloaded_images, &book.cover_url, &settings.image_budget);
+    if !skipped_images.is_empty() {
+        MULTI_PROGRESS.eprintln(&format!(
+            "Skipped {} image(s) of '{}' to stay within the image budget : {}",
+            skipped_images.len(),
+            book.title,
+            skipped_images
+                .iter()
+                .map(|i| i.filename.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    let mut skipped_urls: HashSet<String> = skipped_images.into_iter().map(|i| i.url).collect();
+    skipped_urls.extend(stripped_urls);
+
+    // Write each chapter.
+    for chapter in &book.chapters {
+        epub_file.start_file(format!("OEBPS/text/{}.xhtml", chapter.identifier), options)?;
+        chapter_html(chapter, &skipped_urls, &mut epub_file)?;
+    }
+
+    // The volumes of a split book leave the exports and the cache to the whole book.
+    if settings.export_cbz && book.part.is_none() {
+        if let Err(e) = write_cbz(book, &images, &outfile) {
+            MULTI_PROGRESS.eprintln(&format!("Could not export the image chapters : {e}"));
+        }
+    }
+
+    // Store image filenames to add them to the content_opf
+    let mut image_filenames: HashSet<String> = HashSet::new();
+    let mut cover: Option<Vec<u8>> = None;
+
+    // Add the images to the e-book
+    for image in images {
+        epub_file.start_file(format!("OEBPS/images/{}", image.filename), options)?;
+        epub_file.write_all(&image.buffer)?;
+
+        image_filenames.insert(image.filename);
+        if image.url == book.cover_url {
+            cover = Some(image.buffer);
+        }
+    }
+
+    // Some readers only display the conventional JPEG cover item.
+    let cover = cover.and_then(|c| {
+        image::to_jpeg(&c)
+            .map_err(|e| MULTI_PROGRESS.eprintln(&format!("Could not convert the cover : {e}")))
+            .ok()
+    });
+    if let Some(cover) = &cover {
+        epub_file.start_file(format!("OEBPS/images/{COVER_FILENAME}"), options)?;
+        epub_file.write_all(cover)?;
+    }
+
+    if !Config::get().keep_orphans && book.part.is_none() {
+        if let Err(e) =
+            Cache::remove_orphaned_images(book, Path::new(&outfile), &referenced_filenames)
+        {
+            MULTI_PROGRESS.eprintln(&format!("Could not clean the image cache : {e}"));
+        }
+    }
+
     // Write the title page.
     epub_file.start_file("OEBPS/text/title.xhtml", options)?;
     title_html(book, &mut epub_file)?;
 
+    // Write the content warnings page.
+    let warnings_page = has_warnings_page(book, &settings);
+    if warnings_page {
+        epub_file.start_file(format!("OEBPS/text/{WARNINGS_PAGE_ID}.xhtml"), options)?;
+        warnings_html(book, &mut epub_file)?;
+    }
+
     // Write the content.opf file.
     epub_file.start_file("OEBPS/content.opf", options)?;
-    content_opf(book, &image_filenames, &mut epub_file)?;
+    content_opf(
+        book,
+        &image_filenames,
+        cover.is_some(),
+        warnings_page,
+        &mut epub_file,
+    )?;
 
     // Write the stylesheet.
     epub_file.start_file("OEBPS/styles/stylesheet.css", options)?;
@@ -461,6 +1142,63 @@ pub fn write(book: &Book, outfile: Option<String>) -> eyre::Result<String> {
     Ok(outfile)
 }
 
+/// Export the image-only chapters as a comic book archive next to the e-book.
+fn write_cbz(book: &Book, images: &[DownloadedImage], outfile: &str) -> eyre::Result<()> {
+    let images_by_url: HashMap<&str, &DownloadedImage> =
+        images.iter().map(|i| (i.url.as_str(), i)).collect();
+
+    // Pages are named so that they sort in reading order.
+    let mut pages: Vec<(String, &DownloadedImage)> = Vec::new();
+    for (chapter_index, chapter) in book
+        .chapters
+        .iter()
+        .filter(|c| c.is_image_only())
+        .enumerate()
+    {
+        let base = Url::parse(&chapter.url).ok();
+        let urls = image::extract_urls_from_html(chapter.content.as_ref(), base.as_ref());
+        for (page_index, page) in urls
+            .iter()
+            .filter_map(|url| images_by_url.get(url.as_str()).copied())
+            .enumerate()
+        {
+            pages.push((
+                format!("{chapter_index:04}_{page_index:03}_{}", page.filename),
+                page,
+            ));
+        }
+    }
+    if pages.is_empty() {
+        return Ok(());
+    }
+
+    let cbz_path = Path::new(outfile).with_extension("cbz");
+    let mut cbz_file = zip::ZipWriter::new(std::fs::File::create(cbz_path)?);
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    for (name, page) in pages {
+        cbz_file.start_file(name, options)?;
+        cbz_file.write_all(&page.buffer)?;
+    }
+    cbz_file.finish()?;
+    Ok(())
+}
+
+/// Rough upper bound of the size of the written e-book : the text of the chapters,
+/// or the size of the previous version of the e-book if it is bigger (e.g. because of images).
+fn estimate_size(book: &Book, outfile: &Path) -> u64 {
+    let text_size: usize = book
+        .chapters
+        .iter()
+        .flat_map(|c| [&c.content, &c.authors_note_start, &c.authors_note_end])
+        .flatten()
+        .map(String::len)
+        .sum();
+    let previous_size = std::fs::metadata(outfile).map_or(0, |m| m.len());
+    u64::try_from(text_size)
+        .unwrap_or(u64::MAX)
+        .max(previous_size)
+}
+
 fn stylesheet(file: &mut impl Write) -> eyre::Result<()> {
     file.write_all(include_bytes!("./assets/styles.css"))?;
     Ok(())
@@ -519,7 +1257,73 @@ fn title_html(book: &Book, file: &mut impl Write) -> eyre::Result<()> {
     Ok(())
 }
 
-fn chapter_html(chapter: &Chapter, file: &mut impl Write) -> eyre::Result<()> {
+const fn has_warnings_page(book: &Book, settings: &BookSettings) -> bool {
+    settings.warnings_page && !book.content_warnings.is_empty()
+}
+
+fn warnings_html(book: &Book, file: &mut impl Write) -> eyre::Result<()> {
+    let mut xml = EmitterConfig::new().perform_indent(true);
+    xml.perform_escaping = false;
+    let mut xml = xml.create_writer(file);
+
+    #[rustfmt::skip]
+    write_elements(
+        &mut xml,
+        vec![
+            XmlEvent::characters("\n<!DOCTYPE html>\n"),
+            XmlEvent::start_element("html")
+                .ns("", "http://www.w3.org/1999/xhtml")
+                .into(),
+
+                // Write the head.
+                XmlEvent::start_element("head").into(),
+                    XmlEvent::start_element("title").into(),
+                        XmlEvent::characters("Content Warnings"),
+                    XmlEvent::end_element().into(), // title
+
+                    XmlEvent::start_element("link")
+                        .attr("rel", "stylesheet")
+                        .attr("type", "text/css")
+                        .attr("href", "../styles/stylesheet.css")
+                        .into(),
+                    XmlEvent::end_element().into(), // link
+                XmlEvent::end_element().into(), // head
+
+                XmlEvent::start_element("body").into(),
+                    XmlEvent::start_element("h2").attr("class", "warnings").into(),
+                        XmlEvent::characters("Content Warnings"),
+                    XmlEvent::end_element().into(),
+                    XmlEvent::start_element("ul").attr("class", "warnings").into(),
+        ],
+    )?;
+    for warning in &book.content_warnings {
+        write_elements(
+            &mut xml,
+            vec![
+                XmlEvent::start_element("li").into(),
+                XmlEvent::characters(&escape_str_pcdata(warning)),
+                XmlEvent::end_element().into(),
+            ],
+        )?;
+    }
+    write_elements(
+        &mut xml,
+        vec![
+            XmlEvent::end_element().into(), // ul
+            XmlEvent::end_element().into(), // body
+            XmlEvent::end_element().into(), // html
+        ],
+    )?;
+    Ok(())
+}
+
+#[allow(clippy::too_many_lines)]
+fn chapter_html(
+    chapter: &Chapter,
+    skipped_images: &HashSet<String>,
+    file: &mut impl Write,
+) -> eyre::Result<()> {
+    let base = Url::parse(&chapter.url).ok();
     let mut xml = EmitterConfig::new().perform_indent(true);
     xml.perform_escaping = false;
     let mut xml = xml.create_writer(file);
@@ -545,9 +1349,16 @@ fn chapter_html(chapter: &Chapter, file: &mut impl Write) -> eyre::Result<()> {
                         .into(),
                     XmlEvent::end_element().into(),
 
+                    // Escaping is off for the content, the query of the URL may hold a `&`.
                     XmlEvent::start_element("meta")
                         .attr("name", "chapterurl")
-                        .attr("content", &chapter.url)
+                        .attr("content", &escape_str_attribute(&chapter.url))
+                        .into(),
+                    XmlEvent::end_element().into(),
+
+                    XmlEvent::start_element("meta")
+                        .attr("name", "chapterid")
+                        .attr("content", &escape_str_attribute(&chapter.identifier))
                         .into(),
                     XmlEvent::end_element().into(),
 
@@ -557,6 +1368,12 @@ fn chapter_html(chapter: &Chapter, file: &mut impl Write) -> eyre::Result<()> {
                         .into(),
                     XmlEvent::end_element().into(),
 
+                    XmlEvent::start_element("meta")
+                        .attr("name", "volume")
+                        .attr("content", chapter.volume.as_deref().unwrap_or_default())
+                        .into(),
+                    XmlEvent::end_element().into(),
+
                     XmlEvent::start_element("link")
                         .attr("href", "../styles/stylesheet.css")
                         .attr("rel", "stylesheet")
@@ -577,6 +1394,9 @@ fn chapter_html(chapter: &Chapter, file: &mut impl Write) -> eyre::Result<()> {
 
     // Write the starting author's note, if any.
     if let Some(mut authors_note_start) = chapter.authors_note_start.clone() {
+        authors_note_start = replace_widgets(&authors_note_start, &chapter.url);
+        authors_note_start =
+            image::remove_images(authors_note_start, base.as_ref(), skipped_images);
         authors_note_start = clean_html(&authors_note_start);
         write_elements(
             &mut xml,
@@ -584,13 +1404,22 @@ fn chapter_html(chapter: &Chapter, file: &mut impl Write) -> eyre::Result<()> {
                 XmlEvent::start_element("div")
                     .attr("class", "authors-note-start")
                     .into(),
-                XmlEvent::characters(&image::replace_url_with_path(authors_note_start)),
+                XmlEvent::characters(&image::replace_url_with_path(
+                    authors_note_start,
+                    base.as_ref(),
+                )),
                 XmlEvent::end_element().into(),
             ],
         )?;
     }
-    // Write the content.
+    // Write the content, image-only chapters (e.g. art or comic pages) are laid out full-bleed.
+    let content_class = if chapter.is_image_only() {
+        "chapter-content image-chapter"
+    } else {
+        "chapter-content"
+    };
     if let Some(mut content) = chapter.content.clone() {
+        content = image::remove_images(content, base.as_ref(), skipped_images);
         content = clean_html(&content);
 
         // Remove any "stolen from Amazon" messages.
@@ -604,16 +1433,18 @@ fn chapter_html(chapter: &Chapter, file: &mut impl Write) -> eyre::Result<()> {
             &mut xml,
             vec![
                 XmlEvent::start_element("div")
-                    .attr("class", "chapter-content")
+                    .attr("class", content_class)
                     .into(),
                 // Rewrite the images to be pointing to our downloaded ones.
-                XmlEvent::characters(&image::replace_url_with_path(content)),
+                XmlEvent::characters(&image::replace_url_with_path(content, base.as_ref())),
                 XmlEvent::end_element().into(),
             ],
         )?;
     }
     // Write the ending author's note, if any.
     if let Some(mut authors_note_end) = chapter.authors_note_end.clone() {
+        authors_note_end = replace_widgets(&authors_note_end, &chapter.url);
+        authors_note_end = image::remove_images(authors_note_end, base.as_ref(), skipped_images);
         authors_note_end = clean_html(&authors_note_end);
         write_elements(
             &mut xml,
@@ -621,7 +1452,10 @@ fn chapter_html(chapter: &Chapter, file: &mut impl Write) -> eyre::Result<()> {
                 XmlEvent::start_element("div")
                     .attr("class", "authors-note-end")
                     .into(),
-                XmlEvent::characters(&image::replace_url_with_path(authors_note_end)),
+                XmlEvent::characters(&image::replace_url_with_path(
+                    authors_note_end,
+                    base.as_ref(),
+                )),
                 XmlEvent::end_element().into(),
             ],
         )?;
@@ -638,6 +1472,33 @@ fn chapter_html(chapter: &Chapter, file: &mut impl Write) -> eyre::Result<()> {
     Ok(())
 }
 
+/// Replace the polls and embedded widgets of author's notes, which cannot work in an e-book,
+/// with a link back to the chapter, and drop their leftover controls (e.g. spoiler buttons).
+fn replace_widgets(note: &str, chapter_url: &str) -> String {
+    let fragment = Html::parse_fragment(note);
+    let mut note = fragment.root_element().inner_html();
+    let href = chapter_url.replace('&', "&amp;");
+    for widget in fragment.select(&WIDGET_SELECTOR) {
+        let label = if widget
+            .attr("class")
+            .is_some_and(|class| class.contains("poll"))
+        {
+            "Poll available on Royal Road"
+        } else {
+            "Embedded content available on Royal Road"
+        };
+        note = note.replacen(
+            &widget.html(),
+            &format!("<p><a href=\"{href}\">{label}</a></p>"),
+            1,
+        );
+    }
+    for control in fragment.select(&CONTROL_SELECTOR) {
+        note = note.replacen(&control.html(), "", 1);
+    }
+    note
+}
+
 fn clean_html(original_content: &str) -> String {
     // Remove the font-family: *; from styles.
     let font_family_regex = regex!(r#"\s*font-family:[^;"]*(?:;\s*|("))"#);
@@ -703,6 +1564,8 @@ fn container_xml(_: &Book, file: &mut impl Write) -> eyre::Result<()> {
 fn content_opf(
     book: &Book,
     image_filenames: &HashSet<String>,
+    has_cover: bool,
+    warnings_page: bool,
     file: &mut impl Write,
 ) -> eyre::Result<()> {
     let mut xml = EmitterConfig::new()
@@ -737,14 +1600,14 @@ fn content_opf(
             XmlEvent::start_element("dc:identifier")
                 .attr("id", "bookid")
                 .into(),
-            XmlEvent::characters(&book.id.to_string()),
+            XmlEvent::characters(&book.identifier()),
             XmlEvent::end_element().into(),
             XmlEvent::start_element("dc:language").into(),
             XmlEvent::characters("en"),
             XmlEvent::end_element().into(),
             XmlEvent::start_element("meta")
                 .attr("name", "cover")
-                .attr("content", "cover")
+                .attr("content", "cover-image")
                 .into(),
             XmlEvent::end_element().into(),
             XmlEvent::start_element("meta")
@@ -757,9 +1620,117 @@ fn content_opf(
                 .attr("content", &book.id.to_string())
                 .into(),
             XmlEvent::end_element().into(),
+            XmlEvent::start_element("meta")
+                .attr("name", COVER_URL_META)
+                .attr(
+                    "content",
+                    if image::is_data_uri(&book.cover_url) {
+                        ""
+                    } else {
+                        &book.cover_url
+                    },
+                )
+                .into(),
             XmlEvent::end_element().into(),
-            // Write the manifest.
-            XmlEvent::start_element("manifest").into(),
+            XmlEvent::start_element("meta")
+                .attr("name", BACKEND_META)
+                .attr("content", "native")
+                .into(),
+            XmlEvent::end_element().into(),
+            XmlEvent::start_element("meta")
+                .attr("name", VERSION_META)
+                .attr("content", env!("CARGO_PKG_VERSION"))
+                .into(),
+            XmlEvent::end_element().into(),
+            XmlEvent::start_element("meta")
+                .attr("name", FORMAT_META)
+                .attr("content", &FORMAT_VERSION.to_string())
+                .into(),
+            XmlEvent::end_element().into(),
+        ],
+    )?;
+    if let Some(rating) = book.rating() {
+        write_elements(
+            &mut xml,
+            vec![
+                XmlEvent::start_element("meta")
+                    .attr("name", RATING_META)
+                    .attr("content", rating.label())
+                    .into(),
+                XmlEvent::end_element().into(),
+            ],
+        )?;
+    }
+    if let Some(last_modified) = &book.last_modified {
+        write_elements(
+            &mut xml,
+            vec![
+                XmlEvent::start_element("meta")
+                    .attr("name", LAST_MODIFIED_META)
+                    .attr("content", last_modified)
+                    .into(),
+                XmlEvent::end_element().into(),
+            ],
+        )?;
+    }
+    if !book.tags.is_empty() {
+        write_elements(
+            &mut xml,
+            vec![
+                XmlEvent::start_element("meta")
+                    .attr("name", TAGS_META)
+                    .attr("content", &book.tags.join(","))
+                    .into(),
+                XmlEvent::end_element().into(),
+            ],
+        )?;
+    }
+    if !book.content_warnings.is_empty() {
+        write_elements(
+            &mut xml,
+            vec![
+                XmlEvent::start_element("meta")
+                    .attr("name", WARNINGS_META)
+                    .attr("content", &book.content_warnings.join(","))
+                    .into(),
+                XmlEvent::end_element().into(),
+            ],
+        )?;
+    }
+    if let Some(status) = &book.status {
+        write_elements(
+            &mut xml,
+            vec![
+                XmlEvent::start_element("meta")
+                    .attr("name", STATUS_META)
+                    .attr("content", status)
+                    .into(),
+                XmlEvent::end_element().into(),
+            ],
+        )?;
+    }
+    // Library software shows the subjects as tags, sorting by genre and completion.
+    for subject in book
+        .tags
+        .iter()
+        .chain(&book.content_warnings)
+        .chain(&book.status)
+    {
+        write_elements(
+            &mut xml,
+            vec![
+                XmlEvent::start_element("dc:subject").into(),
+                XmlEvent::characters(subject),
+                XmlEvent::end_element().into(),
+            ],
+        )?;
+    }
+    write_elements(
+        &mut xml,
+        vec![
+            XmlEvent::end_element().into(),
+            // Write the manifest.
+            XmlEvent::start_element("manifest").into(),
             // Write the title page.
             XmlEvent::start_element("item")
                 .attr("id", "title")
@@ -767,6 +1738,24 @@ fn content_opf(
                 .attr("media-type", "application/xhtml+xml")
                 .into(),
             XmlEvent::end_element().into(),
+        ],
+    )?;
+    if warnings_page {
+        write_elements(
+            &mut xml,
+            vec![
+                XmlEvent::start_element("item")
+                    .attr("id", WARNINGS_PAGE_ID)
+                    .attr("href", &format!("text/{WARNINGS_PAGE_ID}.xhtml"))
+                    .attr("media-type", "application/xhtml+xml")
+                    .into(),
+                XmlEvent::end_element().into(),
+            ],
+        )?;
+    }
+    write_elements(
+        &mut xml,
+        vec![
             // Write the stylesheet.
             XmlEvent::start_element("item")
                 .attr("id", "stylesheet")
@@ -792,17 +1781,36 @@ fn content_opf(
         ],
     )?;
 
-    for filename in image_filenames {
+    if has_cover {
         write_elements(
             &mut xml,
             vec![
                 // Write the cover.
+                XmlEvent::start_element("item")
+                    .attr("id", "cover-image")
+                    .attr("href", &format!("images/{COVER_FILENAME}"))
+                    .attr("media-type", "image/jpeg")
+                    .attr("properties", "cover-image")
+                    .into(),
+                XmlEvent::end_element().into(),
+            ],
+        )?;
+    }
+
+    for filename in image_filenames {
+        write_elements(
+            &mut xml,
+            vec![
+                // Write the images.
                 XmlEvent::start_element("item")
                     .attr("id", filename)
                     .attr("href", &format!("images/{}", &filename))
                     .attr(
                         "media-type",
-                        &format!("image/{}", filename.split('.').last().unwrap_or("jpeg")),
+                        &format!(
+                            "image/{}",
+                            filename.split('.').next_back().unwrap_or("jpeg")
+                        ),
                     )
                     .into(),
                 XmlEvent::end_element().into(),
@@ -837,6 +1845,17 @@ fn content_opf(
             XmlEvent::end_element().into(),
         ],
     )?;
+    if warnings_page {
+        write_elements(
+            &mut xml,
+            vec![
+                XmlEvent::start_element("itemref")
+                    .attr("idref", WARNINGS_PAGE_ID)
+                    .into(),
+                XmlEvent::end_element().into(),
+            ],
+        )?;
+    }
     // For each chapter, write a link.
     for chapter in &book.chapters {
         write_elements(
@@ -905,20 +1924,45 @@ fn toc_nav(book: &Book, file: &mut impl Write) -> eyre::Result<()> {
         ],
     )?;
 
-    // Write each chapter.
-    for chapter in &book.chapters {
-        write_elements(
-            &mut xml,
-            vec![
-                XmlEvent::start_element("li").into(),
-                XmlEvent::start_element("a")
-                    .attr("href", &format!("text/{}.xhtml", &chapter.identifier))
-                    .into(),
-                XmlEvent::characters(&chapter.title),
-                XmlEvent::end_element().into(),
-                XmlEvent::end_element().into(),
-            ],
-        )?;
+    // Write each chapter, within the list of its volume if any.
+    for (volume, chapters) in volume_groups(&book.chapters) {
+        if let Some(volume) = volume {
+            write_elements(
+                &mut xml,
+                vec![
+                    XmlEvent::start_element("li").into(),
+                    XmlEvent::start_element("a")
+                        .attr("href", &format!("text/{}.xhtml", &chapters[0].identifier))
+                        .into(),
+                    XmlEvent::characters(volume),
+                    XmlEvent::end_element().into(),
+                    XmlEvent::start_element("ol").into(),
+                ],
+            )?;
+        }
+        for chapter in chapters {
+            write_elements(
+                &mut xml,
+                vec![
+                    XmlEvent::start_element("li").into(),
+                    XmlEvent::start_element("a")
+                        .attr("href", &format!("text/{}.xhtml", &chapter.identifier))
+                        .into(),
+                    XmlEvent::characters(&chapter.title),
+                    XmlEvent::end_element().into(),
+                    XmlEvent::end_element().into(),
+                ],
+            )?;
+        }
+        if volume.is_some() {
+            write_elements(
+                &mut xml,
+                vec![
+                    XmlEvent::end_element().into(),
+                    XmlEvent::end_element().into(),
+                ],
+            )?;
+        }
     }
     write_elements(
         &mut xml,
@@ -933,6 +1977,15 @@ fn toc_nav(book: &Book, file: &mut impl Write) -> eyre::Result<()> {
     Ok(())
 }
 
+/// Runs of consecutive chapters of the same volume, `None` for the chapters without one.
+fn volume_groups(chapters: &[Chapter]) -> Vec<(Option<&str>, &[Chapter])> {
+    chapters
+        .chunk_by(|a, b| a.volume == b.volume)
+        .map(|group| (group[0].volume.as_deref(), group))
+        .collect()
+}
+
+#[allow(clippy::too_many_lines)]
 fn toc_ncx(book: &Book, file: &mut impl Write) -> eyre::Result<()> {
     let mut xml = EmitterConfig::new()
         .perform_indent(true)
@@ -948,7 +2001,7 @@ fn toc_ncx(book: &Book, file: &mut impl Write) -> eyre::Result<()> {
             XmlEvent::start_element("head").into(),
             XmlEvent::start_element("meta")
                 .attr("name", "dtb:uid")
-                .attr("content", &format!("{}", book.id))
+                .attr("content", &book.identifier())
                 .into(),
             XmlEvent::end_element().into(),
             XmlEvent::start_element("meta")
@@ -990,27 +2043,55 @@ fn toc_ncx(book: &Book, file: &mut impl Write) -> eyre::Result<()> {
         ],
     )?;
 
-    // For each chapter, write a link.
-    for (index, chapter) in book.chapters.iter().enumerate() {
-        write_elements(
-            &mut xml,
-            vec![
-                XmlEvent::start_element("navPoint")
-                    .attr("id", &chapter.identifier)
-                    .attr("playOrder", &format!("{}", index + 1))
-                    .into(),
-                XmlEvent::start_element("navLabel").into(),
-                XmlEvent::start_element("text").into(),
-                XmlEvent::characters(&chapter.title),
-                XmlEvent::end_element().into(),
-                XmlEvent::end_element().into(),
-                XmlEvent::start_element("content")
-                    .attr("src", &format!("text/{}.xhtml", &chapter.identifier))
-                    .into(),
-                XmlEvent::end_element().into(),
-                XmlEvent::end_element().into(),
-            ],
-        )?;
+    // For each chapter, write a link, within the one of its volume if any (which leads to the
+    // first chapter of the volume).
+    let mut play_order = 0;
+    for (index, (volume, chapters)) in volume_groups(&book.chapters).into_iter().enumerate() {
+        if let Some(volume) = volume {
+            write_elements(
+                &mut xml,
+                vec![
+                    XmlEvent::start_element("navPoint")
+                        .attr("id", &format!("volume-{index}"))
+                        .attr("playOrder", &format!("{}", play_order + 1))
+                        .into(),
+                    XmlEvent::start_element("navLabel").into(),
+                    XmlEvent::start_element("text").into(),
+                    XmlEvent::characters(volume),
+                    XmlEvent::end_element().into(),
+                    XmlEvent::end_element().into(),
+                    XmlEvent::start_element("content")
+                        .attr("src", &format!("text/{}.xhtml", &chapters[0].identifier))
+                        .into(),
+                    XmlEvent::end_element().into(),
+                ],
+            )?;
+        }
+        for chapter in chapters {
+            play_order += 1;
+            write_elements(
+                &mut xml,
+                vec![
+                    XmlEvent::start_element("navPoint")
+                        .attr("id", &chapter.identifier)
+                        .attr("playOrder", &format!("{play_order}"))
+                        .into(),
+                    XmlEvent::start_element("navLabel").into(),
+                    XmlEvent::start_element("text").into(),
+                    XmlEvent::characters(&chapter.title),
+                    XmlEvent::end_element().into(),
+                    XmlEvent::end_element().into(),
+                    XmlEvent::start_element("content")
+                        .attr("src", &format!("text/{}.xhtml", &chapter.identifier))
+                        .into(),
+                    XmlEvent::end_element().into(),
+                    XmlEvent::end_element().into(),
+                ],
+            )?;
+        }
+        if volume.is_some() {
+            write_elements(&mut xml, vec![XmlEvent::end_element().into()])?;
+        }
     }
 
     // Write the end of the document.
@@ -1025,33 +2106,135 @@ fn toc_ncx(book: &Book, file: &mut impl Write) -> eyre::Result<()> {
     Ok(())
 }
 
-pub fn download_image(book: &Book, url: &str, filename: &str) -> eyre::Result<Vec<u8>> {
-    // If the image is in the cache, directly use it.
-    if let Some(image) = Cache::read_inline_image(book, filename)? {
-        return Ok(image.into());
+pub fn is_royal_road(url: &str) -> bool {
+    url.starts_with("https://www.royalroad.com/")
+}
+
+async fn download_image(
+    book: &Book,
+    url: &str,
+    referer: &str,
+    filename: &str,
+) -> eyre::Result<Vec<u8>> {
+    // Data URIs embed the image itself, there is nothing to download nor to cache.
+    if image::is_data_uri(url) {
+        return image::resize(image::decode_data_uri(url)?);
     }
 
-    let image = send_get_request(url)?;
+    // If the image is in the cache, directly use it (unless it changed at the source).
+    let cached = Cache::read_inline_image(book, filename)?;
+    if let Some(image) = &cached {
+        // A source that cannot be reached keeps the cached image as well.
+        if !Config::get().refresh_images
+            || !image_changed(book, url, referer, filename)
+                .await
+                .unwrap_or(false)
+        {
+            return Ok(image.to_vec());
+        }
+    }
 
-    if !image.status().is_success() {
-        // Ignore failed images.
-        bail!(
-            "Failed to download image from URL. This is likely NOT a bug with rr-to-epub. URL: {}",
-            url
-        );
+    // An image which changed but cannot be downloaded again is not lost either.
+    match fetch_image(book, url, referer, filename).await {
+        Err(e) => cached.map(Into::into).ok_or(e),
+        buffer => buffer,
     }
+}
 
-    let buffer = image::resize(image.bytes()?).map_err(|err| eyre!("{err} URL: {url}"))?;
+/// Download the image at `url` and save it in the cache.
+async fn fetch_image(
+    book: &Book,
+    url: &str,
+    referer: &str,
+    filename: &str,
+) -> eyre::Result<Vec<u8>> {
+    // Failed images are left out.
+    let image = request::get_from(url, referer)
+        .await?
+        .error_for_status(url)?;
+
+    let source = ImageSource::from_response(&image);
+    let buffer = image::resize(image.body).map_err(|err| eyre!("{err} URL: {url}"))?;
 
     // Save the image in the cache.
     Cache::write_inline_image(book, filename, &buffer)?;
+    Cache::write_image_source(book, filename, &source)?;
 
     Ok(buffer)
 }
 
+/// Compare the validators of the cached image with the ones currently sent by the source.
+async fn image_changed(
+    book: &Book,
+    url: &str,
+    referer: &str,
+    filename: &str,
+) -> eyre::Result<bool> {
+    let Some(cached) = Cache::read_image_source(book, filename)? else {
+        return Ok(true);
+    };
+    let response = request::head_from(url, referer).await?;
+    if !response.status.is_success() {
+        // Keep the cached image rather than losing it.
+        return Ok(false);
+    }
+    Ok(ImageSource::from_response(&response).differs_from(&cached))
+}
+
 #[cfg(test)]
 mod test {
-    use crate::updater::native::epub::clean_html;
+    use crate::config::ParallelText;
+    use crate::updater::native::epub::{
+        add_original_text, apply_reading_order, clean_html, compile_time_selector,
+        default_filename, is_volume, numbering_warnings, replace_widgets, royal_road_status,
+        table_chapters, volume_groups, without_matches, write, Book, Chapter, RoyalRoadChapter,
+        WARNINGS_PAGE_ID,
+    };
+    use crate::updater::native::test_util;
+    use crate::updater::{set_cover, set_provenance, Rating, Warning, BACKEND_META, VERSION_META};
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use epub::doc::EpubDoc;
+    use scraper::Html;
+    use std::path::Path;
+
+    #[test]
+    fn watermarks_are_removed() {
+        let page = Html::parse_document(
+            "<div class=\"chapter-content\"><p>Text</p><p class=\"cnXyz\">Stolen</p></div>",
+        );
+        let content = page
+            .select(&compile_time_selector(".chapter-content"))
+            .next()
+            .map(|e| without_matches(e, &compile_time_selector(".cnXyz")));
+        assert_eq!(content.as_deref(), Some("<p>Text</p>"));
+    }
+
+    #[test]
+    fn configured_selectors_replace_the_built_in_ones() -> eyre::Result<()> {
+        let selectors: crate::profile::RoyalRoadSelectors = toml_edit::de::from_str(
+            r#"
+            content = ".chapter-text"
+            watermark = "p["
+            "#,
+        )?;
+        let page = Html::parse_document(
+            "<div class=\"chapter-text\"><p>Text</p></div>\
+            <div class=\"chapter-inner chapter-content\"><p>Old layout</p></div>",
+        );
+        let selector = |configured: Option<&String>, default: &str| {
+            super::configured_selector(configured).unwrap_or_else(|| compile_time_selector(default))
+        };
+
+        let content = selector(selectors.content.as_ref(), ".chapter-inner.chapter-content");
+        let content = page.select(&content).next().map(|e| e.inner_html());
+        assert_eq!(content.as_deref(), Some("<p>Text</p>"));
+        // The built-in selectors are used for those left unset, invalid ones are ignored.
+        let title = selector(selectors.title.as_ref(), "h1");
+        assert_eq!(title, compile_time_selector("h1"));
+        assert!(super::configured_selector(selectors.watermark.as_ref()).is_none());
+        Ok(())
+    }
 
     #[test]
     fn clean_font_familly_1() {
@@ -1079,6 +2262,70 @@ mod test {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn size_estimate_covers_the_previous_book() -> eyre::Result<()> {
+        // Prepare
+        let book = super::Book {
+            chapters: vec![super::Chapter {
+                content: Some("a".repeat(100)),
+                authors_note_end: Some("b".repeat(10)),
+                ..super::Chapter::default()
+            }],
+            ..super::Book::default()
+        };
+        let dir = tempfile::tempdir()?;
+        let outfile = dir.path().join("book.epub");
+
+        // Act
+        let new_book = super::estimate_size(&book, &outfile);
+        std::fs::write(&outfile, vec![0; 1000])?;
+        let rewritten_book = super::estimate_size(&book, &outfile);
+
+        // Assert
+        assert_eq!(new_book, 110);
+        assert_eq!(rewritten_book, 1000);
+        Ok(())
+    }
+
+    #[test]
+    fn image_chapters_are_exported_as_pages() -> eyre::Result<()> {
+        // Prepare
+        let chapter = |url: &str, content: &str| super::Chapter {
+            url: url.to_string(),
+            content: Some(content.to_string()),
+            ..super::Chapter::default()
+        };
+        let book = super::Book {
+            chapters: vec![
+                chapter("https://site.com/1", "<p>Text <img src=\"a.png\"></p>"),
+                chapter(
+                    "https://site.com/2",
+                    "<p><img src=\"b.png\"><img src=\"c.png\"></p>",
+                ),
+            ],
+            ..super::Book::default()
+        };
+        let images: Vec<_> = ["a.png", "b.png", "c.png"]
+            .into_iter()
+            .map(|name| super::DownloadedImage {
+                url: format!("https://site.com/{name}"),
+                filename: name.to_string(),
+                buffer: name.as_bytes().to_vec(),
+            })
+            .collect();
+        let dir = tempfile::tempdir()?;
+        let outfile = dir.path().join("book.epub");
+
+        // Act
+        super::write_cbz(&book, &images, &outfile.to_string_lossy())?;
+
+        // Assert
+        let cbz = zip::ZipArchive::new(std::fs::File::open(outfile.with_extension("cbz"))?)?;
+        let pages: Vec<_> = cbz.file_names().collect();
+        assert_eq!(pages, ["0000_000_b.png", "0000_001_c.png"]);
+        Ok(())
+    }
+
     #[test]
     fn clean_nbsp() {
         // Prepare
@@ -1117,4 +2364,618 @@ mod test {
         let expected = String::from("<img src=\"https://site.com/img.gif\" alt=\"image\"/>");
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn chapters_from_table() {
+        // Prepare
+        let page = Html::parse_document(
+            "<table id=\"chapters\"><tbody>\
+            <tr class=\"chapter-row\" data-url=\"/fiction/1/a-book/chapter/11/prologue\">\
+            <td><a href=\"/fiction/1/a-book/chapter/11/prologue\"> Prologue </a></td>\
+            <td><a><time unixtime=\"1704067200\">a year ago</time></a></td></tr>\
+            </tbody></table>",
+        );
+
+        // Act
+        let chapters = table_chapters(&page);
+
+        // Assert
+        assert_eq!(chapters.len(), 1);
+        assert_eq!(chapters[0].identifier, "11");
+        assert_eq!(chapters[0].title, "Prologue");
+        assert_eq!(
+            chapters[0].url,
+            "https://www.royalroad.com/fiction/1/a-book/chapter/11/prologue"
+        );
+        assert_eq!(chapters[0].date_published.timestamp(), 1_704_067_200);
+    }
+
+    #[test]
+    fn chapters_grouped_by_volume() {
+        // Prepare
+        let chapter = |identifier: &str, volume: Option<&str>| Chapter {
+            identifier: identifier.to_string(),
+            volume: volume.map(String::from),
+            ..Chapter::default()
+        };
+        let chapters = [
+            chapter("1", None),
+            chapter("2", Some("Book 1")),
+            chapter("3", Some("Book 1")),
+            chapter("4", Some("Book 2")),
+        ];
+
+        // Act
+        let groups = volume_groups(&chapters);
+
+        // Assert
+        let groups: Vec<(Option<&str>, usize)> =
+            groups.iter().map(|(v, c)| (*v, c.len())).collect();
+        assert_eq!(
+            groups,
+            [(None, 1), (Some("Book 1"), 2), (Some("Book 2"), 1)]
+        );
+    }
+
+    #[test]
+    fn status_labels() {
+        assert_eq!(
+            royal_road_status(" COMPLETED ").as_deref(),
+            Some("Completed")
+        );
+        assert_eq!(royal_road_status("ORIGINAL"), None);
+    }
+
+    #[test]
+    fn numbering_gaps_and_regressions() {
+        // Prepare
+        let chapter = |order: u32| RoyalRoadChapter {
+            order,
+            title: format!("Chapter {order}"),
+            ..RoyalRoadChapter::default()
+        };
+
+        // Act
+        let warnings = numbering_warnings(&[chapter(0), chapter(1), chapter(4), chapter(3)]);
+
+        // Assert
+        assert_eq!(
+            warnings,
+            [
+                Warning::ChapterGap {
+                    after: String::from("Chapter 1"),
+                    nb_missing: 2
+                },
+                Warning::ChapterRegression {
+                    title: String::from("Chapter 3")
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn reading_order_moves_listed_chapters_only() {
+        let chapter = |identifier: &str, title: &str| Chapter {
+            identifier: identifier.to_string(),
+            title: title.to_string(),
+            ..Chapter::default()
+        };
+        let mut chapters = vec![
+            chapter("1", "Chapter 1"),
+            chapter("2", "Interlude"),
+            chapter("3", "Chapter 2"),
+            chapter("4", "Chapter 3"),
+        ];
+        apply_reading_order(&mut chapters, &["Chapter 2".to_string(), "2".to_string()]);
+        let titles: Vec<&str> = chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, ["Chapter 1", "Chapter 2", "Interlude", "Chapter 3"]);
+    }
+
+    #[test]
+    fn spine_follows_the_reading_order_file() -> eyre::Result<()> {
+        let chapter = |identifier: &str| super::Chapter {
+            identifier: identifier.to_string(),
+            title: format!("Chapter {identifier}"),
+            content: Some(String::from("<p>Text</p>")),
+            ..super::Chapter::default()
+        };
+        let book = super::Book {
+            title: String::from("Book"),
+            chapters: vec![chapter("1"), chapter("2"), chapter("3")],
+            ..super::Book::default()
+        };
+        let dir = tempfile::tempdir()?;
+        let outfile = dir.path().join("book.epub");
+        std::fs::write(
+            dir.path().join("book.order"),
+            "# Side story first\n3\n\nChapter 2\n",
+        )?;
+
+        super::write(&book, Some(outfile.to_string_lossy().to_string()))?;
+
+        let spine = EpubDoc::new(&outfile)?.spine;
+        let chapters: Vec<&str> = spine
+            .iter()
+            .map(String::as_str)
+            .filter(|id| ["1", "2", "3"].contains(id))
+            .collect();
+        assert_eq!(chapters, ["1", "3", "2"]);
+        Ok(())
+    }
+
+    #[test]
+    fn validator_is_read_back() -> eyre::Result<()> {
+        let book = super::Book {
+            url: String::from("https://www.royalroad.com/fiction/12345/book"),
+            title: String::from("Book"),
+            last_modified: Some(String::from("\"etag-1\"")),
+            chapters: vec![super::Chapter {
+                identifier: String::from("1"),
+                title: String::from("Chapter 1"),
+                content: Some(String::from("<p>Text</p>")),
+                ..super::Chapter::default()
+            }],
+            ..super::Book::default()
+        };
+        let dir = tempfile::tempdir()?;
+        let outfile = dir.path().join("book.epub");
+
+        super::write(&book, Some(outfile.to_string_lossy().to_string()))?;
+
+        let read_back = super::Book::from_path(&book.url, &outfile)?;
+        assert_eq!(read_back.last_modified, book.last_modified);
+        Ok(())
+    }
+
+    #[test]
+    fn written_notes_link_to_their_polls() -> eyre::Result<()> {
+        let chapter_url = "https://www.royalroad.com/fiction/1/book/chapter/2/chapter-1";
+        let book = super::Book {
+            title: String::from("Book"),
+            chapters: vec![super::Chapter {
+                identifier: String::from("1"),
+                title: String::from("Chapter 1"),
+                url: chapter_url.to_string(),
+                content: Some(String::from("<p>Text</p>")),
+                authors_note_start: Some(String::from(
+                    "<p>Vote!</p><div class=\"poll-container\"><form><input type=\"radio\">A</form></div>",
+                )),
+                ..super::Chapter::default()
+            }],
+            ..super::Book::default()
+        };
+        let dir = tempfile::tempdir()?;
+        let outfile = dir.path().join("book.epub");
+
+        super::write(&book, Some(outfile.to_string_lossy().to_string()))?;
+
+        let (xhtml, _mime) = EpubDoc::new(&outfile)?
+            .get_resource_str("1")
+            .ok_or_else(|| eyre::eyre!("The chapter is missing"))?;
+        assert!(xhtml.contains("Vote!"));
+        assert!(xhtml.contains(&format!(
+            "<a href=\"{chapter_url}\">Poll available on Royal Road</a>"
+        )));
+        assert!(!xhtml.contains("<form"));
+        Ok(())
+    }
+
+    #[test]
+    fn tags_and_status_are_subjects() -> eyre::Result<()> {
+        let book = super::Book {
+            url: String::from("https://www.royalroad.com/fiction/12345/book"),
+            title: String::from("Book"),
+            tags: vec![String::from("Fantasy"), String::from("LitRPG")],
+            status: Some(String::from("Completed")),
+            content_warnings: vec![String::from("Gore")],
+            chapters: vec![super::Chapter {
+                identifier: String::from("1"),
+                title: String::from("Chapter 1"),
+                content: Some(String::from("<p>Text</p>")),
+                ..super::Chapter::default()
+            }],
+            ..super::Book::default()
+        };
+        let dir = tempfile::tempdir()?;
+        let outfile = dir.path().join("book.epub");
+
+        super::write(&book, Some(outfile.to_string_lossy().to_string()))?;
+
+        let subjects = EpubDoc::new(&outfile)?
+            .metadata
+            .get("subject")
+            .cloned()
+            .unwrap_or_default();
+        assert_eq!(subjects, ["Fantasy", "LitRPG", "Gore", "Completed"]);
+        let read_back = super::Book::from_path(&book.url, &outfile)?;
+        assert_eq!(read_back.tags, book.tags);
+        assert_eq!(read_back.status, book.status);
+        assert_eq!(read_back.content_warnings, book.content_warnings);
+        Ok(())
+    }
+
+    #[test]
+    fn table_of_contents_nests_the_volumes() -> eyre::Result<()> {
+        let chapter = |identifier: &str, volume: Option<&str>| super::Chapter {
+            identifier: identifier.to_string(),
+            title: format!("Chapter {identifier}"),
+            volume: volume.map(String::from),
+            content: Some(String::from("<p>Text</p>")),
+            ..super::Chapter::default()
+        };
+        let book = super::Book {
+            url: String::from("https://www.royalroad.com/fiction/12345/book"),
+            title: String::from("Book"),
+            chapters: vec![
+                chapter("1", Some("Book One")),
+                chapter("2", Some("Book One")),
+                chapter("3", None),
+            ],
+            ..super::Book::default()
+        };
+        let dir = tempfile::tempdir()?;
+        let outfile = dir.path().join("book.epub");
+
+        super::write(&book, Some(outfile.to_string_lossy().to_string()))?;
+
+        let (nav, _mime) = EpubDoc::new(&outfile)?
+            .get_resource_str("nav")
+            .ok_or_else(|| eyre::eyre!("The table of contents is missing"))?;
+        let nav = Html::parse_document(&nav);
+        let labels = |selector: &str| -> eyre::Result<Vec<String>> {
+            let selector = scraper::Selector::parse(selector)
+                .map_err(|e| eyre::eyre!("Invalid selector: {e}"))?;
+            Ok(nav
+                .select(&selector)
+                .map(|a| a.text().collect())
+                .filter(|l: &String| l.starts_with("Book ") || l.starts_with("Chapter "))
+                .collect())
+        };
+        assert_eq!(labels("nav > ol > li > a")?, ["Book One", "Chapter 3"]);
+        assert_eq!(
+            labels("nav > ol > li > ol > li > a")?,
+            ["Chapter 1", "Chapter 2"]
+        );
+        let read_back = super::Book::from_path(&book.url, &outfile)?;
+        let volumes: Vec<_> = read_back
+            .chapters
+            .iter()
+            .map(|c| c.volume.clone())
+            .collect();
+        assert_eq!(
+            volumes,
+            [
+                Some(String::from("Book One")),
+                Some(String::from("Book One")),
+                None
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn original_text_is_interleaved_or_appended() {
+        let chapter = |identifier: &str| Chapter {
+            identifier: identifier.to_string(),
+            ..Chapter::default()
+        };
+        let identifiers = |mode| {
+            let mut chapters = vec![chapter("1"), chapter("2")];
+            let originals = ["original-1", "original-2", "original-3"].map(chapter);
+            add_original_text(&mut chapters, originals.to_vec(), mode);
+            chapters
+                .into_iter()
+                .map(|c| c.identifier)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            identifiers(ParallelText::Interleave),
+            ["1", "original-1", "2", "original-2", "original-3"]
+        );
+        assert_eq!(
+            identifiers(ParallelText::Append),
+            ["1", "2", "original-1", "original-2", "original-3"]
+        );
+    }
+
+    #[test]
+    fn original_text_is_paired_by_position() {
+        let date = |day| {
+            chrono::DateTime::parse_from_rfc3339(&format!("2024-01-{day:02}T00:00:00Z"))
+                .map(Into::into)
+                .unwrap_or_default()
+        };
+        let chapter = |identifier: &str, day| Chapter {
+            identifier: identifier.to_string(),
+            date_published: date(day),
+            volume: Some(String::from("Volume 1")),
+            ..Chapter::default()
+        };
+
+        // The chapters missing from the original text are left alone.
+        let mut chapters = vec![chapter("1", 1), chapter("2", 2), chapter("3", 3)];
+        add_original_text(
+            &mut chapters,
+            vec![Chapter {
+                identifier: String::from("original-1"),
+                ..Chapter::default()
+            }],
+            ParallelText::Interleave,
+        );
+        let identifiers: Vec<_> = chapters.iter().map(|c| c.identifier.as_str()).collect();
+        assert_eq!(identifiers, ["1", "original-1", "2", "3"]);
+        // An original chapter takes the date and the volume of its translation.
+        assert_eq!(chapters[1].date_published, date(1));
+        assert_eq!(chapters[1].volume.as_deref(), Some("Volume 1"));
+
+        // The original chapters without a translation are dated like the latest chapter.
+        let mut chapters = vec![chapter("1", 1), chapter("2", 2)];
+        let originals = ["original-1", "original-2", "original-3"]
+            .map(|identifier| chapter(identifier, 9))
+            .to_vec();
+        add_original_text(&mut chapters, originals, ParallelText::Interleave);
+        assert_eq!(chapters[4].identifier, "original-3");
+        assert_eq!(chapters[4].date_published, date(2));
+
+        // Without any original chapter, nothing changes.
+        let mut chapters = vec![chapter("1", 1), chapter("2", 2)];
+        add_original_text(&mut chapters, Vec::new(), ParallelText::Append);
+        assert_eq!(chapters, [chapter("1", 1), chapter("2", 2)]);
+    }
+
+    #[test]
+    fn author_note_widgets_are_replaced() {
+        let note =
+            "<p>Vote!</p><div class=\"poll-container\"><form><input type=\"radio\">A</form></div>\
+            <div class=\"spoiler\"><button>Show</button><p>Hidden</p></div>\
+            <iframe src=\"https://example.com/widget\"></iframe>";
+        let actual = replace_widgets(note, "https://www.royalroad.com/fiction/1/chapter/2");
+        assert_eq!(
+            actual,
+            "<p>Vote!</p>\
+            <p><a href=\"https://www.royalroad.com/fiction/1/chapter/2\">Poll available on Royal Road</a></p>\
+            <div class=\"spoiler\"><p>Hidden</p></div>\
+            <p><a href=\"https://www.royalroad.com/fiction/1/chapter/2\">Embedded content available on Royal Road</a></p>"
+        );
+    }
+
+    #[test]
+    fn fetched_chapters_keep_their_notes() {
+        let chapter = Chapter {
+            url: String::from("https://www.royalroad.com/fiction/1/a/chapter/2/b"),
+            authors_note_start: Some(String::from("<p>Before</p>")),
+            content: Some(String::from("<p>Text&nbsp;</p>")),
+            authors_note_end: Some(String::from("<p>After</p>")),
+            ..Chapter::default()
+        };
+        let html = chapter.cleaned_html();
+        let positions: Vec<_> = ["Before", "Text", "After"]
+            .map(|text| html.find(text))
+            .to_vec();
+        assert!(positions.iter().all(Option::is_some) && positions.is_sorted());
+
+        let without_notes = Chapter {
+            authors_note_start: None,
+            authors_note_end: None,
+            ..chapter
+        };
+        assert_eq!(
+            without_notes.cleaned_html(),
+            clean_html("<p>Text&nbsp;</p>")
+        );
+    }
+
+    #[test]
+    fn adopted_books_get_their_source() -> eyre::Result<()> {
+        // Prepare
+        let dir = tempfile::tempdir()?;
+        let outfile = write_book(dir.path())?;
+        let url = "https://example.com/story?id=1&chapter=2";
+
+        // Act
+        crate::updater::set_source_metadata(Path::new(&outfile), url)?;
+
+        // Assert
+        let mut doc = EpubDoc::new(&outfile)?;
+        assert_eq!(doc.mdata("source").as_deref(), Some(url));
+        let opf = doc
+            .get_resource_str_by_path(doc.root_file.clone())
+            .unwrap_or_default();
+        assert_eq!(opf.matches("<dc:source").count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn covers_are_replaced_in_their_format() -> eyre::Result<()> {
+        let png = |width| -> eyre::Result<Vec<u8>> {
+            let mut png = Vec::new();
+            image::DynamicImage::new_rgb8(width, 2)
+                .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)?;
+            Ok(png)
+        };
+        let mut book = test_util::book_from_fixture("sample_book")?;
+        book.cover_url = format!("data:image/png;base64,{}", STANDARD.encode(png(4)?));
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(default_filename(&book));
+        let outfile = write(&book, Some(path.to_string_lossy().to_string()))?;
+
+        set_cover(Path::new(&outfile), &png(8)?)?;
+
+        let (cover, mime) = EpubDoc::new(&outfile)?
+            .get_cover()
+            .ok_or_else(|| eyre::eyre!("No cover"))?;
+        let format = image::ImageFormat::from_mime_type(&mime);
+        assert_eq!(image::guess_format(&cover).ok(), format);
+        assert_eq!(image::load_from_memory(&cover)?.width(), 8);
+        Ok(())
+    }
+
+    #[test]
+    fn provenance_is_replaced() -> eyre::Result<()> {
+        // Prepare
+        let dir = tempfile::tempdir()?;
+        let outfile = write_book(dir.path())?;
+
+        // Act
+        set_provenance(Path::new(&outfile), "fanficfare")?;
+
+        // Assert
+        let mut doc = EpubDoc::new(&outfile)?;
+        assert_eq!(doc.mdata(BACKEND_META).as_deref(), Some("fanficfare"));
+        assert_eq!(
+            doc.mdata(VERSION_META).as_deref(),
+            Some(env!("CARGO_PKG_VERSION"))
+        );
+        let opf = doc
+            .get_resource_str_by_path(doc.root_file.clone())
+            .unwrap_or_default();
+        assert_eq!(opf.matches(BACKEND_META).count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn royal_road_ratings_follow_the_content_warnings() {
+        let rated = |url: &str, warnings: &[&str], label: Option<&str>| {
+            super::Book {
+                url: url.to_owned(),
+                content_warnings: warnings.iter().map(ToString::to_string).collect(),
+                rating_label: label.map(ToOwned::to_owned),
+                ..super::Book::default()
+            }
+            .rating()
+        };
+        let royal_road = "https://www.royalroad.com/fiction/1";
+        assert_eq!(rated(royal_road, &[], None), Some(Rating::General));
+        assert_eq!(rated(royal_road, &["Profanity"], None), Some(Rating::Teen));
+        assert_eq!(
+            rated(royal_road, &["Profanity", "Sexual Content"], None),
+            Some(Rating::Mature)
+        );
+        assert_eq!(rated("https://example.com/story/1", &[], None), None);
+        assert_eq!(
+            rated("https://example.com/story/1", &[], Some("T")),
+            Some(Rating::Teen)
+        );
+    }
+
+    #[test]
+    fn volumes_are_recognised_by_their_name() {
+        assert!(is_volume(Path::new("dir/Title (1 of 2).epub")));
+        assert!(!is_volume(Path::new("dir/Title (1 of 2).cbz")));
+        assert!(!is_volume(Path::new("dir/Title (Book 1).epub")));
+    }
+
+    #[test]
+    fn large_books_are_split_into_volumes() -> eyre::Result<()> {
+        // Numbers which do not compress well, so that each chapter weighs on the size.
+        let chapter = |n: u64| Chapter {
+            identifier: n.to_string(),
+            title: format!("Chapter {n}"),
+            content: Some(format!(
+                "<p>{}</p>",
+                (0..5000)
+                    .map(|i| (i * 7919 + n * 104_729) % 1_000_003)
+                    .map(|i| i.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            )),
+            ..Chapter::default()
+        };
+        let book = super::Book {
+            title: String::from("Book"),
+            chapters: (1..=4).map(chapter).collect(),
+            ..super::Book::default()
+        };
+        let dir = tempfile::tempdir()?;
+        let outfile = dir.path().join("Book.epub").to_string_lossy().to_string();
+        write(&book, Some(outfile.clone()))?;
+        let size = std::fs::metadata(&outfile)?.len();
+
+        super::write_volumes(&book, &outfile, size * 3 / 4)?;
+
+        let mut volumes: Vec<_> = std::fs::read_dir(dir.path())?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<_, _>>()?;
+        volumes.retain(|path| is_volume(path));
+        volumes.sort();
+        assert!(volumes.len() >= 2);
+        let mut chapters = Vec::new();
+        for volume in &volumes {
+            assert!(std::fs::metadata(volume)?.len() <= size * 3 / 4);
+            let spine = EpubDoc::new(volume)?.spine;
+            chapters.extend(spine.into_iter().filter(|id| id.parse::<u32>().is_ok()));
+        }
+        assert_eq!(chapters, ["1", "2", "3", "4"]);
+
+        // The volumes are removed once the book fits again.
+        super::write_volumes(&book, &outfile, u64::MAX)?;
+        assert!(volumes.iter().all(|volume| !volume.exists()));
+        Ok(())
+    }
+
+    #[test]
+    fn warnings_are_read_back_from_their_metadata() -> eyre::Result<()> {
+        // A warning which is also a genre tag is kept among the warnings.
+        let book = super::Book {
+            url: String::from("https://www.royalroad.com/fiction/12345/book"),
+            title: String::from("Book"),
+            tags: vec![String::from("Violence")],
+            content_warnings: vec![String::from("Violence"), String::from("Gore")],
+            chapters: vec![Chapter {
+                identifier: String::from("1"),
+                title: String::from("Chapter 1"),
+                content: Some(String::from("<p>Text</p>")),
+                ..Chapter::default()
+            }],
+            ..super::Book::default()
+        };
+        let dir = tempfile::tempdir()?;
+        let outfile = dir.path().join("book.epub");
+
+        write(&book, Some(outfile.to_string_lossy().to_string()))?;
+
+        let epub_doc = EpubDoc::new(&outfile)?;
+        assert_eq!(
+            epub_doc.mdata(super::WARNINGS_META).as_deref(),
+            Some("Violence,Gore")
+        );
+        // The page is only added when asked for.
+        assert!(!epub_doc.spine.iter().any(|id| id == "warnings"));
+        let read_back = super::Book::from_path(&book.url, &outfile)?;
+        assert_eq!(read_back.content_warnings, book.content_warnings);
+        assert_eq!(read_back.tags, book.tags);
+        Ok(())
+    }
+
+    #[test]
+    fn warnings_page_is_not_read_back_as_a_chapter() -> eyre::Result<()> {
+        let mut book = test_util::book_from_fixture("sample_book")?;
+        book.content_warnings = vec![String::from("Gore")];
+        let dir = tempfile::tempdir()?;
+        std::fs::write(dir.path().join(".autebook.toml"), "warnings_page = true")?;
+        let path = dir.path().join(default_filename(&book));
+        let outfile = write(&book, Some(path.to_string_lossy().to_string()))?;
+        let spine = EpubDoc::new(&outfile)?.spine;
+        assert!(spine.iter().any(|id| id == WARNINGS_PAGE_ID));
+
+        let read_back = Book::from_path(&book.url, Path::new(&outfile))?;
+        assert_eq!(read_back.chapters.len(), book.chapters.len());
+        write(&read_back, Some(outfile))?;
+        Ok(())
+    }
+
+    /// Write a book of a single chapter to `dir`, returns the path of the e-book.
+    fn write_book(dir: &Path) -> eyre::Result<String> {
+        let book = super::Book {
+            title: "Book".to_string(),
+            chapters: vec![super::Chapter {
+                title: "Chapter 1".to_string(),
+                content: Some("<p>Text</p>".to_string()),
+                ..super::Chapter::default()
+            }],
+            ..super::Book::default()
+        };
+        let path = dir.join(super::default_filename(&book));
+        super::write(&book, Some(path.to_string_lossy().to_string()))
+    }
 }
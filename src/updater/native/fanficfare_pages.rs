@@ -0,0 +1,196 @@
+//! Pages `FanFicFare` adds to its e-books, whose dates stand in for the chapter dates it does not
+//! record: its update log tells when each chapter was first downloaded, its title page when
+//! the story was published and last updated.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use lazy_regex::regex;
+use lazy_static::lazy_static;
+use scraper::{Html, Selector};
+
+use crate::updater::native::epub::compile_time_selector;
+
+/// Spine items of the pages, which are not chapters.
+pub const PAGE_IDS: [&str; 3] = ["title_page", "toc_page", "log_page"];
+pub const LOG_PAGE_ID: &str = "log_page";
+pub const TITLE_PAGE_ID: &str = "title_page";
+
+lazy_static! {
+    static ref LOG_ENTRY_SELECTOR: Selector = compile_time_selector(".log_entry");
+    static ref LOG_DATE_SELECTOR: Selector = compile_time_selector("[id=dateUpdated]");
+    static ref LOG_CHAPTERS_SELECTOR: Selector = compile_time_selector("[id=numChapters]");
+}
+
+/// Dates as written by `FanFicFare`, with or without the time.
+fn parse_date(text: &str) -> Option<DateTime<Utc>> {
+    let text = text.trim();
+    NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(0, 0, 0)
+        })
+        .map(|date| date.and_utc())
+}
+
+/// Date and number of chapters of each update of the log page, in its order (oldest first).
+pub fn log_updates(log_page: &Html) -> Vec<(DateTime<Utc>, usize)> {
+    let text = |entry: &scraper::ElementRef, selector: &Selector| {
+        entry
+            .select(selector)
+            .next()
+            .map(|e| e.text().collect::<String>())
+    };
+    log_page
+        .select(&LOG_ENTRY_SELECTOR)
+        .filter_map(|entry| {
+            let date = parse_date(&text(&entry, &LOG_DATE_SELECTOR)?)?;
+            let nb_chapters = text(&entry, &LOG_CHAPTERS_SELECTOR)?.trim().parse().ok()?;
+            Some((date, nb_chapters))
+        })
+        .collect()
+}
+
+/// Publication and last update dates of the title page.
+pub fn title_page_dates(title_page: &Html) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+    let text = title_page.root_element().text().collect::<String>();
+    let date_after = |label: &str| {
+        regex!(r"(Published|Updated):\s*(\d{4}-\d{2}-\d{2})")
+            .captures_iter(&text)
+            .find(|c| &c[1] == label)
+            .and_then(|c| parse_date(&c[2]))
+    };
+    (date_after("Published"), date_after("Updated"))
+}
+
+/// Date of each of the `nb_chapters` chapters: the date of the first update which included it.
+pub fn chapter_dates(
+    updates: &[(DateTime<Utc>, usize)],
+    nb_chapters: usize,
+) -> Vec<Option<DateTime<Utc>>> {
+    (0..nb_chapters)
+        .map(|index| {
+            updates
+                .iter()
+                .find(|(_, nb_included)| index < *nb_included)
+                .map(|(date, _)| *date)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chapter_dates() {
+        let log_page = Html::parse_document(
+            "<h3>Update Log</h3>\
+            <p class='log_entry'><b>Updated:</b> <span id=\"dateUpdated\">2024-01-01</span>\
+            <b>Chapters:</b> <span id=\"numChapters\">2</span></p>\
+            <p class='log_entry'><b>Updated:</b> <span id=\"dateUpdated\">2024-02-01 10:00:00</span>\
+            <b>Chapters:</b> <span id=\"numChapters\">3</span></p>",
+        );
+        let updates = log_updates(&log_page);
+        assert_eq!(updates.len(), 2);
+        let dates = chapter_dates(&updates, 4);
+        assert_eq!(dates[1], parse_date("2024-01-01"));
+        assert_eq!(dates[2], parse_date("2024-02-01 10:00:00"));
+        assert_eq!(dates[3], None);
+
+        let title_page = Html::parse_document(
+            "<b>Published:</b> 2023-12-01<br /><b>Updated:</b> 2024-02-01<br />",
+        );
+        assert_eq!(
+            title_page_dates(&title_page),
+            (parse_date("2023-12-01"), parse_date("2024-02-01"))
+        );
+    }
+
+    /// `FanFicFare` e-book made of its cover, title and log pages and of `nb_chapters` undated
+    /// chapters.
+    fn write_epub(path: &std::path::Path, nb_chapters: usize) -> eyre::Result<()> {
+        use std::fmt::Write as _;
+        use std::io::Write as _;
+        use zip::write::SimpleFileOptions;
+
+        let mut pages = vec![
+            (String::from("cover"), String::new()),
+            (
+                TITLE_PAGE_ID.to_string(),
+                String::from("<b>Published:</b> 2023-12-01<br /><b>Updated:</b> 2024-02-01<br />"),
+            ),
+            (
+                LOG_PAGE_ID.to_string(),
+                String::from(
+                    "<p class='log_entry'><span id=\"dateUpdated\">2024-01-01</span>\
+                    <span id=\"numChapters\">2</span></p>",
+                ),
+            ),
+        ];
+        pages
+            .extend((1..=nb_chapters).map(|n| (format!("file{n:04}"), format!("<p>Text {n}</p>"))));
+        let (mut items, mut spine) = (String::new(), String::new());
+        for (id, _) in &pages {
+            let _ = write!(
+                items,
+                r#"<item id="{id}" href="{id}.xhtml" media-type="application/xhtml+xml"/>"#
+            );
+            let _ = write!(spine, r#"<itemref idref="{id}"/>"#);
+        }
+
+        let mut epub = zip::ZipWriter::new(std::fs::File::create(path)?);
+        let options = SimpleFileOptions::default();
+        let mut add = |name: &str, contents: &str| -> eyre::Result<()> {
+            epub.start_file(name, options)?;
+            epub.write_all(contents.as_bytes())?;
+            Ok(())
+        };
+        add("mimetype", "application/epub+zip")?;
+        add(
+            "META-INF/container.xml",
+            r#"<?xml version="1.0"?><container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container"><rootfiles><rootfile full-path="content.opf" media-type="application/oebps-package+xml"/></rootfiles></container>"#,
+        )?;
+        add(
+            "content.opf",
+            &format!(
+                r#"<?xml version="1.0"?><package xmlns="http://www.idpf.org/2007/opf" version="3.0"><metadata xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:title>Book</dc:title></metadata><manifest>{items}</manifest><spine>{spine}</spine></package>"#
+            ),
+        )?;
+        for (id, body) in &pages {
+            add(
+                &format!("{id}.xhtml"),
+                &format!("<html><head><title>{id}</title></head><body>{body}</body></html>"),
+            )?;
+        }
+        epub.finish()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_dates_of_read_back_chapters() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("book.epub");
+        write_epub(&path, 3)?;
+
+        let book = crate::updater::native::epub::Book::from_path(
+            "https://www.royalroad.com/fiction/12345/book",
+            &path,
+        )?;
+        let dates: Vec<_> = book
+            .chapters
+            .iter()
+            .map(|c| Some(c.date_published))
+            .collect();
+        // The first two chapters are in the log, the last one was added by the last update.
+        assert_eq!(
+            dates,
+            [
+                parse_date("2024-01-01"),
+                parse_date("2024-01-01"),
+                parse_date("2024-02-01")
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,183 @@
+//! Stories of FanFiction.net and `FictionPress`, which share their layout.
+
+use chrono::{DateTime, Utc};
+use eyre::Result;
+use lazy_regex::regex;
+use lazy_static::lazy_static;
+use scraper::{Html, Selector};
+use url::Url;
+
+use crate::error::Error;
+use crate::updater::native::epub::{compile_time_selector, Book, Chapter};
+use crate::updater::native::request;
+
+lazy_static! {
+    static ref TITLE_SELECTOR: Selector = compile_time_selector("#profile_top b.xcontrast_txt");
+    static ref AUTHOR_SELECTOR: Selector =
+        compile_time_selector("#profile_top a.xcontrast_txt[href^=\"/u/\"]");
+    static ref DESCRIPTION_SELECTOR: Selector =
+        compile_time_selector("#profile_top div.xcontrast_txt");
+    static ref DETAILS_SELECTOR: Selector = compile_time_selector("#profile_top span.xgray");
+    static ref DATE_SELECTOR: Selector = compile_time_selector("span[data-xutime]");
+    static ref COVER_SELECTOR: Selector = compile_time_selector("#profile_top img.cimage");
+    static ref CHAPTER_OPTION_SELECTOR: Selector = compile_time_selector("#chap_select option");
+    static ref CONTENT_SELECTOR: Selector = compile_time_selector("#storytext");
+}
+
+/// Canonical URL of the story (`https://www.<site>/s/<id>/`) from the URL of any of its chapters.
+pub fn story_url(url: &str) -> Option<String> {
+    let captures = regex!(r"^https://(?:www\.|m\.)?(fanfiction\.net|fictionpress\.com)/s/(\d+)")
+        .captures(url)?;
+    Some(format!("https://www.{}/s/{}/", &captures[1], &captures[2]))
+}
+
+/// Story of `url`, with its chapters (without their content).
+pub fn fetch_book(url: &str) -> Result<Book> {
+    let story_url = story_url(url)
+        .ok_or_else(|| Error::Parse(format!("Not a FanFiction.net story : {url}")))?;
+    let first_chapter_url = format!("{story_url}1/");
+    let response = request::block_on(request::get(&first_chapter_url))?
+        .error_for_status(&first_chapter_url)?
+        .text();
+    let page = Html::parse_document(&response);
+
+    let text_of = |selector: &Selector| {
+        page.select(selector)
+            .next()
+            .map(|e| e.text().collect::<String>().trim().to_string())
+    };
+    let title =
+        text_of(&TITLE_SELECTOR).ok_or_else(|| Error::Parse(String::from("No title found")))?;
+    let author = text_of(&AUTHOR_SELECTOR).unwrap_or_default();
+    let description = text_of(&DESCRIPTION_SELECTOR).unwrap_or_default();
+    let details = text_of(&DETAILS_SELECTOR).unwrap_or_default();
+    let cover_url = page
+        .select(&COVER_SELECTOR)
+        .next()
+        .and_then(|img| img.attr("data-original").or_else(|| img.attr("src")))
+        .and_then(|src| Url::parse(&story_url).ok()?.join(src).ok())
+        .map(String::from)
+        .unwrap_or_default();
+    let published = published_date(&page);
+
+    Ok(Book {
+        id: story_id(&story_url)?,
+        chapters: chapters(&page, &story_url, &title, published),
+        url: story_url,
+        title,
+        author,
+        description,
+        date_published: published.to_rfc3339(),
+        cover_url,
+        rating_label: rating_label(&details),
+        ..Book::default()
+    })
+}
+
+fn story_id(story_url: &str) -> Result<u32> {
+    Ok(regex!(r"/s/(\d+)/")
+        .captures(story_url)
+        .and_then(|c| c[1].parse().ok())
+        .ok_or_else(|| Error::Parse(format!("No story id in {story_url}")))?)
+}
+
+/// The dates of the story are the updated and then the published one, or only the latter.
+fn published_date(page: &Html) -> DateTime<Utc> {
+    page.select(&DETAILS_SELECTOR)
+        .flat_map(|details| details.select(&DATE_SELECTOR))
+        .filter_map(|date| date.attr("data-xutime")?.parse().ok())
+        .filter_map(|t| DateTime::from_timestamp(t, 0))
+        .last()
+        .unwrap_or_default()
+}
+
+/// `T` of `Rated: Fiction T - English - …`.
+fn rating_label(details: &str) -> Option<String> {
+    regex!(r"Rated:\s*(?:Fiction\s+)?(K\+|K|T|MA|M)(?:\s|$)")
+        .captures(details)
+        .map(|c| c[1].to_string())
+}
+
+/// Chapters of the story, from its chapter selector. The site does not date each chapter,
+/// so they all get the publication date of the story and are only downloaded once.
+fn chapters(page: &Html, story_url: &str, title: &str, published: DateTime<Utc>) -> Vec<Chapter> {
+    let mut chapters: Vec<Chapter> = Vec::new();
+    for option in page.select(&CHAPTER_OPTION_SELECTOR) {
+        let Some(number) = option.attr("value") else {
+            continue;
+        };
+        // The selector is shown twice, above and below the chapter.
+        if chapters.iter().any(|c| c.identifier == number) {
+            continue;
+        }
+        let label = option.text().collect::<String>();
+        let chapter_title = label
+            .trim()
+            .strip_prefix(&format!("{number}. "))
+            .unwrap_or_else(|| label.trim())
+            .to_string();
+        chapters.push(Chapter {
+            identifier: number.to_string(),
+            date_published: published,
+            title: chapter_title,
+            url: format!("{story_url}{number}/"),
+            ..Chapter::default()
+        });
+    }
+    // Single chapter stories have no selector.
+    if chapters.is_empty() {
+        chapters.push(Chapter {
+            identifier: String::from("1"),
+            date_published: published,
+            title: title.to_string(),
+            url: format!("{story_url}1/"),
+            ..Chapter::default()
+        });
+    }
+    chapters
+}
+
+pub fn chapter_content(page: &Html) -> Option<String> {
+    page.select(&CONTENT_SELECTOR)
+        .next()
+        .map(|e| e.inner_html())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_story_page() {
+        let page = Html::parse_document(
+            "<div id=\"profile_top\"><b class=\"xcontrast_txt\">A Story</b>\
+            <a class=\"xcontrast_txt\" href=\"/u/1/someone\">Someone</a>\
+            <div class=\"xcontrast_txt\">Summary</div>\
+            <span class=\"xgray xcontrast_txt\">Rated: Fiction T - English - Chapters: 2 - \
+            Updated: <span data-xutime=\"1706745600\">Feb 1</span> - \
+            Published: <span data-xutime=\"1704067200\">Jan 1</span></span></div>\
+            <select id=\"chap_select\"><option value=\"1\">1. Prologue</option>\
+            <option value=\"2\">2. The End</option></select>\
+            <select id=\"chap_select\"><option value=\"1\">1. Prologue</option></select>",
+        );
+        let published = published_date(&page);
+        assert_eq!(published.timestamp(), 1_704_067_200);
+        let chapters = chapters(
+            &page,
+            "https://www.fanfiction.net/s/42/",
+            "A Story",
+            published,
+        );
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[1].title, "The End");
+        assert_eq!(chapters[1].url, "https://www.fanfiction.net/s/42/2/");
+        assert_eq!(
+            rating_label("Rated: Fiction T - English").as_deref(),
+            Some("T")
+        );
+        assert_eq!(
+            story_url("https://m.fanfiction.net/s/42/3/a-story").as_deref(),
+            Some("https://www.fanfiction.net/s/42/")
+        );
+    }
+}
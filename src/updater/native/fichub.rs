@@ -0,0 +1,195 @@
+//! Stories of the sites blocking scraping, fetched through the `FicHub` API : its e-book export
+//! of the story is split back into chapters.
+
+use ::epub::doc::EpubDoc;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use eyre::Result;
+use lazy_static::lazy_static;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use scraper::{Html, Selector};
+use serde::Deserialize;
+use std::io::Cursor;
+
+use crate::error::Error;
+use crate::updater::native::epub::{compile_time_selector, Book, Chapter};
+use crate::updater::native::{generic, request};
+
+const SITE_URL: &str = "https://fichub.net";
+
+lazy_static! {
+    static ref HEADING_SELECTOR: Selector = compile_time_selector("h1, h2, h3");
+    static ref BODY_SELECTOR: Selector = compile_time_selector("body");
+}
+
+#[derive(Deserialize)]
+struct Response {
+    #[serde(default)]
+    err: i32,
+    #[serde(default)]
+    msg: String,
+    meta: Option<Meta>,
+    urls: Option<Urls>,
+}
+
+#[derive(Deserialize)]
+struct Meta {
+    title: String,
+    #[serde(default)]
+    author: String,
+    #[serde(default)]
+    description: String,
+    created: Option<String>,
+    updated: Option<String>,
+    chapters: usize,
+    /// Metadata specific to the source, e.g. the archive warnings of AO3.
+    #[serde(default, rename = "rawExtendedMeta")]
+    extended: Option<serde_json::Value>,
+}
+
+impl Meta {
+    fn content_warnings(&self) -> Vec<String> {
+        self.extended
+            .as_ref()
+            .and_then(|e| e.get("warnings"))
+            .and_then(serde_json::Value::as_array)
+            .map(|warnings| {
+                warnings
+                    .iter()
+                    .filter_map(serde_json::Value::as_str)
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Deserialize)]
+struct Urls {
+    epub: String,
+}
+
+/// Dates of `FicHub`, with or without their time zone.
+fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(date)
+        .map(Into::into)
+        .or_else(|_| NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S").map(|d| d.and_utc()))
+        .ok()
+}
+
+/// Story of `url`, with the content of every chapter.
+pub fn fetch_book(url: &str) -> Result<Book> {
+    let api_url = format!(
+        "{SITE_URL}/api/v0/epub?q={}",
+        utf8_percent_encode(url, NON_ALPHANUMERIC)
+    );
+    let response = request::block_on(request::get(&api_url))?
+        .error_for_status(&api_url)?
+        .text();
+    let response: Response = serde_json::from_str(&response)?;
+    let (0, Some(meta), Some(urls)) = (response.err, response.meta, response.urls) else {
+        return Err(
+            Error::Parse(format!("FicHub could not export {url} : {}", response.msg)).into(),
+        );
+    };
+    let epub_url = format!("{SITE_URL}{}", urls.epub);
+    let export = request::block_on(request::get(&epub_url))?
+        .error_for_status(&epub_url)?
+        .body
+        .to_vec();
+
+    let published = meta
+        .created
+        .as_deref()
+        .and_then(parse_date)
+        .unwrap_or_else(Utc::now);
+    let updated = meta
+        .updated
+        .as_deref()
+        .and_then(parse_date)
+        .unwrap_or(published);
+    let mut chapters = export_chapters(export, meta.chapters, url)?;
+    // The API only dates the story : the last chapter gets its update date, so that new
+    // chapters are downloaded once, and so is the previous last one.
+    for chapter in &mut chapters {
+        chapter.date_published = published;
+    }
+    if let Some(last) = chapters.last_mut() {
+        last.date_published = updated;
+    }
+
+    Ok(Book {
+        id: generic::book_id(url),
+        url: url.to_string(),
+        content_warnings: meta.content_warnings(),
+        title: meta.title,
+        author: meta.author,
+        description: meta.description,
+        date_published: published.to_rfc3339(),
+        chapters,
+        ..Book::default()
+    })
+}
+
+/// The last `nb_chapters` documents of the export, the ones before being its front matter.
+fn export_chapters(export: Vec<u8>, nb_chapters: usize, url: &str) -> Result<Vec<Chapter>> {
+    let mut doc = EpubDoc::from_reader(Cursor::new(export))?;
+    let mut documents = Vec::new();
+    loop {
+        if doc.get_current_id().is_some_and(|id| id != "nav") {
+            if let Some((xhtml, _mime)) = doc.get_current_str() {
+                documents.push(xhtml);
+            }
+        }
+        if !doc.go_next() {
+            break;
+        }
+    }
+    let front_matter = documents.len().saturating_sub(nb_chapters);
+    Ok(documents
+        .into_iter()
+        .skip(front_matter)
+        .enumerate()
+        .map(|(index, xhtml)| {
+            let page = Html::parse_document(&xhtml);
+            let number = index + 1;
+            Chapter {
+                identifier: number.to_string(),
+                title: page
+                    .select(&HEADING_SELECTOR)
+                    .next()
+                    .map(|h| h.text().collect::<String>().trim().to_string())
+                    .filter(|title| !title.is_empty())
+                    .unwrap_or_else(|| format!("Chapter {number}")),
+                url: format!("{url}#chapter-{number}"),
+                content: page.select(&BODY_SELECTOR).next().map(|b| b.inner_html()),
+                ..Chapter::default()
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_response() -> Result<()> {
+        let response: Response = serde_json::from_str(
+            r#"{"err":0,"meta":{"title":"A Story","author":"someone","chapters":2,
+            "created":"2024-01-01T00:00:00","updated":"2024-02-01T00:00:00",
+            "rawExtendedMeta":{"warnings":["Graphic Depictions Of Violence"]}},
+            "urls":{"epub":"/cache/epub/a-story.epub?h=1"}}"#,
+        )?;
+        let meta = response.meta.ok_or_else(|| eyre::eyre!("No meta"))?;
+        assert_eq!(meta.chapters, 2);
+        assert_eq!(meta.content_warnings(), ["Graphic Depictions Of Violence"]);
+        assert_eq!(
+            meta.updated
+                .as_deref()
+                .and_then(parse_date)
+                .map(|d| d.timestamp()),
+            Some(1_706_745_600)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+//! Stories of Fimfiction, read from its public JSON API.
+
+use chrono::{DateTime, Utc};
+use eyre::Result;
+use lazy_regex::regex;
+use lazy_static::lazy_static;
+use scraper::{Html, Selector};
+use serde::Deserialize;
+
+use crate::error::Error;
+use crate::updater::native::epub::{compile_time_selector, Book, Chapter};
+use crate::updater::native::request;
+
+const API_URL: &str = "https://www.fimfiction.net";
+
+lazy_static! {
+    static ref BODY_SELECTOR: Selector = compile_time_selector("body");
+}
+
+#[derive(Deserialize)]
+struct Response {
+    story: Story,
+}
+
+#[derive(Deserialize)]
+struct Story {
+    id: u32,
+    title: String,
+    url: String,
+    #[serde(default)]
+    description: String,
+    full_image: Option<String>,
+    image: Option<String>,
+    author: Author,
+    content_rating_text: Option<String>,
+    #[serde(default)]
+    chapters: Vec<StoryChapter>,
+}
+
+#[derive(Deserialize)]
+struct Author {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct StoryChapter {
+    id: u64,
+    title: String,
+    link: String,
+    /// Chapters are edited in place, their new version has a more recent date.
+    date_modified: i64,
+}
+
+/// Id of the story of `https://www.fimfiction.net/story/<id>/...`.
+fn story_id(url: &str) -> Option<u32> {
+    regex!(r"^https://(?:www\.)?fimfiction\.net/story/(\d+)").captures(url)?[1]
+        .parse()
+        .ok()
+}
+
+/// Story of `url`, with its chapters (without their content).
+pub fn fetch_book(url: &str) -> Result<Book> {
+    let id =
+        story_id(url).ok_or_else(|| Error::Parse(format!("Not a Fimfiction story : {url}")))?;
+    let api_url = format!("{API_URL}/api/story.php?story={id}");
+    let response = request::block_on(request::get(&api_url))?
+        .error_for_status(&api_url)?
+        .text();
+    let response: Response = serde_json::from_str(&response)?;
+    Ok(book(response.story))
+}
+
+fn book(story: Story) -> Book {
+    let chapters: Vec<Chapter> = story
+        .chapters
+        .into_iter()
+        .map(|chapter| Chapter {
+            identifier: chapter.id.to_string(),
+            date_published: DateTime::from_timestamp(chapter.date_modified, 0).unwrap_or_default(),
+            title: chapter.title,
+            url: chapter.link,
+            ..Chapter::default()
+        })
+        .collect();
+    // The API only dates the chapters.
+    let date_published = chapters
+        .iter()
+        .map(|c| c.date_published)
+        .min()
+        .unwrap_or_else(Utc::now);
+    Book {
+        id: story.id,
+        url: story.url,
+        title: story.title,
+        author: story.author.name,
+        description: story.description,
+        date_published: date_published.to_rfc3339(),
+        cover_url: story.full_image.or(story.image).unwrap_or_default(),
+        rating_label: story.content_rating_text,
+        chapters,
+        ..Book::default()
+    }
+}
+
+/// Text of the chapter `identifier`, from its HTML download.
+pub async fn chapter_content(identifier: &str) -> Result<String> {
+    let download_url = format!("{API_URL}/chapters/download/{identifier}/html");
+    let text = request::get(&download_url)
+        .await?
+        .error_for_status(&download_url)?
+        .text();
+    Ok(Html::parse_document(&text)
+        .select(&BODY_SELECTOR)
+        .next()
+        .map(|body| body.inner_html())
+        .ok_or_else(|| Error::Parse(String::from("No content found")))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_story() -> Result<()> {
+        let response: Response = serde_json::from_str(
+            r#"{"story":{"id":42,"title":"A Story","url":"https://www.fimfiction.net/story/42/a-story",
+            "description":"Summary","image":"https://cdn-img.fimfiction.net/story/42.jpg",
+            "full_image":"https://cdn-img.fimfiction.net/story/42-full.jpg",
+            "author":{"id":1,"name":"someone"},"content_rating_text":"Teen","chapters":[
+            {"id":7,"title":"Prologue","link":"https://www.fimfiction.net/story/42/1/a-story/prologue",
+            "date_modified":1706745600},
+            {"id":8,"title":"The End","link":"https://www.fimfiction.net/story/42/2/a-story/the-end",
+            "date_modified":1704067200}]}}"#,
+        )?;
+        let book = book(response.story);
+        assert_eq!(
+            book.cover_url,
+            "https://cdn-img.fimfiction.net/story/42-full.jpg"
+        );
+        assert_eq!(book.date_published, "2024-01-01T00:00:00+00:00");
+        assert_eq!(book.chapters[0].identifier, "7");
+        assert_eq!(book.chapters[0].date_published.timestamp(), 1_706_745_600);
+        assert_eq!(book.rating_label.as_deref(), Some("Teen"));
+        assert_eq!(
+            story_id("https://www.fimfiction.net/story/42/1/a-story/prologue"),
+            Some(42)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use eyre::{eyre, Result};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::updater::native::request::Fetched;
+
+/// Response stored on disk, to be replayed instead of reaching the network.
+#[derive(Serialize, Deserialize)]
+struct Fixture {
+    method: String,
+    url: String,
+    status: u16,
+    headers: BTreeMap<String, String>,
+    /// Base64 of the body.
+    body: String,
+}
+
+fn fixture_path(dir: &Path, method: &str, url: &str) -> PathBuf {
+    let hash = format!("{:x}", Sha256::digest(format!("{method} {url}").as_bytes()));
+    dir.join(format!("{hash}.json"))
+}
+
+/// Store the response in `dir`.
+pub fn record(dir: &Path, method: &str, url: &str, response: &Fetched) -> Result<()> {
+    let fixture = Fixture {
+        method: method.to_string(),
+        url: url.to_string(),
+        status: response.status.as_u16(),
+        headers: response
+            .headers
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect(),
+        body: STANDARD.encode(&response.body),
+    };
+    fs::create_dir_all(dir)?;
+    fs::write(
+        fixture_path(dir, method, url),
+        serde_json::to_string_pretty(&fixture)?,
+    )?;
+    Ok(())
+}
+
+/// Response recorded in `dir` for this request.
+pub fn replay(dir: &Path, method: &str, url: &str) -> Result<Fetched> {
+    let path = fixture_path(dir, method, url);
+    let contents = fs::read_to_string(&path)
+        .map_err(|e| eyre!("No fixture for {method} {url} ({}) : {e}", path.display()))?;
+    let fixture: Fixture = serde_json::from_str(&contents)?;
+
+    let mut headers = HeaderMap::new();
+    for (name, value) in &fixture.headers {
+        headers.insert(
+            HeaderName::from_bytes(name.as_bytes())?,
+            HeaderValue::from_str(value)?,
+        );
+    }
+    Ok(Fetched {
+        status: StatusCode::from_u16(fixture.status)?,
+        headers,
+        body: STANDARD.decode(&fixture.body)?.into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::ETAG;
+
+    #[test]
+    fn test_replay_recorded_fixture() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let url = "https://www.royalroad.com/fiction/1";
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, HeaderValue::from_static("\"abc\""));
+        let response = Fetched {
+            status: StatusCode::OK,
+            headers,
+            body: "<html></html>".into(),
+        };
+
+        record(dir.path(), "GET", url, &response)?;
+        let replayed = replay(dir.path(), "GET", url)?;
+        assert_eq!(replayed.status, StatusCode::OK);
+        assert_eq!(
+            replayed.headers.get(ETAG).and_then(|v| v.to_str().ok()),
+            Some("\"abc\"")
+        );
+        assert_eq!(replayed.text(), "<html></html>");
+        assert!(replay(dir.path(), "HEAD", url).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_replay_hand_written_fixture() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let url = "https://www.royalroad.com/fiction/2";
+        let fixture = Fixture {
+            method: String::from("GET"),
+            url: url.to_string(),
+            status: 404,
+            headers: BTreeMap::new(),
+            body: STANDARD.encode("Not found"),
+        };
+        fs::write(
+            fixture_path(dir.path(), "GET", url),
+            serde_json::to_string(&fixture)?,
+        )?;
+
+        let replayed = replay(dir.path(), "GET", url)?;
+        assert_eq!(replayed.status, StatusCode::NOT_FOUND);
+        assert_eq!(replayed.text(), "Not found");
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+//! Sites without a dedicated source, configured with a few selectors : the book is crawled
+//! from its first chapter by following the links to the next chapter.
+
+use chrono::Utc;
+use eyre::Result;
+use lazy_static::lazy_static;
+use scraper::{Html, Selector};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use url::Url;
+
+use crate::error::Error;
+use crate::profile::{self, Site};
+use crate::updater::native::epub::{compile_time_selector, Book, Chapter};
+use crate::updater::native::request;
+
+/// Limit of the crawl, in case the next links loop through ever-changing URLs.
+const MAX_CHAPTERS: usize = 5000;
+
+lazy_static! {
+    static ref PAGE_TITLE_SELECTOR: Selector = compile_time_selector("title");
+}
+
+/// Id of the book, the site giving none.
+pub fn book_id(url: &str) -> u32 {
+    let digest = Sha256::digest(url.as_bytes());
+    u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]])
+}
+
+fn selector(selector: &str) -> Result<Selector> {
+    Ok(Selector::parse(selector)
+        .map_err(|e| Error::Parse(format!("Invalid selector '{selector}' : {e}")))?)
+}
+
+fn text_of(page: &Html, selector: &Selector) -> Option<String> {
+    page.select(selector)
+        .next()
+        .map(|e| e.text().collect::<String>().trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+/// Book whose first chapter is at `url`, with the content of every chapter.
+pub fn fetch_book(url: &str) -> Result<Book> {
+    let site = profile::site_for(url)
+        .ok_or_else(|| Error::Parse(format!("No site configured for {url}")))?;
+    let first_page = fetch_page(url)?;
+    let title = match &site.title {
+        Some(title) => text_of(&first_page, &selector(title)?),
+        None => text_of(&first_page, &PAGE_TITLE_SELECTOR),
+    }
+    .ok_or_else(|| Error::Parse(String::from("No title found")))?;
+    let author = match &site.author {
+        Some(author) => text_of(&first_page, &selector(author)?),
+        None => None,
+    };
+    let chapters = crawl(site, url, Some(first_page))?;
+
+    Ok(Book {
+        id: book_id(url),
+        url: url.to_string(),
+        title,
+        author: author.unwrap_or_default(),
+        date_published: Utc::now().to_rfc3339(),
+        chapters,
+        ..Book::default()
+    })
+}
+
+/// Chapters published after the last one of `book`, found by following its next link.
+pub fn chapter_delta(book: &Book) -> Option<Vec<Chapter>> {
+    let site = profile::site_for(&book.url)?;
+    let last_known = book.chapters.last()?;
+    let mut chapters = crawl(site, &last_known.url, None).ok()?;
+    // The last known chapter is already in the book.
+    chapters.retain(|c| !book.chapters.contains(c));
+    Some(chapters)
+}
+
+fn fetch_page(url: &str) -> Result<Html> {
+    let response = request::block_on(request::get(url))?
+        .error_for_status(url)?
+        .text();
+    Ok(Html::parse_document(&response))
+}
+
+/// Chapters from `url` onwards, `first_page` being the page of `url` if already fetched.
+fn crawl(site: &Site, url: &str, first_page: Option<Html>) -> Result<Vec<Chapter>> {
+    let content_selector = selector(&site.content)?;
+    let next_selector = selector(&site.next)?;
+    let chapter_title_selector = match &site.chapter_title {
+        Some(chapter_title) => selector(chapter_title)?,
+        None => PAGE_TITLE_SELECTOR.clone(),
+    };
+
+    let mut chapters = Vec::new();
+    let mut visited = HashSet::new();
+    let mut next = Some((url.to_string(), first_page));
+    while let Some((page_url, page)) = next.take() {
+        if !visited.insert(page_url.clone()) || chapters.len() >= MAX_CHAPTERS {
+            break;
+        }
+        let page = match page {
+            Some(page) => page,
+            None => fetch_page(&page_url)?,
+        };
+        let Some(content) = page.select(&content_selector).next() else {
+            break;
+        };
+        chapters.push(Chapter {
+            identifier: chapter_identifier(&page_url),
+            date_published: Utc::now(),
+            title: text_of(&page, &chapter_title_selector)
+                .unwrap_or_else(|| format!("Chapter {}", chapters.len() + 1)),
+            content: Some(content.inner_html()),
+            url: page_url.clone(),
+            ..Chapter::default()
+        });
+        next = page
+            .select(&next_selector)
+            .next()
+            .and_then(|a| a.attr("href"))
+            .and_then(|href| Url::parse(&page_url).ok()?.join(href).ok())
+            .map(|u| (String::from(u), None));
+    }
+    Ok(chapters)
+}
+
+/// Chapters are identified by a hash of their path, as the sites give them no id.
+/// The identifier names the file of the chapter and its id in the e-book, the path may
+/// hold characters which are valid in neither.
+fn chapter_identifier(url: &str) -> String {
+    let path = Url::parse(url).map_or_else(
+        |_| url.to_string(),
+        |u| {
+            let mut path = u.path().to_string();
+            if let Some(query) = u.query() {
+                path.push('?');
+                path.push_str(query);
+            }
+            path
+        },
+    );
+    let hash = format!("{:x}", Sha256::digest(path.as_bytes()));
+    format!("page-{}", &hash[..12])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chapter_identifier() {
+        assert_eq!(
+            chapter_identifier("https://novels.example.com/read.php?book=1&chapter=2#top"),
+            "page-9876f9b55105"
+        );
+        assert_eq!(
+            book_id("https://novels.example.com/a-book/1"),
+            book_id("https://novels.example.com/a-book/1")
+        );
+    }
+}
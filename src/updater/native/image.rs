@@ -1,19 +1,29 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
 use eyre::{bail, eyre};
 use image::codecs::jpeg::JpegEncoder;
 use image::codecs::png::{CompressionType, FilterType, PngEncoder};
-use image::ImageReader;
+use image::{DynamicImage, ImageReader};
 use lazy_static::lazy_static;
+use percent_encoding::percent_decode_str;
 use scraper::{Html, Selector};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
 use std::io::Cursor;
-use url::Url;
+use url::{ParseError, Url};
 use webp::Decoder;
 
+use crate::config::{ImageBudget, ImageHostRules, ImagePriority};
 use crate::updater::native::epub::{compile_time_selector, FORBIDDEN_CHARACTERS};
 lazy_static! {
     static ref IMAGE_SELECTOR: Selector = compile_time_selector("img");
 }
 
 pub fn extract_file_name(url: &str) -> eyre::Result<String> {
+    if is_data_uri(url) {
+        return Ok(data_uri_file_name(url));
+    }
+
     let mut url = Url::parse(url).map_err(|e| eyre!("{e} (Image URL : {url})"))?;
     url.set_query(None);
     url.set_fragment(None);
@@ -26,27 +36,156 @@ pub fn extract_file_name(url: &str) -> eyre::Result<String> {
         .replace(FORBIDDEN_CHARACTERS, "_"))
 }
 
-pub fn extract_urls_from_html(body: &Option<String>) -> Vec<String> {
-    body.as_ref().map_or_else(Vec::new, |text| {
+pub fn is_data_uri(url: &str) -> bool {
+    url.starts_with("data:")
+}
+
+/// Data URIs have no name, derive a stable one from their content and media type.
+fn data_uri_file_name(uri: &str) -> String {
+    let extension = uri
+        .strip_prefix("data:image/")
+        .and_then(|u| u.split([';', ',', '+']).next())
+        .filter(|e| !e.is_empty())
+        .unwrap_or("png");
+    let hash = format!("{:x}", Sha256::digest(uri.as_bytes()));
+    format!("data_{}.{extension}", &hash[..16]).replace(FORBIDDEN_CHARACTERS, "_")
+}
+
+/// Decode a `data:[<media type>][;base64],<data>` URI.
+pub fn decode_data_uri(uri: &str) -> eyre::Result<bytes::Bytes> {
+    let (header, data) = uri
+        .strip_prefix("data:")
+        .and_then(|u| u.split_once(','))
+        .ok_or_else(|| eyre!("Invalid data URI"))?;
+
+    if header.ends_with(";base64") {
+        let data: String = data.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+        Ok(STANDARD.decode(data)?.into())
+    } else {
+        Ok(percent_decode_str(data).collect::<Vec<u8>>().into())
+    }
+}
+
+pub fn extract_urls_from_html(body: Option<&String>, base: Option<&Url>) -> Vec<String> {
+    body.map_or_else(Vec::new, |text| {
         Html::parse_fragment(text)
             .select(&IMAGE_SELECTOR)
             .filter_map(|element| element.value().attr("src"))
-            .filter(|u| Url::parse(u).is_ok())
-            .map(std::string::ToString::to_string)
+            .filter_map(|src| resolve_url(src, base))
             .collect()
     })
 }
 
-pub fn replace_url_with_path(mut body: String) -> String {
+pub fn replace_url_with_path(mut body: String, base: Option<&Url>) -> String {
     Html::parse_fragment(&body)
         .select(&IMAGE_SELECTOR)
         .filter_map(|element| element.value().attr("src"))
-        .filter_map(|src| extract_file_name(src).map(|new_src| (src, new_src)).ok())
+        .filter_map(|src| {
+            resolve_url(src, base)
+                .and_then(|url| extract_file_name(&url).ok())
+                .map(|new_src| (src, new_src))
+        })
         .for_each(|(src, new_src)| body = body.replace(src, &new_src));
 
     body
 }
 
+/// Absolute URLs are kept as is, relative ones are resolved against `base`.
+fn resolve_url(src: &str, base: Option<&Url>) -> Option<String> {
+    match Url::parse(src) {
+        Ok(_) => Some(src.to_string()),
+        Err(ParseError::RelativeUrlWithoutBase) => base?.join(src).ok().map(String::from),
+        Err(_) => None,
+    }
+}
+
+/// Describe the rule excluding the image's host, if any (data URIs have no host and are always kept).
+pub fn rejecting_rule(url: &str, rules: &ImageHostRules) -> Option<String> {
+    let host = Url::parse(url).ok()?.host_str()?.to_lowercase();
+    let matches = |rule: &String| {
+        let rule = rule.to_lowercase();
+        host == rule || host.ends_with(&format!(".{rule}"))
+    };
+
+    if let Some(rule) = rules.deny.iter().find(|r| matches(r)) {
+        return Some(format!("denied host '{rule}'"));
+    }
+    if !rules.allow.is_empty() && !rules.allow.iter().any(matches) {
+        return Some(String::from("host not allowed"));
+    }
+    None
+}
+
+/// Whether the HTML contains images but no text.
+pub fn is_image_only(body: &str) -> bool {
+    let html = Html::parse_fragment(body);
+    html.select(&IMAGE_SELECTOR).next().is_some()
+        && html.root_element().text().all(|t| t.trim().is_empty())
+}
+
+/// Remove the `img` elements whose (resolved) source is one of `urls`.
+pub fn remove_images(mut body: String, base: Option<&Url>, urls: &HashSet<String>) -> String {
+    if urls.is_empty() {
+        return body;
+    }
+    Html::parse_fragment(&body)
+        .select(&IMAGE_SELECTOR)
+        .filter(|element| {
+            element
+                .value()
+                .attr("src")
+                .and_then(|src| resolve_url(src, base))
+                .is_some_and(|url| urls.contains(&url))
+        })
+        .map(|element| element.html())
+        .for_each(|img| body = body.replace(&img, ""));
+
+    body
+}
+
+pub struct DownloadedImage {
+    pub url: String,
+    pub filename: String,
+    pub buffer: Vec<u8>,
+}
+
+/// Split the images between the ones fitting in the budget and the skipped ones,
+/// the cover is always kept and both lists keep the original order.
+pub fn apply_budget(
+    images: Vec<DownloadedImage>,
+    cover_url: &str,
+    budget: &ImageBudget,
+) -> (Vec<DownloadedImage>, Vec<DownloadedImage>) {
+    let mut priority_order: Vec<usize> = (0..images.len()).collect();
+    if budget.priority == ImagePriority::SmallestFirst {
+        priority_order.sort_by_key(|&i| images[i].buffer.len());
+    }
+    priority_order.sort_by_key(|&i| images[i].url != cover_url);
+
+    let mut kept = HashSet::new();
+    let mut total_count: usize = 0;
+    let mut total_size: u64 = 0;
+    for i in priority_order {
+        let size = images[i].buffer.len() as u64;
+        let fits = budget.max_count.is_none_or(|max| total_count < max)
+            && budget.max_size.is_none_or(|max| total_size + size <= max);
+        if fits || images[i].url == cover_url {
+            kept.insert(i);
+            total_count += 1;
+            total_size += size;
+        }
+    }
+
+    let (kept, skipped): (Vec<_>, Vec<_>) = images
+        .into_iter()
+        .enumerate()
+        .partition(|(i, _)| kept.contains(i));
+    (
+        kept.into_iter().map(|(_, image)| image).collect(),
+        skipped.into_iter().map(|(_, image)| image).collect(),
+    )
+}
+
 pub fn resize(bytes: bytes::Bytes) -> eyre::Result<Vec<u8>> {
     let managed_image_format = ManagedImageFormat::new(&bytes).ok_or_else(|| {
         eyre!("Unsupported inline image format. Please report this as a bug and include the link.")
@@ -66,6 +205,29 @@ pub fn resize(bytes: bytes::Bytes) -> eyre::Result<Vec<u8>> {
     Ok(buffer)
 }
 
+/// Re-encode the image as an RGB JPEG, the most widely supported format for covers.
+pub fn to_jpeg(bytes: &[u8]) -> eyre::Result<Vec<u8>> {
+    let image = match ManagedImageFormat::new(bytes) {
+        Some(ManagedImageFormat::Webp) => Decoder::new(bytes)
+            .decode()
+            .ok_or_else(|| eyre!("Image is not a valid WebP"))?
+            .to_image(),
+        Some(ManagedImageFormat::Png | ManagedImageFormat::Jpeg | ManagedImageFormat::Gif) => {
+            ImageReader::new(Cursor::new(bytes))
+                .with_guessed_format()?
+                .decode()?
+        }
+        Some(ManagedImageFormat::Svg | ManagedImageFormat::Html) | None => {
+            bail!("Unsupported cover format.")
+        }
+    };
+
+    let mut buffer = Vec::new();
+    DynamicImage::ImageRgb8(image.to_rgb8())
+        .write_with_encoder(JpegEncoder::new_with_quality(Cursor::new(&mut buffer), 80))?;
+    Ok(buffer)
+}
+
 enum ManagedImageFormat {
     Png,
     Jpeg,
@@ -181,7 +343,7 @@ impl ResizableImageFormat {
             ))?,
             Self::Jpeg => image
                 .write_with_encoder(JpegEncoder::new_with_quality(Cursor::new(&mut buffer), 80))?,
-        };
+        }
         Ok(buffer)
     }
 }
@@ -189,9 +351,193 @@ impl ResizableImageFormat {
 #[cfg(test)]
 mod test {
     use scraper::Selector;
+    use std::path::Path;
+
+    use url::Url;
+
+    use super::{
+        apply_budget, decode_data_uri, extract_file_name, extract_urls_from_html, is_image_only,
+        rejecting_rule, to_jpeg, DownloadedImage,
+    };
+    use crate::config::{ImageBudget, ImageHostRules, ImagePriority};
 
     #[test]
     fn test_selectors() {
         assert!(Selector::parse("img").is_ok());
     }
+
+    #[test]
+    fn data_uri_base64() {
+        let uri = "data:image/gif;base64,R0lGODlh";
+        assert_eq!(decode_data_uri(uri).ok().as_deref(), Some(&b"GIF89a"[..]));
+        assert!(extract_file_name(uri).is_ok_and(
+            |f| f.starts_with("data_") && Path::new(&f).extension() == Some("gif".as_ref())
+        ));
+    }
+
+    #[test]
+    fn data_uri_percent_encoded() {
+        let uri = "data:image/svg+xml,%3Csvg%3E";
+        assert_eq!(decode_data_uri(uri).ok().as_deref(), Some(&b"<svg>"[..]));
+        assert!(
+            extract_file_name(uri).is_ok_and(|f| Path::new(&f).extension() == Some("svg".as_ref()))
+        );
+    }
+
+    #[test]
+    fn data_uri_images_are_embedded() -> eyre::Result<()> {
+        use base64::Engine;
+
+        let mut png = Vec::new();
+        image::RgbImage::new(3, 2)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)?;
+        let uri = format!(
+            "data:image/png;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(&png)
+        );
+
+        // Same file for the same image, so that it is only written once.
+        assert_eq!(extract_file_name(&uri)?, extract_file_name(&uri)?);
+        let embedded = super::resize(decode_data_uri(&uri)?)?;
+        let embedded = image::load_from_memory(&embedded)?;
+        // Resized like the downloaded images.
+        assert_eq!((embedded.width(), embedded.height()), (600, 400));
+        Ok(())
+    }
+
+    #[test]
+    fn relative_urls_are_resolved() {
+        let body =
+            String::from("<img src=\"/images/map.png\"><img src=\"https://site.com/a.gif\">");
+        let base = Url::parse("https://site.com/fiction/1/chapter/2").ok();
+        let urls = extract_urls_from_html(Some(&body), base.as_ref());
+        assert_eq!(
+            urls,
+            vec!["https://site.com/images/map.png", "https://site.com/a.gif"]
+        );
+        assert_eq!(
+            extract_urls_from_html(Some(&body), None),
+            vec!["https://site.com/a.gif"]
+        );
+    }
+
+    #[test]
+    fn relative_images_point_to_their_files() {
+        let body = String::from("<p><img src=\"../images/map.png\"></p>");
+        let base = Url::parse("https://site.com/fiction/1/chapter/2").ok();
+        assert_eq!(
+            super::replace_url_with_path(body.clone(), base.as_ref()),
+            "<p><img src=\"map.png\"></p>"
+        );
+        // Without a chapter URL there is no file to point to.
+        assert_eq!(super::replace_url_with_path(body.clone(), None), body);
+    }
+
+    #[test]
+    fn budget_keeps_cover_and_smallest_images() {
+        let images = [("cover", 40), ("big", 30), ("small", 10), ("medium", 20)]
+            .into_iter()
+            .map(|(name, size)| DownloadedImage {
+                url: name.to_string(),
+                filename: name.to_string(),
+                buffer: vec![0; size],
+            })
+            .collect();
+        let budget = ImageBudget {
+            max_count: None,
+            max_size: Some(75),
+            priority: ImagePriority::SmallestFirst,
+        };
+
+        let (kept, skipped) = apply_budget(images, "cover", &budget);
+
+        let kept: Vec<_> = kept.iter().map(|i| i.filename.as_str()).collect();
+        let skipped: Vec<_> = skipped.iter().map(|i| i.filename.as_str()).collect();
+        assert_eq!(kept, vec!["cover", "small", "medium"]);
+        assert_eq!(skipped, vec!["big"]);
+    }
+
+    #[test]
+    fn images_over_budget_are_left_out_of_the_chapter() {
+        let images = [
+            ("https://site.com/a.png", 10),
+            ("https://site.com/b.png", 20),
+        ]
+        .into_iter()
+        .map(|(url, size)| DownloadedImage {
+            url: url.to_string(),
+            filename: url.to_string(),
+            buffer: vec![0; size],
+        })
+        .collect();
+        let budget = ImageBudget {
+            max_count: Some(1),
+            max_size: None,
+            priority: ImagePriority::SmallestFirst,
+        };
+        let (_, skipped) = apply_budget(images, "", &budget);
+        let skipped = skipped.into_iter().map(|i| i.url).collect();
+
+        let content = String::from("<p><img src=\"a.png\"></p><p><img src=\"b.png\"></p>");
+        let base = Url::parse("https://site.com/chapter").ok();
+        assert_eq!(
+            super::remove_images(content, base.as_ref(), &skipped),
+            "<p><img src=\"a.png\"></p><p></p>"
+        );
+    }
+
+    #[test]
+    fn image_only_chapters() {
+        assert!(is_image_only("<p> <img src=\"a.png\"> </p><p>&nbsp;</p>"));
+        assert!(!is_image_only("<p><img src=\"a.png\">Caption</p>"));
+        assert!(!is_image_only("<p>Text only</p>"));
+    }
+
+    #[test]
+    fn image_host_rules() {
+        let rules = ImageHostRules {
+            allow: vec![String::from("royalroadcdn.com"), String::from("imgur.com")],
+            deny: vec![String::from("i.imgur.com")],
+        };
+        assert!(rejecting_rule("https://www.royalroadcdn.com/a.png", &rules).is_none());
+        assert!(rejecting_rule("https://i.imgur.com/a.png", &rules).is_some());
+        assert!(rejecting_rule("https://tracker.com/pixel.gif", &rules).is_some());
+        assert!(rejecting_rule("data:image/gif;base64,R0lGODlh", &rules).is_none());
+    }
+
+    #[test]
+    fn images_of_denied_hosts_are_stripped() {
+        let rules = ImageHostRules {
+            allow: Vec::new(),
+            deny: vec![String::from("tracker.com")],
+        };
+        let content = String::from(
+            "<p><img src=\"https://cdn.site.com/map.png\"></p>\
+            <p><img src=\"https://pixel.tracker.com/p.gif\"></p>",
+        );
+
+        let stripped = extract_urls_from_html(Some(&content), None)
+            .into_iter()
+            .filter(|url| rejecting_rule(url, &rules).is_some())
+            .collect();
+
+        assert_eq!(
+            super::remove_images(content, None, &stripped),
+            "<p><img src=\"https://cdn.site.com/map.png\"></p><p></p>"
+        );
+    }
+
+    #[test]
+    fn covers_are_converted_to_jpeg() -> eyre::Result<()> {
+        let mut png = Vec::new();
+        image::DynamicImage::new_rgba8(4, 2)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)?;
+
+        let jpeg = to_jpeg(&png)?;
+        assert!(jpeg.starts_with(&[0xFF, 0xD8, 0xFF]));
+        let cover = image::load_from_memory(&jpeg)?;
+        assert_eq!((cover.width(), cover.height()), (4, 2));
+        assert!(to_jpeg(b"<svg></svg>").is_err());
+        Ok(())
+    }
 }
@@ -0,0 +1,124 @@
+//! Stories of Inkitt, read from the JSON API of its reader.
+
+use chrono::{DateTime, Utc};
+use eyre::Result;
+use lazy_regex::regex;
+use lazy_static::lazy_static;
+use scraper::{Html, Selector};
+use serde::Deserialize;
+
+use crate::error::Error;
+use crate::updater::native::epub::{compile_time_selector, Book, Chapter};
+use crate::updater::native::request;
+
+const SITE_URL: &str = "https://www.inkitt.com";
+
+lazy_static! {
+    static ref CONTENT_SELECTOR: Selector = compile_time_selector("#story-text-container");
+}
+
+#[derive(Deserialize)]
+struct Story {
+    id: u32,
+    title: String,
+    #[serde(default)]
+    summary: String,
+    cover_url: Option<String>,
+    user: User,
+    #[serde(default)]
+    chapters: Vec<StoryChapter>,
+}
+
+#[derive(Deserialize)]
+struct User {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct StoryChapter {
+    chapter_number: u32,
+    name: String,
+    created_at: DateTime<Utc>,
+}
+
+/// Genre and id of the story of `https://www.inkitt.com/stories/<genre>/<id>[/chapters/<n>]`.
+fn genre_and_id(url: &str) -> Option<(String, u32)> {
+    let captures =
+        regex!(r"^https://(?:www\.)?inkitt\.com/stories/([\w-]+)/(\d+)").captures(url)?;
+    Some((captures[1].to_string(), captures[2].parse().ok()?))
+}
+
+/// Story of `url`, with its chapters (without their content).
+pub fn fetch_book(url: &str) -> Result<Book> {
+    let (genre, id) =
+        genre_and_id(url).ok_or_else(|| Error::Parse(format!("Not an Inkitt story : {url}")))?;
+    let api_url = format!("{SITE_URL}/api/stories/{id}");
+    let response = request::block_on(request::get(&api_url))?
+        .error_for_status(&api_url)?
+        .text();
+    book(serde_json::from_str(&response)?, &genre)
+}
+
+fn book(story: Story, genre: &str) -> Result<Book> {
+    let story_url = format!("{SITE_URL}/stories/{genre}/{}", story.id);
+    let chapters: Vec<Chapter> = story
+        .chapters
+        .into_iter()
+        .map(|chapter| Chapter {
+            identifier: chapter.chapter_number.to_string(),
+            date_published: chapter.created_at,
+            title: chapter.name,
+            url: format!("{story_url}/chapters/{}", chapter.chapter_number),
+            ..Chapter::default()
+        })
+        .collect();
+    let date_published = chapters
+        .iter()
+        .map(|c| c.date_published)
+        .min()
+        .ok_or_else(|| Error::Parse(String::from("No chapters found")))?;
+    Ok(Book {
+        id: story.id,
+        url: story_url,
+        title: story.title,
+        author: story.user.name,
+        description: story.summary,
+        date_published: date_published.to_rfc3339(),
+        cover_url: story.cover_url.unwrap_or_default(),
+        chapters,
+        ..Book::default()
+    })
+}
+
+pub fn chapter_content(page: &Html) -> Option<String> {
+    page.select(&CONTENT_SELECTOR)
+        .next()
+        .map(|e| e.inner_html())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_story() -> Result<()> {
+        let story: Story = serde_json::from_str(
+            r#"{"id":42,"title":"A Story","summary":"Summary","cover_url":null,
+            "user":{"id":1,"name":"someone"},"chapters":[
+            {"id":7,"chapter_number":1,"name":"Prologue","created_at":"2024-01-01T00:00:00Z"},
+            {"id":8,"chapter_number":2,"name":"The End","created_at":"2024-02-01T00:00:00Z"}]}"#,
+        )?;
+        let book = book(story, "fantasy")?;
+        assert_eq!(book.url, "https://www.inkitt.com/stories/fantasy/42");
+        assert_eq!(book.date_published, "2024-01-01T00:00:00+00:00");
+        assert_eq!(
+            book.chapters[1].url,
+            "https://www.inkitt.com/stories/fantasy/42/chapters/2"
+        );
+        assert_eq!(
+            genre_and_id("https://inkitt.com/stories/fantasy/42/chapters/3"),
+            Some((String::from("fantasy"), 42))
+        );
+        Ok(())
+    }
+}
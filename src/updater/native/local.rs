@@ -0,0 +1,160 @@
+//! Directories of numbered Markdown or HTML files, one per chapter, whether written by hand or
+//! downloaded by other tools. New files are new chapters.
+
+use chrono::{DateTime, Utc};
+use eyre::{eyre, Result};
+use lazy_static::lazy_static;
+use scraper::{Html, Selector};
+use std::fs;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+use crate::updater::native::epub::{compile_time_selector, Book, Chapter};
+use crate::updater::native::generic;
+
+const MARKDOWN_EXTENSIONS: [&str; 2] = ["md", "markdown"];
+const HTML_EXTENSIONS: [&str; 3] = ["html", "htm", "xhtml"];
+
+lazy_static! {
+    static ref TITLE_SELECTOR: Selector = compile_time_selector("h1, title");
+    static ref BODY_SELECTOR: Selector = compile_time_selector("body");
+}
+
+/// URL of `path` if it is a directory, for it to be added as a book.
+pub fn folder_url(path: &str) -> Option<String> {
+    let path = fs::canonicalize(path).ok().filter(|p| p.is_dir())?;
+    Url::from_directory_path(path).ok().map(String::from)
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Chapter files of `dir`, in the order of their number then of their name.
+fn chapter_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            let extension = extension(path);
+            MARKDOWN_EXTENSIONS.contains(&extension.as_str())
+                || HTML_EXTENSIONS.contains(&extension.as_str())
+        })
+        .collect();
+    files.sort_by_cached_key(|path| {
+        let stem = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let digits: String = stem.chars().take_while(char::is_ascii_digit).collect();
+        (digits.parse::<u64>().unwrap_or(u64::MAX), stem)
+    });
+    Ok(files)
+}
+
+/// Identifier of the chapter of `path`, which is also the name of its file in the e-book.
+fn chapter_identifier(path: &Path) -> String {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let stem: String = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    format!("local-{stem}")
+}
+
+/// Title and content of a chapter, its title being its first heading if any.
+fn read_chapter(path: &Path) -> Result<(Option<String>, String)> {
+    let text = fs::read_to_string(path)?;
+    if MARKDOWN_EXTENSIONS.contains(&extension(path).as_str()) {
+        let title = text
+            .lines()
+            .find_map(|line| line.strip_prefix("# "))
+            .map(|title| title.trim().to_string());
+        let mut content = String::new();
+        pulldown_cmark::html::push_html(&mut content, pulldown_cmark::Parser::new(&text));
+        return Ok((title, content));
+    }
+    let page = Html::parse_document(&text);
+    let title = page
+        .select(&TITLE_SELECTOR)
+        .next()
+        .map(|e| e.text().collect::<String>().trim().to_string())
+        .filter(|title| !title.is_empty());
+    let content = page
+        .select(&BODY_SELECTOR)
+        .next()
+        .map_or(text, |body| body.inner_html());
+    Ok((title, content))
+}
+
+/// Book of the directory of `url`, with the content of every chapter.
+pub fn fetch_book(url: &str) -> Result<Book> {
+    let dir = Url::parse(url)?
+        .to_file_path()
+        .map_err(|()| eyre!("Not a local directory : {url}"))?;
+    let mut chapters = Vec::new();
+    for path in chapter_files(&dir)? {
+        let (title, content) = read_chapter(&path)?;
+        // Edited files are dated from their last modification.
+        let date_published: DateTime<Utc> = fs::metadata(&path)?.modified()?.into();
+        chapters.push(Chapter {
+            identifier: chapter_identifier(&path),
+            date_published,
+            title: title.unwrap_or_else(|| format!("Chapter {}", chapters.len() + 1)),
+            url: Url::from_file_path(&path)
+                .map(String::from)
+                .unwrap_or_default(),
+            content: Some(content),
+            ..Chapter::default()
+        });
+    }
+    let date_published = chapters
+        .iter()
+        .map(|c| c.date_published)
+        .min()
+        .unwrap_or_else(Utc::now);
+
+    Ok(Book {
+        id: generic::book_id(url),
+        url: url.to_string(),
+        title: dir
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        date_published: date_published.to_rfc3339(),
+        chapters,
+        ..Book::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fetch_book() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::write(dir.path().join("10-end.md"), "# The End\n\nBye.")?;
+        fs::write(
+            dir.path().join("2 middle.html"),
+            "<h1>Middle</h1><p>Hm.</p>",
+        )?;
+        fs::write(dir.path().join("1-start.md"), "Hello *world*.")?;
+        fs::write(dir.path().join("notes.txt"), "Not a chapter.")?;
+        let url = folder_url(&dir.path().to_string_lossy()).ok_or_else(|| eyre!("No URL"))?;
+
+        let book = fetch_book(&url)?;
+        let titles: Vec<&str> = book.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, ["Chapter 1", "Middle", "The End"]);
+        assert_eq!(book.chapters[1].identifier, "local-2-middle");
+        assert_eq!(
+            book.chapters[0].content.as_deref(),
+            Some("<p>Hello <em>world</em>.</p>\n")
+        );
+        Ok(())
+    }
+}
@@ -1,45 +1,379 @@
 use std::path::Path;
 use std::{collections::HashSet, ffi::OsStr};
 
+use crate::config::Config;
+use crate::settings;
+use crate::source::{self, Source};
 use crate::{get_progress_bar, ErrorPrint, MULTI_PROGRESS};
 use ::epub::doc::EpubDoc;
-use epub::Book;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
 use eyre::{eyre, OptionExt, Result};
+use futures_util::future::join_all;
+use futures_util::stream::{self, StreamExt};
+use indicatif::ProgressBar;
+use lazy_regex::RegexBuilder;
 
-use super::{UpdateResult, WebNovel};
+use super::{
+    check_readable, warn, Audit, Rating, UpdateResult, Warning, WebNovel, FORMAT_META,
+    FORMAT_VERSION,
+};
+use cache::Cache;
 
 mod cache;
+mod collection;
+mod dedupe;
 mod epub;
+mod fanficfare_pages;
+pub mod ffnet;
+pub mod fichub;
+pub mod fimfiction;
+mod fixtures;
+pub mod generic;
 mod image;
+pub mod inkitt;
+pub mod local;
+pub mod plugin;
+pub mod quick_check;
+pub mod quotev;
+mod request;
+mod robots;
+pub mod rss;
+mod search;
+mod self_test;
+mod session;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+mod usage;
+pub mod wattpad;
+pub mod wuxiaworld;
+pub mod xenforo;
 mod xml_ext;
 
+pub use collection::{fiction_urls, listing_url};
+pub use epub::{compile_time_selector, is_volume, remove_exports, Book, Chapter};
+pub use local::folder_url;
+pub use search::{search, SearchSource};
+pub use self_test::self_test;
+pub use session::login;
+pub use usage::{save_usage, stats, Usage};
+
+/// Directory of the cached books and images of the native backend.
+pub fn cache_dir() -> Result<std::path::PathBuf> {
+    Cache::cache_path()
+}
+
+/// Title and cleaned HTML of the chapter at `url`, downloaded on its own.
+pub fn fetch_chapter(url: &str) -> Result<(String, String)> {
+    let chapter = request::block_on(Chapter::fetch(url))?;
+    let html = chapter.cleaned_html();
+    Ok((chapter.title, html))
+}
+
+/// Remove the cached images of the book at `url`, returns whether it had any.
+pub fn remove_cache(url: &str) -> Result<bool> {
+    Cache::remove_book(&Book {
+        id: Book::get_id_from_url(url)?,
+        url: url.to_string(),
+        ..Book::default()
+    })
+}
+
+/// Cover of the book at `url`, as found by the native backend.
+#[cfg_attr(not(feature = "fanficfare"), allow(dead_code))]
+pub fn fetch_cover(url: &str) -> Result<Vec<u8>> {
+    let cover_url = source::native(url).fetch_book(url)?.cover_url;
+    if cover_url.is_empty() {
+        return Err(eyre!("No cover found for {url}"));
+    }
+    Ok(request::block_on(request::get(&cover_url))?
+        .error_for_status(&cover_url)?
+        .body
+        .to_vec())
+}
+
 pub struct Native;
 
 impl WebNovel for Native {
     fn new() -> Self {
         Self {}
     }
+    /// Books are created as a stub which is then updated like any other book, writing the
+    /// downloaded chapters as they come : an interrupted creation is resumed from them.
     fn create(&self, dir: &Path, filename: Option<&OsStr>, url: &str) -> Result<crate::Book> {
-        let (book, _) = get_book(url, None)?;
-        let outfile = epub::write(&book, filename.and_then(|f| f.to_str()).map(String::from))?;
+        let stub = Book::new(url)?;
+        let filename =
+            filename.map_or_else(|| epub::default_filename(&stub).into(), OsStr::to_os_string);
+        let outfile = dir.join(filename);
+        if !is_resumable(&outfile, url) {
+            write_stub(stub, &outfile)?;
+        }
+
+        let updated = get_book(url, Some(&outfile), true)?;
+        let outfile = epub::write(&updated.book, outfile.to_str().map(String::from))?;
+        record_metadata_check(&updated.book, updated.metadata_fetched);
 
-        let file_path = dir.join(outfile);
-        Ok(crate::Book::new(&file_path))
+        Ok(crate::Book::new(Path::new(&outfile)))
+    }
+
+    fn create_stub(&self, dir: &Path, url: &str) -> Result<crate::Book> {
+        let stub = Book::new(url)?;
+        let outfile = dir.join(epub::default_filename(&stub));
+        write_stub(stub, &outfile)?;
+
+        Ok(crate::Book::new(&outfile))
     }
 
     fn update(&self, path: &Path) -> UpdateResult {
         do_update(path).unwrap_or_else(UpdateResult::Error)
     }
+
+    fn check(&self, path: &Path) -> Result<usize> {
+        pending_chapters(path)
+    }
+
+    fn adopt(&self, path: &Path, url: &str) -> Result<()> {
+        adopt(path, url)
+    }
+
+    fn rating(&self, url: &str) -> Result<Option<Rating>> {
+        if !source::route(url).is_some_and(|capabilities| capabilities.ratings) {
+            return Ok(None);
+        }
+        Ok(Book::new(url)?.rating())
+    }
+
+    fn audit(&self, path: &Path, fix: bool) -> Result<Audit> {
+        let url = EpubDoc::new(path)?
+            .mdata("source")
+            .ok_or_eyre("Could not find url")?;
+        let mut book = Book::from_path(&url, path)?;
+        let source = source::get(&url);
+        let fetched_book = source.fetch_book(&url)?;
+        let mut audit = audit_chapters(&book.chapters, &fetched_book.chapters);
+        if !fix || audit.is_consistent() {
+            return Ok(audit);
+        }
+
+        let mut seen = HashSet::new();
+        book.chapters.retain(|c| seen.insert(c.identifier.clone()));
+        let missing: Vec<_> = fetched_book
+            .chapters
+            .iter()
+            .filter(|c| !book.chapters.contains(c))
+            .cloned()
+            .collect();
+        book.chapters.extend(missing);
+        // The chapters removed from the source keep their place relative to each other, at the end.
+        book.chapters.sort_by_key(|c| {
+            fetched_book
+                .chapters
+                .iter()
+                .position(|s| s == c)
+                .unwrap_or(usize::MAX)
+        });
+
+        let downloads = book
+            .chapters
+            .iter_mut()
+            .filter(|c| c.content.is_none())
+            .map(|chapter| chapter.update_chapter_content(source.as_ref()));
+        for result in request::block_on(join_all(downloads)) {
+            result?;
+        }
+        epub::write(&book, path.to_str().map(String::from))?;
+        audit.fixed = true;
+        Ok(audit)
+    }
+
+    fn rerender(&self, path: &Path) -> Result<()> {
+        let mut epub_doc = EpubDoc::new(path)?;
+        let url = epub_doc.mdata("source").ok_or_eyre("Could not find url")?;
+        let mut book = Book::from_path(&url, path)?;
+
+        // Without its URL, the current cover is embedded as is so that nothing has to be downloaded.
+        if book.cover_url.is_empty() {
+            if let Some((cover, mime)) = epub_doc.get_cover() {
+                book.cover_url = format!("data:{mime};base64,{}", STANDARD.encode(cover));
+            }
+        }
+        epub::write(&book, path.to_str().map(String::from))?;
+        Ok(())
+    }
+}
+
+/// Write `book` without its chapters, for them to be downloaded by an update.
+fn write_stub(mut book: Book, path: &Path) -> Result<()> {
+    book.chapters.clear();
+    epub::write(&book, path.to_str().map(String::from))?;
+    Ok(())
+}
+
+/// Whether `path` is an interrupted creation of the book of `url`.
+fn is_resumable(path: &Path, url: &str) -> bool {
+    EpubDoc::new(path)
+        .ok()
+        .and_then(|doc| doc.mdata("source"))
+        .is_some_and(|source| source == url)
+}
+
+/// Metadata identifying the books written by rr-to-epub.
+const RR_TO_EPUB_ID_META: &str = "rr-to-epub:royal-road-id";
+
+/// Give a book written by rr-to-epub the source URL, chapter URLs and chapter dates
+/// needed to update it, returns `false` if it was not written by rr-to-epub.
+pub fn migrate_rr_to_epub(path: &Path) -> Result<bool> {
+    let epub_doc = EpubDoc::new(path)?;
+    if epub_doc.mdata("source").is_some() {
+        return Ok(false);
+    }
+    let Some(id) = epub_doc.mdata(RR_TO_EPUB_ID_META) else {
+        return Ok(false);
+    };
+    adopt(path, &format!("https://www.royalroad.com/fiction/{id}"))?;
+    Ok(true)
 }
 
-fn get_book(url: &str, path: Option<&Path>) -> eyre::Result<(Book, UpdateResult)> {
-    // Do the initial metadata fetch of the book.
-    let mut fetched_book = Book::new(url)?;
+/// Rewrite a book written by another tool as if it was written natively from `url`.
+fn adopt(path: &Path, url: &str) -> Result<()> {
+    let mut book = Book::from_path(url, path)?;
+    book.url = url.to_string();
 
-    // Check the cache.
-    let mut current_book = path
-        .and_then(|path| Book::from_path(url, path).ok())
-        .unwrap_or_else(|| fetched_book.clone_without_chapters());
+    // Chapters are matched by title, against the rr-to-epub cache or else the fiction's page.
+    let reference = match Cache::read_legacy_book(&book)? {
+        Some(reference) => reference,
+        None => Book::new(url)?,
+    };
+    match_reference(&mut book, reference);
+
+    epub::write(&book, path.to_str().map(String::from))?;
+    Ok(())
+}
+
+/// Give the chapters of `book` the identifier, URL and date of the chapters of `reference`
+/// with the same title, and the cover and missing description of `reference`.
+fn match_reference(book: &mut Book, reference: Book) {
+    for chapter in &mut book.chapters {
+        if let Some(known) = reference.chapters.iter().find(|c| c.title == chapter.title) {
+            chapter.identifier.clone_from(&known.identifier);
+            chapter.url.clone_from(&known.url);
+            chapter.date_published = known.date_published;
+        }
+    }
+    book.cover_url = reference.cover_url;
+    if book.description.is_empty() {
+        book.description = reference.description;
+    }
+}
+
+/// Compare the chapters of a book with the ones of its source.
+fn audit_chapters(chapters: &[Chapter], source: &[Chapter]) -> Audit {
+    let mut seen = HashSet::new();
+    let duplicated = chapters
+        .iter()
+        .filter(|c| !seen.insert(&c.identifier))
+        .map(|c| c.title.clone())
+        .collect();
+    let missing = source
+        .iter()
+        .filter(|c| !chapters.contains(c))
+        .map(|c| c.title.clone())
+        .collect();
+    let positions: Vec<usize> = chapters
+        .iter()
+        .filter_map(|c| source.iter().position(|s| s == c))
+        .collect();
+    Audit {
+        missing,
+        duplicated,
+        out_of_order: positions.windows(2).any(|w| w[0] > w[1]),
+        fixed: false,
+    }
+}
+
+/// Number of chapters downloaded between two writes of a book being created.
+const CHECKPOINT_CHAPTERS: usize = 100;
+
+/// The feeds do not carry the metadata of the books (title, cover, tags, status), so the
+/// pages of the books are fetched instead of the feeds once this long passed since the last time.
+const METADATA_REFRESH_INTERVAL: chrono::TimeDelta = chrono::TimeDelta::days(7);
+
+/// Whether the title matches one of the patterns, invalid patterns matching nothing.
+fn is_skipped(title: &str, patterns: &[String]) -> bool {
+    patterns.iter().any(|pattern| {
+        RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .is_ok_and(|re| re.is_match(title))
+    })
+}
+
+/// Announcements, polls and the like are left out of the Royal Road books if configured.
+fn remove_skipped_chapters(book: &mut Book, url: &str, path: Option<&Path>) {
+    if epub::is_royal_road(url) {
+        let patterns = path.map_or_else(
+            || Config::get().skip_chapters.clone(),
+            |path| settings::for_book(path).skip_chapters,
+        );
+        book.chapters
+            .retain(|chapter| !is_skipped(&chapter.title, &patterns));
+    }
+}
+
+/// Validator of the source, for the sources without a feed which are first checked against
+/// the one stored in the book.
+fn source_validator(url: &str) -> Option<String> {
+    source::get(url).validator(url)
+}
+
+/// What the source changed in a book, found before downloading any chapter.
+struct Changes {
+    /// The book with the new chapters appended, their content still to be downloaded.
+    current_book: Book,
+    /// Metadata of the book at the source.
+    fetched_book: Book,
+    /// Identifiers of the new and the updated chapters.
+    chapter_to_update_ids: HashSet<String>,
+    volumes_changed: bool,
+    /// When the metadata was fetched from the page of the book, rather than read from its feed.
+    metadata_fetched: Option<DateTime<Utc>>,
+}
+
+/// The book of `url` brought up to date by `get_book`, not written yet.
+struct UpdatedBook {
+    book: Book,
+    result: UpdateResult,
+    metadata_fetched: Option<DateTime<Utc>>,
+}
+
+/// Compare the book of `url` (`current_book`, read from `path`) with its source.
+fn find_changes(
+    url: &str,
+    path: Option<&Path>,
+    current_book: Option<Book>,
+) -> eyre::Result<Changes> {
+    // Routine updates are built from the RSS feed while the metadata is recent enough (or from
+    // the next links of generic sites), else do the metadata fetch of the book.
+    let delta = current_book.as_ref().and_then(|book| {
+        (!is_metadata_stale(book))
+            .then(|| rss::chapter_delta(book))
+            .flatten()
+            .or_else(|| generic::chapter_delta(book))
+    });
+    let (mut fetched_book, metadata_fetched) =
+        if let (Some(chapters), Some(current_book)) = (delta, &current_book) {
+            let fetched_book = Book {
+                chapters,
+                ..current_book.clone_without_chapters()
+            };
+            (fetched_book, None)
+        } else {
+            (Book::new(url)?, Some(Utc::now()))
+        };
+    let mut current_book = current_book.unwrap_or_else(|| fetched_book.clone_without_chapters());
+
+    remove_skipped_chapters(&mut fetched_book, url, path);
 
     // Determine chapters which already exist but have been updated
     // (same identifier, newer date_published)
@@ -55,6 +389,17 @@ fn get_book(url: &str, path: Option<&Path>) -> eyre::Result<(Book, UpdateResult)
         .map(|c| c.identifier.clone())
         .collect();
 
+    // Volumes are given to the chapters already in the book when the source groups them later.
+    let mut volumes_changed = false;
+    for current in &mut current_book.chapters {
+        if let Some(fetched) = fetched_book.chapters.iter().find(|f| *f == &*current) {
+            if fetched.volume != current.volume {
+                current.volume.clone_from(&fetched.volume);
+                volumes_changed = true;
+            }
+        }
+    }
+
     // Determine new chapters
     fetched_book
         .chapters
@@ -67,50 +412,577 @@ fn get_book(url: &str, path: Option<&Path>) -> eyre::Result<(Book, UpdateResult)
     // Add new chapters to the current book
     current_book.chapters.append(&mut fetched_book.chapters);
 
-    let nb_new_chapter = u16::try_from(chapter_to_update_ids.len()).map_err(|_| {
+    Ok(Changes {
+        current_book,
+        fetched_book,
+        chapter_to_update_ids,
+        volumes_changed,
+        metadata_fetched,
+    })
+}
+
+/// Whether the metadata of the book was last fetched too long ago (or never) to keep
+/// updating it from its feed.
+fn is_metadata_stale(book: &Book) -> bool {
+    Cache::read_metadata_check(book)
+        .ok()
+        .flatten()
+        .is_none_or(|date| Utc::now() - date > METADATA_REFRESH_INTERVAL)
+}
+
+/// Record the metadata fetch of the book once it is written, so that the next updates are built
+/// from its feed : a check or a failed update does not put off the next refresh.
+fn record_metadata_check(book: &Book, metadata_fetched: Option<DateTime<Utc>>) {
+    let Some(date) = metadata_fetched else {
+        return;
+    };
+    if let Err(e) = Cache::write_metadata_check(book, date) {
+        MULTI_PROGRESS.eprintln(&format!("Could not record the metadata fetch : {e}"));
+    }
+}
+
+/// Number of chapters an update of the book at `path` would download.
+fn pending_chapters(path: &Path) -> eyre::Result<usize> {
+    let url = EpubDoc::new(path)?
+        .mdata("source")
+        .ok_or_eyre("Could not find url")?;
+    let current_book = Book::from_path(&url, path)?;
+    let last_modified = source_validator(&url);
+    if last_modified.is_some() && current_book.last_modified == last_modified {
+        return Ok(0);
+    }
+    Ok(find_changes(&url, Some(path), Some(current_book))?
+        .chapter_to_update_ids
+        .len())
+}
+
+/// The book of `url` with its new chapters, updating the one at `path` if any. When `checkpoint`,
+/// the downloaded chapters are written to `path` every `CHECKPOINT_CHAPTERS`.
+#[allow(clippy::too_many_lines)]
+fn get_book(url: &str, path: Option<&Path>, checkpoint: bool) -> eyre::Result<UpdatedBook> {
+    let current_book = path.and_then(|path| Book::from_path(url, path).ok());
+
+    let last_modified = source_validator(url);
+    if let Some(current_book) = current_book
+        .as_ref()
+        .filter(|b| last_modified.is_some() && b.last_modified == last_modified)
+    {
+        return Ok(UpdatedBook {
+            book: current_book.clone(),
+            result: UpdateResult::UpToDate,
+            metadata_fetched: None,
+        });
+    }
+
+    let Changes {
+        mut current_book,
+        fetched_book,
+        chapter_to_update_ids,
+        volumes_changed,
+        metadata_fetched,
+    } = find_changes(url, path, current_book)?;
+
+    let mut nb_new_chapter = u16::try_from(chapter_to_update_ids.len()).map_err(|_| {
         eyre!("There is way too many new chapters (more than 50_000), something probably got wrong")
     })?;
 
     let bar = MULTI_PROGRESS.add(get_progress_bar(nb_new_chapter.into(), 5));
     bar.set_prefix(current_book.title.clone());
+    let source = source::get(url);
+    let source = source.as_ref();
 
-    // Update them in the current book
-    current_book
+    // Update them in the current book, a few at a time as the rate limiter spaces the requests.
+    let pending: Vec<usize> = current_book
         .chapters
-        .iter_mut()
-        .filter(|c| chapter_to_update_ids.contains(&c.identifier))
-        .for_each(|chapter| {
-            if let Err(e) = chapter.update_chapter_content() {
-                bar.eprintln(&format!(
-                    "Could not download chapter '{}' : {}",
-                    chapter.title, e
-                ));
-            };
-            bar.inc(1);
-        });
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| chapter_to_update_ids.contains(&c.identifier))
+        .map(|(index, _)| index)
+        .collect();
+    let nb_failed = download_chapters(
+        &mut current_book,
+        &pending,
+        source,
+        path.filter(|_| checkpoint),
+        &bar,
+    );
     bar.finish_and_clear();
+    nb_new_chapter = nb_new_chapter.saturating_sub(u16::try_from(nb_failed).unwrap_or(u16::MAX));
+
+    // Reposted chapters come back under a new identifier.
+    let duplicates: HashSet<String> =
+        dedupe::find_duplicates(&current_book.chapters, &chapter_to_update_ids)
+            .into_iter()
+            .map(|(duplicate, original)| {
+                warn(
+                    url,
+                    Warning::Repost {
+                        title: duplicate.title.clone(),
+                        original: original.to_string(),
+                    },
+                );
+                duplicate.identifier.clone()
+            })
+            .collect();
+    if Config::get().dedupe_chapters && !duplicates.is_empty() {
+        current_book
+            .chapters
+            .retain(|c| !duplicates.contains(&c.identifier));
+        nb_new_chapter =
+            nb_new_chapter.saturating_sub(u16::try_from(duplicates.len()).unwrap_or(u16::MAX));
+    }
+
+    let result = if nb_new_chapter > 0 {
+        UpdateResult::Updated(nb_new_chapter)
+    } else {
+        unchaptered_result(&current_book, &fetched_book, volumes_changed)
+    };
 
-    // Update the cover URL and resave to cache.
+    // Update the metadata and cover URL and resave to cache.
+    current_book.title = fetched_book.title;
+    current_book.author = fetched_book.author;
+    current_book.description = fetched_book.description;
     current_book.cover_url = fetched_book.cover_url;
+    current_book.content_warnings = fetched_book.content_warnings;
+    current_book.tags = fetched_book.tags;
+    current_book.status = fetched_book.status;
+    current_book.rating_label = fetched_book.rating_label;
+    current_book.last_modified = last_modified;
 
-    Ok((
-        current_book,
-        if nb_new_chapter > 0 {
-            UpdateResult::Updated(nb_new_chapter)
-        } else {
-            UpdateResult::UpToDate
-        },
-    ))
+    Ok(UpdatedBook {
+        book: current_book,
+        result,
+        metadata_fetched,
+    })
+}
+
+/// Download the content of the chapters of `book` at `pending` (sorted indices), writing the
+/// downloaded ones to `checkpoint` every `CHECKPOINT_CHAPTERS` if given. The chapters which could
+/// not be downloaded are left out, to be retried by the next update or when resuming a creation :
+/// returns their number.
+fn download_chapters(
+    book: &mut Book,
+    pending: &[usize],
+    source: &dyn Source,
+    checkpoint: Option<&Path>,
+    bar: &ProgressBar,
+) -> usize {
+    let batch_size = if checkpoint.is_some() {
+        CHECKPOINT_CHAPTERS
+    } else {
+        pending.len().max(1)
+    };
+    let nb_batches = pending.len().div_ceil(batch_size);
+    for (nb_batch, batch) in pending.chunks(batch_size).enumerate() {
+        let downloads = book
+            .chapters
+            .iter_mut()
+            .enumerate()
+            .filter(|(index, _)| batch.binary_search(index).is_ok())
+            .map(|(_, chapter)| async move {
+                if let Err(e) = chapter.update_chapter_content(source).await {
+                    bar.eprintln(&format!(
+                        "Could not download chapter '{}' : {}",
+                        chapter.title, e
+                    ));
+                }
+                bar.inc(1);
+            });
+        request::block_on(
+            stream::iter(downloads)
+                .buffer_unordered(Config::get().chapter_concurrency.max(1))
+                .collect::<Vec<()>>(),
+        );
+
+        if let Some(path) = checkpoint.filter(|_| nb_batch + 1 < nb_batches) {
+            let downloaded = Book {
+                chapters: book
+                    .chapters
+                    .iter()
+                    .filter(|c| c.content.is_some())
+                    .cloned()
+                    .collect(),
+                ..book.clone_without_chapters()
+            };
+            if let Err(e) = epub::write(&downloaded, path.to_str().map(String::from)) {
+                bar.eprintln(&format!("Could not write the downloaded chapters : {e}"));
+            }
+        }
+    }
+
+    let nb_failed = pending
+        .iter()
+        .filter(|&&index| book.chapters[index].content.is_none())
+        .count();
+    book.chapters.retain(|c| c.content.is_some());
+    nb_failed
+}
+
+/// Result of an update without new chapters, from the book as it is and as it was fetched.
+fn unchaptered_result(current: &Book, fetched: &Book, volumes_changed: bool) -> UpdateResult {
+    // Books written before the cover URL was recorded do not know theirs.
+    let cover_changed = !current.cover_url.is_empty() && current.cover_url != fetched.cover_url;
+    let metadata_changed = current.title != fetched.title
+        || current.author != fetched.author
+        || current.description != fetched.description
+        || current.tags != fetched.tags
+        || current.status != fetched.status
+        || volumes_changed;
+    if cover_changed {
+        UpdateResult::CoverUpdated
+    } else if metadata_changed {
+        UpdateResult::MetadataUpdated
+    } else {
+        UpdateResult::UpToDate
+    }
 }
 
 fn do_update(path: &Path) -> eyre::Result<UpdateResult> {
+    check_readable(path)?;
     let url = EpubDoc::new(path)?
         .mdata("source")
         .ok_or_eyre("Could not find url")?;
 
-    let (book, result) = get_book(&url, Some(path))?;
-    if let UpdateResult::Updated(_) = result {
+    let format = EpubDoc::new(path)?
+        .mdata(FORMAT_META)
+        .and_then(|f| f.parse().ok())
+        .unwrap_or(1);
+
+    let UpdatedBook {
+        book,
+        result,
+        metadata_fetched,
+    } = get_book(&url, Some(path), false)?;
+    let result = if matches!(
+        result,
+        UpdateResult::Updated(_) | UpdateResult::MetadataUpdated | UpdateResult::CoverUpdated
+    ) {
         epub::write(&book, path.to_str().map(String::from))?;
-    }
+        result
+    } else if matches!(result, UpdateResult::UpToDate) && format < FORMAT_VERSION {
+        // Read back as any other book, the older layouts are rewritten as the current one.
+        epub::write(&book, path.to_str().map(String::from))?;
+        UpdateResult::FormatUpgraded
+    } else {
+        result
+    };
+    // Up to date, the book already has the fetched metadata.
+    record_metadata_check(&book, metadata_fetched);
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn metadata_and_cover_updates() {
+        let current = Book {
+            title: String::from("A Book"),
+            cover_url: String::from("https://www.royalroadcdn.com/cover.jpg"),
+            ..Book::default()
+        };
+        let fetched = |edit: fn(&mut Book)| {
+            let mut fetched = current.clone_without_chapters();
+            edit(&mut fetched);
+            fetched
+        };
+        let result = |fetched: &Book| unchaptered_result(&current, fetched, false);
+
+        assert!(matches!(result(&fetched(|_| ())), UpdateResult::UpToDate));
+        assert!(matches!(
+            unchaptered_result(&current, &fetched(|_| ()), true),
+            UpdateResult::MetadataUpdated
+        ));
+        assert!(matches!(
+            result(&fetched(|b| b.description = String::from("New"))),
+            UpdateResult::MetadataUpdated
+        ));
+        assert!(matches!(
+            result(&fetched(|b| {
+                b.title = String::from("Renamed");
+                b.cover_url = String::from("https://www.royalroadcdn.com/new.jpg");
+            })),
+            UpdateResult::CoverUpdated
+        ));
+        // Books which do not know their cover URL cannot tell whether it changed.
+        let unknown_cover = Book {
+            cover_url: String::new(),
+            ..current.clone_without_chapters()
+        };
+        assert!(matches!(
+            unchaptered_result(&unknown_cover, &fetched(|_| ()), false),
+            UpdateResult::UpToDate
+        ));
+    }
+
+    #[test]
+    fn chapters_are_matched_by_title() {
+        let chapter = |identifier: &str, title: &str| epub::Chapter {
+            identifier: identifier.to_owned(),
+            title: title.to_owned(),
+            url: format!("https://www.royalroad.com/fiction/1/a/chapter/{identifier}/a"),
+            ..epub::Chapter::default()
+        };
+        let mut book = Book {
+            description: String::from("Kept"),
+            chapters: vec![chapter("0", "Prologue"), chapter("1", "Interlude")],
+            ..Book::default()
+        };
+        let reference = Book {
+            cover_url: String::from("https://www.royalroadcdn.com/cover.jpg"),
+            description: String::from("Ignored"),
+            chapters: vec![chapter("10", "Prologue"), chapter("11", "Chapter 1")],
+            ..Book::default()
+        };
+
+        match_reference(&mut book, reference);
+
+        assert_eq!(book.chapters[0].identifier, "10");
+        assert!(book.chapters[0].url.contains("/chapter/10/"));
+        assert_eq!(book.chapters[1].identifier, "1");
+        assert_eq!(book.cover_url, "https://www.royalroadcdn.com/cover.jpg");
+        assert_eq!(book.description, "Kept");
+    }
+
+    #[test]
+    fn rerendering_keeps_the_chapters_and_cover() -> Result<()> {
+        let mut cover = Vec::new();
+        ::image::DynamicImage::new_rgb8(4, 2).write_to(
+            &mut std::io::Cursor::new(&mut cover),
+            ::image::ImageFormat::Png,
+        )?;
+        let chapter = |n: u32| epub::Chapter {
+            identifier: n.to_string(),
+            title: format!("Chapter {n}"),
+            url: format!("https://www.royalroad.com/fiction/1/book/chapter/{n}"),
+            content: Some(format!("<p>Content {n}</p>")),
+            ..epub::Chapter::default()
+        };
+        let book = Book {
+            id: 1,
+            url: "https://www.royalroad.com/fiction/1/book".to_string(),
+            title: "Book".to_string(),
+            author: "Author".to_string(),
+            cover_url: format!("data:image/png;base64,{}", STANDARD.encode(&cover)),
+            chapters: (1..=3).map(chapter).collect(),
+            ..Book::default()
+        };
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(epub::default_filename(&book));
+        epub::write(&book, path.to_str().map(String::from))?;
+
+        Native.rerender(&path)?;
+
+        let rerendered = Book::from_path(&book.url, &path)?;
+        let titles = |book: &Book| -> Vec<String> {
+            book.chapters.iter().map(|c| c.title.clone()).collect()
+        };
+        assert_eq!(titles(&rerendered), titles(&book));
+        let (cover, _) = EpubDoc::new(&path)?
+            .get_cover()
+            .ok_or_eyre("The cover was lost")?;
+        let cover = ::image::load_from_memory(&cover)?;
+        assert_eq!(cover.width(), cover.height() * 2);
+        Ok(())
+    }
+
+    #[test]
+    fn stubs_only_have_their_title_page() -> Result<()> {
+        let book = test_util::book_from_fixture("sample_book")?;
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(epub::default_filename(&book));
+        write_stub(book, &path)?;
+
+        assert!(!EpubDoc::new(&path)?.spine.is_empty());
+        assert_eq!(crate::Book::new(&path).nb_chapters(), Some(0));
+        Ok(())
+    }
+
+    #[test]
+    fn interrupted_creations_are_resumed() -> Result<()> {
+        let book = test_util::book_from_fixture("sample_book")?;
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(epub::default_filename(&book));
+        assert!(!is_resumable(&path, &book.url));
+
+        write_stub(book.clone_without_chapters(), &path)?;
+        assert!(is_resumable(&path, &book.url));
+        assert!(!is_resumable(&path, "https://example.com/fiction/1/other"));
+        assert!(Book::from_path(&book.url, &path)?.chapters.is_empty());
+        Ok(())
+    }
+
+    /// Source whose chapters come from its API, but for the ones titled "Lost".
+    struct LosingSource;
+
+    impl Source for LosingSource {
+        fn new(_url: &str) -> Option<Self> {
+            None
+        }
+        fn capabilities() -> source::Capabilities {
+            source::Unsupported::capabilities()
+        }
+        fn chapter_content<'a>(
+            &self,
+            chapter: &'a Chapter,
+        ) -> Option<futures_util::future::BoxFuture<'a, Result<String>>> {
+            Some(Box::pin(async move {
+                if chapter.title == "Lost" {
+                    Err(eyre!("Not found"))
+                } else {
+                    Ok(format!("<p>{}</p>", chapter.title))
+                }
+            }))
+        }
+    }
+
+    #[test]
+    fn failed_chapters_are_left_out() {
+        let chapter = |identifier: &str, title: &str| Chapter {
+            identifier: identifier.to_owned(),
+            title: title.to_owned(),
+            ..Chapter::default()
+        };
+        let mut book = Book {
+            chapters: vec![
+                Chapter {
+                    content: Some(String::from("<p>Kept</p>")),
+                    ..chapter("1", "Kept")
+                },
+                chapter("2", "Lost"),
+                chapter("3", "New"),
+            ],
+            ..Book::default()
+        };
+
+        let nb_failed = download_chapters(
+            &mut book,
+            &[1, 2],
+            &LosingSource,
+            None,
+            &ProgressBar::hidden(),
+        );
+
+        assert_eq!(nb_failed, 1);
+        let titles: Vec<&str> = book.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, ["Kept", "New"]);
+        assert_eq!(book.chapters[1].content.as_deref(), Some("<p>New</p>"));
+    }
+
+    #[test]
+    fn skipped_chapters() {
+        let patterns = vec![String::from(r"announcement|\bpoll\b"), String::from("[")];
+        assert!(is_skipped("Important Announcement", &patterns));
+        assert!(is_skipped("POLL: next arc", &patterns));
+        assert!(!is_skipped("Chapter 12 - Polling the crowd", &patterns));
+        assert!(!is_skipped("Chapter 1", &[]));
+    }
+
+    #[test]
+    fn chapters_skipped_by_the_directory_settings() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::write(
+            dir.path().join(".autebook.toml"),
+            "skip_chapters = [\"announcement\"]",
+        )?;
+        let path = dir.path().join("book.epub");
+        let book = || Book {
+            chapters: ["Chapter 1", "Announcement: hiatus", "Chapter 2"]
+                .iter()
+                .map(|title| Chapter {
+                    title: (*title).to_string(),
+                    ..Chapter::default()
+                })
+                .collect(),
+            ..Book::default()
+        };
+        let titles = |book: &Book| -> Vec<String> {
+            book.chapters.iter().map(|c| c.title.clone()).collect()
+        };
+
+        let mut royal_road_book = book();
+        let url = "https://www.royalroad.com/fiction/12345/book";
+        remove_skipped_chapters(&mut royal_road_book, url, Some(&path));
+        assert_eq!(titles(&royal_road_book), ["Chapter 1", "Chapter 2"]);
+
+        // Only the Royal Road chapters are skipped.
+        let mut other_book = book();
+        let url = "https://archiveofourown.org/works/1";
+        remove_skipped_chapters(&mut other_book, url, Some(&path));
+        assert_eq!(titles(&other_book), titles(&book()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_audit_chapters() {
+        let chapter = |id: &str| Chapter {
+            identifier: id.to_string(),
+            title: format!("Chapter {id}"),
+            ..Chapter::default()
+        };
+        let source = [chapter("1"), chapter("2"), chapter("3")];
+        let audit = audit_chapters(&[chapter("2"), chapter("1"), chapter("1")], &source);
+        assert_eq!(audit.missing, ["Chapter 3"]);
+        assert_eq!(audit.duplicated, ["Chapter 1"]);
+        assert!(audit.out_of_order);
+        assert!(audit_chapters(&source, &source).is_consistent());
+    }
+
+    #[test]
+    fn books_are_dated_by_their_last_chapter() -> Result<()> {
+        let book = test_util::book_from_fixture("sample_book")?;
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(epub::default_filename(&book));
+        epub::write(&book, path.to_str().map(String::from))?;
+        assert_eq!(
+            crate::Book::new(&path).last_chapter_date().as_deref(),
+            Some("2024-01-08T00:00:00+00:00")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn pending_chapters_are_counted_without_rewriting() -> Result<()> {
+        let chapters = tempfile::tempdir()?;
+        fs::write(chapters.path().join("1-start.md"), "# Start\n\nHello.")?;
+        let url =
+            local::folder_url(&chapters.path().to_string_lossy()).ok_or_eyre("Not a directory")?;
+        let book = local::fetch_book(&url)?;
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(epub::default_filename(&book));
+        epub::write(&book, path.to_str().map(String::from))?;
+        assert_eq!(pending_chapters(&path)?, 0);
+
+        fs::write(chapters.path().join("2-middle.md"), "# Middle\n\nHm.")?;
+        fs::write(chapters.path().join("3-end.md"), "# The End\n\nBye.")?;
+        let written = fs::read(&path)?;
+        assert_eq!(pending_chapters(&path)?, 2);
+        assert_eq!(fs::read(&path)?, written);
+        Ok(())
+    }
+
+    #[test]
+    fn older_formats_are_upgraded() -> Result<()> {
+        let chapters = tempfile::tempdir()?;
+        fs::write(chapters.path().join("1-start.md"), "# Start\n\nHello.")?;
+        let url =
+            local::folder_url(&chapters.path().to_string_lossy()).ok_or_eyre("Not a directory")?;
+        let book = local::fetch_book(&url)?;
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(epub::default_filename(&book));
+        epub::write(&book, path.to_str().map(String::from))?;
+        let format = || EpubDoc::new(&path).ok()?.mdata(FORMAT_META);
+        assert_eq!(format(), Some(FORMAT_VERSION.to_string()));
+
+        crate::updater::edit_opf(&path, |opf| {
+            Ok(opf.replace(&format!("name=\"{FORMAT_META}\""), "name=\"unrelated\""))
+        })?;
+        assert_eq!(format(), None);
+        assert!(matches!(do_update(&path)?, UpdateResult::FormatUpgraded));
+        assert_eq!(format(), Some(FORMAT_VERSION.to_string()));
+        assert!(matches!(do_update(&path)?, UpdateResult::UpToDate));
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+//! Books of the sources provided by plugins, built by the native backend.
+
+use eyre::Result;
+
+use crate::error::Error;
+use crate::profile;
+use crate::source::plugin::{self, PluginBook, PluginChapter, Request};
+use crate::updater::native::epub::{Book, Chapter};
+use crate::updater::native::generic;
+
+pub fn fetch_book(url: &str) -> Result<Book> {
+    let plugin =
+        profile::plugin_for(url).ok_or_else(|| Error::Parse(format!("No plugin for {url}")))?;
+    let book: PluginBook = plugin::call(plugin, Request::Book { url })?;
+    Ok(Book {
+        id: generic::book_id(url),
+        url: url.to_string(),
+        title: book.title,
+        author: book.author,
+        description: book.description,
+        date_published: book.date_published.to_rfc3339(),
+        cover_url: book.cover_url,
+        rating_label: book.rating,
+        chapters: book
+            .chapters
+            .into_iter()
+            .map(|chapter| Chapter {
+                identifier: chapter.identifier,
+                date_published: chapter.date_published,
+                title: chapter.title,
+                url: chapter.url,
+                volume: chapter.volume,
+                ..Chapter::default()
+            })
+            .collect(),
+        ..Book::default()
+    })
+}
+
+/// Content of the chapter, the plugin running apart from the other downloads.
+pub async fn chapter_content(url: String, identifier: String) -> Result<String> {
+    let plugin =
+        profile::plugin_for(&url).ok_or_else(|| Error::Parse(format!("No plugin for {url}")))?;
+    let chapter: PluginChapter = tokio::task::spawn_blocking(move || {
+        plugin::call(
+            plugin,
+            Request::Chapter {
+                url: &url,
+                identifier: &identifier,
+            },
+        )
+    })
+    .await??;
+    Ok(chapter.content)
+}
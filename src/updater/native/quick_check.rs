@@ -0,0 +1,51 @@
+//! Cheap check of whether a book without a feed changed : a validator of its source (a
+//! modification date given by an API, or else the `Last-Modified` or `ETag` of its page)
+//! is stored in the book, and the book is only fetched again when the validator differs.
+
+use reqwest::header::{ETAG, LAST_MODIFIED};
+
+use crate::updater::native::request::{self, Fetched};
+
+/// Current validator of the page of the book at `url`, if its headers give one.
+pub fn validator(url: &str) -> Option<String> {
+    let response = request::block_on(request::head(url))
+        .and_then(|r| r.error_for_status(url))
+        .ok()?;
+    header_validator(&response)
+}
+
+fn header_validator(response: &Fetched) -> Option<String> {
+    response
+        .headers
+        .get(LAST_MODIFIED)
+        .or_else(|| response.headers.get(ETAG))
+        .and_then(|v| v.to_str().ok())
+        .map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use reqwest::StatusCode;
+
+    #[test]
+    fn test_header_validator() {
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, HeaderValue::from_static("\"abc\""));
+        let mut response = Fetched {
+            status: StatusCode::OK,
+            headers,
+            body: "".into(),
+        };
+        assert_eq!(header_validator(&response).as_deref(), Some("\"abc\""));
+        response.headers.insert(
+            LAST_MODIFIED,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(
+            header_validator(&response).as_deref(),
+            Some("Wed, 21 Oct 2015 07:28:00 GMT")
+        );
+    }
+}
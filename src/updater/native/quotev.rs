@@ -0,0 +1,173 @@
+//! Stories of Quotev, scraped from their page.
+
+use chrono::{DateTime, Utc};
+use eyre::Result;
+use lazy_regex::regex;
+use lazy_static::lazy_static;
+use scraper::{Html, Selector};
+use url::Url;
+
+use crate::error::Error;
+use crate::updater::native::epub::{compile_time_selector, Book, Chapter};
+use crate::updater::native::request;
+
+lazy_static! {
+    static ref TITLE_SELECTOR: Selector = compile_time_selector("#quizHeader h1");
+    static ref AUTHOR_SELECTOR: Selector = compile_time_selector(".quizAuthorList a");
+    static ref DESCRIPTION_SELECTOR: Selector = compile_time_selector("#qdesct");
+    static ref COVER_SELECTOR: Selector = compile_time_selector("#quizHeader img");
+    static ref DATE_SELECTOR: Selector = compile_time_selector("#quizHeader span[ts]");
+    static ref CHAPTER_SELECTOR: Selector = compile_time_selector("#rselectList a[href]");
+    static ref CONTENT_SELECTOR: Selector = compile_time_selector("#rescontent");
+}
+
+/// Canonical URL of the story (`https://www.quotev.com/story/<id>/<slug>`) from the URL of any
+/// of its chapters.
+pub fn story_url(url: &str) -> Option<String> {
+    let captures = regex!(r"^https://(?:www\.)?quotev\.com/story/(\d+)/([\w-]+)").captures(url)?;
+    Some(format!(
+        "https://www.quotev.com/story/{}/{}",
+        &captures[1], &captures[2]
+    ))
+}
+
+/// Story of `url`, with its chapters (without their content).
+pub fn fetch_book(url: &str) -> Result<Book> {
+    let story_url =
+        story_url(url).ok_or_else(|| Error::Parse(format!("Not a Quotev story : {url}")))?;
+    let response = request::block_on(request::get(&story_url))?
+        .error_for_status(&story_url)?
+        .text();
+    let page = Html::parse_document(&response);
+
+    let text_of = |selector: &Selector| {
+        page.select(selector)
+            .next()
+            .map(|e| e.text().collect::<String>().trim().to_string())
+    };
+    let title =
+        text_of(&TITLE_SELECTOR).ok_or_else(|| Error::Parse(String::from("No title found")))?;
+    let author = page
+        .select(&AUTHOR_SELECTOR)
+        .map(|a| a.text().collect::<String>().trim().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let cover_url = page
+        .select(&COVER_SELECTOR)
+        .next()
+        .and_then(|img| img.attr("src"))
+        .and_then(|src| Url::parse(&story_url).ok()?.join(src).ok())
+        .map(String::from)
+        .unwrap_or_default();
+    let (published, updated) = dates(&page);
+
+    Ok(Book {
+        id: story_id(&story_url)?,
+        chapters: chapters(&page, &story_url, &title, published, updated),
+        url: story_url,
+        title,
+        author,
+        description: text_of(&DESCRIPTION_SELECTOR).unwrap_or_default(),
+        date_published: published.to_rfc3339(),
+        cover_url,
+        ..Book::default()
+    })
+}
+
+fn story_id(story_url: &str) -> Result<u32> {
+    Ok(regex!(r"/story/(\d+)/")
+        .captures(story_url)
+        .and_then(|c| c[1].parse().ok())
+        .ok_or_else(|| Error::Parse(format!("No story id in {story_url}")))?)
+}
+
+/// Publication and last update dates of the story, as timestamps of its header.
+fn dates(page: &Html) -> (DateTime<Utc>, DateTime<Utc>) {
+    let dates: Vec<DateTime<Utc>> = page
+        .select(&DATE_SELECTOR)
+        .filter_map(|date| date.attr("ts")?.parse().ok())
+        .filter_map(|t| DateTime::from_timestamp(t, 0))
+        .collect();
+    let published = dates.iter().min().copied().unwrap_or_default();
+    (published, dates.iter().max().copied().unwrap_or(published))
+}
+
+/// Chapters of the story, from its chapter list. The site only dates the story, so the last
+/// chapter gets its update date and the others its publication date : new chapters are
+/// downloaded once, and so is the previous last one.
+fn chapters(
+    page: &Html,
+    story_url: &str,
+    title: &str,
+    published: DateTime<Utc>,
+    updated: DateTime<Utc>,
+) -> Vec<Chapter> {
+    let base = Url::parse(story_url).ok();
+    let mut chapters: Vec<Chapter> = page
+        .select(&CHAPTER_SELECTOR)
+        .filter_map(|link| {
+            let url = base.as_ref()?.join(link.attr("href")?).ok()?;
+            let number = url.path_segments()?.nth(3)?.to_string();
+            Some(Chapter {
+                identifier: number,
+                date_published: published,
+                title: link.text().collect::<String>().trim().to_string(),
+                url: url.to_string(),
+                ..Chapter::default()
+            })
+        })
+        .collect();
+    // Single chapter stories have no list.
+    if chapters.is_empty() {
+        chapters.push(Chapter {
+            identifier: String::from("1"),
+            date_published: published,
+            title: title.to_string(),
+            url: format!("{story_url}/1"),
+            ..Chapter::default()
+        });
+    }
+    if let Some(last) = chapters.last_mut() {
+        last.date_published = updated;
+    }
+    chapters
+}
+
+pub fn chapter_content(page: &Html) -> Option<String> {
+    page.select(&CONTENT_SELECTOR)
+        .next()
+        .map(|e| e.inner_html())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_story_page() {
+        let page = Html::parse_document(
+            "<div id=\"quizHeader\"><h1>A Story</h1>\
+            <span ts=\"1706745600\">Feb 1</span><span ts=\"1704067200\">Jan 1</span></div>\
+            <div id=\"rselectList\"><a href=\"/story/42/a-story/1\">Prologue</a>\
+            <a href=\"/story/42/a-story/2\">The End</a></div>",
+        );
+        let (published, updated) = dates(&page);
+        assert_eq!(published.timestamp(), 1_704_067_200);
+        let chapters = chapters(
+            &page,
+            "https://www.quotev.com/story/42/a-story",
+            "A Story",
+            published,
+            updated,
+        );
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[1].identifier, "2");
+        assert_eq!(chapters[1].url, "https://www.quotev.com/story/42/a-story/2");
+        assert_eq!(chapters[0].date_published, published);
+        assert_eq!(chapters[1].date_published.timestamp(), 1_706_745_600);
+        assert_eq!(
+            story_url("https://quotev.com/story/42/a-story/3").as_deref(),
+            Some("https://www.quotev.com/story/42/a-story")
+        );
+    }
+}
@@ -0,0 +1,434 @@
+//! HTTP requests of the native backend, each spawned on a shared async runtime so that the
+//! requests of a caller (e.g. the chapters of a book) proceed concurrently on its workers,
+//! whichever thread waits for them.
+
+use bytes::Bytes;
+use eyre::Result;
+use governor::{DefaultKeyedRateLimiter, Quota, RateLimiter};
+use reqwest::cookie::Jar;
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, CONTENT_LENGTH, REFERER, USER_AGENT as USER_AGENT_HEADER,
+};
+use reqwest::{Client, Method, StatusCode};
+use std::collections::HashMap;
+use std::future::Future;
+use std::num::NonZeroU32;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tokio::time::Instant;
+use url::Url;
+
+use crate::config::{Config, Fixtures};
+use crate::error::Error;
+use crate::profile;
+use crate::updater::native::{fixtures, robots, session, usage};
+
+pub const USER_AGENT: &str = concat!(
+    "autebooks/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/ValentinLeTallec/AutEBook)"
+);
+/// Waits before asking again for a page withheld by a Cloudflare challenge.
+const CHALLENGE_RETRY_DELAYS: [Duration; 3] = [
+    Duration::from_secs(5),
+    Duration::from_secs(15),
+    Duration::from_secs(45),
+];
+
+/// Response whose body was entirely read.
+#[derive(Debug, Clone)]
+pub struct Fetched {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl Fetched {
+    pub fn error_for_status(self, url: &str) -> Result<Self> {
+        let status = self.status;
+        if status.is_success() {
+            return Ok(self);
+        }
+        Err(match status {
+            StatusCode::NOT_FOUND | StatusCode::GONE => Error::NotFound(url.to_string()),
+            StatusCode::TOO_MANY_REQUESTS => Error::RateLimited(url.to_string()),
+            _ => Error::Network(format!("{status} for {url}")),
+        }
+        .into())
+    }
+
+    /// Whether Cloudflare answered with a challenge page (e.g. "Just a moment...") instead of the page.
+    pub fn is_cloudflare_challenge(&self) -> bool {
+        let header = |name: &str| self.headers.get(name).and_then(|v| v.to_str().ok());
+        if header("cf-mitigated") == Some("challenge") {
+            return true;
+        }
+        matches!(
+            self.status,
+            StatusCode::FORBIDDEN | StatusCode::SERVICE_UNAVAILABLE
+        ) && header("server").is_some_and(|s| s.eq_ignore_ascii_case("cloudflare"))
+            && (self.text().contains("challenge-platform") || self.text().contains("Just a moment"))
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    /// Length announced by the server, or else the length of the received body.
+    pub fn content_length(&self) -> Option<u64> {
+        self.headers
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .or_else(|| u64::try_from(self.body.len()).ok().filter(|len| *len > 0))
+    }
+}
+
+/// Run a future to completion from synchronous code.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    runtime().block_on(future)
+}
+
+#[allow(clippy::expect_used)]
+fn runtime() -> &'static Runtime {
+    static RUNTIME_CELL: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME_CELL.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("Could not start the async runtime")
+    })
+}
+
+pub async fn get(url: &str) -> Result<Fetched> {
+    spawn(Method::GET, url, None).await
+}
+
+pub async fn head(url: &str) -> Result<Fetched> {
+    spawn(Method::HEAD, url, None).await
+}
+
+/// Request `url` as linked from the page at `referer`, which some image hosts require.
+pub async fn get_from(url: &str, referer: &str) -> Result<Fetched> {
+    spawn(Method::GET, url, Some(referer)).await
+}
+
+/// Like `get_from`, for the validators only.
+pub async fn head_from(url: &str, referer: &str) -> Result<Fetched> {
+    spawn(Method::HEAD, url, Some(referer)).await
+}
+
+/// Run the request as a task of the runtime rather than on the thread polling it.
+async fn spawn(method: Method, url: &str, referer: Option<&str>) -> Result<Fetched> {
+    let url = url.to_string();
+    let referer = referer.map(String::from);
+    runtime()
+        .spawn(async move {
+            if Config::get().respect_crawl_delay {
+                wait_for_crawl_delay(&url).await;
+            }
+            send(&method, &url, referer.as_deref()).await
+        })
+        .await?
+}
+
+/// Headers of a request to `url`: the user agent and referer, replaced by the headers the
+/// configuration file sets for the host. Invalid configured headers are ignored.
+fn headers(url: &str, referer: Option<&str>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(USER_AGENT_HEADER, HeaderValue::from_static(USER_AGENT));
+    if let Some(referer) = referer.and_then(|r| HeaderValue::from_str(r).ok()) {
+        headers.insert(REFERER, referer);
+    }
+    for (name, value) in profile::headers_for(url) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            headers.insert(name, value);
+        }
+    }
+    headers
+}
+
+async fn send(method: &Method, url: &str, referer: Option<&str>) -> Result<Fetched> {
+    if let Some(Fixtures::Replay(dir)) = &Config::get().fixtures {
+        return fixtures::replay(dir, method.as_str(), url);
+    }
+    let mut retry_delays = CHALLENGE_RETRY_DELAYS.iter();
+    let fetched = loop {
+        wait_for_rate_limiter(url).await;
+        let response = client()
+            .request(method.clone(), url)
+            .headers(headers(url, referer))
+            .send()
+            .await?;
+        let fetched = Fetched {
+            status: response.status(),
+            headers: response.headers().clone(),
+            body: response.bytes().await?,
+        };
+        usage::record(url, fetched.body.len());
+        if !fetched.is_cloudflare_challenge() {
+            break fetched;
+        }
+        // The challenges are often lifted after a while, else give up on this page.
+        match retry_delays.next() {
+            Some(delay) => tokio::time::sleep(*delay).await,
+            None => {
+                return Err(
+                    Error::Network(format!("Blocked by a Cloudflare challenge : {url}")).into(),
+                )
+            }
+        }
+    };
+    if let Some(Fixtures::Record(dir)) = &Config::get().fixtures {
+        fixtures::record(dir, method.as_str(), url, &fetched)?;
+    }
+    Ok(fetched)
+}
+
+#[allow(clippy::expect_used)]
+fn client() -> &'static Client {
+    static CLIENT_CELL: OnceLock<Client> = OnceLock::new();
+    CLIENT_CELL.get_or_init(|| {
+        // Signed in to Royal Road if an account is set up.
+        let jar = Arc::new(Jar::default());
+        session::add_to(&jar);
+        Client::builder()
+            .cookie_provider(jar)
+            .build()
+            .expect("Could not build the HTTP client")
+    })
+}
+
+/// Wait for the rate limit of the host of `url`.
+pub async fn wait_for_rate_limiter(url: &str) {
+    static RATE_LIMITER_CELL: OnceLock<DefaultKeyedRateLimiter<String>> = OnceLock::new();
+
+    #[allow(clippy::unwrap_used)]
+    let rate_limiter = RATE_LIMITER_CELL.get_or_init(|| {
+        RateLimiter::keyed(
+            Quota::per_second(NonZeroU32::new(5u32).unwrap())
+                .allow_burst(NonZeroU32::new(1u32).unwrap()),
+        )
+    });
+
+    let host = Url::parse(url)
+        .ok()
+        .and_then(|u| u.host().map(|h| h.to_string()))
+        .unwrap_or_default();
+
+    rate_limiter.until_key_ready(&host).await;
+}
+
+/// Space the requests to a host according to the `Crawl-delay` of its robots.txt.
+async fn wait_for_crawl_delay(url: &str) {
+    static CRAWL_DELAYS: OnceLock<Mutex<HashMap<String, Option<Duration>>>> = OnceLock::new();
+    static NEXT_REQUESTS: OnceLock<Mutex<HashMap<String, Instant>>> = OnceLock::new();
+
+    let Ok(url) = Url::parse(url) else {
+        return;
+    };
+    let origin = url.origin().ascii_serialization();
+
+    let crawl_delays = CRAWL_DELAYS.get_or_init(Mutex::default);
+    let cached_delay = crawl_delays
+        .lock()
+        .ok()
+        .and_then(|delays| delays.get(&origin).copied());
+    let delay = if let Some(delay) = cached_delay {
+        delay
+    } else {
+        let delay = fetch_crawl_delay(&origin).await;
+        if let Ok(mut delays) = crawl_delays.lock() {
+            delays.insert(origin.clone(), delay);
+        }
+        delay
+    };
+    let Some(delay) = delay else {
+        return;
+    };
+
+    // Book the next slot of the host, then wait for ours.
+    let slot = {
+        let Ok(mut next_requests) = NEXT_REQUESTS.get_or_init(Mutex::default).lock() else {
+            return;
+        };
+        let now = Instant::now();
+        let slot = next_requests
+            .get(&origin)
+            .copied()
+            .filter(|next| *next > now)
+            .unwrap_or(now);
+        next_requests.insert(origin, slot + delay);
+        slot
+    };
+    tokio::time::sleep_until(slot).await;
+}
+
+/// Fetched like the other pages, except for the crawl delay it tells.
+async fn fetch_crawl_delay(origin: &str) -> Option<Duration> {
+    let response = send(&Method::GET, &format!("{origin}/robots.txt"), None)
+        .await
+        .ok()
+        .filter(|r| r.status.is_success())?;
+    robots::parse_crawl_delay(&response.text(), USER_AGENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Category;
+    use reqwest::header::SERVER;
+
+    fn fetched(status: StatusCode, body: &'static str) -> Fetched {
+        Fetched {
+            status,
+            headers: HeaderMap::new(),
+            body: body.into(),
+        }
+    }
+
+    #[test]
+    fn test_responses() {
+        let page = fetched(StatusCode::OK, "<p>Text</p>");
+        assert_eq!(page.content_length(), Some(11));
+        assert!(page.error_for_status("https://example.com").is_ok());
+
+        let category = |status| {
+            fetched(status, "")
+                .error_for_status("https://example.com")
+                .err()
+                .as_ref()
+                .and_then(Category::of)
+        };
+        assert_eq!(category(StatusCode::GONE), Some(Category::NotFound));
+        assert_eq!(
+            category(StatusCode::TOO_MANY_REQUESTS),
+            Some(Category::RateLimited)
+        );
+        assert_eq!(category(StatusCode::BAD_GATEWAY), Some(Category::Network));
+        assert_eq!(fetched(StatusCode::NO_CONTENT, "").content_length(), None);
+    }
+
+    #[test]
+    fn test_headers() {
+        let image = "https://example.com/map.png";
+        let sent = headers(image, Some("https://example.com/fiction/1/chapter/2"));
+        assert_eq!(
+            sent.get(REFERER).and_then(|v| v.to_str().ok()),
+            Some("https://example.com/fiction/1/chapter/2")
+        );
+        assert_eq!(
+            sent.get(USER_AGENT_HEADER).and_then(|v| v.to_str().ok()),
+            Some(USER_AGENT)
+        );
+        assert!(headers(image, None).get(REFERER).is_none());
+    }
+
+    #[test]
+    fn test_block_on() {
+        // The spawned tasks run on the shared runtime, whichever thread waits for them.
+        let tasks = (0..4).map(|i| runtime().spawn(async move { i * 2 }));
+        let doubled = block_on(futures_util::future::join_all(tasks));
+        assert_eq!(
+            doubled.into_iter().flatten().collect::<Vec<_>>(),
+            [0, 2, 4, 6]
+        );
+    }
+
+    #[test]
+    fn test_cloudflare_challenge() {
+        let mut headers = HeaderMap::new();
+        headers.insert(SERVER, HeaderValue::from_static("cloudflare"));
+        let challenge = Fetched {
+            status: StatusCode::FORBIDDEN,
+            headers: headers.clone(),
+            body: "<title>Just a moment...</title>".into(),
+        };
+        assert!(challenge.is_cloudflare_challenge());
+
+        let not_found = Fetched {
+            status: StatusCode::NOT_FOUND,
+            headers,
+            body: "<title>Story Not Found</title>".into(),
+        };
+        assert!(!not_found.is_cloudflare_challenge());
+    }
+
+    /// Answer the next requests made to the returned URL with `responses` (status line, extra
+    /// headers and body), the handle gives back the number of requests answered.
+    fn serve(
+        responses: Vec<(&'static str, &'static str, &'static str)>,
+    ) -> Result<(String, std::thread::JoinHandle<std::io::Result<usize>>)> {
+        use std::io::{BufRead, BufReader, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/s/1", listener.local_addr()?);
+        let handle = std::thread::spawn(move || {
+            for (status, headers, body) in &responses {
+                let (mut stream, _) = listener.accept()?;
+                let mut line = String::new();
+                let mut reader = BufReader::new(&stream);
+                while reader.read_line(&mut line)? > 2 {
+                    line.clear();
+                }
+                write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )?;
+            }
+            Ok(responses.len())
+        });
+        Ok((url, handle))
+    }
+
+    /// Run `send` on a runtime whose clock only moves forward when it has nothing else to do.
+    fn send_paused(url: &str) -> Result<Result<Fetched>> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .start_paused(true)
+            .build()?;
+        Ok(runtime.block_on(send(&Method::GET, url, None)))
+    }
+
+    const CHALLENGE: (&str, &str, &str) = (
+        "403 Forbidden",
+        "Server: cloudflare\r\n",
+        "<title>Just a moment...</title>",
+    );
+
+    #[test]
+    fn test_cloudflare_challenge_is_retried() -> Result<()> {
+        let (url, server) = serve(vec![CHALLENGE, CHALLENGE, ("200 OK", "", "<p>Chapter</p>")])?;
+
+        let fetched = send_paused(&url)??;
+
+        assert_eq!(fetched.status, StatusCode::OK);
+        assert_eq!(fetched.text(), "<p>Chapter</p>");
+        let answered = server
+            .join()
+            .map_err(|_| eyre::eyre!("The server panicked"))??;
+        assert_eq!(answered, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cloudflare_challenge_gives_up() -> Result<()> {
+        let (url, server) = serve(vec![CHALLENGE; CHALLENGE_RETRY_DELAYS.len() + 1])?;
+
+        let error = send_paused(&url)?.map(|_| ()).map_err(|e| e.to_string());
+
+        assert_eq!(
+            error,
+            Err(format!("Blocked by a Cloudflare challenge : {url}"))
+        );
+        server
+            .join()
+            .map_err(|_| eyre::eyre!("The server panicked"))??;
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+/// Extract the `Crawl-delay` applying to `user_agent` from a robots.txt,
+/// the rules of a group naming the user agent take precedence over the `*` ones.
+pub fn parse_crawl_delay(robots_txt: &str, user_agent: &str) -> Option<Duration> {
+    let user_agent = user_agent.to_lowercase();
+    let mut group_agents: Vec<String> = Vec::new();
+    let mut in_rules = false;
+    let mut specific_delay = None;
+    let mut generic_delay = None;
+
+    for line in robots_txt.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim().to_lowercase().as_str() {
+            "user-agent" => {
+                // A user agent line following rules starts a new group.
+                if in_rules {
+                    group_agents.clear();
+                    in_rules = false;
+                }
+                group_agents.push(value.to_lowercase());
+            }
+            "crawl-delay" => {
+                in_rules = true;
+                let delay = value
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(|s| Duration::try_from_secs_f64(s).ok());
+                if group_agents
+                    .iter()
+                    .any(|a| a != "*" && user_agent.contains(a.as_str()))
+                {
+                    specific_delay = specific_delay.or(delay);
+                } else if group_agents.iter().any(|a| a == "*") {
+                    generic_delay = generic_delay.or(delay);
+                }
+            }
+            _ => in_rules = true,
+        }
+    }
+    specific_delay.or(generic_delay)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS_TXT: &str = "
+User-agent: *
+Disallow: /admin # private
+Crawl-delay: 2
+
+User-agent: Googlebot
+User-agent: autebooks
+Crawl-delay: 0.5
+";
+
+    #[test]
+    fn test_parse_crawl_delay() {
+        assert_eq!(
+            parse_crawl_delay(ROBOTS_TXT, crate::updater::native::request::USER_AGENT),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(
+            parse_crawl_delay(ROBOTS_TXT, "other"),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(parse_crawl_delay("User-agent: *\nDisallow:", "other"), None);
+    }
+
+    #[test]
+    fn test_crawl_delay_of_other_agents() {
+        let robots_txt = "
+User-agent: Googlebot
+Crawl-delay: 10
+
+User-agent: *
+Crawl-delay: soon
+";
+        // Neither the delay of another crawler nor an invalid one slows the requests down.
+        assert_eq!(parse_crawl_delay(robots_txt, "autebooks"), None);
+        assert_eq!(
+            parse_crawl_delay(robots_txt, "Googlebot/2.1"),
+            Some(Duration::from_secs(10))
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use chrono::DateTime;
+use eyre::{bail, Result};
+use lazy_regex::regex;
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::source;
+use crate::updater::native::epub::{Book, Chapter};
+use crate::updater::native::request;
+
+/// Royal Road feed of the latest chapters of a fiction.
+pub fn feed_url(book: &Book) -> String {
+    format!("https://www.royalroad.com/fiction/syndication/{}", book.id)
+}
+
+pub fn royal_road_chapter_id(link: &str) -> Option<String> {
+    regex!(r"/chapter/(\d+)")
+        .captures(link)
+        .map(|c| c[1].to_string())
+}
+
+/// Chapters published since the last one of `book`, if its source has a feed which still
+/// lists it, so that routine updates do not need the fiction's page.
+pub fn chapter_delta(book: &Book) -> Option<Vec<Chapter>> {
+    let last_known = book.chapters.last()?;
+    let source = source::get(&book.url);
+    let feed_url = source.feed_url(book)?;
+    let chapter_id = |link: &str| source.chapter_id(link);
+    let feed = request::block_on(request::get(&feed_url))
+        .and_then(|r| r.error_for_status(&book.url))
+        .ok()?
+        .text();
+    let items = parse_feed(&feed, &book.title, &chapter_id).ok()?;
+
+    // A gap wider than the feed needs the complete list of chapters.
+    let position = items
+        .iter()
+        .position(|c| c.identifier == last_known.identifier)?;
+    let mut delta: Vec<Chapter> = items
+        .into_iter()
+        .take(position)
+        .filter(|c| !book.chapters.contains(c))
+        .collect();
+    // The feed lists the newest chapters first.
+    delta.reverse();
+    Some(delta)
+}
+
+/// Chapters of the feed, in its order.
+fn parse_feed(
+    xml: &str,
+    book_title: &str,
+    chapter_id: &dyn Fn(&str) -> Option<String>,
+) -> Result<Vec<Chapter>> {
+    let title_prefix = format!("{book_title} - ");
+    let mut chapters = Vec::new();
+    let mut item: Option<Chapter> = None;
+    let mut text = String::new();
+
+    for event in EventReader::from_str(xml) {
+        let event = event?;
+        if let XmlEvent::StartElement { name, .. } = &event {
+            if name.local_name == "item" {
+                item = Some(Chapter::default());
+            }
+            text.clear();
+        } else if let XmlEvent::Characters(s) | XmlEvent::CData(s) = &event {
+            text.push_str(s);
+        } else if let XmlEvent::EndElement { name } = &event {
+            let Some(chapter) = &mut item else {
+                continue;
+            };
+            let value = text.trim();
+            match name.local_name.as_str() {
+                "title" => {
+                    chapter.title = value
+                        .strip_prefix(&title_prefix)
+                        .unwrap_or(value)
+                        .to_string();
+                }
+                "link" => {
+                    chapter.url = value.to_string();
+                    chapter.identifier = chapter_id(value).unwrap_or_default();
+                }
+                "pubDate" => {
+                    if let Ok(date) = DateTime::parse_from_rfc2822(value) {
+                        chapter.date_published = date.into();
+                    }
+                }
+                "item" => {
+                    if let Some(chapter) = item.take() {
+                        if chapter.identifier.is_empty() {
+                            bail!("Chapter without identifier in the feed : {}", chapter.url);
+                        }
+                        chapters.push(chapter);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+    Ok(chapters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_feed() -> Result<()> {
+        let feed = r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0"><channel>
+  <title>A Book</title>
+  <item>
+    <title>A Book - Chapter 2</title>
+    <link>https://www.royalroad.com/fiction/1/a-book/chapter/22/chapter-2</link>
+    <pubDate>Mon, 08 Jan 2024 12:00:00 GMT</pubDate>
+  </item>
+  <item>
+    <title>Chapter 1</title>
+    <link>https://www.royalroad.com/fiction/1/a-book/chapter/11/chapter-1</link>
+    <pubDate>Mon, 01 Jan 2024 12:00:00 GMT</pubDate>
+  </item>
+</channel></rss>"#;
+        let chapters = parse_feed(feed, "A Book", &royal_road_chapter_id)?;
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[0].identifier, "22");
+        assert_eq!(chapters[0].title, "Chapter 2");
+        assert_eq!(
+            chapters[1].date_published.to_rfc3339(),
+            "2024-01-01T12:00:00+00:00"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+//! Search of the fictions of a source by their title.
+
+use eyre::Result;
+use lazy_static::lazy_static;
+use scraper::{Html, Selector};
+use url::Url;
+
+use crate::updater::native::epub::compile_time_selector;
+use crate::updater::native::request;
+
+lazy_static! {
+    static ref RESULT_LINK_SELECTOR: Selector =
+        compile_time_selector(".fiction-list-item .fiction-title a[href^=\"/fiction/\"]");
+}
+
+/// Sources which can be searched.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SearchSource {
+    #[default]
+    #[value(name = "royalroad")]
+    RoyalRoad,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    pub title: String,
+    pub url: String,
+}
+
+/// Fictions of `source` whose title matches `query`, in the order given by the source.
+pub fn search(query: &str, source: SearchSource) -> Result<Vec<SearchResult>> {
+    match source {
+        SearchSource::RoyalRoad => {
+            let url = Url::parse_with_params(
+                "https://www.royalroad.com/fictions/search",
+                [("title", query)],
+            )?;
+            let response = request::block_on(request::get(url.as_str()))?
+                .error_for_status(url.as_str())?
+                .text();
+            Ok(royal_road_results(&Html::parse_document(&response)))
+        }
+    }
+}
+
+fn royal_road_results(page: &Html) -> Vec<SearchResult> {
+    page.select(&RESULT_LINK_SELECTOR)
+        .filter_map(|a| {
+            let url = format!("https://www.royalroad.com{}", a.attr("href")?);
+            let title = a.text().collect::<String>().trim().to_string();
+            Some(SearchResult { title, url })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_royal_road_results() {
+        let page = Html::parse_document(
+            "<div class=\"fiction-list-item\">\
+                <h2 class=\"fiction-title\"><a href=\"/fiction/1/a-story\"> A Story </a></h2>\
+            </div>\
+            <a href=\"/fiction/3/another\">Another</a>",
+        );
+        assert_eq!(
+            royal_road_results(&page),
+            [SearchResult {
+                title: String::from("A Story"),
+                url: String::from("https://www.royalroad.com/fiction/1/a-story"),
+            }]
+        );
+    }
+}
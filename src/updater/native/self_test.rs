@@ -0,0 +1,52 @@
+//! Sanity check of the Royal Road parsing against bundled known-good pages, so that broken
+//! selectors (e.g. bad overrides in the configuration file) are caught before any book is
+//! rewritten with missing chapters.
+
+use eyre::{eyre, Result};
+use scraper::Html;
+
+use crate::updater::native::epub::Book;
+
+const FICTION_URL: &str = "https://www.royalroad.com/fiction/1/self-test";
+const FICTION_PAGE: &str = include_str!("./assets/self_test/fiction.html");
+const CHAPTER_PAGE: &str = include_str!("./assets/self_test/chapter.html");
+
+pub fn self_test() -> Result<()> {
+    let book = Book::from_royal_road_page(FICTION_URL, FICTION_PAGE)?;
+    if book.title != "Self Test" || book.author != "Tester" {
+        return Err(eyre!(
+            "The fiction page was misread (title '{}', author '{}')",
+            book.title,
+            book.author
+        ));
+    }
+    if book.chapters.len() != 2 {
+        return Err(eyre!(
+            "{} chapters were found instead of 2",
+            book.chapters.len()
+        ));
+    }
+
+    let mut chapter = book.chapters[0].clone();
+    chapter.read_royal_road_page(&Html::parse_document(CHAPTER_PAGE))?;
+    let has = |html: &Option<String>, text: &str| html.as_deref().is_some_and(|h| h.contains(text));
+    if !has(&chapter.content, "Known-good chapter text.") {
+        return Err(eyre!("The text of the chapter was misread"));
+    }
+    if !has(&chapter.authors_note_start, "Starting note.")
+        || !has(&chapter.authors_note_end, "Ending note.")
+    {
+        return Err(eyre!("The author's notes of the chapter were misread"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_pages_pass() {
+        assert!(self_test().is_ok());
+    }
+}
@@ -0,0 +1,149 @@
+//! Royal Road account, so that the advance chapters of patrons and the follow list are
+//! available : its session cookie is either configured or obtained by `autebooks login`.
+
+use eyre::{bail, eyre, Result};
+use lazy_static::lazy_static;
+use reqwest::cookie::{CookieStore, Jar};
+use reqwest::redirect::Policy;
+use reqwest::Client;
+use scraper::{Html, Selector};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use url::Url;
+
+use crate::profile;
+use crate::updater::native::epub::compile_time_selector;
+use crate::updater::native::request;
+
+const ROYAL_ROAD_URL: &str = "https://www.royalroad.com";
+/// Cookie of a signed-in Royal Road session.
+const SESSION_COOKIE: &str = ".AspNetCore.Identity.Application";
+/// File next to the configuration file storing the session obtained by `autebooks login`.
+const SESSION_FILENAME: &str = "royalroad-session";
+
+lazy_static! {
+    static ref TOKEN_SELECTOR: Selector =
+        compile_time_selector("input[name=\"__RequestVerificationToken\"]");
+}
+
+fn session_file() -> Option<PathBuf> {
+    profile::config_file().map(|f| f.with_file_name(SESSION_FILENAME))
+}
+
+/// Cookies (`name=value; ...`) of the Royal Road session, the configured one first.
+pub fn cookies() -> Option<String> {
+    profile::royal_road_account()
+        .and_then(|account| account.cookie.clone())
+        .or_else(|| {
+            fs::read_to_string(session_file()?)
+                .ok()
+                .map(|s| s.trim().to_string())
+        })
+        .filter(|cookies| !cookies.is_empty())
+}
+
+/// Add the cookies of the Royal Road session to `jar`.
+pub fn add_to(jar: &Jar) {
+    if let Some(cookies) = cookies() {
+        add_cookies(jar, &cookies);
+    }
+}
+
+/// Add the cookies `name=value; ...` to `jar`, for Royal Road only.
+fn add_cookies(jar: &Jar, cookies: &str) {
+    let Ok(url) = Url::parse(ROYAL_ROAD_URL) else {
+        return;
+    };
+    for cookie in cookies.split(';').map(str::trim).filter(|c| !c.is_empty()) {
+        jar.add_cookie_str(&format!("{cookie}; Domain=www.royalroad.com; Path=/"), &url);
+    }
+}
+
+/// Sign in to Royal Road and store the session for the next runs.
+pub fn login(username: &str, password: &str) -> Result<()> {
+    let jar = Arc::new(Jar::default());
+    let client = Client::builder()
+        .cookie_provider(Arc::clone(&jar))
+        .redirect(Policy::none())
+        .user_agent(request::USER_AGENT)
+        .build()?;
+    let login_url = format!("{ROYAL_ROAD_URL}/account/login");
+
+    request::block_on(async {
+        request::wait_for_rate_limiter(&login_url).await;
+        let page = client.get(&login_url).send().await?.text().await?;
+        let token = Html::parse_document(&page)
+            .select(&TOKEN_SELECTOR)
+            .next()
+            .and_then(|input| input.attr("value"))
+            .map(String::from)
+            .ok_or_else(|| eyre!("No login form found on {login_url}"))?;
+        request::wait_for_rate_limiter(&login_url).await;
+        client
+            .post(&login_url)
+            .form(&[
+                ("Email", username),
+                ("Password", password),
+                ("__RequestVerificationToken", token.as_str()),
+                ("ReturnUrl", "/"),
+            ])
+            .send()
+            .await?;
+        Ok::<(), eyre::Report>(())
+    })?;
+
+    let url = Url::parse(ROYAL_ROAD_URL)?;
+    let cookies = jar
+        .cookies(&url)
+        .and_then(|c| c.to_str().ok().map(String::from))
+        .unwrap_or_default();
+    if !cookies.contains(SESSION_COOKIE) {
+        bail!("Could not sign in to Royal Road, check the username and password");
+    }
+    let path = session_file().ok_or_else(|| eyre!("No configuration directory"))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, cookies)?;
+    // The session is as good as the password.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_add_cookies() -> Result<()> {
+        let jar = Jar::default();
+        add_cookies(&jar, &format!("{SESSION_COOKIE}=secret; ; theme=dark"));
+
+        // The cookies are sent in no particular order.
+        let sent = |url: &str| -> Result<BTreeSet<String>> {
+            Ok(jar
+                .cookies(&Url::parse(url)?)
+                .and_then(|c| c.to_str().ok().map(String::from))
+                .unwrap_or_default()
+                .split("; ")
+                .filter(|c| !c.is_empty())
+                .map(String::from)
+                .collect())
+        };
+        assert_eq!(
+            sent("https://www.royalroad.com/fiction/1/chapter/2")?,
+            BTreeSet::from([
+                format!("{SESSION_COOKIE}=secret"),
+                String::from("theme=dark")
+            ])
+        );
+        assert!(sent("https://example.com/")?.is_empty());
+        Ok(())
+    }
+}
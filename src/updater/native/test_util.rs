@@ -0,0 +1,99 @@
+//! Golden-file tests of the e-books written by the native backend.
+//!
+//! A book is built from a fixture JSON (the serialized `epub::Book`, with the content of its
+//! chapters) and written without network access, then the canonical contents of the e-book
+//! are compared with `tests/golden/<name>.snap`. Set `UPDATE_GOLDEN=1` to accept new output.
+#![cfg_attr(not(test), allow(dead_code))]
+
+use eyre::{eyre, Result};
+use sha2::{Digest, Sha256};
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+use crate::updater::native::epub::{self, Book};
+
+fn tests_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+/// Book described by `tests/fixtures/<name>.json`.
+pub fn book_from_fixture(name: &str) -> Result<Book> {
+    let path = tests_dir().join("fixtures").join(format!("{name}.json"));
+    let contents =
+        fs::read_to_string(&path).map_err(|e| eyre!("Could not read {} : {e}", path.display()))?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// Canonical contents of the e-book written for `book` : every file sorted by name,
+/// the text ones in full and the binary ones by their hash.
+pub fn snapshot(book: &Book) -> Result<String> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join(epub::default_filename(book));
+    epub::write(book, Some(path.to_string_lossy().to_string()))?;
+
+    let mut archive = ZipArchive::new(File::open(&path)?)?;
+    let mut names: Vec<String> = archive.file_names().map(ToString::to_string).collect();
+    names.sort();
+
+    let mut snapshot = String::new();
+    for name in names {
+        let mut contents = Vec::new();
+        archive.by_name(&name)?.read_to_end(&mut contents)?;
+        let _ = writeln!(snapshot, "=== {name}");
+        match String::from_utf8(contents) {
+            Ok(text) => {
+                // The version changes with every release, not with the writer.
+                let text = text.replace(env!("CARGO_PKG_VERSION"), "<version>");
+                let _ = writeln!(snapshot, "{}", text.trim_end());
+            }
+            Err(e) => {
+                let bytes = e.into_bytes();
+                let _ = writeln!(
+                    snapshot,
+                    "<{} bytes, sha256 {:x}>",
+                    bytes.len(),
+                    Sha256::digest(&bytes)
+                );
+            }
+        }
+    }
+    Ok(snapshot)
+}
+
+/// Compare `actual` with `tests/golden/<name>.snap`, which is (re)written instead when
+/// `UPDATE_GOLDEN=1`.
+pub fn assert_golden(name: &str, actual: &str) -> Result<()> {
+    let path = tests_dir().join("golden").join(format!("{name}.snap"));
+    if std::env::var_os("UPDATE_GOLDEN").is_some_and(|v| v == "1") {
+        fs::create_dir_all(tests_dir().join("golden"))?;
+        fs::write(&path, actual)?;
+        return Ok(());
+    }
+    let expected = fs::read_to_string(&path).map_err(|e| {
+        eyre!(
+            "Could not read {} ({e}), run with UPDATE_GOLDEN=1 to create it",
+            path.display()
+        )
+    })?;
+    assert_eq!(
+        expected,
+        actual,
+        "The e-book differs from {}, run with UPDATE_GOLDEN=1 if the change is intended",
+        path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_golden_sample_book() -> Result<()> {
+        let book = book_from_fixture("sample_book")?;
+        assert_golden("sample_book", &snapshot(&book)?)
+    }
+}
@@ -0,0 +1,155 @@
+//! Requests made and bytes received per host and per month, kept across runs for the users
+//! on metered connections.
+
+use chrono::Local;
+use eyre::{eyre, Result};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use url::Url;
+
+use super::cache::Cache;
+
+const USAGE_FILENAME: &str = "usage.json";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    pub requests: u64,
+    pub bytes: u64,
+}
+
+impl Usage {
+    const fn add(&mut self, other: Self) {
+        self.requests = self.requests.saturating_add(other.requests);
+        self.bytes = self.bytes.saturating_add(other.bytes);
+    }
+}
+
+/// Usage of each host, by month (`YYYY-MM`).
+pub type Stats = BTreeMap<String, BTreeMap<String, Usage>>;
+
+lazy_static! {
+    /// Usage of this run, not yet added to the usage file.
+    static ref RUN_USAGE: Mutex<Stats> = Mutex::new(Stats::new());
+}
+
+fn usage_path() -> Result<PathBuf> {
+    Ok(Cache::cache_path()?.join(USAGE_FILENAME))
+}
+
+/// Account for a request to `url` which received `bytes`.
+pub fn record(url: &str, bytes: usize) {
+    let host = Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_string))
+        .unwrap_or_default();
+    let month = Local::now().format("%Y-%m").to_string();
+    if let Ok(mut run_usage) = RUN_USAGE.lock() {
+        run_usage
+            .entry(month)
+            .or_default()
+            .entry(host)
+            .or_default()
+            .add(Usage {
+                requests: 1,
+                bytes: u64::try_from(bytes).unwrap_or(u64::MAX),
+            });
+    }
+}
+
+/// Usage of the previous runs.
+pub fn stats() -> Result<Stats> {
+    read_stats(&usage_path()?)
+}
+
+fn read_stats(path: &Path) -> Result<Stats> {
+    if !path.is_file() {
+        return Ok(Stats::new());
+    }
+    Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+}
+
+/// Add the usage of this run to the usage file, read again so that concurrent runs are kept.
+pub fn save_usage() -> Result<()> {
+    let run_usage = std::mem::take(
+        &mut *RUN_USAGE
+            .lock()
+            .map_err(|_| eyre!("The usage lock is poisoned"))?,
+    );
+    if run_usage.is_empty() {
+        return Ok(());
+    }
+    add_to_file(&usage_path()?, run_usage)
+}
+
+fn add_to_file(path: &Path, run_usage: Stats) -> Result<()> {
+    let mut stats = read_stats(path).unwrap_or_default();
+    merge(&mut stats, run_usage);
+    Cache::write_atomically(path, serde_json::to_string_pretty(&stats)?.as_bytes())
+}
+
+fn merge(stats: &mut Stats, other: Stats) {
+    for (month, hosts) in other {
+        let month_stats = stats.entry(month).or_default();
+        for (host, usage) in hosts {
+            month_stats.entry(host).or_default().add(usage);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_adds_the_counts() {
+        let usage = |requests, bytes| Usage { requests, bytes };
+        let mut stats = Stats::from([(
+            String::from("2026-10"),
+            BTreeMap::from([(String::from("www.royalroad.com"), usage(2, 100))]),
+        )]);
+        merge(
+            &mut stats,
+            Stats::from([(
+                String::from("2026-10"),
+                BTreeMap::from([
+                    (String::from("www.royalroad.com"), usage(1, 50)),
+                    (String::from("fichub.net"), usage(1, 10)),
+                ]),
+            )]),
+        );
+        assert_eq!(stats["2026-10"]["www.royalroad.com"], usage(3, 150));
+        assert_eq!(stats["2026-10"]["fichub.net"], usage(1, 10));
+    }
+
+    #[test]
+    fn recorded_usage_is_added_to_the_file() -> Result<()> {
+        record("https://usage.invalid/fiction/1", 100);
+        record("https://usage.invalid/fiction/2", 50);
+        let month = Local::now().format("%Y-%m").to_string();
+        let host = String::from("usage.invalid");
+        let run_usage = RUN_USAGE
+            .lock()
+            .map_err(|_| eyre!("The usage lock is poisoned"))?
+            .get_mut(&month)
+            .and_then(|hosts| hosts.remove(&host))
+            .ok_or_else(|| eyre!("The requests were not recorded"))?;
+        let run_usage = Stats::from([(month.clone(), BTreeMap::from([(host.clone(), run_usage)]))]);
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(USAGE_FILENAME);
+        add_to_file(&path, run_usage.clone())?;
+        // The usage of a later run is added to the one already saved.
+        add_to_file(&path, run_usage)?;
+        assert_eq!(
+            read_stats(&path)?[&month][&host],
+            Usage {
+                requests: 4,
+                bytes: 300
+            }
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+//! Stories of Wattpad, read from its JSON API rather than from its pages.
+
+use chrono::{DateTime, Utc};
+use eyre::Result;
+use lazy_regex::regex;
+use serde::Deserialize;
+
+use crate::error::Error;
+use crate::updater::native::epub::{Book, Chapter};
+use crate::updater::native::request;
+
+const API_URL: &str = "https://www.wattpad.com";
+const STORY_FIELDS: &str = "id,title,description,url,cover,createDate,mature,tags,\
+    user(name),parts(id,title,url,createDate,modifyDate,deleted)";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Story {
+    title: String,
+    #[serde(default)]
+    description: String,
+    url: String,
+    #[serde(default)]
+    cover: String,
+    create_date: DateTime<Utc>,
+    #[serde(default)]
+    mature: bool,
+    user: User,
+    parts: Vec<Part>,
+}
+
+/// Date of the last change of the story, its parts included.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Modification {
+    modify_date: String,
+}
+
+#[derive(Deserialize)]
+struct User {
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Part {
+    id: u64,
+    title: String,
+    url: String,
+    create_date: DateTime<Utc>,
+    /// Parts are edited in place, their new version has a more recent date.
+    modify_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    deleted: bool,
+}
+
+/// Id of the story of `https://www.wattpad.com/story/<id>-<slug>`.
+pub fn story_id(url: &str) -> Option<u32> {
+    regex!(r"^https://(?:www\.)?wattpad\.com/story/(\d+)").captures(url)?[1]
+        .parse()
+        .ok()
+}
+
+/// Story of `url`, with its parts as chapters (without their content).
+pub fn fetch_book(url: &str) -> Result<Book> {
+    let id = story_id(url).ok_or_else(|| Error::Parse(format!("Not a Wattpad story : {url}")))?;
+    let api_url = format!("{API_URL}/api/v3/stories/{id}?fields={STORY_FIELDS}");
+    let response = request::block_on(request::get(&api_url))?
+        .error_for_status(&api_url)?
+        .text();
+    let story: Story = serde_json::from_str(&response)?;
+    Ok(book(id, story))
+}
+
+fn book(id: u32, story: Story) -> Book {
+    Book {
+        id,
+        url: story.url,
+        title: story.title,
+        author: story.user.name,
+        description: story.description,
+        date_published: story.create_date.to_rfc3339(),
+        cover_url: story.cover,
+        rating_label: story.mature.then(|| String::from("Mature")),
+        chapters: story
+            .parts
+            .into_iter()
+            .filter(|part| !part.deleted)
+            .map(|part| Chapter {
+                identifier: part.id.to_string(),
+                date_published: part.modify_date.unwrap_or(part.create_date),
+                title: part.title,
+                url: part.url,
+                ..Chapter::default()
+            })
+            .collect(),
+        ..Book::default()
+    }
+}
+
+/// Date of the last change of the story of `url`, only requesting that field.
+pub fn modify_date(url: &str) -> Option<String> {
+    let api_url = format!(
+        "{API_URL}/api/v3/stories/{}?fields=modifyDate",
+        story_id(url)?
+    );
+    let response = request::block_on(request::get(&api_url))
+        .and_then(|r| r.error_for_status(&api_url))
+        .ok()?
+        .text();
+    serde_json::from_str::<Modification>(&response)
+        .ok()
+        .map(|m| m.modify_date)
+}
+
+/// Text of the part `identifier`, as HTML paragraphs.
+pub async fn part_content(identifier: &str) -> Result<String> {
+    let api_url = format!("{API_URL}/apiv2/storytext?id={identifier}");
+    Ok(request::get(&api_url)
+        .await?
+        .error_for_status(&api_url)?
+        .text())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_story() -> Result<()> {
+        let story: Story = serde_json::from_str(
+            r#"{"id":"42","title":"A Story","description":"Summary",
+            "url":"https://www.wattpad.com/story/42-a-story","cover":"https://img.wattpad.com/cover/42.jpg",
+            "createDate":"2024-01-01T00:00:00Z","mature":false,"user":{"name":"someone"},
+            "parts":[{"id":7,"title":"Prologue","url":"https://www.wattpad.com/7-prologue",
+            "createDate":"2024-01-01T00:00:00Z","modifyDate":"2024-02-01T00:00:00Z"},
+            {"id":8,"title":"Removed","url":"https://www.wattpad.com/8-removed",
+            "createDate":"2024-01-02T00:00:00Z","deleted":true}]}"#,
+        )?;
+        let book = book(42, story);
+        assert_eq!(book.author, "someone");
+        assert_eq!(book.rating_label, None);
+        assert_eq!(book.chapters.len(), 1);
+        assert_eq!(book.chapters[0].identifier, "7");
+        assert_eq!(
+            book.chapters[0].date_published.to_rfc3339(),
+            "2024-02-01T00:00:00+00:00"
+        );
+        assert_eq!(
+            story_id("https://www.wattpad.com/story/42-a-story"),
+            Some(42)
+        );
+        assert_eq!(story_id("https://www.wattpad.com/7-prologue"), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+//! Novels of Wuxiaworld, whose chapters are listed page by page by its API. Locked chapters
+//! (paid or in advance) are left out until they become free.
+
+use chrono::{DateTime, Utc};
+use eyre::Result;
+use lazy_regex::regex;
+use lazy_static::lazy_static;
+use scraper::{Html, Selector};
+use serde::Deserialize;
+
+use crate::error::Error;
+use crate::updater::native::epub::{compile_time_selector, Book, Chapter};
+use crate::updater::native::{generic, request};
+use crate::updater::{warn, Warning};
+
+const SITE_URL: &str = "https://www.wuxiaworld.com";
+/// Limit of the pagination, in case the API keeps saying there is more.
+const MAX_PAGES: u32 = 500;
+
+lazy_static! {
+    static ref CONTENT_SELECTOR: Selector = compile_time_selector(".chapter-content");
+}
+
+#[derive(Deserialize)]
+struct NovelResponse {
+    item: Novel,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Novel {
+    name: String,
+    #[serde(default)]
+    author_name: String,
+    #[serde(default)]
+    synopsis: String,
+    #[serde(default)]
+    cover_url: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChapterPage {
+    items: Vec<ChapterItem>,
+    #[serde(default)]
+    has_more: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChapterItem {
+    entity_id: u64,
+    name: String,
+    slug: String,
+    published_at: DateTime<Utc>,
+    /// Chapters only readable by paying (or in advance) subscribers.
+    #[serde(default)]
+    is_locked: bool,
+}
+
+/// Slug of the novel of `https://www.wuxiaworld.com/novel/<slug>[/<chapter>]`.
+pub fn novel_slug(url: &str) -> Option<String> {
+    regex!(r"^https://(?:www\.)?wuxiaworld\.com/novel/([\w-]+)")
+        .captures(url)
+        .map(|c| c[1].to_string())
+}
+
+fn get_json<T: serde::de::DeserializeOwned>(url: &str) -> Result<T> {
+    let response = request::block_on(request::get(url))?
+        .error_for_status(url)?
+        .text();
+    Ok(serde_json::from_str(&response)?)
+}
+
+/// Novel of `url`, with its free chapters (without their content).
+pub fn fetch_book(url: &str) -> Result<Book> {
+    let slug =
+        novel_slug(url).ok_or_else(|| Error::Parse(format!("Not a Wuxiaworld novel : {url}")))?;
+    let novel = get_json::<NovelResponse>(&format!("{SITE_URL}/api/novels/{slug}"))?.item;
+    let novel_url = format!("{SITE_URL}/novel/{slug}");
+
+    let mut items = Vec::new();
+    for page in 1..=MAX_PAGES {
+        let chapter_page: ChapterPage = get_json(&format!(
+            "{SITE_URL}/api/novels/{slug}/chapters?page={page}"
+        ))?;
+        items.extend(chapter_page.items);
+        if !chapter_page.has_more {
+            break;
+        }
+    }
+    let (locked, chapters) = free_chapters(&novel_url, items);
+    if locked > 0 {
+        warn(&novel_url, Warning::LockedChapters { count: locked });
+    }
+    let date_published = chapters
+        .first()
+        .ok_or_else(|| Error::Parse(String::from("No free chapters found")))?
+        .date_published
+        .to_rfc3339();
+
+    Ok(Book {
+        id: generic::book_id(&novel_url),
+        url: novel_url,
+        title: novel.name,
+        author: novel.author_name,
+        description: novel.synopsis,
+        date_published,
+        cover_url: novel.cover_url,
+        chapters,
+        ..Book::default()
+    })
+}
+
+/// Number of locked chapters, and the free ones.
+fn free_chapters(novel_url: &str, items: Vec<ChapterItem>) -> (u32, Vec<Chapter>) {
+    let mut locked = 0;
+    let mut chapters = Vec::new();
+    for item in items {
+        if item.is_locked {
+            locked += 1;
+            continue;
+        }
+        chapters.push(Chapter {
+            identifier: item.entity_id.to_string(),
+            date_published: item.published_at,
+            title: item.name,
+            url: format!("{novel_url}/{}", item.slug),
+            ..Chapter::default()
+        });
+    }
+    (locked, chapters)
+}
+
+pub fn chapter_content(page: &Html) -> Option<String> {
+    page.select(&CONTENT_SELECTOR)
+        .next()
+        .map(|e| e.inner_html())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locked_chapters_are_skipped() -> Result<()> {
+        let page: ChapterPage = serde_json::from_str(
+            r#"{"items":[
+            {"entityId":1,"name":"Chapter 1","slug":"chapter-1","publishedAt":"2024-01-01T00:00:00Z"},
+            {"entityId":2,"name":"Chapter 2","slug":"chapter-2","publishedAt":"2024-01-02T00:00:00Z","isLocked":true}],
+            "hasMore":false}"#,
+        )?;
+        let (locked, chapters) =
+            free_chapters("https://www.wuxiaworld.com/novel/a-novel", page.items);
+        assert_eq!(locked, 1);
+        assert_eq!(chapters.len(), 1);
+        assert_eq!(
+            chapters[0].url,
+            "https://www.wuxiaworld.com/novel/a-novel/chapter-1"
+        );
+        assert_eq!(
+            novel_slug("https://www.wuxiaworld.com/novel/a-novel/chapter-1").as_deref(),
+            Some("a-novel")
+        );
+        Ok(())
+    }
+}
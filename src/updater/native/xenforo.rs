@@ -0,0 +1,205 @@
+//! Threads of `XenForo` forums (`SpaceBattles`, Sufficient Velocity, Questionable Questing),
+//! whose chapters are the threadmarked posts.
+
+use chrono::DateTime;
+use eyre::Result;
+use lazy_regex::regex;
+use lazy_static::lazy_static;
+use scraper::{Html, Selector};
+use std::collections::HashSet;
+use url::Url;
+
+use crate::error::Error;
+use crate::updater::native::epub::{compile_time_selector, Book, Chapter};
+use crate::updater::native::request;
+
+/// Forums whose threads are supported.
+pub const HOSTS: [&str; 3] = [
+    "forums.spacebattles.com",
+    "forums.sufficientvelocity.com",
+    "forum.questionablequesting.com",
+];
+
+lazy_static! {
+    static ref TITLE_SELECTOR: Selector = compile_time_selector("h1.p-title-value");
+    static ref AUTHOR_SELECTOR: Selector = compile_time_selector(".p-description .username");
+    static ref DESCRIPTION_SELECTOR: Selector =
+        compile_time_selector("meta[property=\"og:description\"]");
+    static ref THREADMARK_SELECTOR: Selector = compile_time_selector(".structItem--threadmark");
+    static ref THREADMARK_LINK_SELECTOR: Selector = compile_time_selector(".structItem-title a");
+    static ref THREADMARK_TIME_SELECTOR: Selector = compile_time_selector("time[data-time]");
+    static ref NEXT_PAGE_SELECTOR: Selector = compile_time_selector("a.pageNav-jump--next");
+}
+
+/// Canonical URL of the thread (`https://<host>/threads/<slug>.<id>/`) of any of its pages.
+pub fn thread_url(url: &str) -> Option<String> {
+    let captures = regex!(r"^(https://[^/]+/threads/[^/]*?\.?\d+)(/|$)").captures(url)?;
+    Some(format!("{}/", &captures[1]))
+}
+
+/// Feed of the latest threadmarks of the thread.
+pub fn feed_url(book: &Book) -> Option<String> {
+    Some(format!("{}threadmarks.rss", thread_url(&book.url)?))
+}
+
+/// Thread of `url`, with the threadmarked posts as chapters (without their content).
+pub fn fetch_book(url: &str) -> Result<Book> {
+    let thread_url =
+        thread_url(url).ok_or_else(|| Error::Parse(format!("Not a XenForo thread : {url}")))?;
+    let response = request::block_on(request::get(&thread_url))?
+        .error_for_status(&thread_url)?
+        .text();
+    let page = Html::parse_document(&response);
+    let title = page
+        .select(&TITLE_SELECTOR)
+        .next()
+        .map(|e| e.text().collect::<String>().trim().to_string())
+        .ok_or_else(|| Error::Parse(String::from("No title found")))?;
+    let author = page
+        .select(&AUTHOR_SELECTOR)
+        .next()
+        .map(|e| e.text().collect::<String>().trim().to_string())
+        .unwrap_or_default();
+    let description = page
+        .select(&DESCRIPTION_SELECTOR)
+        .next()
+        .and_then(|e| e.attr("content"))
+        .unwrap_or_default()
+        .to_string();
+
+    let chapters = threadmarks(&thread_url)?;
+    let date_published = chapters
+        .first()
+        .ok_or_else(|| Error::Parse(String::from("No threadmarks found")))?
+        .date_published
+        .to_rfc3339();
+
+    Ok(Book {
+        id: thread_id(&thread_url)?,
+        url: thread_url,
+        title,
+        author,
+        description,
+        date_published,
+        chapters,
+        ..Book::default()
+    })
+}
+
+fn thread_id(thread_url: &str) -> Result<u32> {
+    Ok(regex!(r"(\d+)/$")
+        .captures(thread_url)
+        .and_then(|c| c[1].parse().ok())
+        .ok_or_else(|| Error::Parse(format!("No thread id in {thread_url}")))?)
+}
+
+/// Threadmarks of every page of the index of the thread.
+fn threadmarks(thread_url: &str) -> Result<Vec<Chapter>> {
+    let mut chapters = Vec::new();
+    let mut next_page = Some(format!("{thread_url}threadmarks"));
+    let mut visited = HashSet::new();
+    while let Some(page_url) = next_page.filter(|u| visited.insert(u.clone())) {
+        let response = request::block_on(request::get(&page_url))?
+            .error_for_status(&page_url)?
+            .text();
+        let page = Html::parse_document(&response);
+        chapters.extend(index_threadmarks(&page, &page_url));
+        next_page = page
+            .select(&NEXT_PAGE_SELECTOR)
+            .next()
+            .and_then(|a| a.attr("href"))
+            .and_then(|href| Url::parse(&page_url).ok()?.join(href).ok())
+            .map(String::from);
+    }
+    Ok(chapters)
+}
+
+fn index_threadmarks(page: &Html, page_url: &str) -> Vec<Chapter> {
+    let base = Url::parse(page_url).ok();
+    page.select(&THREADMARK_SELECTOR)
+        .filter_map(|item| {
+            let link = item.select(&THREADMARK_LINK_SELECTOR).next()?;
+            let href = link.attr("href")?;
+            let identifier = post_id(href)?;
+            let date_published = item
+                .select(&THREADMARK_TIME_SELECTOR)
+                .next()
+                .and_then(|t| t.attr("data-time"))
+                .and_then(|t| t.parse().ok())
+                .and_then(|t| DateTime::from_timestamp(t, 0))
+                .unwrap_or_default();
+            let host = base.as_ref()?.origin().ascii_serialization();
+            Some(Chapter {
+                url: format!("{host}/posts/{identifier}/"),
+                identifier,
+                date_published,
+                title: link.text().collect::<String>().trim().to_string(),
+                ..Chapter::default()
+            })
+        })
+        .collect()
+}
+
+/// Id of the post a link points to (`.../post-123`, `.../posts/123/` or `#post-123`).
+pub fn post_id(link: &str) -> Option<String> {
+    regex!(r"posts?[-/](\d+)")
+        .captures(link)
+        .map(|c| c[1].to_string())
+}
+
+/// Content of the post `identifier` of a page of the thread.
+pub fn post_content(page: &Html, identifier: &str) -> Option<String> {
+    let selector = Selector::parse(&format!(
+        "article[data-content=\"post-{identifier}\"] .message-body .bbWrapper"
+    ))
+    .ok()?;
+    page.select(&selector).next().map(|e| e.inner_html())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_threadmarks_index() {
+        let page = Html::parse_document(
+            "<div class=\"structItem structItem--threadmark\">\
+            <div class=\"structItem-title\"><a href=\"/threads/a-quest.123/post-456\"> Chapter 1 </a></div>\
+            <time class=\"u-dt\" data-time=\"1704067200\">Jan 1, 2024</time></div>\
+            <div class=\"structItem structItem--threadmark\">\
+            <div class=\"structItem-title\"><a href=\"/threads/a-quest.123/page-2#post-789\">Chapter 2</a></div></div>",
+        );
+        let chapters = index_threadmarks(
+            &page,
+            "https://forums.spacebattles.com/threads/a-quest.123/threadmarks",
+        );
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[0].identifier, "456");
+        assert_eq!(chapters[0].title, "Chapter 1");
+        assert_eq!(
+            chapters[0].url,
+            "https://forums.spacebattles.com/posts/456/"
+        );
+        assert_eq!(chapters[0].date_published.timestamp(), 1_704_067_200);
+        assert_eq!(chapters[1].identifier, "789");
+
+        let post = Html::parse_document(
+            "<article data-content=\"post-456\"><div class=\"message-body\">\
+            <div class=\"bbWrapper\"><p>Text</p></div></div></article>",
+        );
+        assert_eq!(post_content(&post, "456").as_deref(), Some("<p>Text</p>"));
+    }
+
+    #[test]
+    fn test_thread_url() {
+        assert_eq!(
+            thread_url("https://forums.sufficientvelocity.com/threads/a-quest.123/page-4#post-9")
+                .as_deref(),
+            Some("https://forums.sufficientvelocity.com/threads/a-quest.123/")
+        );
+        assert_eq!(
+            thread_id("https://forums.sufficientvelocity.com/threads/a-quest.123/").ok(),
+            Some(123)
+        );
+    }
+}